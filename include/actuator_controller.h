@@ -0,0 +1,42 @@
+/* C interface to the actuator controller, see src/ffi.rs.
+ *
+ * Generated from src/ffi.rs with `cbindgen --config cbindgen.toml --output include/actuator_controller.h`;
+ * regenerate it after changing the bindings.
+ */
+
+#ifndef ACTUATOR_CONTROLLER_H
+#define ACTUATOR_CONTROLLER_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct AcClient AcClient;
+
+AcClient *ac_connect(const char *port_path, const char *config_path);
+
+void ac_free(AcClient *client);
+
+const char *ac_last_error(const AcClient *client);
+
+void ac_set_timeout_ms(AcClient *client, uint32_t timeout_ms);
+
+int ac_set_speed(AcClient *client, uint8_t actuator, uint8_t direction, uint16_t speed);
+
+int ac_goto(AcClient *client, uint8_t actuator, double len);
+
+int ac_stop(AcClient *client, int actuator);
+
+int ac_run(AcClient *client, const char *command);
+
+int ac_length(AcClient *client, uint8_t actuator, double *len);
+
+int ac_next_telemetry(AcClient *client, uint32_t timeout_ms, uint8_t *actuator, double *len);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif
+
+#endif  /* ACTUATOR_CONTROLLER_H */
@@ -0,0 +1,89 @@
+//! The `i` popup: which build this is and what it's talking to, to quote
+//! when reporting a bug from one laptop or another.
+
+use actuator_controller::{audit, config, platform, session, telemetry::Schema, vectors};
+use ratatui::{
+    layout::Rect,
+    style::{Modifier, Style},
+    text::{Line, Span, Text},
+    widgets::{Block, Borders, Clear, Paragraph},
+    Frame,
+};
+
+use crossterm::event::KeyCode;
+
+#[cfg(feature = "bridge")]
+const BRIDGE: &str = crate::zmtp::PROTOCOL;
+#[cfg(not(feature = "bridge"))]
+const BRIDGE: &str = "not built";
+
+pub struct About {
+    rows: Vec<(String, String)>,
+}
+
+impl About {
+    /// Looks everything up once, as the platform checks scan for devices.
+    /// `connection` is what the link is to, and `schema` the fields the
+    /// firmware advertised, if it has.
+    pub fn open(connection: String, schema: Option<&Schema>) -> About {
+        let features = session::features();
+        let mut rows = vec![
+            (String::from("Version"), env!("CARGO_PKG_VERSION").to_string()),
+            (String::from("Commit"), session::COMMIT.to_string()),
+            (String::from("Built"), session::built().map_or(String::from("unknown"), audit::timestamp)),
+            (String::from("Target"), format!("{} {}", std::env::consts::OS, std::env::consts::ARCH)),
+            (String::from("Features"), if features.is_empty() { String::from("none") } else { features.join(", ") }),
+            (String::from("Connected to"), connection),
+            (
+                String::from("Firmware"),
+                match schema {
+                    Some(schema) => format!("reports {schema}; it doesn't say its version"),
+                    None => String::from("no reports yet; it doesn't say its version"),
+                },
+            ),
+            (
+                String::from("Protocols"),
+                format!("wire format {} | bridge {} | config {}", vectors::VERSION, BRIDGE, config::VERSION),
+            ),
+        ];
+        for capability in platform::Capability::ALL {
+            let text = match capability.detect() {
+                platform::Support::Available(found) if found.is_empty() => String::from("yes"),
+                platform::Support::Available(found) => format!("yes ({found})"),
+                platform::Support::Unavailable(why) => format!("no ({why})"),
+            };
+            rows.push((capability.to_string(), text));
+        }
+        About { rows }
+    }
+
+    /// Whether `code` closes the popup.
+    pub fn key(&self, code: KeyCode) -> bool {
+        matches!(code, KeyCode::Esc | KeyCode::Enter | KeyCode::Char('i'))
+    }
+
+    pub fn render(&self, f: &mut Frame, area: Rect) {
+        let label = self.rows.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+        let lines: Vec<Line> = self
+            .rows
+            .iter()
+            .map(|(name, value)| {
+                Line::from(vec![
+                    Span::styled(format!("{name:label$}  "), Style::default().add_modifier(Modifier::BOLD)),
+                    Span::raw(value.clone()),
+                ])
+            })
+            .collect();
+        let width = (lines.iter().map(Line::width).max().unwrap_or(0) as u16 + 4).clamp(40.min(area.width), area.width);
+        let height = (lines.len() as u16 + 2).min(area.height);
+        let popup = Rect {
+            x: area.x + (area.width - width) / 2,
+            y: area.y + (area.height - height) / 2,
+            width,
+            height,
+        };
+        let text = Paragraph::new(Text::from(lines)).block(Block::default().title("About").borders(Borders::ALL));
+        f.render_widget(Clear, popup);
+        f.render_widget(text, popup);
+    }
+}
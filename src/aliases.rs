@@ -0,0 +1,57 @@
+//! Short names for commands typed often, e.g. `up = lift fwd 40%` or
+//! `park = both goto 0.05`, read wherever a one-line command is.
+//!
+//! An alias is the first word of a line. It's replaced by what it stands
+//! for, with the rest of the line after it, so one can leave off the end of
+//! a command: with `nudge = m1 fwd 20% for`, `nudge 2s` runs
+//! `m1 fwd 20% for 2s`. Aliases don't expand inside other aliases.
+
+use std::borrow::Cow;
+
+use crate::groups;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Alias {
+    pub name: String,
+    pub command: String,
+}
+
+impl Alias {
+    /// Reads `<name> = <command>`.
+    pub fn parse(text: &str) -> Result<Alias, String> {
+        let (name, command) = text.split_once('=').ok_or("expected `<name> = <command>`")?;
+        let name = name.trim().to_ascii_lowercase();
+        let command = command.trim();
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+            return Err(format!("`{name}` isn't a name; use letters, digits, `_` and `-`"));
+        }
+        if groups::RESERVED.contains(&name.as_str()) {
+            return Err(format!("`{name}` already means something in commands"));
+        }
+        if command.is_empty() {
+            return Err(format!("`{name}` stands for nothing"));
+        }
+        Ok(Alias { name, command: command.to_string() })
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Aliases {
+    pub aliases: Vec<Alias>,
+}
+
+impl Aliases {
+    pub fn get(&self, name: &str) -> Option<&Alias> {
+        self.aliases.iter().find(|alias| alias.name.eq_ignore_ascii_case(name))
+    }
+
+    /// `line` with an alias at the start replaced by its command.
+    pub fn expand<'a>(&self, line: &'a str) -> Cow<'a, str> {
+        let line = line.trim();
+        let first = line.split_whitespace().next().unwrap_or("");
+        match self.get(first) {
+            Some(alias) => Cow::Owned(format!("{}{}", alias.command, &line[first.len()..])),
+            None => Cow::Borrowed(line),
+        }
+    }
+}
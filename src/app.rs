@@ -0,0 +1,1116 @@
+//! Everything the controller knows and decides, independent of the terminal UI.
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::Arc,
+    time::{Instant, SystemTime},
+};
+
+use tokio::sync::mpsc;
+
+use crate::{
+    arming, audit, checklist, estop,
+    cadence::Cadence,
+    commands::{Actuator, ActuatorCommand, Direction, Drive, Encoder},
+    config::Config,
+    countdown::{self, Countdown},
+    dsl,
+    history, hooks, journal, keymap, kinematics,
+    latency::Profiler,
+    link::Request,
+    load, mode, position, sensor, sequence, skew, standstill,
+    status::{Fault, StatusEvent},
+    toast::{Severity, Toasts},
+    trajectory::Trajectory,
+};
+
+/// Something the operator asked for as a whole, as opposed to a key press.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action {
+    Dig,
+    Command(dsl::Command),
+    /// nothing but arming, for manual driving under two-person arming
+    Arm,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Dig => f.write_str("dig cycle"),
+            Action::Arm => f.write_str("arm"),
+            Action::Command(cmd) => write!(f, "{cmd}"),
+        }
+    }
+}
+
+/// Something to mark on the Telemetry chart's timeline.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TimelineEvent {
+    /// a command or dig cycle that was started
+    Command(String),
+    Fault(String),
+    Reconnect,
+    /// a `mark` note
+    Note(String),
+}
+
+/// How many timeline events are kept for the UI to pick up.
+const TIMELINE_CAPACITY: usize = 100;
+
+/// How many settled moves are kept for the frontend to pick up.
+const SETTLES_CAPACITY: usize = 100;
+
+/// How many lines of the firmware's debug text are kept for the UI.
+const FIRMWARE_LOG_CAPACITY: usize = 500;
+
+/// A `follow` waiting for the operator to look it over.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Preview {
+    pub path: String,
+    pub trajectory: Trajectory,
+}
+
+pub struct App {
+    /// manually commanded speed of each actuator, negative when backward,
+    /// indexed by `Actuator as usize`
+    pub speeds: [i32; 2],
+    /// the way each actuator's speed keys drive it, kept while it's stopped
+    pub heading: [Direction; 2],
+    pub max_speed: u32,
+    pub status_message: String,
+    /// notifications raised along the way, shown for a while and kept for the log
+    pub toasts: Toasts,
+    /// the link's latest fault, until the port is reopened or the task restarted
+    pub fault: Option<Fault>,
+    /// the firmware's debug text as it came in, oldest first
+    pub firmware_log: VecDeque<(SystemTime, String)>,
+    /// what the link's power budget last scaled speeds by, see [`crate::budget`]
+    pub budget_scale: f64,
+    pub actuator: Actuator,
+    /// last reported length of each actuator, indexed by `Actuator as usize`
+    pub actuator_len_meters: [Option<f64>; 2],
+    /// last reported current of each actuator, if the firmware reports one
+    pub current: [Option<f64>; 2],
+    /// last force (N) on the load cell, if the firmware reports one
+    pub force: Option<f64>,
+    pub load: load::LoadCell,
+    /// whether the last force was over the alarm, so it only goes off once
+    overloaded: bool,
+    pub linkage: kinematics::Linkage,
+    /// text typed after `:`, while the command prompt is open
+    pub prompt: Option<String>,
+    /// a trajectory typed at the prompt, shown until it's confirmed or cancelled
+    pub preview: Option<Preview>,
+    pub position: position::PositionController,
+    /// how each actuator's last move to a target settled
+    pub last_settle: [Option<position::Settle>; 2],
+    /// settled moves the frontend hasn't taken yet, oldest first
+    pub settles: Vec<position::Settle>,
+    pub sequence: Option<sequence::Runner>,
+    /// with `--journal`, where `sequence` is kept on disk, see [`crate::journal`]
+    pub journal: Option<journal::Journal>,
+    /// what the journal says was running when the last session ended
+    /// without finishing it, until it's resumed or discarded
+    pub interrupted: Option<journal::Entry>,
+    pub dig: sequence::DigCycle,
+    pub effort: sequence::Effort,
+    pub mode: mode::Mode,
+    pub trim: [f64; 2],
+    pub keys: keymap::Keymap,
+    /// differential-mode speed skew, added to M1 and taken from M2
+    pub offset: i32,
+    pub skew: skew::SkewMonitor,
+    /// how far each length sensor can be trusted
+    pub sensors: sensor::Sensors,
+    /// once tripped, refuses anything that would move until it's reset
+    pub estop: estop::Latch,
+    /// input stays off while it checks both actuators are stopped; idle
+    /// unless the frontend starts it
+    pub standstill: standstill::Standstill,
+    /// mirror-mode bias currently applied to level the lift
+    pub correction: i32,
+    /// drives queued by the app itself, sent at the top of the next loop
+    pub pending: Vec<Drive>,
+    /// actuators to be told to hold where they are, sent after `pending`
+    holds: Vec<Actuator>,
+    /// which actuators the firmware was last told to hold, until they're next driven
+    pub held: [bool; 2],
+    /// the signed speed each actuator was last sent, by hand or not
+    pub commanded: [i32; 2],
+    encoder: Encoder,
+    /// frames handed to the link so far; less the link's acks, what's still queued
+    pub sent: u64,
+    /// with `--profile`, where frames are timed from input to ack
+    pub profiler: Option<Arc<Profiler>>,
+    /// when the input being handled arrived, for the profiler
+    pub input_at: Option<Instant>,
+    /// when the frontend's next `Msg::Tick` is due, and how well it's kept to
+    pub cadence: Cadence,
+    pub audit: audit::AuditLog,
+    pub arming: arming::Gate,
+    pub history: history::History,
+    pub countdown: Countdown,
+    /// how far into the countdown the run was at the last tick
+    countdown_stage: countdown::Stage,
+    /// timeline events the UI hasn't taken yet, oldest first
+    pub timeline: Vec<(Instant, TimelineEvent)>,
+    /// `[startup]` commands still to run, one at a time once the first
+    /// report shows the link is up; empty unless the frontend fills it
+    pub startup: VecDeque<dsl::Command>,
+    /// commands to run as readings cross thresholds, see [`crate::hooks`]
+    pub hooks: hooks::Hooks,
+    /// how many `[startup]` commands have been run
+    started: usize,
+    /// what to see to before drives may run at full speed; empty, so
+    /// nothing is capped, unless the frontend fills it
+    pub checklist: checklist::Checklist,
+    /// the config's groups and aliases, for reading commands
+    pub vocabulary: dsl::Vocabulary,
+}
+
+impl App {
+    pub fn new(config: &Config) -> App {
+        App {
+            speeds: [0; 2],
+            heading: [Direction::Forward; 2],
+            max_speed: 65535, // Adjust based on the motor's capabilities
+            status_message: String::from("Ready"),
+            toasts: Toasts::default(),
+            fault: None,
+            firmware_log: VecDeque::new(),
+            budget_scale: 1.0,
+            actuator: Actuator::M1,
+            actuator_len_meters: [None; 2],
+            current: [None; 2],
+            force: None,
+            load: config.load,
+            overloaded: false,
+            linkage: config.linkage,
+            prompt: None,
+            preview: None,
+            position: position::PositionController::new(config.position),
+            last_settle: [None; 2],
+            settles: Vec::new(),
+            sequence: None,
+            journal: None,
+            interrupted: None,
+            dig: config.dig,
+            effort: config.effort,
+            mode: mode::Mode::Single,
+            trim: config.trim,
+            keys: config.keys,
+            offset: 0,
+            skew: config.skew,
+            sensors: sensor::Sensors::new(config.sensor),
+            estop: estop::Latch::default(),
+            standstill: standstill::Standstill::new(config.standstill),
+            correction: 0,
+            pending: Vec::new(),
+            holds: Vec::new(),
+            held: [false; 2],
+            commanded: [0; 2],
+            encoder: Encoder::default(),
+            sent: 0,
+            profiler: None,
+            input_at: None,
+            cadence: Cadence::new(config.position.rate),
+            audit: audit::AuditLog::default(),
+            arming: arming::Gate::new(config.arming),
+            history: history::History::default(),
+            countdown: config.countdown,
+            countdown_stage: countdown::Stage::Running,
+            timeline: Vec::new(),
+            startup: VecDeque::new(),
+            hooks: config.hooks.clone(),
+            started: 0,
+            checklist: checklist::Checklist::default(),
+            vocabulary: config.vocabulary(),
+        }
+    }
+
+    /// Tells the operator something: it goes in the status line and pops up
+    /// as a notification.
+    pub fn notify(&mut self, severity: Severity, text: String) {
+        self.toasts.push(severity, text.clone(), Instant::now());
+        self.status_message = text;
+    }
+
+    /// Tells the operator how a command went.
+    pub fn report(&mut self, result: Result<String, String>) {
+        match result {
+            Ok(msg) => self.notify(Severity::Info, msg),
+            Err(e) => self.notify(Severity::Error, format!("Error: {e}")),
+        }
+    }
+
+    /// An actuator's manually commanded speed, whichever way it's going.
+    pub fn speed(&self, actuator: Actuator) -> u32 {
+        self.speeds[actuator as usize].unsigned_abs()
+    }
+
+    /// Drives an actuator at a signed speed from manual input, fanned out
+    /// according to the control mode. Every manual key ends up here.
+    pub fn drive_manual(&mut self, actuator: Actuator, speed: i32) {
+        let max = self.max_speed as i32;
+        let speed = self.checklist.limit(speed.clamp(-max, max));
+        if speed != 0 {
+            self.heading[actuator as usize] = Direction::of(speed);
+        }
+        self.speeds[actuator as usize] = speed;
+        let drive = Drive::new(actuator, speed);
+        self.audit.record(&format!("manual {drive}"));
+        self.queue_manual(drive);
+    }
+
+    /// Speeds an actuator up (or down, for a negative `delta`) in its heading.
+    pub fn change_speed(&mut self, actuator: Actuator, delta: i32) {
+        let speed = (self.speed(actuator) as i32 + delta).max(0) as u32;
+        self.drive_manual(actuator, self.heading[actuator as usize].signed(speed));
+    }
+
+    /// Points an actuator's speed keys `direction`, reversing it if it's moving.
+    pub fn set_direction(&mut self, actuator: Actuator, direction: Direction) {
+        self.heading[actuator as usize] = direction;
+        if self.speeds[actuator as usize] != 0 {
+            self.drive_manual(actuator, direction.signed(self.speed(actuator)));
+        }
+    }
+
+    /// Has the firmware hold `actuator` at its current length against load,
+    /// until it's next sent a speed. It's stopped first, so firmware that
+    /// can't hold at least doesn't keep it moving.
+    pub fn hold(&mut self, actuator: Actuator) {
+        self.abort();
+        self.speeds[actuator as usize] = 0;
+        self.pending.push(Drive::stop(actuator));
+        self.holds.push(actuator);
+        self.audit.record(&format!("hold {actuator:?}"));
+    }
+
+    /// Manual stop of both actuators, leaving their headings alone.
+    pub fn stop_all(&mut self) {
+        for actuator in [Actuator::M1, Actuator::M2] {
+            self.speeds[actuator as usize] = 0;
+            self.pending.push(Drive::stop(actuator));
+        }
+        self.audit.record("manual stop");
+    }
+
+    /// Trips the e-stop for `why`: aborts whatever's running, stops both
+    /// actuators and refuses anything that would move them until it's reset.
+    /// Returns what to tell the operator.
+    pub fn estop(&mut self, why: &str) -> String {
+        self.abort();
+        self.stop_all();
+        if self.estop.trip(why) {
+            self.audit.record(&format!("e-stop: {why}"));
+            self.estop.outbox.push(estop::Event::Tripped(why.to_string()));
+        }
+        format!("E-STOP ({}): E, then y, to reset", self.estop.why().unwrap_or(why))
+    }
+
+    /// Asks to reset the e-stop, which `y` then confirms.
+    pub fn ask_reset(&mut self) {
+        match self.estop.ask_reset(Instant::now()) {
+            Ok(()) => self.notify(Severity::Warn, String::from("Reset the e-stop? y: Confirm")),
+            Err(e) => self.notify(Severity::Warn, format!("Nothing to reset: {e}")),
+        }
+    }
+
+    /// Confirms a reset asked for with [`App::ask_reset`].
+    pub fn confirm_reset(&mut self) {
+        match self.estop.confirm_reset(Instant::now()) {
+            Ok(why) => {
+                let operator = self.audit.operator().to_string();
+                self.audit.record(&format!("e-stop reset (was: {why})"));
+                self.notify(Severity::Info, format!("E-stop reset by {operator}"));
+                self.estop.outbox.push(estop::Event::Reset(operator));
+            }
+            Err(e) => self.notify(Severity::Warn, format!("Not reset: {e}")),
+        }
+    }
+
+    /// Passes our e-stop trips and resets to the daemon, and latches or
+    /// resets along with the other clients'.
+    pub fn sync_estop(&mut self, channel: &mut estop::Channel) {
+        for event in self.estop.outbox.drain(..) {
+            let _ = channel.requests.try_send(event);
+        }
+        while let Ok(event) = channel.events.try_recv() {
+            match event {
+                // ours coming back, or another trip while we're latched anyway
+                estop::Event::Tripped(_) if self.estop.is_latched() => {}
+                estop::Event::Tripped(why) => {
+                    let msg = self.estop(&why);
+                    self.notify(Severity::Error, msg);
+                }
+                estop::Event::Reset(operator) => {
+                    if let Some(why) = self.estop.clear() {
+                        self.audit.record(&format!("e-stop reset by {operator} (was: {why})"));
+                        self.notify(Severity::Info, format!("E-stop reset by {operator}"));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Stops both actuators and takes no input that could move them until
+    /// each has shown it's still, see [`standstill`].
+    pub fn check_standstill(&mut self) {
+        let Some(order) = self.standstill.start(Instant::now()) else { return };
+        self.abort();
+        for actuator in order {
+            self.speeds[actuator as usize] = 0;
+            self.pending.push(Drive::stop(actuator));
+        }
+        self.audit.record("standstill check");
+        self.notify(Severity::Info, String::from("Checking both actuators are stopped before taking input"));
+    }
+
+    /// Takes in a load cell reading, sounding the alarm as it goes over.
+    pub(crate) fn on_force(&mut self, reported: f64) {
+        let force = self.load.force(reported);
+        self.force = Some(force);
+        let overloaded = self.load.is_excessive(force);
+        if overloaded && !self.overloaded {
+            self.audit.record(&format!("force alarm: {force:.0} N"));
+            self.notify(Severity::Warn, format!("Force {force:.0} N is over the {} N alarm", self.load.alarm));
+            if self.load.stop {
+                let msg = self.estop("force alarm");
+                self.notify(Severity::Error, msg);
+            }
+        }
+        self.overloaded = overloaded;
+    }
+
+    /// Takes in a length report for the sensor scores, warning as one drops,
+    /// and the standstill check.
+    pub(crate) fn on_length(&mut self, actuator: Actuator, length: f64) {
+        let now = Instant::now();
+        self.standstill.observe(actuator, length, now);
+        let stroke = self.linkage.joint(actuator).max_stroke;
+        self.sensors.observe(actuator, length, stroke, now);
+        if let Some(health) = self.sensors.dropped(actuator) {
+            let why = health.worst.map(|problem| format!(": {problem}")).unwrap_or_default();
+            let msg = format!("{actuator:?} length sensor scores {:.0}{why}", health.score);
+            self.audit.record(&msg);
+            self.notify(Severity::Warn, msg);
+        }
+    }
+
+    /// Notes an event for the UI's timeline, forgetting the oldest if the UI
+    /// isn't taking them.
+    fn mark_timeline(&mut self, event: TimelineEvent) {
+        if self.timeline.len() == TIMELINE_CAPACITY {
+            self.timeline.remove(0);
+        }
+        self.timeline.push((Instant::now(), event));
+    }
+
+    /// Takes in something the link reported.
+    pub(crate) fn on_status(&mut self, event: StatusEvent) {
+        match &event {
+            StatusEvent::FaultRaised(fault) => {
+                if matches!(fault, Fault::LinkHung(_)) || matches!(fault, Fault::TaskFailed(task, _) if task == "writer") {
+                    // the link threw away what we'd queued, or lost track of
+                    // it, and is stopping both actuators itself
+                    self.start_over();
+                }
+                self.audit.record(&format!("fault: {event}"));
+                self.mark_timeline(TimelineEvent::Fault(fault.to_string()));
+                self.fault = Some(fault.clone());
+            }
+            StatusEvent::Reconnected => {
+                self.mark_timeline(TimelineEvent::Reconnect);
+                self.fault = None;
+                // the firmware may have reset along with the port
+                if self.standstill.has_started() {
+                    self.check_standstill();
+                }
+            }
+            StatusEvent::TaskRestarted(task) => {
+                if matches!(&self.fault, Some(Fault::TaskFailed(failed, _)) if failed == task) {
+                    self.fault = None;
+                }
+            }
+            StatusEvent::ConstraintStop(actuator, _) => {
+                // the firmware was sent a stop, whatever the keys last asked for,
+                // and maybe nothing else we sent, so the next drive starts afresh
+                self.speeds[*actuator as usize] = 0;
+                self.encoder.forget(*actuator);
+                self.audit.record(&event.to_string());
+            }
+            StatusEvent::TimedStop(actuator) => self.speeds[*actuator as usize] = 0,
+            // only going over or coming back is news; the Info panel has the rest
+            StatusEvent::BudgetScaled(scale, _) => {
+                let crossed = (*scale < 1.0) != (self.budget_scale < 1.0);
+                self.budget_scale = *scale;
+                if !crossed {
+                    return;
+                }
+                self.audit.record(&event.to_string());
+            }
+            StatusEvent::Disconnected => {
+                self.audit.record("lost the daemon");
+                self.start_over();
+            }
+            // too chatty for notifications; it has a pane of its own
+            StatusEvent::FirmwareLog(text) => {
+                self.audit.record(&format!("firmware: {text}"));
+                if self.firmware_log.len() == FIRMWARE_LOG_CAPACITY {
+                    self.firmware_log.pop_front();
+                }
+                self.firmware_log.push_back((SystemTime::now(), text.clone()));
+                return;
+            }
+            _ => {}
+        }
+        let severity = match &event {
+            // every frame is acked; that's no news
+            StatusEvent::CommandAcked(_) => {
+                self.status_message = event.to_string();
+                return;
+            }
+            event if event.is_error() => Severity::Error,
+            StatusEvent::InterlockStop(_) | StatusEvent::ConstraintStop(..) => Severity::Warn,
+            StatusEvent::BudgetScaled(scale, _) if *scale < 1.0 => Severity::Warn,
+            _ => Severity::Info,
+        };
+        self.notify(severity, event.to_string());
+    }
+
+    /// Forgets every move and speed, for when nothing we sent can be relied on.
+    fn start_over(&mut self) {
+        self.abort();
+        self.pending.clear();
+        self.holds.clear();
+        self.held = [false; 2];
+        self.speeds = [0; 2];
+        self.encoder = Encoder::default();
+    }
+
+    /// Stops any move or sequence in progress.
+    pub fn abort(&mut self) {
+        if let Some(waiting) = self.arming.waiting.take() {
+            self.audit.record(&format!("withdraw `{}`", waiting.text));
+        }
+        if let Some(runner) = self.sequence.take() {
+            self.audit.record(&format!("abort {}", runner.describe()));
+            self.notify(Severity::Info, format!("Aborted {}", runner.describe()));
+            self.pending.extend(runner.abort());
+            self.finish_journal();
+        }
+        self.pending.extend(self.position.cancel());
+    }
+
+    /// Starts `sequence`, journaling it as started from `origin`.
+    fn start_sequence(&mut self, origin: journal::Origin, sequence: sequence::Sequence) {
+        // its journal entry is about to be written over
+        if let Some(entry) = self.interrupted.take() {
+            self.audit.record(&format!("discard interrupted {}", entry.running));
+        }
+        let runner = sequence::Runner::new(sequence);
+        if let Some(journal) = &mut self.journal
+            && let Err(e) = journal.start(origin, &runner, Instant::now())
+        {
+            self.notify(Severity::Warn, e);
+        }
+        self.sequence = Some(runner);
+    }
+
+    /// Clears the journal once the sequence has finished or been aborted.
+    fn finish_journal(&mut self) {
+        if let Some(journal) = &mut self.journal
+            && let Err(e) = journal.finish()
+        {
+            self.notify(Severity::Warn, e);
+        }
+    }
+
+    /// Starts what the journal says was running again, from the step it was
+    /// on (`R`).
+    pub fn resume_interrupted(&mut self) -> Result<String, String> {
+        let entry = self.interrupted.take().ok_or("nothing interrupted to resume")?;
+        // confirmed, it would start over from the first step
+        if self.arming.required() {
+            self.interrupted = Some(entry);
+            return Err(String::from("two-person arming is on; run it again to have it confirmed"));
+        }
+        let started = match &entry.origin {
+            journal::Origin::Command(line) => self.vocabulary.parse(line).and_then(|cmd| self.run(cmd)).map(|_| ()),
+            journal::Origin::Dig(start) => {
+                self.abort();
+                self.audit.record("dig cycle");
+                self.start_sequence(entry.origin.clone(), self.dig.sequence(*start));
+                Ok(())
+            }
+        };
+        let runner = match (started, &mut self.sequence) {
+            (Ok(()), Some(runner)) => runner,
+            (Err(e), _) => {
+                self.interrupted = Some(entry);
+                return Err(e);
+            }
+            (Ok(()), None) => return Err(format!("{} didn't start a sequence to resume", entry.running)),
+        };
+        runner.skip_to(entry.step, entry.played);
+        let msg = format!("Resumed {}", runner.describe());
+        self.audit.record(&format!("resume interrupted {} at step {}, {:.1} s in", entry.running, entry.step + 1, entry.played));
+        Ok(msg)
+    }
+
+    /// Stops both actuators and forgets what the journal says was running
+    /// (`X`).
+    pub fn discard_interrupted(&mut self) -> Result<String, String> {
+        let entry = self.interrupted.take().ok_or("nothing interrupted to discard")?;
+        self.stop_all();
+        self.audit.record(&format!("discard interrupted {}", entry.running));
+        self.finish_journal();
+        Ok(format!("Discarded {}", entry.running))
+    }
+
+    /// Runs a one-line command, from the `:` prompt or `--stdin`.
+    pub fn run(&mut self, cmd: dsl::Command) -> Result<String, String> {
+        let text = cmd.to_string();
+        let entry = history::Entry { undo: self.undo_for(&cmd), action: Action::Command(cmd.clone()) };
+        let harmless = cmd.is_stop() || matches!(cmd, dsl::Command::Mark(_));
+        // until armed, the daemon would only stop a manual move too
+        let held = self.arming.required() && !harmless && (cmd.is_autonomous() || !self.arming.armed);
+        let result = if self.estop.is_latched() && !harmless {
+            Err(String::from("the e-stop is latched; E, then y, to reset"))
+        } else if self.standstill.is_checking() && !harmless {
+            Err(String::from("not until both actuators have shown they're stopped"))
+        } else if held {
+            let operator = self.audit.operator().to_string();
+            self.arming.hold(Action::Command(cmd), text.clone(), &operator)
+        } else {
+            self.run_unlogged(cmd)
+        };
+        let event = match &entry.action {
+            Action::Command(dsl::Command::Mark(note)) => TimelineEvent::Note(note.clone()),
+            _ => TimelineEvent::Command(text.clone()),
+        };
+        // a note isn't anything to repeat or undo
+        if result.is_ok() && !matches!(entry.action, Action::Command(dsl::Command::Mark(_))) {
+            self.history.push(entry);
+        }
+        match &result {
+            Ok(_) if held => self.audit.record(&format!("ask to confirm `{text}`")),
+            Ok(_) => {
+                self.audit.record(&text);
+                self.mark_timeline(event);
+            }
+            Err(e) => self.audit.record(&format!("{text} (refused: {e})")),
+        }
+        result
+    }
+
+    fn run_unlogged(&mut self, cmd: dsl::Command) -> Result<String, String> {
+        let origin = journal::Origin::Command(cmd.to_string());
+        match cmd {
+            dsl::Command::Drive { actuator, direction, speed, duration } => {
+                self.abort();
+                self.heading[actuator as usize] = direction;
+                self.speeds[actuator as usize] = direction.signed(speed as u32);
+                match duration {
+                    Some(duration) => {
+                        let steps = vec![(
+                            format!("{speed} for {duration:?}"),
+                            sequence::Step::Drive { actuator, direction, speed, duration },
+                        )];
+                        self.start_sequence(origin, sequence::Sequence { name: format!("{actuator:?} timed drive"), steps });
+                    }
+                    None => self.pending.push(Drive::new(actuator, direction.signed(speed as u32))),
+                }
+                Ok(format!("Driving {actuator:?} {direction:?} at {speed}"))
+            }
+            dsl::Command::Push { actuator, direction, speed, limit } => {
+                if self.current[actuator as usize].is_none() {
+                    return Err(format!("pushing {actuator:?} needs a `current` field from the firmware"));
+                }
+                self.abort();
+                self.heading[actuator as usize] = direction;
+                self.speeds[actuator as usize] = direction.signed(speed as u32);
+                let sequence::Effort { backoff, timeout } = self.effort;
+                let steps = vec![(
+                    format!("{speed} until {limit}"),
+                    sequence::Step::Push { actuator, direction, speed, limit, backoff, timeout },
+                )];
+                self.start_sequence(origin, sequence::Sequence { name: format!("{actuator:?} push"), steps });
+                Ok(format!("Pushing {actuator:?} {direction:?} at {speed} until {limit}"))
+            }
+            dsl::Command::Goto { actuator, len } => {
+                self.abort();
+                self.position.set_target(actuator, len);
+                Ok(format!("Moving {actuator:?} to {len} m"))
+            }
+            dsl::Command::Stop(which) => {
+                self.abort();
+                let actuators = match which {
+                    Some(actuator) => vec![actuator],
+                    None => vec![Actuator::M1, Actuator::M2],
+                };
+                for actuator in actuators {
+                    self.pending.push(Drive::stop(actuator));
+                    self.speeds[actuator as usize] = 0;
+                }
+                Ok(String::from("Stopped"))
+            }
+            dsl::Command::Angle { degrees, height } => {
+                let [Some(lift), Some(bucket)] = self.actuator_len_meters else {
+                    return Err(String::from("waiting for telemetry from both actuators"));
+                };
+                let current = self.linkage.forward(lift, bucket).ok_or("current lengths are outside the linkage geometry")?;
+                let height = height.unwrap_or(current.bucket_height);
+                let target = kinematics::Pose { bucket_angle: degrees, bucket_height: height };
+                let (lift, bucket) = self.linkage.inverse(target).ok_or(format!("{degrees}° at {height} m is out of reach"))?;
+                self.abort();
+                self.position.set_target(Actuator::M1, lift);
+                self.position.set_target(Actuator::M2, bucket);
+                Ok(format!("Moving bucket to {degrees}° at {height:.3} m"))
+            }
+            dsl::Command::Hold(actuator) => {
+                self.hold(actuator);
+                Ok(format!("Holding {actuator:?}"))
+            }
+            // the audit log line is the whole point
+            dsl::Command::Mark(note) => Ok(format!("Marked: {note}")),
+            dsl::Command::EStop => Ok(self.estop("`estop` command")),
+            dsl::Command::Follow(path) => {
+                let trajectory = self.load_trajectory(&path)?;
+                self.abort();
+                let duration = trajectory.duration();
+                let steps = vec![(format!("{duration} s"), sequence::Step::Track(trajectory))];
+                self.start_sequence(origin, sequence::Sequence { name: format!("follow {path}"), steps });
+                Ok(format!("Following {path} for {duration} s"))
+            }
+            dsl::Command::Group { commands, .. } => {
+                self.abort();
+                let mut done = Vec::new();
+                for cmd in commands {
+                    match cmd {
+                        // one abort for them all, or each would cancel the target before it
+                        dsl::Command::Goto { actuator, len } => {
+                            self.position.set_target(actuator, len);
+                            done.push(format!("Moving {actuator:?} to {len} m"));
+                        }
+                        cmd => done.push(self.run_unlogged(cmd)?),
+                    }
+                }
+                done.dedup();
+                Ok(done.join("; "))
+            }
+        }
+    }
+
+    /// Reads the trajectory at `path`, checking it stays within each stroke.
+    pub fn load_trajectory(&self, path: &str) -> Result<Trajectory, String> {
+        let src = std::fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+        let trajectory = Trajectory::parse(&src).map_err(|e| format!("{path}: {e}"))?;
+        for actuator in [Actuator::M1, Actuator::M2] {
+            let stroke = self.linkage.joint(actuator).max_stroke;
+            if let Some((t, len)) = trajectory.outside(actuator, stroke) {
+                return Err(format!("{path}: {actuator:?} at {t} s is {len} m, outside its 0 to {stroke} m stroke"));
+            }
+        }
+        Ok(trajectory)
+    }
+
+    /// Shows the trajectory `follow <path>` would run, instead of running it.
+    pub fn preview(&mut self, path: String) -> Result<String, String> {
+        let trajectory = self.load_trajectory(&path)?;
+        let msg = format!("Preview of {path}, {} s: Enter runs it, Esc cancels", trajectory.duration());
+        self.preview = Some(Preview { path, trajectory });
+        Ok(msg)
+    }
+
+    /// Runs the trajectory being previewed, as long as the file hasn't
+    /// changed since.
+    pub fn confirm_preview(&mut self) -> Result<String, String> {
+        let preview = self.preview.take().ok_or("nothing to confirm")?;
+        if self.load_trajectory(&preview.path)? != preview.trajectory {
+            return Err(format!("{} changed since the preview; run it again to see the new one", preview.path));
+        }
+        self.run(dsl::Command::Follow(preview.path))
+    }
+
+    /// Queues a manual drive, fanned out according to the control mode.
+    fn queue_manual(&mut self, drive: Drive) {
+        let offset = match self.mode {
+            mode::Mode::Differential => self.offset,
+            _ => self.correction,
+        };
+        let drives = self.mode.expand(drive, self.trim, offset);
+        self.pending.extend(drives);
+    }
+
+    /// Starts the dig cycle from where the actuators are now.
+    pub fn start_dig(&mut self) -> Result<(), String> {
+        if self.sequence.is_some() {
+            return Ok(());
+        }
+        if self.actuator_len_meters.contains(&None) {
+            return Err(String::from("Dig cycle needs telemetry from both actuators"));
+        }
+        if self.arming.required() {
+            let operator = self.audit.operator().to_string();
+            let msg = self.arming.hold(Action::Dig, String::from("dig cycle"), &operator)?;
+            self.notify(Severity::Info, msg);
+            self.audit.record("ask to confirm dig cycle");
+        } else {
+            self.audit.record("dig cycle");
+            self.begin_dig()?;
+            self.mark_timeline(TimelineEvent::Command(String::from("dig cycle")));
+        }
+        self.history.push(history::Entry { action: Action::Dig, undo: dsl::Command::Stop(None) });
+        Ok(())
+    }
+
+    /// The command that takes `cmd` back, given where the actuators are now.
+    fn undo_for(&self, cmd: &dsl::Command) -> dsl::Command {
+        match *cmd {
+            dsl::Command::Drive { actuator, direction, speed, duration: Some(duration) } => dsl::Command::Drive {
+                actuator,
+                direction: direction.reversed(),
+                speed,
+                duration: Some(duration),
+            },
+            dsl::Command::Drive { actuator, .. } => dsl::Command::Stop(Some(actuator)),
+            dsl::Command::Goto { actuator, .. } | dsl::Command::Push { actuator, .. } => match self.actuator_len_meters[actuator as usize] {
+                Some(len) => dsl::Command::Goto { actuator, len },
+                None => dsl::Command::Stop(Some(actuator)),
+            },
+            // back to where both started, as for an angle
+            dsl::Command::Angle { .. } | dsl::Command::Follow(_) => match self.actuator_len_meters {
+                [Some(lift), Some(bucket)] => match self.linkage.forward(lift, bucket) {
+                    Some(pose) => dsl::Command::Angle { degrees: pose.bucket_angle, height: Some(pose.bucket_height) },
+                    None => dsl::Command::Stop(None),
+                },
+                _ => dsl::Command::Stop(None),
+            },
+            dsl::Command::Stop(which) => dsl::Command::Stop(which),
+            // nothing undoes an e-stop but its reset
+            dsl::Command::EStop => dsl::Command::Stop(None),
+            dsl::Command::Hold(actuator) => dsl::Command::Stop(Some(actuator)),
+            dsl::Command::Mark(ref note) => dsl::Command::Mark(note.clone()),
+            dsl::Command::Group { ref name, ref commands } => dsl::Command::Group {
+                name: name.clone(),
+                commands: commands.iter().map(|cmd| self.undo_for(cmd)).collect(),
+            },
+        }
+    }
+
+    fn act(&mut self, action: Action) -> Result<String, String> {
+        match action {
+            Action::Dig => self.start_dig().map(|()| self.status_message.clone()),
+            Action::Command(cmd) => self.run(cmd),
+            Action::Arm => {
+                self.arm();
+                Ok(self.status_message.clone())
+            }
+        }
+    }
+
+    /// Runs the last action again (`.`).
+    pub fn repeat_last(&mut self) -> Result<String, String> {
+        let action = self.history.last().ok_or("nothing to repeat yet")?.action.clone();
+        self.act(action)
+    }
+
+    /// Runs the opposite of the last action (`u`), which then becomes the last action itself.
+    pub fn undo_last(&mut self) -> Result<String, String> {
+        let undo = self.history.last().ok_or("nothing to undo yet")?.undo.clone();
+        self.run(undo)
+    }
+
+    fn begin_dig(&mut self) -> Result<(), String> {
+        let [Some(lift), Some(bucket)] = self.actuator_len_meters else {
+            return Err(String::from("Dig cycle needs telemetry from both actuators"));
+        };
+        self.start_sequence(journal::Origin::Dig([lift, bucket]), self.dig.sequence([lift, bucket]));
+        Ok(())
+    }
+
+    /// Confirms another operator's pending request, if there is one.
+    pub fn confirm_arming(&mut self) {
+        let operator = self.audit.operator().to_string();
+        match self.arming.confirm(&operator) {
+            Some(msg) => {
+                self.audit.record(&msg);
+                self.notify(Severity::Info, msg);
+            }
+            None => self.notify(Severity::Warn, String::from("Nothing to confirm")),
+        }
+    }
+
+    /// Ticks the `i`th checklist item off, or back on.
+    pub fn check_off(&mut self, i: usize) {
+        match self.checklist.toggle(i) {
+            Ok(ticked) => {
+                let text = &self.checklist.items[i].text;
+                self.audit.record(&format!("checklist: {} `{text}`", if ticked { "ticked" } else { "unticked" }));
+                self.notify(Severity::Info, format!("Checklist {}/{} done", self.checklist.done(), self.checklist.items.len()));
+            }
+            Err(e) => self.notify(Severity::Error, format!("Error: {e}")),
+        }
+    }
+
+    /// Lifts the checklist's speed cap, if every item is done, and under
+    /// two-person arming asks a second operator to arm this client.
+    pub fn arm(&mut self) {
+        match self.checklist.arm() {
+            Ok(()) => {
+                self.audit.record("checklist done, armed for full speed");
+                self.notify(Severity::Info, String::from("Armed for full speed"));
+            }
+            Err(e) => {
+                self.notify(Severity::Warn, format!("Not armed: {e}"));
+                return;
+            }
+        }
+        if self.arming.required() && !self.arming.armed {
+            let operator = self.audit.operator().to_string();
+            match self.arming.hold(Action::Arm, String::from("arm"), &operator) {
+                Ok(msg) => {
+                    self.audit.record("ask to confirm arming");
+                    self.notify(Severity::Info, msg);
+                }
+                Err(e) => self.notify(Severity::Warn, format!("Not armed: {e}")),
+            }
+        }
+    }
+
+    /// Passes arming requests to the daemon and acts on its answers. Returns
+    /// how our own request turned out, once it has.
+    pub fn sync_arming(&mut self, channel: &mut arming::Channel) -> Option<Result<String, String>> {
+        for request in self.arming.outbox.drain(..) {
+            let _ = channel.requests.try_send(request);
+        }
+        let mut outcome = None;
+        while let Ok(event) = channel.events.try_recv() {
+            match event {
+                arming::Event::Required(timeout) => {
+                    self.arming.config.two_person = true;
+                    self.arming.config.timeout = timeout;
+                }
+                arming::Event::Requested { token, operator, text } => {
+                    self.notify(Severity::Warn, format!("{operator} asks to run `{text}`; press y to confirm"));
+                    self.arming.incoming = Some((token, operator, text));
+                }
+                arming::Event::Withdrawn(token) => {
+                    if self.arming.incoming.as_ref().is_some_and(|(t, ..)| *t == token) {
+                        self.arming.incoming = None;
+                    }
+                }
+                arming::Event::Granted { operator } => {
+                    let Some(waiting) = self.arming.waiting.take() else { continue };
+                    // the daemon armed us along with the grant
+                    self.arming.armed = true;
+                    self.audit.record(&format!("{} (confirmed by {operator})", waiting.text));
+                    let result = match waiting.action {
+                        Action::Dig => self.begin_dig().map(|()| String::from("Running dig cycle")),
+                        Action::Command(cmd) => self.run_unlogged(cmd),
+                        Action::Arm => Ok(String::from("Armed")),
+                    };
+                    if result.is_ok() {
+                        self.mark_timeline(TimelineEvent::Command(waiting.text));
+                    }
+                    match &result {
+                        Ok(msg) => self.notify(Severity::Info, format!("{msg} (confirmed by {operator})")),
+                        Err(e) => self.notify(Severity::Error, format!("Error: {e}")),
+                    }
+                    outcome = Some(result.map(|_| self.status_message.clone()));
+                }
+                arming::Event::Denied(reason) => {
+                    let Some(waiting) = self.arming.waiting.take() else { continue };
+                    let msg = format!("`{}` not confirmed: {reason}", waiting.text);
+                    self.audit.record(&msg);
+                    self.notify(Severity::Warn, msg.clone());
+                    outcome = Some(Err(msg));
+                }
+            }
+        }
+        if let Some(waiting) = self.arming.expired(Instant::now()) {
+            let msg = format!("`{}` not confirmed: no answer from the daemon", waiting.text);
+            self.audit.record(&msg);
+            self.notify(Severity::Warn, msg.clone());
+            outcome = Some(Err(msg));
+        }
+        outcome
+    }
+
+    /// Sends everything the app has queued up, as the frames the firmware takes.
+    pub async fn flush(&mut self, tx: &mpsc::Sender<Request>) {
+        for drive in std::mem::take(&mut self.pending) {
+            let drive = Drive { speed: self.checklist.limit(drive.speed), ..drive };
+            // any speed releases a hold
+            self.held[drive.actuator as usize] = false;
+            self.commanded[drive.actuator as usize] = drive.speed;
+            self.position.driven(drive.actuator, drive.speed);
+            for frame in self.encoder.encode(drive) {
+                self.send(tx, frame).await;
+            }
+            // not a frame, so not counted in `sent`
+            if let Some(after) = drive.stop_after {
+                let _ = tx.send(Request::StopAfter(drive.actuator, after)).await;
+            }
+        }
+        for actuator in std::mem::take(&mut self.holds) {
+            let frame = self.encoder.hold(actuator);
+            self.send(tx, frame).await;
+            self.held[actuator as usize] = true;
+            self.commanded[actuator as usize] = 0;
+        }
+    }
+
+    async fn send(&mut self, tx: &mpsc::Sender<Request>, frame: ActuatorCommand) {
+        if tx.send(frame.into()).await.is_ok() {
+            self.sent += 1;
+            if let Some(profiler) = &self.profiler {
+                profiler.queued(self.input_at);
+            }
+        }
+    }
+
+    /// Whether nothing is moving under the app's own control.
+    pub fn is_idle(&self) -> bool {
+        self.sequence.is_none()
+            && !self.position.is_active()
+            && self.pending.is_empty()
+            && self.holds.is_empty()
+            && self.arming.waiting.is_none()
+    }
+
+    /// Runs the closed-loop parts: skew correction, sequences and position moves.
+    pub(crate) fn tick(&mut self, now: Instant) {
+        if self.mode == mode::Mode::Mirror {
+            let speed = self.speeds[self.actuator as usize];
+            let correction = match self.skew.skew(self.actuator_len_meters) {
+                Some(skew) if speed != 0 => self.skew.correction(skew, Direction::of(speed)),
+                _ => 0,
+            };
+            if correction != self.correction {
+                self.correction = correction;
+                self.queue_manual(Drive::new(self.actuator, speed));
+            }
+        }
+        if let Some(runner) = &mut self.sequence {
+            if runner.tick(&mut self.position, self.current, now, &mut self.pending) {
+                self.status_message = runner.describe();
+                if let Some(journal) = &mut self.journal
+                    && let Err(e) = journal.update(runner, now)
+                {
+                    self.notify(Severity::Warn, e);
+                }
+            } else {
+                self.sequence = None;
+                self.finish_journal();
+                self.notify(Severity::Info, String::from("Sequence finished"));
+            }
+        }
+        if self.position.is_active() {
+            let update = self.position.update(self.actuator_len_meters, now);
+            self.pending.extend(update);
+            let mut settled = Vec::new();
+            for settle in self.position.take_settled() {
+                self.audit.record(&settle.to_string());
+                self.last_settle[settle.actuator as usize] = Some(settle);
+                if self.settles.len() == SETTLES_CAPACITY {
+                    self.settles.remove(0);
+                }
+                self.settles.push(settle);
+                settled.push(settle.to_string());
+            }
+            if !self.position.is_active() && self.sequence.is_none() {
+                let msg = match settled.is_empty() {
+                    true => String::from("Target reached"),
+                    false => format!("Target reached: {}", settled.join("; ")),
+                };
+                self.notify(Severity::Info, msg);
+            }
+        }
+        self.tick_countdown();
+        self.tick_standstill(now);
+        self.tick_startup();
+        self.tick_hooks();
+    }
+
+    /// Moves the standstill check on to the next actuator, or stops one
+    /// again that hasn't shown it's still.
+    fn tick_standstill(&mut self, now: Instant) {
+        match self.standstill.tick(now) {
+            Some(standstill::Step::Still { next: Some(next), .. }) => self.pending.push(Drive::stop(next)),
+            Some(standstill::Step::Still { next: None, .. }) => {
+                self.audit.record("standstill check passed");
+                self.notify(Severity::Info, String::from("Both actuators are stopped; taking input"));
+            }
+            Some(standstill::Step::Timeout { actuator, speed }) => {
+                self.pending.push(Drive::stop(actuator));
+                let msg = match speed {
+                    Some(speed) => format!("{actuator:?} is still moving at {:.1} mm/s; stopping it again", speed * 1000.0),
+                    None => format!("No reports from {actuator:?} to show it's stopped; stopping it again"),
+                };
+                self.audit.record(&format!("standstill check: {msg}"));
+                self.notify(Severity::Error, msg);
+            }
+            None => {}
+        }
+    }
+
+    /// Runs the next `[startup]` command once the last has finished, giving
+    /// up on the rest if one fails.
+    fn tick_startup(&mut self) {
+        let connected = self.actuator_len_meters.iter().any(Option::is_some);
+        if !connected || !self.is_idle() || self.standstill.is_checking() || self.estop.is_latched() {
+            return;
+        }
+        let Some(cmd) = self.startup.pop_front() else { return };
+        self.started += 1;
+        let step = format!("{}/{}", self.started, self.started + self.startup.len());
+        let text = cmd.to_string();
+        match self.run(cmd) {
+            Ok(msg) => self.notify(Severity::Info, format!("Startup {step}: {msg}")),
+            Err(e) => {
+                let skipped = std::mem::take(&mut self.startup).len();
+                self.audit.record(&format!("startup failed at `{text}`: {e}; skipped {skipped} more"));
+                self.notify(Severity::Error, format!("Startup failed at {step} `{text}`: {e}"));
+            }
+        }
+    }
+
+    /// Runs every hook that's fired since the last tick, as if typed.
+    fn tick_hooks(&mut self) {
+        while let Some(hook) = self.hooks.take() {
+            self.audit.record(&format!("hook `{}` fired", hook.text));
+            match self.run(hook.command) {
+                Ok(msg) => self.notify(Severity::Info, format!("Hook: {msg}")),
+                Err(e) => self.notify(Severity::Error, format!("Hook `{}` didn't run: {e}", hook.text)),
+            }
+        }
+    }
+
+    /// Warns as the countdown passes each threshold, and stops everything
+    /// when it runs out if it's meant to.
+    fn tick_countdown(&mut self) {
+        let run = self.audit.run();
+        let stage = self.countdown.stage(run);
+        let passed = stage > self.countdown_stage;
+        // a new run starts the countdown over
+        self.countdown_stage = stage;
+        if !passed {
+            return;
+        }
+        let left = self.countdown.left(run).unwrap_or_default();
+        let msg = match stage {
+            countdown::Stage::Expired if self.countdown.stop => {
+                self.estop("countdown");
+                String::from("Time's up: stopped both actuators and latched the e-stop")
+            }
+            countdown::Stage::Expired => String::from("Time's up"),
+            _ => format!("{} left in the run", audit::clock(left)),
+        };
+        self.notify(Severity::Warn, msg);
+        self.audit.record(&format!("countdown: {}", self.status_message));
+    }
+}
@@ -0,0 +1,121 @@
+//! Two-person arming: autonomous moves (the dig cycle, timed drives, `goto`
+//! and `angle`) don't start until a second operator, on another client
+//! attached to the same daemon, confirms them.
+//!
+//! Arming is on if the daemon's config or the client's own says so. The
+//! daemon tells every client that attaches, relays requests to every other
+//! client, and answers the requester with a grant or a refusal once its
+//! timeout runs out. A grant also arms the client that asked: until then the
+//! daemon stops whatever it tries to move, so a client that skips the
+//! request gets nowhere.
+
+use std::time::{Duration, Instant};
+
+use tokio::sync::mpsc;
+
+use crate::app::Action;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ArmingConfig {
+    pub two_person: bool,
+    /// how long a second operator has to confirm
+    pub timeout: Duration,
+}
+
+impl Default for ArmingConfig {
+    fn default() -> Self {
+        ArmingConfig {
+            two_person: false,
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Client to daemon.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Request {
+    Ask { operator: String, text: String },
+    Confirm { token: u64, operator: String },
+}
+
+/// Daemon to client.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    /// sent on attach when the daemon requires two-person arming, with its timeout
+    Required(Duration),
+    /// another client wants `text` confirmed
+    Requested { token: u64, operator: String, text: String },
+    /// a request we were shown is no longer open
+    Withdrawn(u64),
+    /// our request was confirmed by `operator`
+    Granted { operator: String },
+    Denied(String),
+}
+
+/// The arming half of a link to a daemon.
+pub struct Channel {
+    pub requests: mpsc::Sender<Request>,
+    pub events: mpsc::Receiver<Event>,
+}
+
+/// Held until confirmed.
+pub struct Waiting {
+    pub action: Action,
+    pub text: String,
+    /// in case the daemon never answers
+    deadline: Instant,
+}
+
+#[derive(Default)]
+pub struct Gate {
+    pub config: ArmingConfig,
+    /// set once attached to a daemon that can relay confirmations
+    pub connected: bool,
+    pub waiting: Option<Waiting>,
+    /// another operator's request we could confirm: token, operator, action
+    pub incoming: Option<(u64, String, String)>,
+    /// requests for the daemon, sent at the top of the next loop
+    pub outbox: Vec<Request>,
+    /// a request of ours has been confirmed, so the daemon passes our moves on
+    pub armed: bool,
+}
+
+impl Gate {
+    pub fn new(config: ArmingConfig) -> Gate {
+        Gate { config, ..Gate::default() }
+    }
+
+    pub fn required(&self) -> bool {
+        self.config.two_person
+    }
+
+    /// Asks for `action` to be confirmed, replacing any earlier request.
+    pub fn hold(&mut self, action: Action, text: String, operator: &str) -> Result<String, String> {
+        if !self.connected {
+            return Err(String::from("two-person arming is on, which needs a second operator attached to a --daemon"));
+        }
+        self.outbox.push(Request::Ask { operator: operator.to_string(), text: text.clone() });
+        let msg = format!("Waiting up to {:?} for a second operator to confirm `{text}`", self.config.timeout);
+        self.waiting = Some(Waiting {
+            action,
+            text,
+            deadline: Instant::now() + self.config.timeout + Duration::from_secs(2),
+        });
+        Ok(msg)
+    }
+
+    /// Confirms the request we were shown, if there is one.
+    pub fn confirm(&mut self, operator: &str) -> Option<String> {
+        let (token, from, text) = self.incoming.take()?;
+        self.outbox.push(Request::Confirm { token, operator: operator.to_string() });
+        Some(format!("Confirmed {from}'s `{text}`"))
+    }
+
+    /// Gives up on our own request if the daemon has gone quiet.
+    pub fn expired(&mut self, now: Instant) -> Option<Waiting> {
+        match &self.waiting {
+            Some(w) if now >= w.deadline => self.waiting.take(),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,124 @@
+//! Who did what and when, for reviewing an incident afterwards.
+//!
+//! One line per action, appended so several sessions can share a file. After
+//! the time and operator come the session and run clocks, how long since this
+//! session started and since its current run did:
+//!
+//! ```text
+//! 2026-03-02T18:04:11.532Z alice 00:12:03 00:04:31 m1 fwd 32768 for 2000ms
+//! 2026-03-02T18:04:15.020Z alice 00:12:07 00:04:35 manual M1 speed 0
+//! ```
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+pub struct AuditLog {
+    operator: String,
+    file: Option<File>,
+    session_started: Instant,
+    run_started: Instant,
+}
+
+impl Default for AuditLog {
+    /// No file, operator from `$USER`.
+    fn default() -> AuditLog {
+        AuditLog {
+            operator: default_operator(),
+            file: None,
+            session_started: Instant::now(),
+            run_started: Instant::now(),
+        }
+    }
+}
+
+pub fn default_operator() -> String {
+    std::env::var("USER").unwrap_or_else(|_| String::from("unknown"))
+}
+
+impl AuditLog {
+    /// Appends to `path`, creating it if needed. `None` only records the operator.
+    pub fn open(path: Option<&Path>, operator: &str) -> io::Result<AuditLog> {
+        let file = match path {
+            Some(path) => Some(OpenOptions::new().create(true).append(true).open(path)?),
+            None => None,
+        };
+        let mut log = AuditLog {
+            operator: operator.to_string(),
+            file,
+            session_started: Instant::now(),
+            run_started: Instant::now(),
+        };
+        log.record("session start");
+        Ok(log)
+    }
+
+    pub fn operator(&self) -> &str {
+        &self.operator
+    }
+
+    /// How long since the session started.
+    pub fn session(&self) -> Duration {
+        self.session_started.elapsed()
+    }
+
+    /// How long since the current run started.
+    pub fn run(&self) -> Duration {
+        self.run_started.elapsed()
+    }
+
+    /// Starts the run clock again from zero.
+    pub fn start_run(&mut self) {
+        self.run_started = Instant::now();
+    }
+
+    pub fn record(&mut self, action: &str) {
+        let (session, run) = (clock(self.session()), clock(self.run()));
+        if let Some(file) = &mut self.file {
+            // write the whole line at once so concurrent sessions don't interleave
+            let line = format!("{} {} {session} {run} {action}\n", timestamp(SystemTime::now()), self.operator);
+            let _ = file.write_all(line.as_bytes());
+        }
+    }
+}
+
+/// A duration as `HH:MM:SS`, hours going past 24 if need be.
+pub fn clock(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+/// The UTC time of day as `HH:MM:SS`.
+pub fn time_of_day(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    clock(Duration::from_secs(secs % 86400))
+}
+
+/// ISO 8601 in UTC, to the millisecond.
+pub fn timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (days, rem) = ((secs / 86400) as i64, secs % 86400);
+
+    // days since 1970-01-01 to a civil date (Howard Hinnant's algorithm)
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        rem / 3600,
+        rem / 60 % 60,
+        rem % 60,
+        since_epoch.subsec_millis()
+    )
+}
@@ -0,0 +1,78 @@
+//! `--stdin`: run commands from standard input without the terminal UI.
+//!
+//! Each line is a one-line command (see [`actuator_controller::dsl`]) or the
+//! same command as a JSON object. A command only counts as done once every
+//! frame it produced has been written to the port and any move it started
+//! has finished, so the next line never races the previous one.
+
+use actuator_controller::{
+    client::{ActuatorClient, Update},
+    dsl,
+};
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+use crate::output::{Event, Output};
+
+/// Runs `startup`, then every line on stdin, and returns the process exit
+/// code: 0 if all of them succeeded, 1 otherwise. A failed `startup` command
+/// skips the rest of `startup`, but not stdin.
+pub async fn run(mut client: ActuatorClient, startup: &[dsl::Command], out: &Output) -> i32 {
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let mut failures = 0;
+    let observe = |update: Update| match update {
+        Update::Telemetry(report) => out.emit(Event::Telemetry(report)),
+        Update::Status(status) => out.emit(Event::Status(status)),
+        Update::Queue { depth, coalesced, dropped } => out.emit(Event::Queue { depth, coalesced, dropped }),
+        Update::Settle(settle) => out.emit(Event::Settle(settle)),
+    };
+
+    for cmd in startup {
+        let command = format!("startup: {cmd}");
+        match client.run_observed(cmd.clone(), observe).await {
+            Ok(msg) => out.emit(Event::Ack { command: &command, message: &msg }),
+            Err(e) => {
+                failures += 1;
+                out.emit(Event::Error { command: &command, message: &e });
+                break;
+            }
+        }
+    }
+
+    loop {
+        let line = match lines.next_line().await {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(e) => {
+                out.emit(Event::Error { command: "", message: &format!("reading stdin: {e}") });
+                failures += 1;
+                break;
+            }
+        };
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let result = match client.parse(line) {
+            Ok(cmd) => client.run_observed(cmd, observe).await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(msg) => out.emit(Event::Ack { command: line, message: &msg }),
+            Err(e) => {
+                failures += 1;
+                out.emit(Event::Error { command: line, message: &e });
+            }
+        }
+    }
+
+    // never leave anything running once the script is over
+    if let Err(e) = client.run_observed(dsl::Command::Stop(None), observe).await {
+        failures += 1;
+        out.emit(Event::Error { command: "stop", message: &e });
+    }
+    out.emit(Event::Done { failures });
+    client.close().await;
+
+    if failures > 0 { 1 } else { 0 }
+}
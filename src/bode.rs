@@ -0,0 +1,157 @@
+//! Frequency response of an actuator, for `--sweep`: sine speeds at a range
+//! of frequencies, and how far and how late the length follows each one.
+//!
+//! A sweep is written `<actuator>:<amplitude>:<from hz>:<to hz>`, e.g.
+//! `m1:40%:0.1:2`. Each frequency is held for one cycle to settle and then
+//! [`SweepConfig::cycles`] more, over which the length is fitted as
+//!
+//! ```text
+//! len(t) = a sin(wt) + b cos(wt) + c + d t
+//! ```
+//!
+//! the straight-line part soaking up any drift. The gain is `hypot(a, b)` per
+//! speed unit of amplitude and the phase `atan2(b, a)`, relative to the
+//! commanded speed. An ideal actuator, whose length is its speed integrated,
+//! sits at -90°.
+
+use std::f64::consts::PI;
+
+use crate::{commands::Actuator, dsl};
+
+/// Header of the CSV [`Point::csv`] rows go under.
+pub const CSV_HEADER: &str = "hz,gain_m_per_unit,gain_db,phase_deg";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SweepConfig {
+    /// frequencies tested, spaced evenly on a log scale
+    pub points: u16,
+    /// cycles measured at each frequency, after one to settle
+    pub cycles: u16,
+}
+
+impl Default for SweepConfig {
+    fn default() -> SweepConfig {
+        SweepConfig { points: 10, cycles: 4 }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sweep {
+    pub actuator: Actuator,
+    /// peak speed either way
+    pub amplitude: u16,
+    pub from: f64,
+    pub to: f64,
+}
+
+impl Sweep {
+    pub fn parse(spec: &str) -> Result<Sweep, String> {
+        let parts: Vec<&str> = spec.split(':').collect();
+        let [actuator, amplitude, from, to] = parts[..] else {
+            return Err(format!("expected <actuator>:<amplitude>:<from hz>:<to hz>, e.g. m1:40%:0.1:2, not `{spec}`"));
+        };
+        let actuator = dsl::parse_actuator(actuator)
+            .ok_or_else(|| format!("unknown actuator `{actuator}`; expected m1, m2, lift or bucket"))?;
+        // a period of at most `MAX_DURATION`, so it's a duration too
+        let slowest = 1.0 / dsl::MAX_DURATION.as_secs_f64();
+        let hz = |word: &str| match word.trim_end_matches("Hz").trim_end_matches("hz").parse::<f64>() {
+            Ok(hz) if hz >= slowest && hz.is_finite() => Ok(hz),
+            _ => Err(format!(
+                "frequency `{word}` must be a positive number of Hz, at least one cycle every {} h",
+                dsl::MAX_DURATION.as_secs() / 3600
+            )),
+        };
+        let (from, to) = (hz(from)?, hz(to)?);
+        if to < from {
+            return Err(format!("the sweep has to go up, not from {from} Hz down to {to} Hz"));
+        }
+        Ok(Sweep { actuator, amplitude: dsl::parse_speed(amplitude)?, from, to })
+    }
+
+    /// `points` frequencies from `from` to `to`, both included.
+    pub fn frequencies(&self, points: u16) -> Vec<f64> {
+        if points < 2 || self.from == self.to {
+            return vec![self.from];
+        }
+        let ratio = (self.to / self.from).powf(1.0 / (points - 1) as f64);
+        (0..points).map(|i| self.from * ratio.powi(i as i32)).collect()
+    }
+}
+
+/// The response at one frequency.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Point {
+    pub frequency: f64,
+    /// amplitude of the length (m) per speed unit of commanded amplitude
+    pub gain: f64,
+    /// degrees, relative to the commanded speed
+    pub phase: f64,
+}
+
+impl Point {
+    /// Fits the lengths reported while a sine of `amplitude` at `frequency`
+    /// was commanded, `samples` being (seconds since the sine started, m).
+    /// `None` if there are too few to pin down the four terms.
+    pub fn fit(frequency: f64, amplitude: u16, samples: &[(f64, f64)]) -> Option<Point> {
+        if samples.len() < 8 || amplitude == 0 {
+            return None;
+        }
+        let w = 2.0 * PI * frequency;
+        // normal equations for the terms sin, cos, 1 and t
+        let mut m = [[0.0; 5]; 4];
+        for &(t, len) in samples {
+            let basis = [(w * t).sin(), (w * t).cos(), 1.0, t];
+            for i in 0..4 {
+                for j in 0..4 {
+                    m[i][j] += basis[i] * basis[j];
+                }
+                m[i][4] += basis[i] * len;
+            }
+        }
+        let [a, b, _, _] = solve(m)?;
+        Some(Point {
+            frequency,
+            gain: a.hypot(b) / amplitude as f64,
+            phase: b.atan2(a).to_degrees(),
+        })
+    }
+
+    pub fn csv(&self) -> String {
+        format!("{:.4},{:e},{:.2},{:.1}", self.frequency, self.gain, 20.0 * self.gain.log10(), self.phase)
+    }
+}
+
+/// Gaussian elimination with partial pivoting on an augmented 4x4 system.
+fn solve(mut m: [[f64; 5]; 4]) -> Option<[f64; 4]> {
+    for col in 0..4 {
+        let pivot = (col..4).max_by(|&a, &b| m[a][col].abs().total_cmp(&m[b][col].abs()))?;
+        if m[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        m.swap(col, pivot);
+        let pivot = m[col];
+        for (row, r) in m.iter_mut().enumerate() {
+            if row != col {
+                let factor = r[col] / pivot[col];
+                for (x, p) in r.iter_mut().zip(pivot).skip(col) {
+                    *x -= factor * p;
+                }
+            }
+        }
+    }
+    Some([0, 1, 2, 3].map(|i| m[i][4] / m[i][i]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sweeps() {
+        let sweep = Sweep::parse("m1:40%:0.1Hz:2").unwrap();
+        assert_eq!((sweep.actuator, sweep.from, sweep.to), (Actuator::M1, 0.1, 2.0));
+        for spec in ["m1:40%:1e-300:2", "m1:40%:0:2", "m1:40%:0.1:inf", "m1:40%:2:0.1", "m1:40%:0.1", "m3:40%:0.1:2", "m1:40%:x:2"] {
+            assert!(Sweep::parse(spec).is_err(), "{spec:?} parsed");
+        }
+    }
+}
@@ -0,0 +1,245 @@
+//! `--zmq-pub`/`--zmq-pull`: a headless bridge to ZeroMQ.
+//!
+//! The PUB socket sends two-frame messages: the topic (`telemetry`, `status`,
+//! `queue`, `ack` or `error`) and the same JSON object `--output json` prints for that
+//! event. The PULL socket takes messages whose last frame is a command in
+//! either form `--stdin` accepts, and runs them one after another; every one
+//! is answered with an `ack` or `error` on the PUB socket. With `[roles]`
+//! set, the frame before the command is the sender's key, and the command
+//! only runs if the key's role allows it.
+//!
+//! Each client (PUSH connection) may send at most `[throttle] client_rate`
+//! commands a second. One that comes too soon waits its turn, and is
+//! answered with an `error` if a newer one from the same client replaces
+//! it first, see [`actuator_controller::throttle`]. Stops always run at
+//! once: one that comes in while a command is running cuts it short (it's
+//! answered with an `error`), and anything else waits for it to finish. A
+//! `source` event gives a client's totals whenever one of its
+//! commands is replaced, and for every client on the way out.
+//!
+//! Telemetry is published at `[telemetry] publish_rate`, see
+//! [`actuator_controller::telemetry`], but still printed for every report.
+//! Subscribers that can't keep up get less of it, see [`Publisher`].
+
+use std::{
+    cell::RefCell,
+    collections::{HashMap, VecDeque},
+    net::SocketAddr,
+    time::Instant,
+};
+
+use actuator_controller::{
+    client::{ActuatorClient, Update},
+    config::Config,
+    dsl,
+    json::Value,
+    roles::Roles,
+    telemetry::{Decimator, Report},
+    throttle::Throttle,
+};
+use tokio::{sync::mpsc, time::sleep_until};
+
+use crate::{
+    output::{Event, Output},
+    zmtp::Publisher,
+};
+
+/// One client's commands, as far as the rate limit goes.
+struct Source {
+    throttle: Throttle,
+    /// a command that came too soon, with its key, waiting for its turn
+    waiting: Option<(Option<String>, String)>,
+    run: u64,
+    coalesced: u64,
+}
+
+/// Runs `[startup]`, then takes commands until Ctrl-C or the port goes away.
+pub async fn run(
+    mut client: ActuatorClient,
+    publisher: Option<Publisher>,
+    mut commands: Option<mpsc::Receiver<(SocketAddr, Vec<Vec<u8>>)>>,
+    config: &Config,
+    out: &Output,
+) {
+    let emit = |event: Event| {
+        if let Some(publisher) = &publisher {
+            publisher.send(event.name(), out.to_json(&event).to_string());
+        }
+        out.emit(event);
+    };
+    let decimator = RefCell::new(Decimator::new(config.telemetry.publish));
+    let report = |report: &Report| {
+        out.emit(Event::Telemetry(report));
+        if let Some(publisher) = &publisher
+            && let Some(mean) = decimator.borrow_mut().push(report, Instant::now())
+        {
+            let body = out.to_json(&Event::Telemetry(&mean));
+            let mut lean = body.clone();
+            if let Value::Object(fields) = &mut lean {
+                // event, t, actuator and the length, which every schema starts with
+                fields.truncate(4);
+            }
+            publisher.send_telemetry(mean.actuator as usize, body.to_string(), lean.to_string());
+        }
+    };
+    let observe = |update: Update| match update {
+        Update::Telemetry(telemetry) => report(telemetry),
+        Update::Status(status) => emit(Event::Status(status)),
+        Update::Queue { depth, coalesced, dropped } => emit(Event::Queue { depth, coalesced, dropped }),
+        Update::Settle(settle) => emit(Event::Settle(settle)),
+    };
+
+    for cmd in &config.startup {
+        let command = format!("startup: {cmd}");
+        match client.run_observed(cmd.clone(), observe).await {
+            Ok(msg) => emit(Event::Ack { command: &command, message: &msg }),
+            Err(e) => {
+                emit(Event::Error { command: &command, message: &e });
+                break;
+            }
+        }
+    }
+
+    let vocabulary = config.vocabulary();
+    let mut sources: HashMap<SocketAddr, Source> = HashMap::new();
+    // messages that came in while a command ran, oldest first
+    let mut pending = VecDeque::new();
+    loop {
+        // the client whose waiting command's turn comes first
+        let due = sources
+            .iter()
+            .filter(|(_, source)| source.waiting.is_some())
+            .map(|(peer, source)| (source.throttle.wait_until(Instant::now()).unwrap_or_else(Instant::now), *peer))
+            .min();
+        let (peer, key, line) = tokio::select! {
+            Some((peer, parts)) = async {
+                match pending.pop_front() {
+                    Some(message) => Some(message),
+                    None => commands.as_mut()?.recv().await,
+                }
+            } => {
+                let Some((key, line)) = message(&parts) else { continue };
+                let source = sources.entry(peer).or_insert_with(|| Source {
+                    throttle: Throttle::new(config.throttle.client),
+                    waiting: None,
+                    run: 0,
+                    coalesced: 0,
+                });
+                let stop = client.parse(&line).is_ok_and(|cmd| cmd.is_stop());
+                // nothing that was waiting should run after a stop
+                let replaced = if stop || source.throttle.wait_until(Instant::now()).is_some() {
+                    let replaced = source.waiting.take();
+                    if !stop {
+                        source.waiting = Some((key.clone(), line.clone()));
+                    }
+                    replaced
+                } else {
+                    None
+                };
+                if let Some((_, older)) = replaced {
+                    source.coalesced += 1;
+                    let message = "coalesced: a newer command from the same client came in before its turn";
+                    emit(Event::Error { command: &older, message });
+                    emit(Event::Source { client: &peer.to_string(), run: source.run, coalesced: source.coalesced });
+                }
+                if !stop && source.waiting.is_some() {
+                    continue;
+                }
+                (peer, key, line)
+            }
+            _ = async { sleep_until(due?.0.into()).await; Some(()) }, if due.is_some() => {
+                let Some((_, peer)) = due else { continue };
+                let Some((key, line)) = sources.get_mut(&peer).and_then(|source| source.waiting.take()) else { continue };
+                (peer, key, line)
+            }
+            telemetry = client.telemetry() => match telemetry {
+                Some(telemetry) => {
+                    report(&telemetry);
+                    while let Some(hook) = client.fired() {
+                        let command = format!("hook: {}", hook.text);
+                        match client.run_observed(hook.command, observe).await {
+                            Ok(msg) => emit(Event::Ack { command: &command, message: &msg }),
+                            Err(e) => emit(Event::Error { command: &command, message: &e }),
+                        }
+                    }
+                    continue;
+                }
+                None => break,
+            },
+            _ = tokio::signal::ctrl_c() => break,
+        };
+        if let Some(source) = sources.get_mut(&peer) {
+            source.throttle.sent(Instant::now());
+            source.run += 1;
+        }
+        let run = execute(&mut client, &config.roles, key.as_deref(), &line, observe);
+        tokio::pin!(run);
+        let stop = loop {
+            tokio::select! {
+                result = &mut run => {
+                    match result {
+                        Ok(msg) => emit(Event::Ack { command: &line, message: &msg }),
+                        Err(e) => emit(Event::Error { command: &line, message: &e }),
+                    }
+                    break None;
+                }
+                Some((peer, parts)) = async { commands.as_mut()?.recv().await } => {
+                    let stops = message(&parts).is_some_and(|(key, line)| {
+                        vocabulary.parse(&line).is_ok_and(|cmd| cmd.is_stop() && config.roles.check(key.as_deref(), &cmd).is_ok())
+                    });
+                    if stops {
+                        break Some((peer, parts));
+                    }
+                    pending.push_back((peer, parts));
+                }
+            }
+        };
+        if let Some(stop) = stop {
+            emit(Event::Error { command: &line, message: "cut short by a stop" });
+            pending.push_front(stop);
+        }
+    }
+    for (peer, source) in &sources {
+        emit(Event::Source { client: &peer.to_string(), run: source.run, coalesced: source.coalesced });
+    }
+
+    if let Err(e) = client.run_observed(dsl::Command::Stop(None), observe).await {
+        emit(Event::Error { command: "stop", message: &e });
+    }
+    client.close().await;
+}
+
+/// A message's key, if it has one, and command.
+fn message(parts: &[Vec<u8>]) -> Option<(Option<String>, String)> {
+    let (msg, rest) = parts.split_last()?;
+    let key = rest.last().map(|key| String::from_utf8_lossy(key).into_owned());
+    Some((key, String::from_utf8_lossy(msg).trim().to_string()))
+}
+
+/// Runs one command from a client with `key`, if its role allows it.
+async fn execute(
+    client: &mut ActuatorClient,
+    roles: &Roles,
+    key: Option<&str>,
+    line: &str,
+    observe: impl FnMut(Update),
+) -> Result<String, String> {
+    let cmd = client.parse(line)?;
+    roles.check(key, &cmd)?;
+    client.run_observed(cmd, observe).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages() {
+        assert_eq!(message(&[b" stop\n".to_vec()]), Some((None, String::from("stop"))));
+        assert_eq!(message(&[b"id".to_vec(), b"key".to_vec(), b"stop".to_vec()]), Some((Some(String::from("key")), String::from("stop"))));
+        assert_eq!(message(&[]), None);
+        assert_eq!(message(&[vec![0xff, b'x']]), Some((None, String::from("\u{fffd}x"))));
+        let long = vec![b'x'; 1 << 20];
+        assert_eq!(message(&[long.clone(), long]).map(|(key, line)| (key.map(|k| k.len()), line.len())), Some((Some(1 << 20), 1 << 20)));
+    }
+}
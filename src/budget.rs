@@ -0,0 +1,70 @@
+//! A cap on the current both actuators draw together, enforced by the
+//! serial writer alongside the [speed limits](crate::limits).
+//!
+//! Either actuator alone may be well within what the breaker feeding them
+//! allows while both under load at once trip it. With a budget set, whenever
+//! the currents the firmware last reported for the actuators being driven add
+//! up to more than it, every speed goes out scaled down by the same factor, so
+//! both slow in proportion rather than one stalling, and the factor recovers
+//! as the draw falls back under. It only works with firmware that reports a
+//! `current` field.
+
+use crate::commands::ActuatorCommand;
+
+/// The slowest the budget scales speeds to, so an actuator is never scaled to
+/// a standstill by a bad reading.
+const MIN_SCALE: f64 = 0.1;
+
+/// The scale only changes in steps of this much, so a noisy current doesn't
+/// turn into a new frame every report.
+const SCALE_STEP: f64 = 0.05;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PowerBudget {
+    /// most current (A) the actuators may draw together
+    limit: f64,
+    /// what speeds are multiplied by, 1 while within budget
+    scale: f64,
+}
+
+impl PowerBudget {
+    pub fn new(limit: f64) -> PowerBudget {
+        PowerBudget { limit, scale: 1.0 }
+    }
+
+    pub fn limit(&self) -> f64 {
+        self.limit
+    }
+
+    /// What speeds are multiplied by, 1 while within budget.
+    pub fn scale(&self) -> f64 {
+        self.scale
+    }
+
+    /// `cmd`, with its speed scaled down if it's a `SetSpeed`.
+    pub fn apply(&self, cmd: ActuatorCommand) -> ActuatorCommand {
+        match cmd {
+            ActuatorCommand::SetSpeed(speed, actuator) => {
+                ActuatorCommand::SetSpeed((speed as f64 * self.scale).round() as u16, actuator)
+            }
+            cmd => cmd,
+        }
+    }
+
+    /// Adjusts the scale for `draw`, the current (A) the actuators being
+    /// driven are drawing together. Whether it changed.
+    pub fn observe(&mut self, draw: f64) -> bool {
+        // the draw goes roughly with speed, so scaling by how far over or
+        // under it is lands near the budget
+        let scale = match draw {
+            draw if draw > 0.0 => (self.scale * self.limit / draw).clamp(MIN_SCALE, 1.0),
+            _ => 1.0,
+        };
+        // back within budget is worth a frame however small the step
+        if scale == self.scale || (scale < 1.0 && (scale - self.scale).abs() < SCALE_STEP) {
+            return false;
+        }
+        self.scale = scale;
+        true
+    }
+}
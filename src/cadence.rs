@@ -0,0 +1,184 @@
+//! The fixed rate closed-loop control runs at (`position.rate`), and how
+//! closely it's kept: how late each tick starts (jitter), how long it takes,
+//! and how many ticks are missed outright because the ones before ran long
+//! (overruns), so a slow Pi shows up as numbers rather than as a sluggish
+//! position loop.
+//!
+//! Whatever runs the loop sleeps until [`Cadence::due`], then brackets the
+//! tick with [`Cadence::start`] and [`Cadence::finish`]. A tick that starts
+//! whole periods late skips them, as `tokio`'s `MissedTickBehavior::Skip`
+//! would, rather than running them back to back to catch up. A loop that
+//! only runs now and then, like [`crate::client`]'s while a command is going,
+//! calls [`Cadence::pause`] in between, so the gaps don't count against it.
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use crate::latency::Stats;
+
+/// Ticks per second unless the config says otherwise.
+pub const DEFAULT_RATE: f64 = 100.0;
+
+/// How many of the latest ticks the statistics cover: 10 s at the default rate.
+const WINDOW: usize = 1000;
+
+/// Achieved rates under this share of the target count as falling behind.
+const BEHIND: f64 = 0.95;
+
+#[derive(Clone, Copy, Debug)]
+struct Tick {
+    at: Instant,
+    /// after it was due
+    late: Duration,
+    /// after the one before, unless it's the first since a pause
+    after: Option<Duration>,
+    took: Option<Duration>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Cadence {
+    rate: f64,
+    period: Duration,
+    /// when the next tick is due; `None` before the first
+    next: Option<Instant>,
+    ticks: u64,
+    overruns: u64,
+    recent: VecDeque<Tick>,
+}
+
+impl Cadence {
+    pub fn new(rate: f64) -> Cadence {
+        Cadence {
+            rate,
+            period: Duration::from_secs_f64(1.0 / rate),
+            next: None,
+            ticks: 0,
+            overruns: 0,
+            recent: VecDeque::new(),
+        }
+    }
+
+    /// The rate asked for (Hz).
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// When the next tick is due, or `now` if the first hasn't run yet.
+    pub fn due(&self, now: Instant) -> Instant {
+        self.next.unwrap_or(now)
+    }
+
+    pub fn is_due(&self, now: Instant) -> bool {
+        self.next.is_none_or(|next| now >= next)
+    }
+
+    /// A tick started at `now`. Any periods that went by entirely since the
+    /// last one are counted as overruns and skipped.
+    pub fn start(&mut self, now: Instant) {
+        let scheduled = match self.next {
+            Some(next) if now >= next + self.period => {
+                let missed = (now.duration_since(next).as_nanos() / self.period.as_nanos()) as u32;
+                self.overruns += missed as u64;
+                next + self.period * missed
+            }
+            Some(next) => next.min(now),
+            None => now,
+        };
+        let after = self.next.and(self.recent.back()).map(|last| now.duration_since(last.at));
+        self.next = Some(scheduled + self.period);
+        self.ticks += 1;
+        if self.recent.len() == WINDOW {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(Tick { at: now, late: now.duration_since(scheduled), after, took: None });
+    }
+
+    /// The loop has stopped until the next [`Cadence::start`], which is due
+    /// straight away and isn't late or overrun for the wait.
+    pub fn pause(&mut self) {
+        self.next = None;
+    }
+
+    /// The tick last started finished at `now`.
+    pub fn finish(&mut self, now: Instant) {
+        if let Some(tick) = self.recent.back_mut() {
+            tick.took = Some(now.duration_since(tick.at));
+        }
+    }
+
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    /// Ticks skipped since the start because the loop was running late.
+    pub fn overruns(&self) -> u64 {
+        self.overruns
+    }
+
+    /// Ticks per second over the latest ones, leaving out pauses, once
+    /// there are two in a row.
+    pub fn achieved(&self) -> Option<f64> {
+        let gaps: Vec<Duration> = self.recent.iter().filter_map(|tick| tick.after).collect();
+        let span = gaps.iter().sum::<Duration>().as_secs_f64();
+        (span > 0.0).then(|| gaps.len() as f64 / span)
+    }
+
+    /// Whether the latest ticks have come noticeably less often than asked.
+    pub fn is_behind(&self) -> bool {
+        self.achieved().is_some_and(|hz| hz < self.rate * BEHIND)
+    }
+
+    /// How late the latest ticks started.
+    pub fn jitter(&self) -> Option<Stats> {
+        let late: Vec<Duration> = self.recent.iter().map(|tick| tick.late).collect();
+        Stats::of(&late)
+    }
+
+    /// How long the latest ticks took.
+    pub fn work(&self) -> Option<Stats> {
+        let took: Vec<Duration> = self.recent.iter().filter_map(|tick| tick.took).collect();
+        Stats::of(&took)
+    }
+
+    /// One line for the Info panel.
+    pub fn summary(&self) -> String {
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        let achieved = match self.achieved() {
+            Some(hz) => format!("{hz:.1} of {} Hz", self.rate),
+            None => format!("{} Hz", self.rate),
+        };
+        match self.jitter() {
+            Some(jitter) => format!(
+                "{achieved} | jitter p95 {:.2} ms, max {:.2} ms | {} overruns",
+                ms(jitter.p95),
+                ms(jitter.max),
+                self.overruns
+            ),
+            None => format!("{achieved} | not run yet"),
+        }
+    }
+
+    /// Jitter and tick time as a table, with the totals.
+    pub fn report(&self) -> String {
+        let mut out = format!("{:<20} {:>6} {:>8} {:>8} {:>8} {:>8}\n", "control loop", "ticks", "mean", "p50", "p95", "max");
+        for (label, stats) in [("start late", self.jitter()), ("tick time", self.work())] {
+            match stats {
+                Some(stats) => out += &format!("{label:<20} {stats}\n"),
+                None => out += &format!("{label:<20} {:>6}\n", 0),
+            }
+        }
+        out += &format!("{} ticks at {} Hz, {} overruns", self.ticks, self.rate, self.overruns);
+        match self.achieved() {
+            Some(hz) if self.is_behind() => out += &format!(", latest at {hz:.1} Hz: BEHIND\n"),
+            Some(hz) => out += &format!(", latest at {hz:.1} Hz\n"),
+            None => out += "\n",
+        }
+        out
+    }
+}
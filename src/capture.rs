@@ -0,0 +1,265 @@
+//! `--capture`: every byte the reader takes off the serial port, as it took
+//! it, and every frame written to it, for stepping through afterwards with
+//! `frames` (see [`crate::frames`]) or in Wireshark.
+//!
+//! A capture is text, one read or write per line, after a header line:
+//!
+//! ```text
+//! # actuator_controller serial capture
+//! 0.012 rx 9a9999999999b93f
+//! 0.050 tx 00008000
+//! 1.204 rx 3333
+//! 1.204 short
+//! 1.517 reopen
+//! ```
+//!
+//! Times are seconds since the port was opened. `short` follows the bytes of
+//! a read that came up short, which the reader threw away before starting
+//! on the next report, and `reopen` is the watchdog reopening the port.
+//! Anything without the header, e.g. `cat /dev/ttyACM0 > dump`, is taken as
+//! the raw bytes of the stream.
+//!
+//! A path ending in `.pcapng` gets the same as a pcapng file instead, one
+//! packet per line above, with the time of day and the direction, on the
+//! [`LINKTYPE`] for private use. Each packet is a byte saying which kind of
+//! [`Chunk`] it is, then its bytes; `generate wireshark` writes a dissector
+//! for them (see [`crate::dissector`]).
+
+use std::{
+    fmt,
+    fs::File,
+    io::{self, Write},
+    path::Path,
+    sync::Mutex,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+pub const HEADER: &str = "# actuator_controller serial capture";
+
+/// `LINKTYPE_USER0`, which Wireshark leaves to whoever writes the dissector,
+/// [`crate::dissector`] here.
+pub const LINKTYPE: u16 = 147;
+
+/// pcapng block types, and the magic that says which order the rest is in.
+const SECTION_HEADER: u32 = 0x0A0D_0D0A;
+const INTERFACE: u32 = 1;
+const PACKET: u32 = 6;
+const BYTE_ORDER: u32 = 0x1A2B_3C4D;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Chunk {
+    Bytes(Vec<u8>),
+    /// a frame written to the port
+    Sent(Vec<u8>),
+    /// the reader gave up on the report it was partway through
+    Short,
+    /// the watchdog reopened the port
+    Reopen,
+}
+
+impl fmt::Display for Chunk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Chunk::Bytes(bytes) => write!(f, "rx {}", hex(bytes)),
+            Chunk::Sent(bytes) => write!(f, "tx {}", hex(bytes)),
+            Chunk::Short => f.write_str("short"),
+            Chunk::Reopen => f.write_str("reopen"),
+        }
+    }
+}
+
+impl Chunk {
+    /// The byte a pcapng packet starts with.
+    pub fn kind(&self) -> u8 {
+        match self {
+            Chunk::Bytes(_) => 0,
+            Chunk::Sent(_) => 1,
+            Chunk::Short => 2,
+            Chunk::Reopen => 3,
+        }
+    }
+
+    fn bytes(&self) -> &[u8] {
+        match self {
+            Chunk::Bytes(bytes) | Chunk::Sent(bytes) => bytes,
+            Chunk::Short | Chunk::Reopen => &[],
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    /// seconds since the capture started; `None` for a raw dump
+    pub at: Option<f64>,
+    pub chunk: Chunk,
+}
+
+/// Appends to a capture as the link reads and writes.
+pub struct Writer {
+    file: Mutex<File>,
+    start: Instant,
+    /// the time of day at `start`, for pcapng, which is written as such
+    pcapng: Option<SystemTime>,
+}
+
+impl Writer {
+    /// A pcapng capture if `path` ends in `.pcapng`, text otherwise.
+    pub fn create(path: &Path) -> io::Result<Writer> {
+        let mut file = File::create(path)?;
+        let pcapng = path.extension().is_some_and(|ext| ext == "pcapng");
+        if pcapng {
+            // no options, and the length of the section unknown
+            let mut section = Vec::new();
+            section.extend(BYTE_ORDER.to_le_bytes());
+            section.extend(1u16.to_le_bytes());
+            section.extend(0u16.to_le_bytes());
+            section.extend((-1i64).to_le_bytes());
+            file.write_all(&block(SECTION_HEADER, &section))?;
+            // whole packets, however long, and times in microseconds
+            let mut interface = Vec::new();
+            interface.extend(LINKTYPE.to_le_bytes());
+            interface.extend(0u16.to_le_bytes());
+            interface.extend(0u32.to_le_bytes());
+            file.write_all(&block(INTERFACE, &interface))?;
+        } else {
+            writeln!(file, "{HEADER}")?;
+        }
+        Ok(Writer { file: Mutex::new(file), start: Instant::now(), pcapng: pcapng.then(SystemTime::now) })
+    }
+
+    /// Notes `chunk`. A capture that can't be written to is no reason to
+    /// stop reading, so errors are ignored.
+    pub fn record(&self, chunk: Chunk) {
+        if matches!(&chunk, Chunk::Bytes(bytes) if bytes.is_empty()) {
+            return;
+        }
+        let elapsed = self.start.elapsed();
+        let Ok(mut file) = self.file.lock() else { return };
+        let Some(opened) = self.pcapng else {
+            let _ = writeln!(file, "{:.3} {chunk}", elapsed.as_secs_f64());
+            return;
+        };
+        let micros = (opened + elapsed).duration_since(UNIX_EPOCH).unwrap_or_default().as_micros() as u64;
+        let mut data = vec![chunk.kind()];
+        data.extend(chunk.bytes());
+        let mut packet = Vec::new();
+        packet.extend(0u32.to_le_bytes());
+        packet.extend(((micros >> 32) as u32).to_le_bytes());
+        packet.extend((micros as u32).to_le_bytes());
+        packet.extend((data.len() as u32).to_le_bytes());
+        packet.extend((data.len() as u32).to_le_bytes());
+        packet.extend(padded(&data));
+        // epb_flags: inbound or outbound
+        let inbound = !matches!(chunk, Chunk::Sent(_));
+        packet.extend(2u16.to_le_bytes());
+        packet.extend(4u16.to_le_bytes());
+        packet.extend((if inbound { 1u32 } else { 2 }).to_le_bytes());
+        packet.extend([0; 4]);
+        let _ = file.write_all(&block(PACKET, &packet));
+    }
+}
+
+/// A pcapng block of `kind` around `body`, which is a multiple of 4 bytes.
+fn block(kind: u32, body: &[u8]) -> Vec<u8> {
+    let length = (12 + body.len() as u32).to_le_bytes();
+    let mut out = Vec::with_capacity(body.len() + 12);
+    out.extend(kind.to_le_bytes());
+    out.extend(length);
+    out.extend(body);
+    out.extend(length);
+    out
+}
+
+/// `data` with zeros up to a multiple of 4 bytes.
+fn padded(data: &[u8]) -> Vec<u8> {
+    let mut out = data.to_vec();
+    out.resize(data.len().next_multiple_of(4), 0);
+    out
+}
+
+/// Reads a capture, either kind, or a raw dump.
+pub fn parse(data: &[u8]) -> Result<Vec<Entry>, String> {
+    if data.starts_with(&SECTION_HEADER.to_le_bytes()) {
+        return parse_pcapng(data);
+    }
+    let Some(text) = data.strip_prefix(HEADER.as_bytes()) else {
+        return Ok(vec![Entry { at: None, chunk: Chunk::Bytes(data.to_vec()) }]);
+    };
+    let text = std::str::from_utf8(text).map_err(|e| format!("not text after the header: {e}"))?;
+    let mut entries = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        // the header was line 1
+        let error = |msg: &str| format!("line {}: {msg}", i + 1);
+        let mut words = line.split_whitespace();
+        let at = words.next().and_then(|w| w.parse::<f64>().ok()).ok_or_else(|| error("expected a time in seconds"))?;
+        let chunk = match (words.next(), words.next()) {
+            (Some("rx"), Some(bytes)) => Chunk::Bytes(unhex(bytes).ok_or_else(|| error("expected hex bytes after `rx`"))?),
+            (Some("tx"), Some(bytes)) => Chunk::Sent(unhex(bytes).ok_or_else(|| error("expected hex bytes after `tx`"))?),
+            (Some("short"), None) => Chunk::Short,
+            (Some("reopen"), None) => Chunk::Reopen,
+            _ => return Err(error("expected `rx <hex>`, `tx <hex>`, `short` or `reopen`")),
+        };
+        entries.push(Entry { at: Some(at), chunk });
+    }
+    Ok(entries)
+}
+
+/// Reads the packets of a pcapng capture, timed from the first, skipping
+/// any other blocks.
+fn parse_pcapng(data: &[u8]) -> Result<Vec<Entry>, String> {
+    let mut big_endian = false;
+    let word = |at: usize, big_endian: bool| -> Option<u32> {
+        let bytes = data.get(at..at + 4)?.try_into().ok()?;
+        Some(if big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+    };
+    let mut entries = Vec::new();
+    let mut first = None;
+    let mut at = 0;
+    while at < data.len() {
+        let error = |msg: &str| format!("block at byte {at}: {msg}");
+        let kind = word(at, big_endian).ok_or_else(|| error("cut short"))?;
+        if kind == SECTION_HEADER {
+            big_endian = match word(at + 8, false) {
+                Some(BYTE_ORDER) => false,
+                Some(magic) if magic == BYTE_ORDER.swap_bytes() => true,
+                _ => return Err(error("not a pcapng section header")),
+            };
+        }
+        let length = word(at + 4, big_endian).ok_or_else(|| error("cut short"))? as usize;
+        if length < 12 || !length.is_multiple_of(4) || at + length > data.len() {
+            return Err(error(&format!("a length of {length} bytes doesn't fit")));
+        }
+        if kind == PACKET {
+            let number = |offset: usize| word(at + 8 + offset, big_endian).ok_or_else(|| error("cut short"));
+            let micros = (number(4)? as u64) << 32 | number(8)? as u64;
+            let captured = number(12)? as usize;
+            let bytes = data.get(at + 28..at + 28 + captured).filter(|_| 28 + captured <= length).ok_or_else(|| error("cut short"))?;
+            let chunk = match bytes.split_first() {
+                Some((0, rest)) => Chunk::Bytes(rest.to_vec()),
+                Some((1, rest)) => Chunk::Sent(rest.to_vec()),
+                Some((2, [])) => Chunk::Short,
+                Some((3, [])) => Chunk::Reopen,
+                _ => return Err(error("not a packet from --capture")),
+            };
+            let first = *first.get_or_insert(micros);
+            entries.push(Entry { at: Some(micros.saturating_sub(first) as f64 / 1e6), chunk });
+        }
+        at += length;
+    }
+    Ok(entries)
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn unhex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len()).step_by(2).map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok()).collect()
+}
@@ -0,0 +1,222 @@
+//! Derived channels: telemetry fields worked out from the ones the firmware
+//! sends, configured under `[channels]` as `name:unit = expression`, e.g.
+//! `lift_skew:m = m1.len - m2.len` or `power:W = current * voltage`.
+//!
+//! An expression is numbers and field names joined by `+`, `-`, `*` and `/`,
+//! with brackets. A bare name is a field of the report being worked on; with
+//! `m1.` or `m2.` (or `lift.`, `bucket.`) in front, it's from that actuator's
+//! latest report. A channel can use the ones listed before it. Each is added
+//! to every report as one more field as it arrives, so the UI, the checklist,
+//! the logs, the daemon's clients and the bridges take it like any other.
+//! One that can't be worked out, e.g. before the other actuator has
+//! reported, is NaN, which JSON shows as `null`.
+
+use std::sync::Arc;
+
+use crate::{
+    commands::Actuator,
+    dsl,
+    telemetry::{Field, Report, Schema},
+};
+
+#[derive(Clone, Debug, PartialEq)]
+enum Expr {
+    Number(f64),
+    /// a field of `of`'s latest report, or of the one being worked on
+    Field { of: Option<Actuator>, name: String },
+    Neg(Box<Expr>),
+    /// `+`, `-`, `*` or `/`
+    Op(char, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn eval(&self, actuator: Actuator, fields: &[Field], values: &[f64], latest: &[Option<Report>; 2]) -> f64 {
+        let eval = |expr: &Expr| expr.eval(actuator, fields, values, latest);
+        match self {
+            Expr::Number(n) => *n,
+            Expr::Field { of, name } => {
+                let find = |fields: &[Field], values: &[f64]| {
+                    fields.iter().zip(values).find(|(field, _)| field.name == *name).map(|(_, value)| *value)
+                };
+                let value = match of {
+                    Some(of) if *of != actuator => {
+                        latest[*of as usize].as_ref().and_then(|report| find(report.schema.fields(), &report.values))
+                    }
+                    _ => find(fields, values),
+                };
+                value.unwrap_or(f64::NAN)
+            }
+            Expr::Neg(expr) => -eval(expr),
+            Expr::Op(op, a, b) => {
+                let (a, b) = (eval(a), eval(b));
+                match op {
+                    '+' => a + b,
+                    '-' => a - b,
+                    '*' => a * b,
+                    _ => a / b,
+                }
+            }
+        }
+    }
+}
+
+/// Reads an expression by recursive descent, `*` and `/` binding tighter.
+struct Parser<'a> {
+    /// what's left to read
+    text: &'a str,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&mut self) -> Option<char> {
+        self.text = self.text.trim_start();
+        self.text.chars().next()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let next = self.peek() == Some(c);
+        if next {
+            self.text = &self.text[1..];
+        }
+        next
+    }
+
+    fn sum(&mut self) -> Result<Expr, String> {
+        let mut expr = self.product()?;
+        while let Some(op @ ('+' | '-')) = self.peek() {
+            self.eat(op);
+            expr = Expr::Op(op, Box::new(expr), Box::new(self.product()?));
+        }
+        Ok(expr)
+    }
+
+    fn product(&mut self) -> Result<Expr, String> {
+        let mut expr = self.unary()?;
+        while let Some(op @ ('*' | '/')) = self.peek() {
+            self.eat(op);
+            expr = Expr::Op(op, Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat('-') {
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        if self.eat('(') {
+            let expr = self.sum()?;
+            return match self.eat(')') {
+                true => Ok(expr),
+                false => Err(String::from("a `(` isn't closed")),
+            };
+        }
+        let word = self.word();
+        if word.is_empty() {
+            return Err(match self.peek() {
+                Some(c) => format!("expected a number or a field, found `{c}`"),
+                None => String::from("expected a number or a field at the end"),
+            });
+        }
+        if word.starts_with(|c: char| c.is_ascii_digit() || c == '.') {
+            return word.parse().map(Expr::Number).map_err(|_| format!("`{word}` isn't a number"));
+        }
+        if !self.eat('.') {
+            return Ok(Expr::Field { of: None, name: word.to_string() });
+        }
+        let of = dsl::parse_actuator(word)
+            .ok_or_else(|| format!("unknown actuator `{word}`; expected m1, m2, lift or bucket"))?;
+        match self.word() {
+            "" => Err(format!("expected a field after `{word}.`")),
+            name => Ok(Expr::Field { of: Some(of), name: name.to_string() }),
+        }
+    }
+
+    /// A run of letters, digits, `_`, and `.` within a number.
+    fn word(&mut self) -> &'a str {
+        self.peek();
+        let number = self.text.starts_with(|c: char| c.is_ascii_digit());
+        let len = self
+            .text
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || (number && c == '.')))
+            .unwrap_or(self.text.len());
+        let (word, rest) = self.text.split_at(len);
+        self.text = rest;
+        word
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Channel {
+    pub field: Field,
+    expr: Expr,
+}
+
+impl Channel {
+    /// Reads `<name>[:<unit>] = <expression>`.
+    pub fn parse(text: &str) -> Result<Channel, String> {
+        let (name, expression) = text.split_once('=').ok_or("expected `<name> = <expression>`")?;
+        let (name, unit) = name.split_once(':').unwrap_or((name, ""));
+        let (name, unit) = (name.trim(), unit.trim());
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(format!("`{name}` isn't a name; use letters, digits and `_`"));
+        }
+        // the same as the firmware's own fields, see `Schema::parse`
+        if ["event", "t", "actuator", "len"].contains(&name) {
+            return Err(format!("{name} is reserved"));
+        }
+        if unit.contains([',', ':']) || unit.contains(char::is_whitespace) {
+            return Err(format!("bad unit `{unit}` for {name}"));
+        }
+        let mut parser = Parser { text: expression.trim() };
+        let expr = parser.sum()?;
+        if let Some(c) = parser.peek() {
+            return Err(format!("unexpected `{c}` at {}", expression.trim().len() - parser.text.len() + 1));
+        }
+        Ok(Channel { field: Field { name: name.to_string(), unit: unit.to_string() }, expr })
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Channels {
+    pub list: Vec<Channel>,
+    /// which one the Telemetry chart can show
+    pub chart: Option<String>,
+    /// each actuator's latest report, channels and all
+    latest: [Option<Report>; 2],
+    /// the last schema added to, and what it became
+    extended: Option<(Arc<Schema>, Arc<Schema>)>,
+}
+
+impl Channels {
+    pub fn get(&self, name: &str) -> Option<&Channel> {
+        self.list.iter().find(|channel| channel.field.name == name)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    /// `report` with every channel added. One it already has was worked out
+    /// upstream, e.g. by the daemon attached to, and is left as it is.
+    pub fn apply(&mut self, report: Report) -> Report {
+        if self.list.is_empty() {
+            return report;
+        }
+        let fresh = |channel: &&Channel| !report.schema.fields().iter().any(|field| field.name == channel.field.name);
+        let schema = match &self.extended {
+            Some((from, to)) if *from == report.schema => Arc::clone(to),
+            _ => {
+                let to = Arc::new(report.schema.extended(self.list.iter().filter(fresh).map(|c| c.field.clone())));
+                self.extended = Some((Arc::clone(&report.schema), Arc::clone(&to)));
+                to
+            }
+        };
+        let mut values = report.values.clone();
+        for channel in self.list.iter().filter(fresh) {
+            let value = channel.expr.eval(report.actuator, schema.fields(), &values, &self.latest);
+            values.push(value);
+        }
+        let report = Report { actuator: report.actuator, schema, values };
+        self.latest[report.actuator as usize] = Some(report.clone());
+        report
+    }
+}
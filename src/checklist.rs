@@ -0,0 +1,143 @@
+//! The pre-flight checklist: things to see to before the actuators may run at
+//! full speed, such as "pins removed" or "area clear".
+//!
+//! An item the telemetry can settle, written `<field> > <value>` or
+//! `<field> < <value>` (`battery > 14V`), checks itself against the latest
+//! report with that field. The rest the operator ticks off by hand. Until
+//! every item is done and the operator arms, drives are capped at
+//! [`Checklist::unarmed_speed`]. With no items there's nothing to arm and
+//! nothing is capped.
+
+use crate::telemetry::Report;
+
+/// A quarter of full speed.
+pub const DEFAULT_UNARMED_SPEED: u16 = 16384;
+
+/// A comparison against a telemetry field.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Check {
+    pub field: String,
+    /// whether the field has to be over `threshold`, or else under it
+    pub above: bool,
+    pub threshold: f64,
+}
+
+impl Check {
+    pub fn passes(&self, value: f64) -> bool {
+        if self.above { value > self.threshold } else { value < self.threshold }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Item {
+    pub text: String,
+    /// `None` for an item the operator ticks off
+    pub check: Option<Check>,
+}
+
+impl Item {
+    /// Reads one item, which is a [`Check`] if it compares something.
+    pub fn parse(text: &str) -> Result<Item, String> {
+        let text = text.trim();
+        let Some(at) = text.find(['>', '<']) else {
+            return Ok(Item { text: text.to_string(), check: None });
+        };
+        let field = text[..at].trim();
+        // the unit is only there for the operator to read
+        let value = text[at + 1..].trim().trim_end_matches(|c: char| c.is_ascii_alphabetic()).trim();
+        if field.is_empty() || !field.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(format!("expected a telemetry field before `{}`, found `{field}`", &text[at..at + 1]));
+        }
+        let threshold = value.parse::<f64>().map_err(|_| format!("expected a number after `{}`", &text[at..at + 1]))?;
+        let check = Check { field: field.to_string(), above: text[at..].starts_with('>'), threshold };
+        Ok(Item { text: text.to_string(), check: Some(check) })
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Checklist {
+    pub items: Vec<Item>,
+    /// fastest any actuator is driven until armed
+    pub unarmed_speed: u16,
+    /// which of `items` the operator has ticked off
+    ticked: Vec<bool>,
+    /// the latest value of each item's field, if it has a check
+    readings: Vec<Option<f64>>,
+    armed: bool,
+}
+
+impl Checklist {
+    pub fn new(items: Vec<Item>, unarmed_speed: u16) -> Checklist {
+        let n = items.len();
+        Checklist { items, unarmed_speed, ticked: vec![false; n], readings: vec![None; n], armed: false }
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.items.is_empty()
+    }
+
+    /// Whether drives run at full speed.
+    pub fn is_armed(&self) -> bool {
+        self.armed || !self.enabled()
+    }
+
+    /// Takes in a report for the items that check its fields.
+    pub fn observe(&mut self, report: &Report) {
+        for (item, reading) in self.items.iter().zip(&mut self.readings) {
+            if let Some(value) = item.check.as_ref().and_then(|check| report.field(&check.field)) {
+                *reading = Some(value);
+            }
+        }
+    }
+
+    pub fn reading(&self, i: usize) -> Option<f64> {
+        self.readings.get(i).copied().flatten()
+    }
+
+    pub fn is_done(&self, i: usize) -> bool {
+        match &self.items[i].check {
+            Some(check) => self.reading(i).is_some_and(|value| check.passes(value)),
+            None => self.ticked[i],
+        }
+    }
+
+    pub fn done(&self) -> usize {
+        (0..self.items.len()).filter(|&i| self.is_done(i)).count()
+    }
+
+    /// Ticks the `i`th item off, or back on. Returns whether it's now ticked.
+    pub fn toggle(&mut self, i: usize) -> Result<bool, String> {
+        if self.armed {
+            return Err(String::from("already armed"));
+        }
+        let item = self.items.get(i).ok_or("no such item")?;
+        if let Some(check) = &item.check {
+            return Err(format!("`{}` is checked from the `{}` telemetry", item.text, check.field));
+        }
+        self.ticked[i] = !self.ticked[i];
+        Ok(self.ticked[i])
+    }
+
+    /// Lifts the speed cap, as long as every item is done.
+    pub fn arm(&mut self) -> Result<(), String> {
+        if let Some(i) = (0..self.items.len()).find(|&i| !self.is_done(i)) {
+            let item = &self.items[i];
+            return Err(match (&item.check, self.reading(i)) {
+                (Some(check), None) => format!("`{}`: no `{}` from the firmware yet", item.text, check.field),
+                (Some(_), Some(value)) => format!("`{}`: it's {value:.2}", item.text),
+                (None, _) => format!("`{}` isn't ticked off", item.text),
+            });
+        }
+        self.armed = true;
+        Ok(())
+    }
+
+    /// `speed`, brought down to the cap while unarmed.
+    pub fn limit(&self, speed: i32) -> i32 {
+        if self.is_armed() {
+            return speed;
+        }
+        let cap = self.unarmed_speed as i32;
+        speed.clamp(-cap, cap)
+    }
+}
@@ -0,0 +1,441 @@
+//! Command line arguments.
+
+use std::{path::PathBuf, time::Duration};
+
+#[cfg(unix)]
+use actuator_controller::daemon;
+use actuator_controller::{audit, bode::Sweep, config, dsl, signal::Signal};
+
+use crate::output::Format;
+
+pub struct Opt {
+    pub long: &'static str,
+    /// placeholder for the option's value, if it takes one
+    pub value: Option<&'static str>,
+    pub help: &'static str,
+}
+
+pub const OPTIONS: &[Opt] = &[
+    Opt {
+        long: "--config",
+        value: Some("PATH"),
+        help: "read settings from PATH instead of ./actuator.toml",
+    },
+    Opt {
+        long: "--set",
+        value: Some("KEY=VALUE"),
+        help: "override a config key, e.g. position.gain=300000, on top of the file and ACTUATOR_* variables",
+    },
+    Opt {
+        long: "--operator",
+        value: Some("NAME"),
+        help: "who is at the controls, shown in the UI and recorded in the audit log (default $USER)",
+    },
+    Opt {
+        long: "--audit",
+        value: Some("PATH"),
+        help: "append every command, with the time and operator, to PATH",
+    },
+    Opt {
+        long: "--trip-log",
+        value: Some("PATH"),
+        help: "append a summary of each run (travel, max current, stalls, time at limits) to PATH",
+    },
+    Opt {
+        long: "--journal",
+        value: Some("PATH"),
+        help: "keep the step of any running sequence or trajectory in PATH, to resume or abort it after a crash",
+    },
+    Opt {
+        long: "--stdin",
+        value: None,
+        help: "run commands from standard input instead of the terminal UI",
+    },
+    Opt {
+        long: "--monitor",
+        value: None,
+        help: "print telemetry and status without the terminal UI or sending any commands",
+    },
+    Opt {
+        long: "--excite",
+        value: Some("SIGNAL"),
+        help: "drive an actuator with a test wave, e.g. m1:square:40%:0.25 (actuator:wave:amplitude:hz), printing speeds and telemetry; once per actuator",
+    },
+    Opt {
+        long: "--duration",
+        value: Some("SECS"),
+        help: "how long --excite runs (default 10), or soak (default soak.hours)",
+    },
+    Opt {
+        long: "--sweep",
+        value: Some("SWEEP"),
+        help: "run a swept-sine test, e.g. m1:40%:0.1:2 (actuator:amplitude:from hz:to hz), printing gain and phase as CSV",
+    },
+    Opt {
+        long: "--replay",
+        value: Some("PATH"),
+        help: "send the speeds in an --output json log again and fail if the lengths stray from it by more than [replay] allows",
+    },
+    Opt {
+        long: "--sim",
+        value: Some("PATH"),
+        help: "drive simulated actuators that behave like the ones in an --excite --output json log, instead of opening a port",
+    },
+    Opt {
+        long: "--daemon",
+        value: None,
+        help: "hold the port open in the background and serve it on the socket for --attach",
+    },
+    Opt {
+        long: "--attach",
+        value: None,
+        help: "use a running --daemon instead of opening the port (no device path needed)",
+    },
+    Opt {
+        long: "--socket",
+        value: Some("PATH"),
+        help: "socket for --daemon and --attach (default $XDG_RUNTIME_DIR/actuator_controller.sock)",
+    },
+    Opt {
+        long: "--zmq-pub",
+        value: Some("ENDPOINT"),
+        help: "run headless, publishing telemetry and results on a ZeroMQ PUB socket bound to ENDPOINT (e.g. tcp://*:5556)",
+    },
+    Opt {
+        long: "--zmq-pull",
+        value: Some("ENDPOINT"),
+        help: "run headless, taking commands from a ZeroMQ PULL socket bound to ENDPOINT (e.g. tcp://*:5557)",
+    },
+    Opt {
+        long: "--advertise",
+        value: Some("NAME"),
+        help: "with the --zmq-* options, advertise the bridge as NAME over mDNS so `discover` finds it",
+    },
+    Opt {
+        long: "--shm",
+        value: Some("PATH"),
+        help: "also keep the latest telemetry in a shared-memory file (e.g. /dev/shm/actuator_controller)",
+    },
+    Opt {
+        long: "--capture",
+        value: Some("PATH"),
+        help: "record every byte read from the port and frame written to it to PATH, as pcapng if PATH ends in .pcapng, for `frames` or Wireshark",
+    },
+    Opt {
+        long: "--lock",
+        value: None,
+        help: "lock the port, refusing it (and saying who has it) if another instance already has it locked",
+    },
+    Opt {
+        long: "--output",
+        value: Some("FORMAT"),
+        help: "when headless, print `text` (default) or one `json` object per line",
+    },
+    Opt {
+        long: "--timeout",
+        value: Some("SECS"),
+        help: "when headless, fail a command that hasn't finished after SECS (default 60)",
+    },
+    Opt {
+        long: "--profile",
+        value: None,
+        help: "time each frame from key press to acknowledgement and print per-stage latency on exit",
+    },
+    Opt {
+        long: "--help",
+        value: None,
+        help: "print this message",
+    },
+];
+
+pub struct Args {
+    /// `None` with `--attach` or `--sim`
+    pub port_path: Option<String>,
+    /// the log `--sim` models the actuators on
+    pub sim: Option<PathBuf>,
+    pub config_path: PathBuf,
+    /// `--set` overrides, in order
+    pub sets: Vec<(String, String)>,
+    pub operator: String,
+    pub audit: Option<PathBuf>,
+    #[cfg_attr(not(feature = "tui"), allow(dead_code))]
+    pub trip_log: Option<PathBuf>,
+    /// where the running sequence is kept, see [`actuator_controller::journal`]
+    pub journal: Option<PathBuf>,
+    /// `soak` before the options
+    pub soak: bool,
+    pub stdin: bool,
+    pub monitor: bool,
+    /// test signals to run, at most one per actuator
+    pub excite: Vec<Signal>,
+    /// `--duration`, if given
+    pub duration: Option<Duration>,
+    pub sweep: Option<Sweep>,
+    /// the log `--replay` sends again
+    pub replay: Option<PathBuf>,
+    #[cfg_attr(not(unix), allow(dead_code))]
+    pub daemon: bool,
+    #[cfg_attr(not(unix), allow(dead_code))]
+    pub socket: PathBuf,
+    #[cfg_attr(not(feature = "bridge"), allow(dead_code))]
+    pub zmq_pub: Option<String>,
+    #[cfg_attr(not(feature = "bridge"), allow(dead_code))]
+    pub zmq_pull: Option<String>,
+    /// the mDNS instance name to advertise the bridge under
+    #[cfg_attr(not(feature = "mdns"), allow(dead_code))]
+    pub advertise: Option<String>,
+    #[cfg_attr(not(unix), allow(dead_code))]
+    pub shm: Option<PathBuf>,
+    /// where to record what's read from the port
+    pub capture: Option<PathBuf>,
+    pub lock: bool,
+    pub output: Format,
+    pub timeout: Duration,
+    pub profile: bool,
+}
+
+pub fn usage() -> String {
+    let mut out = String::from(
+        "Usage: actuator_controller [OPTIONS] <device path>\n       actuator_controller --attach [OPTIONS]\n       actuator_controller --sim <log path> [OPTIONS]\n       actuator_controller soak <device path> [OPTIONS]\n       actuator_controller generate <bash|zsh|fish|man|vectors|wireshark>\n       actuator_controller identify <log path>\n       actuator_controller frames <capture path> [--fields LIST] [--step]\n       actuator_controller config <schema|migrate [PATH]>\n       actuator_controller remote <pull endpoint> [<pub endpoint>] [--key <key>]\n       actuator_controller discover [SECS]\n       actuator_controller platform\n\nExample: actuator_controller /dev/ttyACM0\n\nOptions:\n",
+    );
+    for opt in OPTIONS {
+        let name = match opt.value {
+            Some(value) => format!("{} <{value}>", opt.long),
+            None => opt.long.to_string(),
+        };
+        out += &format!("  {name:<20} {}\n", opt.help);
+    }
+    out
+}
+
+/// Parses the arguments after the program name. `Err` carries the message to
+/// print; an empty message means `--help` was asked for.
+/// `text` as a positive number of seconds, up to [`dsl::MAX_DURATION`].
+pub fn seconds(text: &str) -> Option<Duration> {
+    let secs = text.parse::<f64>().ok().filter(|s| *s > 0.0)?;
+    Duration::try_from_secs_f64(secs).ok().filter(|d| *d <= dsl::MAX_DURATION)
+}
+
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+    let mut port_path = None;
+    let mut config_path = PathBuf::from(config::DEFAULT_PATH);
+    let mut sets = Vec::new();
+    let mut operator = audit::default_operator();
+    let mut audit = None;
+    let mut trip_log = None;
+    let mut journal = None;
+    let mut stdin = false;
+    let mut monitor = false;
+    let mut excite: Vec<Signal> = Vec::new();
+    let mut duration = None;
+    let mut sweep = None;
+    let mut replay = None;
+    let mut sim = None;
+    let mut daemon = false;
+    let mut attach = false;
+    #[cfg(unix)]
+    let mut socket = daemon::default_socket_path();
+    #[cfg(not(unix))]
+    let mut socket = PathBuf::new();
+    let mut zmq_pub = None;
+    let mut zmq_pull = None;
+    let mut advertise = None;
+    let mut shm = None;
+    let mut capture = None;
+    let mut lock = false;
+    let mut output = None;
+    let mut timeout = Duration::from_secs(60);
+    let mut profile = false;
+
+    let mut args = args.into_iter().peekable();
+    let soak = args.next_if(|arg| arg == "soak").is_some();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("{name} needs a value"));
+        match arg.as_str() {
+            "--config" => config_path = PathBuf::from(value("--config")?),
+            "--set" => {
+                let set = value("--set")?;
+                let Some((key, value)) = set.split_once('=') else {
+                    return Err(format!("--set takes KEY=VALUE, not `{set}`"));
+                };
+                sets.push((key.trim().to_string(), value.to_string()));
+            }
+            "--operator" => operator = value("--operator")?,
+            "--audit" => audit = Some(PathBuf::from(value("--audit")?)),
+            "--trip-log" => trip_log = Some(PathBuf::from(value("--trip-log")?)),
+            "--journal" => journal = Some(PathBuf::from(value("--journal")?)),
+            "--stdin" => stdin = true,
+            "--monitor" => monitor = true,
+            "--excite" => {
+                let signal = Signal::parse(&value("--excite")?).map_err(|e| format!("--excite: {e}"))?;
+                if excite.iter().any(|s| s.actuator == signal.actuator) {
+                    return Err(format!("--excite is given twice for {:?}", signal.actuator));
+                }
+                excite.push(signal);
+            }
+            "--duration" => {
+                let secs = value("--duration")?;
+                duration = Some(seconds(&secs).ok_or_else(|| {
+                    format!("--duration must be a positive number of seconds up to {}, not `{secs}`", dsl::MAX_DURATION.as_secs())
+                })?);
+            }
+            "--sweep" => sweep = Some(Sweep::parse(&value("--sweep")?).map_err(|e| format!("--sweep: {e}"))?),
+            "--replay" => replay = Some(PathBuf::from(value("--replay")?)),
+            "--sim" => sim = Some(PathBuf::from(value("--sim")?)),
+            "--daemon" => daemon = true,
+            "--attach" => attach = true,
+            "--socket" => socket = PathBuf::from(value("--socket")?),
+            "--zmq-pub" => zmq_pub = Some(value("--zmq-pub")?),
+            "--zmq-pull" => zmq_pull = Some(value("--zmq-pull")?),
+            "--advertise" => advertise = Some(value("--advertise")?),
+            "--shm" => shm = Some(PathBuf::from(value("--shm")?)),
+            "--capture" => capture = Some(PathBuf::from(value("--capture")?)),
+            "--lock" => lock = true,
+            "--output" => {
+                let name = value("--output")?;
+                output = Some(Format::parse(&name).ok_or_else(|| format!("--output must be text or json, not `{name}`"))?);
+            }
+            "--timeout" => {
+                let secs = value("--timeout")?;
+                timeout = seconds(&secs).ok_or_else(|| {
+                    format!("--timeout must be a positive number of seconds up to {}, not `{secs}`", dsl::MAX_DURATION.as_secs())
+                })?;
+            }
+            "--profile" => profile = true,
+            "--help" | "-h" => return Err(String::new()),
+            flag if flag.starts_with("--") => return Err(format!("unknown option {flag}")),
+            _ if port_path.is_some() => return Err(format!("unexpected argument `{arg}`")),
+            _ => port_path = Some(arg),
+        }
+    }
+
+    if operator.is_empty() || operator.contains(char::is_whitespace) {
+        return Err(format!("--operator must be a single word, not `{operator}`"));
+    }
+    if attach && port_path.is_some() {
+        return Err(String::from("--attach uses the daemon's port, so don't give a device path"));
+    }
+    if sim.is_some() && (attach || port_path.is_some()) {
+        return Err(String::from("--sim stands in for the port, so don't give a device path or --attach"));
+    }
+    if sim.is_some() && (shm.is_some() || profile || capture.is_some() || lock) {
+        return Err(String::from("--shm, --profile, --capture and --lock need a real port, not --sim"));
+    }
+    if !attach && sim.is_none() && port_path.is_none() {
+        return Err(String::from("supply path argument. Example: /dev/ttyACM0"));
+    }
+    if daemon && attach {
+        return Err(String::from("--daemon and --attach can't be used together"));
+    }
+    if !cfg!(unix) && (daemon || attach || shm.is_some() || lock) {
+        return Err(format!("--daemon, --attach, --shm and --lock need Unix, not {}", std::env::consts::OS));
+    }
+    if attach && shm.is_some() {
+        return Err(String::from("--shm needs the port; give it to the daemon instead"));
+    }
+    if attach && capture.is_some() {
+        return Err(String::from("--capture needs the port; give it to the daemon instead"));
+    }
+    if attach && lock {
+        return Err(String::from("--lock needs the port; give it to the daemon instead"));
+    }
+    let bridge = zmq_pub.is_some() || zmq_pull.is_some();
+    let exciting = !excite.is_empty();
+    let sweeping = sweep.is_some();
+    let replaying = replay.is_some();
+    if [soak, stdin, monitor, exciting, sweeping, replaying, bridge, daemon].iter().filter(|m| **m).count() > 1 {
+        return Err(String::from(
+            "only one of soak, --stdin, --monitor, --excite, --sweep, --replay, --daemon and the --zmq-* options can be used",
+        ));
+    }
+    if bridge && !cfg!(feature = "bridge") {
+        return Err(String::from("the --zmq-* options need the bridge feature, which this build was made without"));
+    }
+    if !(soak || stdin || monitor || exciting || sweeping || replaying || bridge || daemon) && !cfg!(feature = "tui") {
+        return Err(String::from(
+            "this build has no terminal UI (the tui feature); use soak, --stdin, --monitor, --excite, --sweep, --replay or --daemon",
+        ));
+    }
+    if advertise.is_some() && !cfg!(feature = "mdns") {
+        return Err(String::from("--advertise needs the mdns feature, which this build was made without"));
+    }
+    if advertise.is_some() && !bridge {
+        return Err(String::from("--advertise only applies to the --zmq-* options"));
+    }
+    if let Some(name) = &advertise
+        && (name.is_empty() || name.len() > 63 || name.contains('.'))
+    {
+        return Err(format!("--advertise: `{name}` must be 1 to 63 bytes with no dots"));
+    }
+    if output.is_some() && !(stdin || monitor || exciting || replaying || bridge) {
+        return Err(String::from("--output only applies to --stdin, --monitor, --excite, --replay and the --zmq-* options"));
+    }
+    if duration.is_some() && !(exciting || soak) {
+        return Err(String::from("--duration only applies to --excite and soak"));
+    }
+    if trip_log.is_some() && (soak || stdin || monitor || exciting || sweeping || replaying || bridge || daemon) {
+        return Err(String::from("--trip-log only applies to the terminal UI"));
+    }
+    if journal.is_some() && (soak || stdin || monitor || exciting || sweeping || replaying || bridge || daemon) {
+        return Err(String::from("--journal only applies to the terminal UI"));
+    }
+    if profile && attach {
+        return Err(String::from("--profile needs the port; frames written by a daemon can't be timed"));
+    }
+    if profile && (soak || stdin || monitor || exciting || sweeping || replaying || bridge || daemon) {
+        return Err(String::from("--profile only applies to the terminal UI"));
+    }
+    Ok(Args {
+        port_path,
+        sim,
+        config_path,
+        sets,
+        operator,
+        audit,
+        trip_log,
+        journal,
+        soak,
+        stdin,
+        monitor,
+        excite,
+        duration,
+        sweep,
+        replay,
+        daemon,
+        socket,
+        zmq_pub,
+        zmq_pull,
+        advertise,
+        shm,
+        capture,
+        lock,
+        output: output.unwrap_or(Format::Text),
+        timeout,
+        profile,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Result<Args, String> {
+        parse(line.split_whitespace().map(str::to_string))
+    }
+
+    #[test]
+    fn timeouts() {
+        assert_eq!(args("--timeout 2.5 /dev/ttyACM0").map(|a| a.timeout), Ok(Duration::from_millis(2500)));
+        for secs in ["1e300", "inf", "NaN", "0", "-1", "90000", "soon"] {
+            assert!(args(&format!("--timeout {secs} /dev/ttyACM0")).is_err(), "--timeout {secs} parsed");
+        }
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(args("soak --duration 60 /dev/ttyACM0").map(|a| a.duration), Ok(Some(Duration::from_secs(60))));
+        for secs in ["1e300", "inf", "0", "-5", "86401"] {
+            assert!(args(&format!("soak --duration {secs} /dev/ttyACM0")).is_err(), "--duration {secs} parsed");
+        }
+    }
+}
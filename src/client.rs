@@ -0,0 +1,285 @@
+//! A handle for driving the actuators from other programs, without the
+//! terminal UI.
+//!
+//! ```no_run
+//! # async fn demo() -> Result<(), String> {
+//! use actuator_controller::{
+//!     client::{ActuatorClient, Confirmation},
+//!     commands::{Actuator, Direction},
+//!     config::Config,
+//! };
+//!
+//! let mut client = ActuatorClient::connect("/dev/ttyACM0", &Config::load_default()?)
+//!     .map_err(|e| e.to_string())?;
+//! client.set_speed(Actuator::M1, Direction::Forward, 20000).await.into_result()?;
+//! match client.goto(Actuator::M2, 0.10).await {
+//!     Confirmation::Acked(_) => println!("M2 is there"),
+//!     Confirmation::Nacked(why) => eprintln!("M2 didn't move: {why}"),
+//!     Confirmation::TimedOut(after) => eprintln!("M2 still wasn't there after {after:?}"),
+//! }
+//! while let Some(report) = client.telemetry().await {
+//!     println!("{:?} is at {} m", report.actuator, report.length());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::time::{Duration, Instant};
+
+use tokio::{sync::broadcast::error::TryRecvError, time::sleep_until};
+
+use crate::{
+    app::App,
+    cadence::Cadence,
+    commands::{Actuator, Direction},
+    config::Config,
+    dsl::Command,
+    hooks::Hook,
+    link::{self, Link, Outcome},
+    position::Settle,
+    status::StatusEvent,
+    stream::Subscription,
+    telemetry::Report,
+    update::Msg,
+};
+
+/// Something that happened while a command was running.
+pub enum Update<'a> {
+    Telemetry(&'a Report),
+    Status(&'a StatusEvent),
+    /// the link skipped or threw away frames, with its running totals
+    Queue { depth: u64, coalesced: u64, dropped: u64 },
+    /// a move to a target settled
+    Settle(&'a Settle),
+}
+
+/// What became of a command, once every frame it sent is accounted for by
+/// its sequence number (see [`Link::outcomes`]) and any move it started is over.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Confirmation {
+    /// every frame was written, or replaced by a newer one before it could
+    /// be, with what the command said it did
+    Acked(String),
+    /// refused before anything was sent, or a frame failed to write or was
+    /// thrown away, with why
+    Nacked(String),
+    /// still going when the client's timeout ran out, so it was stopped
+    TimedOut(Duration),
+}
+
+impl Confirmation {
+    pub fn is_acked(&self) -> bool {
+        matches!(self, Confirmation::Acked(_))
+    }
+
+    /// `Ok` with what the command did if it was acked, otherwise `Err` with why not.
+    pub fn into_result(self) -> Result<String, String> {
+        match self {
+            Confirmation::Acked(msg) => Ok(msg),
+            Confirmation::Nacked(why) => Err(why),
+            Confirmation::TimedOut(after) => Err(format!("timed out after {after:?}")),
+        }
+    }
+}
+
+pub struct ActuatorClient {
+    link: Link,
+    app: App,
+    timeout: Duration,
+}
+
+impl ActuatorClient {
+    pub fn connect(port_path: &str, config: &Config) -> Result<ActuatorClient, tokio_serial::Error> {
+        let options = link::Options {
+            safety: config.safety(),
+            encoding: config.encoding,
+            ..Default::default()
+        };
+        let link = link::open_with(port_path, options)?;
+        link.derive(config.channels.clone());
+        Ok(ActuatorClient::new(link, App::new(config)))
+    }
+
+    pub fn new(link: Link, mut app: App) -> ActuatorClient {
+        app.arming.connected = link.arming.is_some();
+        ActuatorClient {
+            link,
+            app,
+            timeout: Duration::from_secs(60),
+        }
+    }
+
+    /// How long a command may take before it's stopped and reported as failed.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Waits for everything queued to be written and lets go of the port (or
+    /// detaches from the daemon).
+    pub async fn close(self) {
+        self.link.close().await;
+    }
+
+    /// Reads a command in either form, with the config's groups and aliases.
+    pub fn parse(&self, line: &str) -> Result<Command, String> {
+        self.app.vocabulary.parse(line)
+    }
+
+    /// How closely the closed loop is keeping to its rate, see [`crate::cadence`].
+    pub fn cadence(&self) -> &Cadence {
+        &self.app.cadence
+    }
+
+    /// Last reported length of `actuator` (m).
+    pub fn length(&self, actuator: Actuator) -> Option<f64> {
+        self.app.actuator_len_meters[actuator as usize]
+    }
+
+    /// Drives `actuator` open loop, resolving once the speed has gone out.
+    pub async fn set_speed(&mut self, actuator: Actuator, direction: Direction, speed: u16) -> Confirmation {
+        self.confirm(Command::Drive { actuator, direction, speed, duration: None }).await
+    }
+
+    /// Moves `actuator` to `len` meters, resolving once it gets there.
+    pub async fn goto(&mut self, actuator: Actuator, len: f64) -> Confirmation {
+        self.confirm(Command::Goto { actuator, len }).await
+    }
+
+    /// Stops one actuator, or both with `None`.
+    pub async fn stop(&mut self, actuator: Option<Actuator>) -> Confirmation {
+        self.confirm(Command::Stop(actuator)).await
+    }
+
+    /// Waits for the next report. `None` once the port is gone.
+    pub async fn telemetry(&mut self) -> Option<Report> {
+        let report = self.link.telemetry.recv().await?;
+        self.app.update(Msg::Telemetry(report.clone()));
+        Some(report)
+    }
+
+    /// A hook that fired on telemetry read between commands, for the caller
+    /// to run; during a command they run along with it.
+    pub fn fired(&mut self) -> Option<Hook> {
+        self.app.hooks.take()
+    }
+
+    /// Reports for another task, e.g. a logger, alongside `telemetry` and
+    /// the commands run here, which keep getting every report regardless.
+    pub fn subscribe(&self) -> Subscription {
+        self.link.subscribe()
+    }
+
+    pub async fn run(&mut self, cmd: Command) -> Result<String, String> {
+        self.run_observed(cmd, |_| {}).await
+    }
+
+    /// [`ActuatorClient::confirm_observed`], as a `Result`.
+    pub async fn run_observed(&mut self, cmd: Command, observe: impl FnMut(Update)) -> Result<String, String> {
+        self.confirm_observed(cmd, observe).await.into_result()
+    }
+
+    pub async fn confirm(&mut self, cmd: Command) -> Confirmation {
+        self.confirm_observed(cmd, |_| {}).await
+    }
+
+    /// Runs `cmd` and waits until every frame it produced has been written to
+    /// the port and any move it started has finished, passing telemetry and
+    /// status to `observe` along the way.
+    pub async fn confirm_observed(&mut self, cmd: Command, mut observe: impl FnMut(Update)) -> Confirmation {
+        let link = &mut self.link;
+        let app = &mut self.app;
+        // subscribed first, so no outcome numbered after `before` is missed
+        let mut outcomes = link.outcomes();
+        let before = *link.acks.borrow();
+        let sent = app.sent;
+        let mut seen = before;
+        // the highest sequence number accounted for, and how many of ours were lost
+        let mut settled = before.total();
+        let mut lost = 0;
+        // pick up the daemon's arming policy and latch before deciding how to run `cmd`
+        if let Some(channel) = &mut link.arming {
+            app.sync_arming(channel);
+        }
+        if let Some(channel) = &mut link.estop {
+            app.sync_estop(channel);
+        }
+        let mut msg = match app.run(cmd) {
+            Ok(msg) => msg,
+            Err(why) => return Confirmation::Nacked(why),
+        };
+        let deadline = Instant::now() + self.timeout;
+        // the loop only runs while a command does
+        app.cadence.pause();
+
+        loop {
+            while let Ok(status) = link.status.try_recv() {
+                observe(Update::Status(&status));
+                app.update(Msg::Status(status));
+            }
+            while let Some(report) = link.telemetry.try_recv() {
+                observe(Update::Telemetry(&report));
+                app.update(Msg::Telemetry(report));
+            }
+            if let Some(channel) = &mut link.estop {
+                app.sync_estop(channel);
+            }
+            if let Some(channel) = &mut link.arming
+                && let Some(outcome) = app.sync_arming(channel)
+            {
+                msg = match outcome {
+                    Ok(msg) => msg,
+                    Err(why) => return Confirmation::Nacked(why),
+                };
+            }
+            let now = Instant::now();
+            let ticking = app.cadence.is_due(now);
+            if ticking {
+                app.cadence.start(now);
+                app.update(Msg::Tick(now));
+            }
+            for settle in app.settles.drain(..) {
+                observe(Update::Settle(&settle));
+            }
+            app.flush(&link.commands).await;
+            if ticking {
+                app.cadence.finish(Instant::now());
+            }
+
+            let acks = *link.acks.borrow();
+            if (acks.coalesced, acks.dropped) != (seen.coalesced, seen.dropped) {
+                let depth = app.sent.saturating_sub(acks.total());
+                observe(Update::Queue { depth, coalesced: acks.coalesced, dropped: acks.dropped });
+            }
+            seen = acks;
+            loop {
+                match outcomes.try_recv() {
+                    Ok((seq, outcome)) if seq > before.total() => {
+                        settled = settled.max(seq);
+                        lost += matches!(outcome, Outcome::Failed | Outcome::Dropped) as u64;
+                    }
+                    Ok(_) => {}
+                    Err(TryRecvError::Lagged(missed)) => {
+                        app.abort();
+                        app.flush(&link.commands).await;
+                        return Confirmation::Nacked(format!("lost track of {missed} frame(s)"));
+                    }
+                    Err(TryRecvError::Empty | TryRecvError::Closed) => break,
+                }
+            }
+            if lost > 0 {
+                app.abort();
+                app.flush(&link.commands).await;
+                return Confirmation::Nacked(format!("{lost} frame(s) failed to write to the port"));
+            }
+            if app.is_idle() && settled >= before.total() + (app.sent - sent) {
+                return Confirmation::Acked(msg);
+            }
+            if Instant::now() >= deadline {
+                app.abort();
+                app.flush(&link.commands).await;
+                return Confirmation::TimedOut(self.timeout);
+            }
+            sleep_until(app.cadence.due(Instant::now()).into()).await;
+        }
+    }
+}
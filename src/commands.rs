@@ -1,3 +1,5 @@
+use std::{fmt, time::Duration};
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 #[repr(u8)]
 pub enum Direction {
@@ -5,6 +7,26 @@ pub enum Direction {
     Backward = 1,
 }
 
+impl Direction {
+    pub fn reversed(self) -> Direction {
+        match self {
+            Direction::Forward => Direction::Backward,
+            Direction::Backward => Direction::Forward,
+        }
+    }
+
+    /// The direction a signed speed runs in; zero counts as forward.
+    pub fn of(speed: i32) -> Direction {
+        if speed < 0 { Direction::Backward } else { Direction::Forward }
+    }
+
+    /// `speed` with this direction's sign.
+    pub fn signed(self, speed: u32) -> i32 {
+        let speed = speed.min(i32::MAX as u32) as i32;
+        if self == Direction::Backward { -speed } else { speed }
+    }
+}
+
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 #[repr(u8)]
@@ -16,25 +38,145 @@ pub enum Actuator {
     M2 = 1
 }
 
+impl TryFrom<u8> for Actuator {
+    type Error = &'static str;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Actuator::M1),
+            1 => Ok(Actuator::M2),
+            _ => Err("Unknown actuator specifier (not m1 or m2)"),
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum ActuatorCommand {
     SetSpeed(u16, Actuator),
     SetDirection(Direction, Actuator),
+    /// Hold the current length against load, on firmware that can, until
+    /// the next `SetSpeed` for that actuator.
+    HoldPosition(Actuator),
+}
+
+impl fmt::Display for ActuatorCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ActuatorCommand::SetSpeed(speed, actuator) => write!(f, "{actuator:?} speed {speed}"),
+            ActuatorCommand::SetDirection(dir, actuator) => write!(f, "{actuator:?} direction {dir:?}"),
+            ActuatorCommand::HoldPosition(actuator) => write!(f, "{actuator:?} hold"),
+        }
+    }
+}
+
+/// What the controller asks of an actuator: a speed whose sign is the
+/// direction, negative being backward. Everything above the wire works in
+/// these; [`Encoder`] turns them into the frames the firmware understands.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Drive {
+    pub actuator: Actuator,
+    pub speed: i32,
+    /// for a timed move, how long until the link stops it of its own accord
+    pub stop_after: Option<Duration>,
+}
+
+impl Drive {
+    pub fn new(actuator: Actuator, speed: i32) -> Drive {
+        Drive { actuator, speed, stop_after: None }
+    }
+
+    pub fn stop(actuator: Actuator) -> Drive {
+        Drive { actuator, speed: 0, stop_after: None }
+    }
+
+    /// This drive, stopped by the link after `duration` even if nothing
+    /// else stops it first.
+    pub fn lasting(self, duration: Duration) -> Drive {
+        Drive { stop_after: Some(duration), ..self }
+    }
+
+    pub fn direction(self) -> Direction {
+        Direction::of(self.speed)
+    }
+
+    /// The unsigned speed the firmware is sent.
+    pub fn magnitude(self) -> u16 {
+        self.speed.unsigned_abs().min(u16::MAX as u32) as u16
+    }
+}
+
+impl fmt::Display for Drive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} speed {}", self.actuator, self.speed)
+    }
+}
+
+/// Turns [`Drive`]s into `SetDirection`/`SetSpeed` frames, the only place
+/// the two are ever put together. A direction goes out only when it changes
+/// or the actuator is starting from a stop, and a reversal while moving is
+/// always preceded by a stop.
+#[derive(Clone, Debug, Default)]
+pub struct Encoder {
+    /// what each actuator was last sent, `None` until something has been
+    direction: [Option<Direction>; 2],
+    speed: [Option<u16>; 2],
+}
+
+impl Encoder {
+    pub fn encode(&mut self, drive: Drive) -> Vec<ActuatorCommand> {
+        let i = drive.actuator as usize;
+        let speed = drive.magnitude();
+        let mut out = Vec::new();
+        if speed != 0 {
+            let direction = drive.direction();
+            let stopped = self.speed[i] == Some(0);
+            if self.direction[i] != Some(direction) || stopped {
+                if !stopped {
+                    out.push(ActuatorCommand::SetSpeed(0, drive.actuator));
+                    self.speed[i] = Some(0);
+                }
+                out.push(ActuatorCommand::SetDirection(direction, drive.actuator));
+                self.direction[i] = Some(direction);
+            }
+        }
+        // stops always go out, even if one was just sent
+        if speed == 0 || self.speed[i] != Some(speed) {
+            out.push(ActuatorCommand::SetSpeed(speed, drive.actuator));
+            self.speed[i] = Some(speed);
+        }
+        out
+    }
+
+    /// Forgets what `actuator` was sent, for when it may not have been
+    /// written, so the next drive sends the direction again.
+    pub fn forget(&mut self, actuator: Actuator) {
+        self.direction[actuator as usize] = None;
+        self.speed[actuator as usize] = None;
+    }
+
+    /// The frame that has `actuator` hold where it is. The firmware stops
+    /// moving it to do that, so the next drive starts from a stop.
+    pub fn hold(&mut self, actuator: Actuator) -> ActuatorCommand {
+        self.speed[actuator as usize] = Some(0);
+        ActuatorCommand::HoldPosition(actuator)
+    }
 }
 
 impl ActuatorCommand {
+    /// The actuator it's for.
+    pub fn actuator(&self) -> Actuator {
+        match *self {
+            ActuatorCommand::SetSpeed(_, actuator)
+            | ActuatorCommand::SetDirection(_, actuator)
+            | ActuatorCommand::HoldPosition(actuator) => actuator,
+        }
+    }
+
+    /// Mirror of the firmware's decoder, kept next to `serialize` so the two stay in sync.
     pub fn deserialize(bytes: [u8; 4]) -> Result<Self, &'static str> {
-        let actuator = {
-            if bytes[3] == Actuator::M1 as u8 {
-                Actuator::M1
-            } else if bytes[3] == Actuator::M2 as u8{
-                Actuator::M2
-            } else {
-                return Err("Unknown actuator specifier (not m1 or m2)");
-            }
-        };
+        let actuator = Actuator::try_from(bytes[3])?;
         match bytes[0] {
-            tag if tag == 0 => {
+            0 => {
                 let speed = u16::from_le_bytes(
                     bytes[1..=2]
                         .try_into()
@@ -42,7 +184,7 @@ impl ActuatorCommand {
                 );
                 Ok(ActuatorCommand::SetSpeed(speed, actuator))
             }
-            tag if tag == 1 => {
+            1 => {
                 let dir = match bytes[1] {
                     0 => Direction::Forward,
                     1 => Direction::Backward,
@@ -50,6 +192,7 @@ impl ActuatorCommand {
                 };
                 Ok(ActuatorCommand::SetDirection(dir, actuator))
             }
+            2 => Ok(ActuatorCommand::HoldPosition(actuator)),
             _ => Err("Invalid variant tag"),
         }
     }
@@ -71,6 +214,7 @@ impl ActuatorCommand {
                 bytes[3] = *actuator as u8;
                 bytes
             }
+            ActuatorCommand::HoldPosition(actuator) => [2, 0, 0, *actuator as u8],
         }
     }
 }
\ No newline at end of file
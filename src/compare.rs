@@ -0,0 +1,177 @@
+//! Regression runs: replaying the speeds of a recorded run and checking the
+//! lengths still come out the same, for `--replay`.
+//!
+//! The recording is an `--output json` log with `drive` events, as
+//! `--excite` writes, and the same log from the replay can be the next
+//! recording. Both runs are timed from the first drive, and each length is
+//! taken from where that run's actuator started, so a run that starts
+//! somewhere else along the stroke can still be compared.
+
+use std::time::Duration;
+
+use crate::{
+    commands::Actuator,
+    dsl::MAX_DURATION,
+    identify::{self, Trace},
+};
+
+/// How far a replay may stray from the recording, in meters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tolerance {
+    /// root mean square over the whole run
+    pub rms: f64,
+    /// at any one report
+    pub max: f64,
+}
+
+impl Default for Tolerance {
+    fn default() -> Tolerance {
+        Tolerance { rms: 0.005, max: 0.02 }
+    }
+}
+
+pub struct Recording {
+    traces: [Trace; 2],
+    /// log time of the first drive
+    start: f64,
+    /// log time of the last event
+    end: f64,
+}
+
+/// How one actuator's replay differed from the recording.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Deviation {
+    /// recorded reports compared
+    pub samples: usize,
+    pub rms: f64,
+    pub max: f64,
+    /// seconds into the run of the largest difference
+    pub at: f64,
+}
+
+impl Deviation {
+    pub fn within(&self, tolerance: Tolerance) -> bool {
+        self.rms <= tolerance.rms && self.max <= tolerance.max
+    }
+}
+
+impl Recording {
+    pub fn parse(src: &str) -> Result<Recording, String> {
+        let traces = identify::traces(src)?;
+        let start = traces.iter().flat_map(|trace| trace.drives.iter().map(|d| d.0)).fold(f64::INFINITY, f64::min);
+        let end = traces
+            .iter()
+            .flat_map(|trace| trace.drives.iter().map(|d| d.0).chain(trace.lengths.iter().map(|l| l.0)))
+            .fold(start, f64::max);
+        // every drive is then timed within `MAX_DURATION` of the first
+        if !Duration::try_from_secs_f64(end - start).is_ok_and(|d| d <= MAX_DURATION) {
+            return Err(format!("the log runs from t = {start} to {end}, more than {} h", MAX_DURATION.as_secs() / 3600));
+        }
+        Ok(Recording { traces, start, end })
+    }
+
+    /// Every speed sent, in order, timed from the first.
+    pub fn drives(&self) -> Vec<(Duration, Actuator, i32)> {
+        let mut drives: Vec<_> = [Actuator::M1, Actuator::M2]
+            .into_iter()
+            .flat_map(|actuator| {
+                let trace = &self.traces[actuator as usize];
+                trace.drives.iter().map(move |&(t, speed)| (Duration::from_secs_f64(t - self.start), actuator, speed))
+            })
+            .collect();
+        drives.sort_by_key(|(at, _, _)| *at);
+        drives
+    }
+
+    /// From the first drive to the last thing recorded.
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.end - self.start)
+    }
+
+    /// Whether `actuator` reported its length at any time after the first drive.
+    pub fn reported(&self, actuator: Actuator) -> bool {
+        self.traces[actuator as usize].lengths.iter().any(|&(t, _)| t >= self.start)
+    }
+
+    /// Compares the lengths of a replay, as (seconds since its first drive,
+    /// m) per actuator, with the recording's. `None` for an actuator with no
+    /// reports to compare in one run or the other.
+    pub fn compare(&self, replayed: &[Vec<(f64, f64)>; 2]) -> [Option<Deviation>; 2] {
+        [Actuator::M1, Actuator::M2].map(|actuator| {
+            let recorded: Vec<(f64, f64)> = self.traces[actuator as usize]
+                .lengths
+                .iter()
+                .map(|&(t, len)| (t - self.start, len))
+                .filter(|&(t, _)| t >= 0.0)
+                .collect();
+            let replayed = &replayed[actuator as usize];
+            let (&(_, from), &(_, replay_from)) = (recorded.first()?, replayed.first()?);
+            let mut sum = 0.0;
+            let mut deviation = Deviation { samples: 0, rms: 0.0, max: 0.0, at: 0.0 };
+            for &(t, len) in &recorded {
+                let Some(other) = interpolate(replayed, t) else {
+                    continue;
+                };
+                let error = ((other - replay_from) - (len - from)).abs();
+                sum += error * error;
+                deviation.samples += 1;
+                if error > deviation.max {
+                    deviation.max = error;
+                    deviation.at = t;
+                }
+            }
+            (deviation.samples > 0).then(|| Deviation { rms: (sum / deviation.samples as f64).sqrt(), ..deviation })
+        })
+    }
+}
+
+/// The length at `t` between the reports either side of it, `None` outside
+/// the run.
+fn interpolate(samples: &[(f64, f64)], t: f64) -> Option<f64> {
+    let after = samples.iter().position(|&(at, _)| at >= t)?;
+    let (t1, len1) = samples[after];
+    if after == 0 {
+        return (t1 == t).then_some(len1);
+    }
+    let (t0, len0) = samples[after - 1];
+    Some(len0 + (len1 - len0) * (t - t0) / (t1 - t0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drive(t: &str, speed: i32) -> String {
+        format!(r#"{{"t": {t}, "event": "drive", "actuator": "m1", "speed": {speed}}}"#)
+    }
+
+    fn telemetry(t: &str, len: f64) -> String {
+        format!(r#"{{"t": {t}, "event": "telemetry", "actuator": "m1", "len": {len}}}"#)
+    }
+
+    #[test]
+    fn recording() {
+        let log = [telemetry("0.5", 0.1), drive("1", 200), telemetry("1.5", 0.1), drive("3", 0), telemetry("4", 0.2)].join("\n");
+        let recording = Recording::parse(&log).unwrap();
+        assert_eq!(recording.duration(), Duration::from_secs(3));
+        assert_eq!(recording.drives(), [(Duration::ZERO, Actuator::M1, 200), (Duration::from_secs(2), Actuator::M1, 0)]);
+        assert!(recording.reported(Actuator::M1) && !recording.reported(Actuator::M2));
+    }
+
+    #[test]
+    fn malformed() {
+        for log in [
+            [drive("0", 100), telemetry("1e300", 0.1)].join("\n"),
+            [drive("0", 100), drive("1e400", 0)].join("\n"),
+            [drive("5", 100), drive("-1e300", 0)].join("\n"),
+            [drive("0", 100), telemetry(&(MAX_DURATION.as_secs() + 1).to_string(), 0.1)].join("\n"),
+            telemetry("0", 0.1),
+            String::from("{"),
+        ] {
+            assert!(Recording::parse(&log).is_err(), "{log:?} parsed");
+        }
+        let unordered = [drive("5", 100), drive("2", 0)].join("\n");
+        let recording = Recording::parse(&unordered).unwrap();
+        assert_eq!(recording.drives()[0], (Duration::ZERO, Actuator::M1, 0));
+    }
+}
@@ -0,0 +1,1099 @@
+//! Loading of the optional `actuator.toml` config file.
+//!
+//! Only the small subset of TOML this tool needs is understood: `[section]`
+//! headers, `key = value` pairs, `#` comments, and values that are numbers,
+//! booleans, quoted strings or flat arrays of those.
+//!
+//! A top-level `version` says which layout the file was written for. Older
+//! files are upgraded as they're read, and `config migrate` writes the
+//! upgrade back; `config schema` describes every key as JSON Schema.
+//!
+//! Any key can be overridden without touching the file: by an environment
+//! variable named after it (`ACTUATOR_POSITION_GAIN` for `position.gain`),
+//! and then by `--set position.gain=300000`.
+
+use std::{cell::RefCell, collections::BTreeMap, env, fs, io, path::{Path, PathBuf}, time::Duration};
+
+use crate::{
+    arming::ArmingConfig,
+    json,
+    bode::SweepConfig,
+    channels::{Channel, Channels},
+    checklist::{self, Checklist, Item},
+    health::SoakConfig,
+    hooks::{Hook, Hooks},
+    compare::Tolerance,
+    constraints::{Constraints, Rule},
+    countdown::Countdown,
+    dsl,
+    aliases::{Alias, Aliases},
+    groups::{Group, Groups},
+    input::{InputConfig, Policy},
+    interlock,
+    keymap::{Key, Keymap, Steps},
+    kinematics::{Joint, Linkage},
+    limits::SpeedLimits,
+    link,
+    load::LoadCell,
+    position::Gains,
+    roles::{Role, Roles},
+    safety::{self, Policies},
+    sequence::{DigCycle, Effort},
+    sim::Injection,
+    sensor::SensorConfig,
+    softstart::{self, SoftStart},
+    standstill::StandstillConfig,
+    skew::SkewMonitor,
+    telemetry::{self, Encoding, Rates},
+    throttle::RateLimits,
+    trip::TripConfig,
+};
+
+/// Path the config is read from when `--config` isn't given.
+pub const DEFAULT_PATH: &str = "actuator.toml";
+
+/// Prefix of the environment variables that override keys.
+pub const ENV_PREFIX: &str = "ACTUATOR_";
+
+/// The layout this build reads and writes. Files without a `version` are 0.
+pub const VERSION: u16 = 1;
+
+/// `MIGRATIONS[n]` takes a file from version `n` to `n + 1`; the version line
+/// itself is updated afterwards.
+const MIGRATIONS: &[fn(&str) -> String] = &[
+    // version 0 is every file from before versions existed, which is what 1 reads
+    str::to_string,
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+}
+
+/// Every key of a config file, stored under its full dotted path
+/// (`linkage.arm_length`).
+#[derive(Clone, Debug, Default)]
+pub struct Table {
+    entries: BTreeMap<String, Value>,
+    /// every key looked up so far, with its kind and default, for the schema
+    read: RefCell<Vec<(String, Kind, Value)>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Kind {
+    Number,
+    Integer,
+    Bool,
+    String,
+}
+
+impl Table {
+    pub fn parse(src: &str) -> Result<Table, String> {
+        let mut table = Table::default();
+        let mut section = String::new();
+
+        for (i, raw) in src.lines().enumerate() {
+            let line_no = i + 1;
+            let line = strip_comment(raw).trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(rest) = line.strip_prefix('[') {
+                let Some(name) = rest.strip_suffix(']') else {
+                    return Err(format!("line {line_no}: unterminated section header"));
+                };
+                section = name.trim().to_string();
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(format!("line {line_no}: expected `key = value`"));
+            };
+            let key = key.trim();
+            if key.is_empty() {
+                return Err(format!("line {line_no}: missing key"));
+            }
+            let value = parse_value(value.trim()).map_err(|e| format!("line {line_no}: {e}"))?;
+            let path = if section.is_empty() {
+                key.to_string()
+            } else {
+                format!("{section}.{key}")
+            };
+            if table.entries.insert(path.clone(), value).is_some() {
+                return Err(format!("line {line_no}: duplicate key `{path}`"));
+            }
+        }
+
+        Ok(table)
+    }
+
+    /// Sets `key` from text written as in the file, e.g. from `--set`. Text
+    /// that isn't a number, boolean, quoted string or array is taken as a
+    /// string, so `keys.m1.select=F3` needs no quotes.
+    pub fn set(&mut self, key: &str, raw: &str) -> Result<(), String> {
+        if !Config::keys().iter().any(|k| k == key) {
+            return Err(format!("unknown config key `{key}`"));
+        }
+        let raw = raw.trim();
+        let value = parse_value(raw).unwrap_or_else(|_| Value::String(raw.to_string()));
+        self.entries.insert(key.to_string(), value);
+        Ok(())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.entries.get(key)
+    }
+
+    /// Every key in the table, and every other key read so far at its
+    /// default, sorted.
+    fn resolved(&self) -> BTreeMap<String, Value> {
+        let mut resolved = self.entries.clone();
+        for (key, _, default) in self.read.borrow().iter() {
+            resolved.entry(key.clone()).or_insert_with(|| default.clone());
+        }
+        resolved
+    }
+
+    fn note(&self, key: &str, kind: Kind, default: Value) {
+        self.read.borrow_mut().push((key.to_string(), kind, default));
+    }
+
+    pub fn f64(&self, key: &str, default: f64) -> Result<f64, String> {
+        self.note(key, Kind::Number, Value::Number(default));
+        match self.get(key) {
+            None => Ok(default),
+            Some(Value::Number(n)) => Ok(*n),
+            Some(_) => Err(format!("`{key}` must be a number")),
+        }
+    }
+
+    pub fn bool(&self, key: &str, default: bool) -> Result<bool, String> {
+        self.note(key, Kind::Bool, Value::Bool(default));
+        match self.get(key) {
+            None => Ok(default),
+            Some(Value::Bool(b)) => Ok(*b),
+            Some(_) => Err(format!("`{key}` must be true or false")),
+        }
+    }
+
+    pub fn str<'a>(&'a self, key: &str, default: &'a str) -> Result<&'a str, String> {
+        self.note(key, Kind::String, Value::String(default.to_string()));
+        match self.get(key) {
+            None => Ok(default),
+            Some(Value::String(s)) => Ok(s),
+            Some(_) => Err(format!("`{key}` must be a quoted string")),
+        }
+    }
+
+    pub fn u16(&self, key: &str, default: u16) -> Result<u16, String> {
+        self.note(key, Kind::Integer, Value::Number(default as f64));
+        match self.get(key) {
+            None => Ok(default),
+            Some(Value::Number(n)) if n.fract() == 0.0 && (0.0..=u16::MAX as f64).contains(n) => Ok(*n as u16),
+            Some(_) => Err(format!("`{key}` must be a whole number between 0 and {}", u16::MAX)),
+        }
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn parse_value(src: &str) -> Result<Value, String> {
+    if let Some(rest) = src.strip_prefix('"') {
+        let Some(s) = rest.strip_suffix('"') else {
+            return Err("unterminated string".into());
+        };
+        return Ok(Value::String(s.to_string()));
+    }
+    if let Some(rest) = src.strip_prefix('[') {
+        let Some(inner) = rest.strip_suffix(']') else {
+            return Err("unterminated array".into());
+        };
+        return inner
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(parse_value)
+            .collect::<Result<Vec<_>, _>>()
+            .map(Value::Array);
+    }
+    match src {
+        "true" => Ok(Value::Bool(true)),
+        "false" => Ok(Value::Bool(false)),
+        _ => src
+            .replace('_', "")
+            .parse::<f64>()
+            .map(Value::Number)
+            .map_err(|_| format!("can't parse value `{src}`")),
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub linkage: Linkage,
+    pub position: Gains,
+    pub dig: DigCycle,
+    /// speed multiplier per actuator in mirror mode
+    pub trim: [f64; 2],
+    pub skew: SkewMonitor,
+    pub sensor: SensorConfig,
+    /// the check that both actuators are stopped before input is taken
+    pub standstill: StandstillConfig,
+    pub load: LoadCell,
+    pub arming: ArmingConfig,
+    pub keys: Keymap,
+    /// how long an actuator is held stopped before it reverses
+    pub reverse_dwell: Duration,
+    /// fastest each actuator may extend and retract, whoever's driving it
+    pub speed_limits: SpeedLimits,
+    /// how gently an actuator is started from rest, whoever's driving it
+    pub soft_start: Option<SoftStart>,
+    /// most current (A) the actuators may draw together, whoever's driving
+    pub power_budget: Option<f64>,
+    /// rules tying the lengths together, whoever's driving
+    pub constraints: Constraints,
+    /// how long frames may fail while moving before the link is reopened
+    pub watchdog: Option<Duration>,
+    /// the safety profile in use, and which policies it leaves on
+    pub safety_profile: String,
+    pub safety_policies: Policies,
+    /// how often length reports are shown and published
+    pub telemetry: Rates,
+    /// how the firmware sends each value of a report
+    pub encoding: Encoding,
+    /// fields worked out from the others, see [`crate::channels`]
+    pub channels: Channels,
+    /// how often commands may go out, per actuator and per bridge client
+    pub throttle: RateLimits,
+    pub trip: TripConfig,
+    pub effort: Effort,
+    pub sweep: SweepConfig,
+    /// how far `--replay` may stray from the recording
+    pub replay: Tolerance,
+    pub countdown: Countdown,
+    /// who may do what over the ZeroMQ bridge
+    pub roles: Roles,
+    /// where the `f` picker looks for trajectories
+    pub trajectory_dirs: Vec<String>,
+    /// named sets of actuators commands can target, see [`crate::groups`]
+    pub groups: Groups,
+    /// short names for commands, see [`crate::aliases`]
+    pub aliases: Aliases,
+    /// commands run in order once connected, see [`crate::app::App::startup`]
+    pub startup: Vec<dsl::Command>,
+    /// commands run when a reading crosses a threshold, see [`crate::hooks`]
+    pub hooks: Hooks,
+    /// what `soak` cycles through and when it halts, see [`crate::health`]
+    pub soak: SoakConfig,
+    /// what to see to before full speed, see [`crate::app::App::checklist`]
+    pub checklist: Checklist,
+    /// faults `--sim` injects, and how long after it starts
+    pub sim_faults: Vec<(Duration, Injection)>,
+    /// where the terminal UI takes inputs from, and which wins
+    pub input: InputConfig,
+    /// every key as it resolved, defaults included, for [`Config::snapshot`]
+    pub resolved: BTreeMap<String, Value>,
+}
+
+impl Config {
+    /// Reads the config at `path`, upgrading it from an older version if
+    /// need be, with any `ACTUATOR_*` environment variables on top. A missing
+    /// file at the default path just means "use the defaults"; anything else
+    /// is an error.
+    pub fn load(path: &Path) -> Result<Config, String> {
+        Config::load_with(path, &[])
+    }
+
+    /// `load`, then each `(key, value)` of `sets` on top of that, as from `--set`.
+    pub fn load_with(path: &Path, sets: &[(String, String)]) -> Result<Config, String> {
+        let mut table = match fs::read_to_string(path) {
+            Ok(src) => {
+                let (_, src) = migrate(&src).map_err(|e| format!("{}: {e}", path.display()))?;
+                Table::parse(&src).map_err(|e| format!("{}: {e}", path.display()))?
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound && path == Path::new(DEFAULT_PATH) => Table::default(),
+            Err(e) => return Err(format!("{}: {e}", path.display())),
+        };
+        for key in Config::keys() {
+            let name = env_name(&key);
+            if let Ok(raw) = env::var(&name) {
+                table.set(&key, &raw).map_err(|e| format!("{name}: {e}"))?;
+            }
+        }
+        for (key, raw) in sets {
+            table.set(key, raw).map_err(|e| format!("--set {key}: {e}"))?;
+        }
+        Config::from_table(&table).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    /// Every key the config reads, dotted (`keys.m1.step`).
+    pub fn keys() -> Vec<String> {
+        // every key is optional, so reading an empty table looks each one up
+        let table = Table::default();
+        let _ = Config::from_table(&table);
+        let mut keys: Vec<String> = Vec::new();
+        for (key, _, _) in table.read.into_inner() {
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+        keys
+    }
+
+    /// The groups and aliases, for reading commands.
+    pub fn vocabulary(&self) -> dsl::Vocabulary {
+        dsl::Vocabulary { groups: self.groups.clone(), aliases: self.aliases.clone() }
+    }
+
+    /// JSON Schema for the config file, with every key's type and default.
+    pub fn schema() -> json::Value {
+        // every key is optional, so reading an empty table looks each one up
+        let table = Table::default();
+        let _ = Config::from_table(&table);
+        let text = |s: &str| json::Value::String(s.to_string());
+        let object = |properties: Vec<(String, json::Value)>| {
+            json::Value::Object(vec![
+                (String::from("type"), text("object")),
+                (String::from("properties"), json::Value::Object(properties)),
+                (String::from("additionalProperties"), json::Value::Bool(false)),
+            ])
+        };
+
+        let mut root: Vec<(String, json::Value)> = vec![(
+            String::from("version"),
+            json::Value::Object(vec![
+                (String::from("type"), text("integer")),
+                (String::from("minimum"), json::Value::Number(0.0)),
+                (String::from("maximum"), json::Value::Number(VERSION as f64)),
+                (String::from("default"), json::Value::Number(VERSION as f64)),
+            ]),
+        )];
+        for (key, kind, default) in table.read.into_inner() {
+            let mut property = vec![(
+                String::from("type"),
+                text(match kind {
+                    Kind::Number => "number",
+                    Kind::Integer => "integer",
+                    Kind::Bool => "boolean",
+                    Kind::String => "string",
+                }),
+            )];
+            if kind == Kind::Integer {
+                property.push((String::from("minimum"), json::Value::Number(0.0)));
+                property.push((String::from("maximum"), json::Value::Number(u16::MAX as f64)));
+            }
+            property.push((String::from("default"), match default {
+                Value::Number(n) => json::Value::Number(n),
+                Value::Bool(b) => json::Value::Bool(b),
+                Value::String(s) => json::Value::String(s),
+                Value::Array(_) => json::Value::Null,
+            }));
+            insert(&mut root, &key.split('.').collect::<Vec<_>>(), json::Value::Object(property), &object);
+        }
+
+        let mut schema = vec![
+            (String::from("$schema"), text("https://json-schema.org/draft/2020-12/schema")),
+            (String::from("title"), text("actuator_controller config")),
+        ];
+        let json::Value::Object(body) = object(root) else { unreachable!() };
+        schema.extend(body);
+        json::Value::Object(schema)
+    }
+
+    /// `load` from [`DEFAULT_PATH`].
+    pub fn load_default() -> Result<Config, String> {
+        Config::load(Path::new(DEFAULT_PATH))
+    }
+
+    /// Reads and checks every key, reporting all the problems at once rather
+    /// than just the first.
+    pub fn from_table(table: &Table) -> Result<Config, String> {
+        let problems = Problems::default();
+        // divided by, so zero would show up as infinite percentages and speeds
+        let positive = |key: &str, default: f64| -> f64 {
+            let value = problems.or(table.f64(key, default), default);
+            problems.check(value > 0.0 && value.is_finite(), || format!("{key} must be a positive number, not {value}"));
+            value
+        };
+        // a value of the wrong type is noted and the default used in its place
+        let number = |key: &str, default: f64| problems.or(table.f64(key, default), default);
+        let whole = |key: &str, default: u16| problems.or(table.u16(key, default), default);
+        let flag = |key: &str, default: bool| problems.or(table.bool(key, default), default);
+        // capped like the durations commands give, so a typo'd exponent is a
+        // problem to report rather than a panic
+        let seconds = |key: &str, default: Duration, zero_ok: bool| -> Duration {
+            let secs = number(key, default.as_secs_f64());
+            let ok = secs.is_finite() && if zero_ok { secs >= 0.0 } else { secs > 0.0 };
+            let what = if zero_ok { "a number of seconds" } else { "a positive number of seconds" };
+            problems.check(ok, || format!("{key} must be {what}, not {secs}"));
+            let duration = Duration::try_from_secs_f64(secs).ok().filter(|d| *d <= dsl::MAX_DURATION);
+            problems.check(!ok || duration.is_some(), || {
+                format!("{key} must be at most {} seconds, not {secs}", dsl::MAX_DURATION.as_secs())
+            });
+            duration.filter(|_| ok).unwrap_or(default)
+        };
+
+        // once per `MAX_DURATION` at the least, so a period is a duration too
+        let slowest = 1.0 / dsl::MAX_DURATION.as_secs_f64();
+
+        let d = Linkage::default();
+        let joint = |prefix: &str, d: Joint| -> Joint {
+            Joint {
+                base: positive(&format!("linkage.{prefix}_base"), d.base),
+                rod: positive(&format!("linkage.{prefix}_rod"), d.rod),
+                retracted: positive(&format!("linkage.{prefix}_retracted"), d.retracted),
+                max_stroke: positive(&format!("linkage.{prefix}_stroke"), d.max_stroke),
+                lead: positive(&format!("linkage.{prefix}_lead"), d.lead),
+                gear_ratio: positive(&format!("linkage.{prefix}_gear_ratio"), d.gear_ratio),
+                offset: number(&format!("linkage.{prefix}_offset_deg"), d.offset.to_degrees()).to_radians(),
+            }
+        };
+        let linkage = Linkage {
+            lift: joint("lift", d.lift),
+            bucket: joint("bucket", d.bucket),
+            arm_length: positive("linkage.arm_length", d.arm_length),
+            pivot_height: number("linkage.pivot_height", d.pivot_height),
+        };
+        let strokes = [linkage.lift.max_stroke, linkage.bucket.max_stroke];
+        let within_stroke = |key: &str, value: f64, stroke: f64| {
+            problems.check((0.0..=stroke).contains(&value), || {
+                format!("{key} = {value} is outside the actuator's 0 to {stroke} m stroke")
+            });
+        };
+
+        let d = Gains::default();
+        let position = Gains {
+            gain: positive("position.gain", d.gain),
+            min_speed: whole("position.min_speed", d.min_speed),
+            max_speed: whole("position.max_speed", d.max_speed),
+            tolerance: positive("position.tolerance", d.tolerance),
+            settle: seconds("position.settle", d.settle, true),
+            backlash: [number("position.m1_backlash", d.backlash[0]), number("position.m2_backlash", d.backlash[1])],
+            rate: positive("position.rate", d.rate),
+        };
+        // `positive` has already reported anything that isn't a positive number
+        problems.check(position.rate.is_nan() || position.rate <= 0.0 || position.rate >= slowest, || {
+            format!("position.rate must be at least one every {} h, not {}", dsl::MAX_DURATION.as_secs() / 3600, position.rate)
+        });
+        problems.check(position.min_speed <= position.max_speed, || {
+            format!("position.min_speed ({}) is above position.max_speed ({})", position.min_speed, position.max_speed)
+        });
+        for (key, backlash) in ["m1_backlash", "m2_backlash"].into_iter().zip(position.backlash) {
+            problems.check(backlash >= 0.0 && backlash.is_finite(), || format!("position.{key} must be zero or more, not {backlash}"));
+        }
+
+        let d = DigCycle::default();
+        let dig = DigCycle {
+            lower_depth: number("dig.lower_depth", d.lower_depth),
+            lower_speed: whole("dig.lower_speed", d.lower_speed),
+            curl: number("dig.curl", d.curl),
+            curl_speed: whole("dig.curl_speed", d.curl_speed),
+            raise: number("dig.raise", d.raise),
+            raise_speed: whole("dig.raise_speed", d.raise_speed),
+            return_speed: whole("dig.return_speed", d.return_speed),
+        };
+        within_stroke("dig.lower_depth", dig.lower_depth, strokes[0]);
+        within_stroke("dig.curl", dig.curl, strokes[1]);
+        within_stroke("dig.raise", dig.raise, strokes[0]);
+
+        let trim = [positive("mirror.m1_trim", 1.0), positive("mirror.m2_trim", 1.0)];
+
+        let d = SkewMonitor::default();
+        let skew = SkewMonitor {
+            enabled: flag("skew.enabled", d.enabled),
+            warn: positive("skew.warn", d.warn),
+            correct: flag("skew.correct", d.correct),
+            gain: positive("skew.gain", d.gain),
+            max_correction: whole("skew.max_correction", d.max_correction as u16) as i32,
+        };
+
+        let d = SensorConfig::default();
+        let sensor = SensorConfig {
+            window: whole("sensor.window", d.window as u16) as usize,
+            noise: positive("sensor.noise", d.noise),
+            dropout: positive("sensor.dropout", d.dropout),
+            rail: positive("sensor.rail", d.rail),
+            warn: number("sensor.warn", d.warn),
+        };
+        problems.check(sensor.window >= 10, || format!("sensor.window must be at least 10 reports, not {}", sensor.window));
+        problems.check((0.0..=100.0).contains(&sensor.warn), || {
+            format!("sensor.warn must be a score from 0 to 100, not {}", sensor.warn)
+        });
+
+        let d = StandstillConfig::default();
+        let standstill = StandstillConfig {
+            enabled: flag("standstill.enabled", d.enabled),
+            window: seconds("standstill.window", d.window, false),
+            speed: positive("standstill.speed", d.speed),
+            timeout: seconds("standstill.timeout", d.timeout, false),
+        };
+        problems.check(standstill.timeout > standstill.window, || {
+            format!(
+                "standstill.timeout ({} s) has to be longer than standstill.window ({} s)",
+                standstill.timeout.as_secs_f64(),
+                standstill.window.as_secs_f64()
+            )
+        });
+
+        let d = LoadCell::default();
+        let load = LoadCell {
+            scale: number("load.scale", d.scale),
+            tare: number("load.tare", d.tare),
+            alarm: number("load.alarm", d.alarm),
+            stop: flag("load.stop", d.stop),
+        };
+        problems.check(load.scale != 0.0 && load.scale.is_finite(), || {
+            format!("load.scale must be a nonzero number of newtons per unit, not {}", load.scale)
+        });
+        problems.check(load.alarm >= 0.0, || format!("load.alarm must be zero or more, not {}", load.alarm));
+
+        let d = ArmingConfig::default();
+        let arming = ArmingConfig {
+            two_person: flag("arming.two_person", d.two_person),
+            timeout: seconds("arming.timeout", d.timeout, false),
+        };
+
+        let d = Steps::default();
+        let steps = |prefix: &str, d: Steps| -> Steps {
+            Steps {
+                fine: whole(&format!("{prefix}fine_step"), d.fine as u16) as u32,
+                normal: whole(&format!("{prefix}step"), d.normal as u16) as u32,
+                coarse: whole(&format!("{prefix}coarse_step"), d.coarse as u16) as u32,
+            }
+        };
+        let shared = steps("keys.", d);
+        let d = Keymap::default();
+        let select = |key: &str, d: Key| -> Key {
+            let name = problems.or(table.str(key, &d.to_string()).map(str::to_string), d.to_string());
+            problems.or(Key::parse(&name).map_err(|e| format!("{key}: {e}")), d)
+        };
+        let keys = Keymap {
+            select: [select("keys.m1.select", d.select[0]), select("keys.m2.select", d.select[1])],
+            steps: [steps("keys.m1.", shared), steps("keys.m2.", shared)],
+        };
+        problems.check(keys.select[0] != keys.select[1], || {
+            format!("keys.m1.select and keys.m2.select are both `{}`", keys.select[0])
+        });
+
+        let reverse_dwell = seconds("interlock.dwell", interlock::DEFAULT_DWELL, true);
+
+        let d = SpeedLimits::default();
+        let speed_limits = SpeedLimits {
+            extend: [whole("limits.m1_extend", d.extend[0]), whole("limits.m2_extend", d.extend[1])],
+            retract: [whole("limits.m1_retract", d.retract[0]), whole("limits.m2_retract", d.retract[1])],
+        };
+        for (key, cap) in ["m1_extend", "m2_extend", "m1_retract", "m2_retract"]
+            .into_iter()
+            .zip(speed_limits.extend.into_iter().chain(speed_limits.retract))
+        {
+            problems.check(cap > 0, || format!("limits.{key} of 0 would never let it move that way"));
+        }
+
+        let soft_speed = whole("softstart.speed", 0);
+        let soft_time = seconds("softstart.time", softstart::DEFAULT_TIME, false);
+        let soft_start = (soft_speed > 0).then_some(SoftStart { speed: soft_speed, time: soft_time });
+
+        let budget = number("power.budget", 0.0);
+        let ok = budget >= 0.0 && budget.is_finite();
+        problems.check(ok, || format!("power.budget must be a current in amps, or 0 for none, not {budget}"));
+        let power_budget = (ok && budget > 0.0).then_some(budget);
+
+        let mut constraints = Constraints::default();
+        let list = problems.or(table.str("constraints.rules", "").map(str::to_string), String::new());
+        for text in list.split(';').map(str::trim).filter(|t| !t.is_empty()) {
+            match Rule::parse(text) {
+                Ok(rule) => constraints.rules.push(rule),
+                Err(e) => problems.check(false, || format!("constraints.rules: `{text}`: {e}")),
+            }
+        }
+
+        let timeout = seconds("watchdog.timeout", link::DEFAULT_WATCHDOG, true);
+        let watchdog = (!timeout.is_zero()).then_some(timeout);
+
+        let mut profiles = vec![(String::from(safety::DEFAULT_PROFILE), Policies::ALL)];
+        let list = problems.or(table.str("safety.profiles", "").map(str::to_string), String::new());
+        for text in list.split(';').map(str::trim).filter(|t| !t.is_empty()) {
+            let Some((name, policies)) = text.split_once('=') else {
+                problems.check(false, || format!("safety.profiles: `{text}` isn't `name = policy, policy`"));
+                continue;
+            };
+            let name = name.trim();
+            match Policies::parse(policies) {
+                Ok(_) if profiles.iter().any(|(known, _)| known == name) => {
+                    problems.check(false, || format!("safety.profiles: `{name}` is defined twice"))
+                }
+                Ok(policies) => profiles.push((name.to_string(), policies)),
+                Err(e) => problems.check(false, || format!("safety.profiles: `{name}`: {e}")),
+            }
+        }
+        let safety_profile = problems.or(
+            table.str("safety.profile", safety::DEFAULT_PROFILE).map(str::to_string),
+            String::from(safety::DEFAULT_PROFILE),
+        );
+        let safety_policies = profiles.iter().find(|(name, _)| *name == safety_profile).map(|&(_, policies)| policies);
+        problems.check(safety_policies.is_some(), || {
+            let names: Vec<&str> = profiles.iter().map(|(name, _)| name.as_str()).collect();
+            format!("safety.profile: no profile `{safety_profile}`; there's {}", names.join(", "))
+        });
+        let safety_policies = safety_policies.unwrap_or(Policies::ALL);
+
+        let rate = |key: &str, default: f64| -> Option<f64> {
+            let hz = number(key, default);
+            let ok = hz == 0.0 || (hz >= slowest && hz.is_finite());
+            problems.check(ok, || {
+                format!("{key} must be a number per second, at least one every {} h, or 0 for no limit, not {hz}", dsl::MAX_DURATION.as_secs() / 3600)
+            });
+            let hz = if ok { hz } else { default };
+            (hz > 0.0).then_some(hz)
+        };
+        let telemetry = Rates {
+            display: rate("telemetry.display_rate", telemetry::DEFAULT_DISPLAY_RATE),
+            publish: rate("telemetry.publish_rate", telemetry::DEFAULT_PUBLISH_RATE),
+        };
+        let name = problems.or(table.str("telemetry.encoding", "f64le").map(str::to_string), String::from("f64le"));
+        let scale = number("telemetry.scale", 1.0);
+        let encoding = problems.or(
+            Encoding::parse(&name, scale).map_err(|e| format!("telemetry.encoding = `{name}` x {scale}: {e}")),
+            Encoding::default(),
+        );
+
+        let mut channels = Channels::default();
+        let list = problems.or(table.str("channels.list", "").map(str::to_string), String::new());
+        for text in list.split(';').map(str::trim).filter(|t| !t.is_empty()) {
+            match Channel::parse(text) {
+                Ok(channel) if channels.get(&channel.field.name).is_some() => {
+                    problems.check(false, || format!("channels.list: `{}` is defined twice", channel.field.name))
+                }
+                Ok(channel) => channels.list.push(channel),
+                Err(e) => problems.check(false, || format!("channels.list: `{text}`: {e}")),
+            }
+        }
+        let chart = problems.or(table.str("channels.chart", "").map(str::to_string), String::new());
+        problems.check(chart.is_empty() || channels.get(&chart).is_some(), || format!("channels.chart: no channel `{chart}`"));
+        channels.chart = match chart.is_empty() {
+            true => channels.list.first().map(|channel| channel.field.name.clone()),
+            false => Some(chart),
+        };
+
+        let throttle = RateLimits { actuator: rate("throttle.actuator_rate", 0.0), client: rate("throttle.client_rate", 0.0) };
+
+        let d = TripConfig::default();
+        let trip = TripConfig {
+            stall_time: seconds("trip.stall_time", d.stall_time, false),
+            stall_distance: number("trip.stall_distance", d.stall_distance),
+            limit_margin: number("trip.limit_margin", d.limit_margin),
+        };
+        problems.check(trip.stall_distance >= 0.0, || {
+            format!("trip.stall_distance must be zero or more, not {}", trip.stall_distance)
+        });
+        problems.check(trip.limit_margin >= 0.0, || {
+            format!("trip.limit_margin must be zero or more, not {}", trip.limit_margin)
+        });
+        for (actuator, stroke) in ["lift", "bucket"].into_iter().zip(strokes) {
+            problems.check(trip.limit_margin < stroke / 2.0, || {
+                format!("trip.limit_margin ({} m) covers the whole {stroke} m {actuator} stroke", trip.limit_margin)
+            });
+        }
+
+        let d = Effort::default();
+        let effort = Effort {
+            backoff: seconds("effort.backoff", d.backoff, true),
+            timeout: seconds("effort.timeout", d.timeout, false),
+        };
+
+        let d = SweepConfig::default();
+        let sweep = SweepConfig { points: whole("sweep.points", d.points), cycles: whole("sweep.cycles", d.cycles) };
+        problems.check(sweep.points > 0 && sweep.cycles > 0, || {
+            String::from("sweep.points and sweep.cycles must be at least 1")
+        });
+
+        let d = Tolerance::default();
+        let replay = Tolerance { rms: positive("replay.rms", d.rms), max: positive("replay.max", d.max) };
+
+        let d = Countdown::default();
+        let countdown = Countdown {
+            length: seconds("countdown.length", d.length, true),
+            warn: seconds("countdown.warn", d.warn, true),
+            final_warn: seconds("countdown.final_warn", d.final_warn, true),
+            stop: flag("countdown.stop", d.stop),
+        };
+        problems.check(countdown.final_warn <= countdown.warn, || {
+            format!(
+                "countdown.final_warn ({} s) is more than countdown.warn ({} s)",
+                countdown.final_warn.as_secs_f64(),
+                countdown.warn.as_secs_f64()
+            )
+        });
+
+        let mut roles = Roles::default();
+        for (key, role) in [("roles.viewers", Role::Viewer), ("roles.operators", Role::Operator), ("roles.admins", Role::Admin)] {
+            let list = problems.or(table.str(key, "").map(str::to_string), String::new());
+            for client in list.split(',').map(str::trim).filter(|k| !k.is_empty()) {
+                match roles.of(client) {
+                    Some(other) => problems.check(false, || format!("{key}: a key is also listed for {other}s")),
+                    None => roles.keys.push((client.to_string(), role)),
+                }
+            }
+        }
+
+        let dirs = problems.or(table.str("files.dirs", ".").map(str::to_string), String::from("."));
+        let trajectory_dirs = dirs.split(',').map(str::trim).filter(|d| !d.is_empty()).map(str::to_string).collect();
+
+        let mut groups = Groups::default();
+        let list = problems.or(table.str("groups.list", "").map(str::to_string), String::new());
+        for text in list.split(';').map(str::trim).filter(|t| !t.is_empty()) {
+            match Group::parse(text) {
+                Ok(group) if groups.get(&group.name).is_some() => {
+                    problems.check(false, || format!("groups.list: `{}` is defined twice", group.name))
+                }
+                Ok(group) => groups.groups.push(group),
+                Err(e) => problems.check(false, || format!("groups.list: `{text}`: {e}")),
+            }
+        }
+
+        let mut aliases = Aliases::default();
+        let list = problems.or(table.str("aliases.list", "").map(str::to_string), String::new());
+        for text in list.split(';').map(str::trim).filter(|t| !t.is_empty()) {
+            match Alias::parse(text) {
+                Ok(alias) if aliases.get(&alias.name).is_some() => {
+                    problems.check(false, || format!("aliases.list: `{}` is defined twice", alias.name))
+                }
+                Ok(alias) if groups.get(&alias.name).is_some() => {
+                    problems.check(false, || format!("aliases.list: `{}` is already a group", alias.name))
+                }
+                Ok(alias) => aliases.aliases.push(alias),
+                Err(e) => problems.check(false, || format!("aliases.list: `{text}`: {e}")),
+            }
+        }
+        let vocabulary = dsl::Vocabulary { groups, aliases };
+
+        let commands = problems.or(table.str("startup.commands", "").map(str::to_string), String::new());
+        let mut startup = Vec::new();
+        for line in commands.split(';').map(str::trim).filter(|l| !l.is_empty()) {
+            match vocabulary.parse(line) {
+                Ok(cmd) => startup.push(cmd),
+                Err(e) => problems.check(false, || format!("startup.commands: `{line}`: {e}")),
+            }
+        }
+
+        let list = problems.or(table.str("hooks.list", "").map(str::to_string), String::new());
+        let mut hooks = Vec::new();
+        for text in list.split(';').map(str::trim).filter(|t| !t.is_empty()) {
+            match Hook::parse(text, &vocabulary) {
+                Ok(hook) => hooks.push(hook),
+                Err(e) => problems.check(false, || format!("hooks.list: `{text}`: {e}")),
+            }
+        }
+        let hooks = Hooks::new(hooks);
+
+        let d = SoakConfig::default();
+        let commands = problems.or(table.str("soak.cycle", "").map(str::to_string), String::new());
+        let mut cycle = Vec::new();
+        for line in commands.split(';').map(str::trim).filter(|l| !l.is_empty()) {
+            match vocabulary.parse(line) {
+                Ok(cmd) => cycle.push(cmd),
+                Err(e) => problems.check(false, || format!("soak.cycle: `{line}`: {e}")),
+            }
+        }
+        let hours = positive("soak.hours", d.duration.as_secs_f64() / 3600.0);
+        // `positive` has already reported anything that isn't a positive number
+        let duration = Duration::try_from_secs_f64(hours * 3600.0).ok().filter(|d| *d <= dsl::MAX_DURATION);
+        problems.check(duration.is_some() || !(hours > 0.0 && hours.is_finite()), || {
+            format!("soak.hours must be at most {}, not {hours}", dsl::MAX_DURATION.as_secs() / 3600)
+        });
+        let duration = duration.filter(|_| hours > 0.0).unwrap_or(d.duration);
+        let report = problems.or(table.str("soak.report", &d.report.to_string_lossy()).map(PathBuf::from), d.report.clone());
+        let soak = SoakConfig {
+            cycle,
+            duration,
+            max_temp: number("soak.max_temp", d.max_temp),
+            max_current: number("soak.max_current", d.max_current),
+            repeatability: number("soak.repeatability", d.repeatability),
+            report,
+        };
+        for (key, limit) in [("max_temp", soak.max_temp), ("max_current", soak.max_current), ("repeatability", soak.repeatability)] {
+            problems.check(limit >= 0.0, || format!("soak.{key} must be zero (no limit) or more, not {limit}"));
+        }
+
+        let mut items = Vec::new();
+        let list = problems.or(table.str("checklist.items", "").map(str::to_string), String::new());
+        for text in list.split(';').map(str::trim).filter(|t| !t.is_empty()) {
+            match Item::parse(text) {
+                Ok(item) => items.push(item),
+                Err(e) => problems.check(false, || format!("checklist.items: `{text}`: {e}")),
+            }
+        }
+        let unarmed_speed = whole("checklist.unarmed_speed", checklist::DEFAULT_UNARMED_SPEED);
+        problems.check(unarmed_speed > 0, || String::from("checklist.unarmed_speed of 0 would never let anything move"));
+        let checklist = Checklist::new(items, unarmed_speed);
+
+        let list = problems.or(table.str("sim.faults", "").map(str::to_string), String::new());
+        let mut sim_faults = Vec::new();
+        for entry in list.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+            let (at, injection) = entry.split_once(char::is_whitespace).unwrap_or((entry, ""));
+            match dsl::parse_duration(at).and_then(|at| Ok((at, Injection::parse(injection)?))) {
+                Ok(fault) => sim_faults.push(fault),
+                Err(e) => problems.check(false, || format!("sim.faults: `{entry}`: {e}")),
+            }
+        }
+
+        let text = |key: &str| problems.or(table.str(key, "").map(str::to_string), String::new());
+        let d = Policy::default();
+        let priority = problems.or(Policy::rank(&text("input.priority")).map_err(|e| format!("input.priority: {e}")), d.priority);
+        let hold = seconds("input.hold", d.hold, true);
+        let input = InputConfig {
+            policy: Policy { priority, hold },
+            fifo: Some(text("input.fifo")).filter(|path| !path.is_empty()).map(PathBuf::from),
+            listen: Some(text("input.listen")).filter(|endpoint| !endpoint.is_empty()),
+            script: Some(text("input.script")).filter(|path| !path.is_empty()).map(PathBuf::from),
+        };
+
+        problems.into_result()?;
+        Ok(Config {
+            linkage,
+            position,
+            dig,
+            trim,
+            skew,
+            sensor,
+            standstill,
+            load,
+            arming,
+            keys,
+            reverse_dwell,
+            speed_limits,
+            soft_start,
+            power_budget,
+            constraints,
+            watchdog,
+            safety_profile,
+            safety_policies,
+            telemetry,
+            encoding,
+            channels,
+            throttle,
+            trip,
+            effort,
+            sweep,
+            replay,
+            countdown,
+            roles,
+            trajectory_dirs,
+            groups: vocabulary.groups,
+            aliases: vocabulary.aliases,
+            startup,
+            hooks,
+            soak,
+            checklist,
+            sim_faults,
+            input,
+            resolved: table.resolved(),
+        })
+    }
+
+    /// What the serial writer checks every frame against, see [`crate::safety`].
+    pub fn safety(&self) -> safety::Settings {
+        safety::Settings {
+            policies: self.safety_policies,
+            dwell: self.reverse_dwell,
+            limits: self.speed_limits,
+            constraints: self.constraints.clone(),
+            actuator_rate: self.throttle.actuator,
+            soft_start: self.soft_start,
+            power_budget: self.power_budget,
+            watchdog: self.watchdog,
+        }
+    }
+
+    /// Every key as it resolved, nested by section, for recording what a
+    /// session ran with (see [`crate::session`]).
+    pub fn snapshot(&self) -> json::Value {
+        fn convert(value: &Value) -> json::Value {
+            match value {
+                Value::Bool(b) => json::Value::Bool(*b),
+                Value::Number(n) => json::Value::Number(*n),
+                Value::String(s) => json::Value::String(s.clone()),
+                Value::Array(items) => json::Value::Array(items.iter().map(convert).collect()),
+            }
+        }
+        fn nest(fields: &mut Vec<(String, json::Value)>, path: &[&str], value: json::Value) {
+            let [first, rest @ ..] = path else { return };
+            if rest.is_empty() {
+                fields.push((first.to_string(), value));
+                return;
+            }
+            if !fields.iter().any(|(k, _)| k == first) {
+                fields.push((first.to_string(), json::Value::Object(Vec::new())));
+            }
+            if let Some((_, json::Value::Object(inner))) = fields.iter_mut().find(|(k, _)| k == first) {
+                nest(inner, rest, value);
+            }
+        }
+        let mut root = Vec::new();
+        for (key, value) in &self.resolved {
+            nest(&mut root, &key.split('.').collect::<Vec<_>>(), convert(value));
+        }
+        json::Value::Object(root)
+    }
+}
+
+/// Everything wrong with a config, gathered while reading it.
+#[derive(Default)]
+struct Problems(RefCell<Vec<String>>);
+
+impl Problems {
+    /// The value, or `default` with the error noted.
+    fn or<T>(&self, result: Result<T, String>, default: T) -> T {
+        result.unwrap_or_else(|e| {
+            self.0.borrow_mut().push(e);
+            default
+        })
+    }
+
+    fn check(&self, ok: bool, problem: impl FnOnce() -> String) {
+        if !ok {
+            self.0.borrow_mut().push(problem());
+        }
+    }
+
+    /// One problem on its own, or a numbered list of them.
+    fn into_result(self) -> Result<(), String> {
+        let problems = self.0.into_inner();
+        match problems.len() {
+            0 => Ok(()),
+            1 => Err(problems.into_iter().next().unwrap()),
+            n => {
+                let mut report = format!("{n} problems:");
+                for (i, problem) in problems.iter().enumerate() {
+                    report += &format!("\n  {}. {problem}", i + 1);
+                }
+                Err(report)
+            }
+        }
+    }
+}
+
+/// Puts `property` at the dotted `path` under `properties`, making the
+/// sections on the way as `object` describes them.
+fn insert(
+    properties: &mut Vec<(String, json::Value)>,
+    path: &[&str],
+    property: json::Value,
+    object: &impl Fn(Vec<(String, json::Value)>) -> json::Value,
+) {
+    let [first, rest @ ..] = path else { return };
+    let exists = properties.iter().any(|(k, _)| k == first);
+    if rest.is_empty() {
+        if !exists {
+            properties.push((first.to_string(), property));
+        }
+        return;
+    }
+    if !exists {
+        properties.push((first.to_string(), object(Vec::new())));
+    }
+    let section = properties.iter_mut().find(|(k, _)| k == first).map(|(_, v)| v);
+    if let Some(json::Value::Object(fields)) = section
+        && let Some((_, json::Value::Object(inner))) = fields.iter_mut().find(|(k, _)| k == "properties")
+    {
+        insert(inner, rest, property, object);
+    }
+}
+
+/// Upgrades a config file's text to [`VERSION`], keeping its comments and
+/// layout. Returns the version it was at and the upgraded text.
+pub fn migrate(src: &str) -> Result<(u16, String), String> {
+    let table = Table::parse(src)?;
+    let version = table.u16("version", 0).map_err(|_| String::from("`version` must be a whole number"))?;
+    if version > VERSION {
+        return Err(format!("written for config version {version}, but this build only reads up to {VERSION}"));
+    }
+    if version == VERSION {
+        return Ok((version, src.to_string()));
+    }
+    let mut out = MIGRATIONS[version as usize..].iter().fold(src.to_string(), |src, migration| migration(&src));
+
+    // replace the version line, or put one above the first section
+    let line = format!("version = {VERSION}");
+    let lines: Vec<&str> = out.lines().collect();
+    let top = lines.iter().position(|l| strip_comment(l).trim().starts_with('[')).unwrap_or(lines.len());
+    let existing = lines[..top].iter().position(|l| strip_comment(l).split('=').next().is_some_and(|k| k.trim() == "version"));
+    let mut lines: Vec<String> = lines.into_iter().map(str::to_string).collect();
+    match existing {
+        Some(i) => lines[i] = line,
+        None => {
+            // after any comment block at the very top
+            let at = lines.iter().take(top).position(|l| !l.trim_start().starts_with('#')).unwrap_or(top);
+            lines.insert(at, line);
+            if lines.get(at + 1).is_some_and(|l| !l.trim().is_empty()) {
+                lines.insert(at + 1, String::new());
+            }
+        }
+    }
+    out = lines.join("\n") + "\n";
+    Ok((version, out))
+}
+
+/// The environment variable that overrides `key`: `keys.m1.step` is
+/// `ACTUATOR_KEYS_M1_STEP`.
+pub fn env_name(key: &str) -> String {
+    format!("{ENV_PREFIX}{}", key.to_ascii_uppercase().replace('.', "_"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with(key: &str, raw: &str) -> Result<Config, String> {
+        let mut table = Table::default();
+        table.set(key, raw)?;
+        Config::from_table(&table)
+    }
+
+    #[test]
+    fn oversized_seconds() {
+        for key in ["arming.timeout", "countdown.length", "interlock.dwell", "watchdog.timeout", "position.settle", "input.hold"] {
+            let Err(e) = with(key, "1e300") else { panic!("{key} = 1e300 loaded") };
+            assert!(e.contains(key), "{e}");
+            assert!(with(key, &dsl::MAX_DURATION.as_secs().to_string()).is_ok(), "{key} at the cap");
+        }
+        assert!(with("arming.timeout", "-1").is_err());
+        assert!(with("watchdog.timeout", "0").is_ok_and(|config| config.watchdog.is_none()));
+    }
+
+    #[test]
+    fn slow_rates() {
+        for key in ["telemetry.display_rate", "telemetry.publish_rate"] {
+            assert!(with(key, "1e-300").is_err_and(|e| e.contains(key)), "{key} = 1e-300 loaded");
+            assert!(with(key, "0").is_ok());
+            assert!(with(key, "0.5").is_ok());
+        }
+    }
+
+    #[test]
+    fn slow_position_rate() {
+        assert!(with("position.rate", "1e-300").is_err_and(|e| e.contains("position.rate")));
+        assert!(with("position.rate", "0").is_err());
+        assert!(with("position.rate", "0.001").is_ok());
+    }
+
+    #[test]
+    fn oversized_soak() {
+        assert!(with("soak.hours", "1e300").is_err_and(|e| e.contains("soak.hours")));
+        assert!(with("soak.hours", "25").is_err());
+        assert!(with("soak.hours", "24").is_ok_and(|config| config.soak.duration == dsl::MAX_DURATION));
+    }
+}
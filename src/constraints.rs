@@ -0,0 +1,112 @@
+//! Rules tying the actuators' lengths together, enforced by the serial
+//! writer alongside the [interlock](crate::interlock) for mechanisms that
+//! would bind or collide.
+//!
+//! Whatever queued a frame, a speed that would move an actuator further into
+//! breaking a rule goes out as a stop instead, and an actuator already
+//! moving that way is stopped as soon as a report shows the rule broken.
+//! Moves that take it back within the rule are always let through.
+
+use std::fmt;
+
+use crate::{
+    commands::{Actuator, Direction},
+    dsl,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rule {
+    /// `a - b within max`: the two lengths stay within `max` m of each other
+    Within { a: Actuator, b: Actuator, max: f64 },
+    /// `actuator only when other < value` (or `>`): `actuator` moves only
+    /// while `other` is known to be on that side of `value` m
+    OnlyWhen { actuator: Actuator, other: Actuator, above: bool, value: f64 },
+}
+
+impl Rule {
+    pub fn parse(text: &str) -> Result<Rule, String> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let actuator = |word: &str| {
+            dsl::parse_actuator(word).ok_or_else(|| format!("unknown actuator `{word}`; expected m1, m2, lift or bucket"))
+        };
+        let meters = |word: &str| match word.trim_end_matches('m').parse::<f64>() {
+            Ok(value) if value.is_finite() => Ok(value),
+            _ => Err(format!("expected a length in m, found `{word}`")),
+        };
+        match words[..] {
+            [a, "-", b, "within", max] => {
+                let (a, b, max) = (actuator(a)?, actuator(b)?, meters(max)?);
+                if a == b {
+                    return Err(String::from("a rule between an actuator and itself"));
+                }
+                if max <= 0.0 {
+                    return Err(format!("`within` must be a positive length, not {max}"));
+                }
+                Ok(Rule::Within { a, b, max })
+            }
+            [a, "only", "when", other, op @ ("<" | ">"), value] => {
+                let (actuator, other, value) = (actuator(a)?, actuator(other)?, meters(value)?);
+                if actuator == other {
+                    return Err(String::from("a rule between an actuator and itself"));
+                }
+                Ok(Rule::OnlyWhen { actuator, other, above: op == ">", value })
+            }
+            _ => Err(String::from("expected `<actuator> - <actuator> within <m>` or `<actuator> only when <actuator> < <m>`")),
+        }
+    }
+
+    /// Whether moving `actuator` in `direction` breaks this rule, or takes it
+    /// further from being kept, given the last reported `lengths`.
+    fn blocks(&self, actuator: Actuator, direction: Direction, lengths: [Option<f64>; 2]) -> bool {
+        match *self {
+            Rule::Within { a, b, max } => {
+                let (Some(len_a), Some(len_b)) = (lengths[a as usize], lengths[b as usize]) else {
+                    // nothing to go on yet
+                    return false;
+                };
+                let gap = len_a - len_b;
+                // which way the gap grows when `actuator` extends
+                let sign = if actuator == a { 1.0 } else if actuator == b { -1.0 } else { return false };
+                let widening = if direction == Direction::Forward { sign } else { -sign };
+                gap.abs() >= max && gap.signum() == widening
+            }
+            Rule::OnlyWhen { actuator: gated, other, above, value } => {
+                gated == actuator
+                    && !lengths[other as usize].is_some_and(|len| if above { len > value } else { len < value })
+            }
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = |actuator: Actuator| format!("{actuator:?}").to_lowercase();
+        match *self {
+            Rule::Within { a, b, max } => write!(f, "{} - {} within {max}", name(a), name(b)),
+            Rule::OnlyWhen { actuator, other, above, value } => {
+                write!(f, "{} only when {} {} {value}", name(actuator), name(other), if above { ">" } else { "<" })
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Constraints {
+    pub rules: Vec<Rule>,
+}
+
+impl Constraints {
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// The rule that stops `actuator` moving in `direction`, if any. With no
+    /// telling which way it'll go, it's held back if either way is.
+    pub fn blocking(&self, actuator: Actuator, direction: Option<Direction>, lengths: [Option<f64>; 2]) -> Option<&Rule> {
+        let directions = match direction {
+            Some(direction) => vec![direction],
+            None => vec![Direction::Forward, Direction::Backward],
+        };
+        self.rules.iter().find(|rule| directions.iter().any(|&direction| rule.blocks(actuator, direction, lengths)))
+    }
+}
@@ -0,0 +1,53 @@
+//! A countdown for time-boxed runs, such as a 15 minute competition run,
+//! timed on the run clock (see [`crate::audit::AuditLog::run`]).
+
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Countdown {
+    /// how long a run may go on; zero is no countdown at all
+    pub length: Duration,
+    /// time left at the first warning
+    pub warn: Duration,
+    /// time left at the last warning
+    pub final_warn: Duration,
+    /// stop both actuators when time's up
+    pub stop: bool,
+}
+
+impl Default for Countdown {
+    fn default() -> Self {
+        Countdown {
+            length: Duration::ZERO,
+            warn: Duration::from_secs(300),
+            final_warn: Duration::from_secs(60),
+            stop: false,
+        }
+    }
+}
+
+/// How far into the countdown a run is, in the order they come.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stage {
+    Running,
+    Warned,
+    FinalWarned,
+    Expired,
+}
+
+impl Countdown {
+    /// The time left after `run` of the run, if there's a countdown.
+    pub fn left(&self, run: Duration) -> Option<Duration> {
+        (!self.length.is_zero()).then(|| self.length.saturating_sub(run))
+    }
+
+    pub fn stage(&self, run: Duration) -> Stage {
+        match self.left(run) {
+            None => Stage::Running,
+            Some(left) if left.is_zero() => Stage::Expired,
+            Some(left) if left <= self.final_warn => Stage::FinalWarned,
+            Some(left) if left <= self.warn => Stage::Warned,
+            Some(_) => Stage::Running,
+        }
+    }
+}
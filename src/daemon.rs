@@ -0,0 +1,841 @@
+//! Sharing one serial connection between processes over a Unix socket.
+//!
+//! [`serve`] (`--daemon`) holds the port and relays frames, telemetry and
+//! status for whoever is attached. [`attach`] (`--attach`) returns a [`Link`]
+//! backed by the socket instead of the port, so the terminal UI and the
+//! headless modes work the same either way, and can come and go without the
+//! port being reopened.
+//!
+//! One line per message:
+//!
+//! ```text
+//! client -> daemon   F <b0> <b1> <b2> <b3>   write this serialized ActuatorCommand
+//! client -> daemon   W <actuator> <ms>       stop this actuator after <ms>, up to dsl::MAX_DURATION, see link::Request::StopAfter
+//! client -> daemon   D                       detaching on purpose, leave everything running
+//! daemon -> client   L <schema>              the fields of the T lines that follow, see Schema::encode
+//! daemon -> client   T <actuator> <values...>  report, actuator 0 (M1) or 1 (M2), one value per field
+//! daemon -> client   E <event>               status event from the port, see StatusEvent::encode
+//! daemon -> client   S <message>             anything else the daemon has to say
+//! daemon -> client   A 1 | A 0               one of this client's frames was written / failed
+//! daemon -> client   A c | A d               ... was superseded by a newer speed / thrown away unwritten
+//! ```
+//!
+//! A client gets one `A` for each of its `F` lines, in the order it sent them.
+//! One that sends a line longer than [`MAX_LINE`] is told so and cut off, as
+//! if it had crashed.
+//!
+//! A client that disconnects without `D` is assumed to have crashed, and the
+//! daemon stops both actuators.
+//!
+//! The e-stop latch (see [`crate::estop`]) is the daemon's, shared by every
+//! client:
+//!
+//! ```text
+//! client -> daemon   Z <why>                 trip the e-stop
+//! client -> daemon   U <operator>            reset it
+//! daemon -> client   Z <why>                 it's latched, also on attaching while it is
+//! daemon -> client   U <operator>            it was reset
+//! ```
+//!
+//! While it's latched, the daemon writes a stop in place of any `F` that
+//! isn't one, tells the client with an `E constraint_stop`, and withdraws
+//! and refuses arming requests.
+//!
+//! Two-person arming (see [`crate::arming`]) adds:
+//!
+//! ```text
+//! daemon -> client   P <timeout_ms> | P 0    first line after attaching: arming is required, with this timeout / isn't
+//! client -> daemon   R <operator> <text>     please have someone confirm <text>
+//! daemon -> client   C <token> <operator> <text>   someone else asks for <text>
+//! client -> daemon   Y <token> <operator>    confirm it
+//! daemon -> client   X <token>               that request is no longer open
+//! daemon -> client   G <operator>            your request was confirmed
+//! daemon -> client   N <reason>              your request was refused or timed out
+//! ```
+//!
+//! An operator is one word, as `--operator` insists. With arming required, a
+//! client is disarmed until one of its requests is confirmed, and stays armed
+//! until it goes. The daemon writes a stop in place of any other `F` from a
+//! disarmed client and tells it with an `E constraint_stop`, so a client that
+//! skips arming can still stop things but not move them.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+    signal::unix::{SignalKind, signal},
+    sync::{mpsc, watch},
+    time::{sleep_until, timeout},
+};
+
+use crate::{
+    arming::{self, ArmingConfig},
+    commands::{Actuator, ActuatorCommand},
+    dsl::MAX_DURATION,
+    estop,
+    link::{Ledger, Link, Outcome, Request},
+    status::StatusEvent,
+    stream::{self, Reports},
+    telemetry::{Report, Schema},
+};
+
+/// `$XDG_RUNTIME_DIR/actuator_controller.sock`, or under `/tmp` without one.
+pub fn default_socket_path() -> PathBuf {
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join("actuator_controller.sock")
+}
+
+/// The longest line a client may send, newline aside.
+pub const MAX_LINE: usize = 1024;
+
+/// A line from a client, see the module docs.
+#[derive(Debug, PartialEq)]
+enum Line {
+    Frame(ActuatorCommand),
+    StopAfter(Actuator, Duration),
+    Detach,
+    Arming(arming::Request),
+    EStop(estop::Event),
+}
+
+/// Reads a line from a client, or says why it can't.
+fn parse_line(line: &str) -> Result<Line, String> {
+    let mut words = line.split_whitespace();
+    match words.next() {
+        Some("F") => {
+            let bytes: Option<Vec<u8>> = words.map(|w| w.parse().ok()).collect();
+            let bytes = bytes.and_then(|bytes| <[u8; 4]>::try_from(bytes).ok()).ok_or("expected 4 bytes")?;
+            Ok(Line::Frame(ActuatorCommand::deserialize(bytes)?))
+        }
+        Some("W") => {
+            let actuator = words.next().and_then(|a| a.parse::<u8>().ok()).and_then(|a| Actuator::try_from(a).ok());
+            let (Some(actuator), Some(ms), None) = (actuator, words.next().and_then(|ms| ms.parse().ok()), words.next()) else {
+                return Err(String::from("expected an actuator and a time in ms"));
+            };
+            let after = Duration::from_millis(ms);
+            if after > MAX_DURATION {
+                return Err(format!("can't stop more than {} ms from now", MAX_DURATION.as_millis()));
+            }
+            Ok(Line::StopAfter(actuator, after))
+        }
+        Some("D") => Ok(Line::Detach),
+        Some("R") => {
+            let Some(operator) = words.next() else {
+                return Err(String::from("expected an operator"));
+            };
+            // split as `Y` is, so whoever asks can be told apart from whoever confirms
+            let text = line.trim_start()[1..].trim_start()[operator.len()..].trim();
+            Ok(Line::Arming(arming::Request::Ask { operator: operator.to_string(), text: text.to_string() }))
+        }
+        Some("Z") => Ok(Line::EStop(estop::Event::Tripped(line.trim_start()[1..].trim().to_string()))),
+        Some("U") => match (words.next(), words.next()) {
+            (Some(operator), None) => Ok(Line::EStop(estop::Event::Reset(operator.to_string()))),
+            _ => Err(String::from("expected an operator")),
+        },
+        Some("Y") => match (words.next().and_then(|t| t.parse().ok()), words.next(), words.next()) {
+            (Some(token), Some(operator), None) => Ok(Line::Arming(arming::Request::Confirm { token, operator: operator.to_string() })),
+            _ => Err(String::from("expected a token and an operator")),
+        },
+        _ => Err(String::from("unknown message")),
+    }
+}
+
+enum ClientEvent {
+    Attached(u64, mpsc::Sender<String>),
+    Frame(u64, ActuatorCommand),
+    StopAfter(Actuator, Duration),
+    Arming(u64, arming::Request),
+    EStop(u64, estop::Event),
+    Gone { id: u64, detached: bool },
+}
+
+/// An arming request waiting for a second operator.
+struct Ask {
+    client: u64,
+    operator: String,
+    text: String,
+    deadline: Instant,
+}
+
+/// Serves `link` on `path` until SIGTERM or Ctrl-C, then stops both actuators.
+/// `log` gets a line for every client coming or going, every arming decision
+/// and every status.
+pub async fn serve(mut link: Link, path: &Path, arming: ArmingConfig, mut log: impl FnMut(&str)) -> io::Result<()> {
+    if path.exists() {
+        if UnixStream::connect(path).await.is_ok() {
+            return Err(io::Error::new(io::ErrorKind::AddrInUse, "another daemon is already listening there"));
+        }
+        // left over from one that didn't shut down cleanly
+        std::fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    let mut terminate = signal(SignalKind::terminate())?;
+    log(&format!("listening on {}", path.display()));
+
+    let (events_tx, mut events) = mpsc::channel(100);
+    let mut clients = HashMap::new();
+    // who sent each frame still waiting for its outcome, by sequence number
+    // (see Link::outcomes); `None` for our own
+    let mut owners = BTreeMap::new();
+    let mut outcomes = link.outcomes();
+    let mut queued = link.acks.borrow().total();
+    // what the firmware last advertised, passed on to every client
+    let mut schema = Arc::new(Schema::default());
+    let mut next_id = 0;
+    let mut asks: HashMap<u64, Ask> = HashMap::new();
+    let mut next_token = 0;
+    // clients with a confirmed request, whose moves are passed on
+    let mut armed = HashSet::new();
+    // why the e-stop was tripped, while it's latched
+    let mut latched: Option<String> = None;
+
+    let result = loop {
+        let next_deadline = asks.values().map(|ask| ask.deadline).min();
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => {
+                    next_id += 1;
+                    tokio::spawn(client(next_id, stream, events_tx.clone()));
+                }
+                Err(e) => break Err(e),
+            },
+            Some(event) = events.recv() => match event {
+                ClientEvent::Attached(id, tx) => {
+                    log(&format!("client {id} attached"));
+                    let timeout_ms = if arming.two_person { arming.timeout.as_millis().max(1) } else { 0 };
+                    let _ = tx.try_send(format!("P {timeout_ms}"));
+                    let _ = tx.try_send(format!("L {}", schema.encode()));
+                    if let Some(why) = &latched {
+                        let _ = tx.try_send(format!("Z {why}"));
+                    }
+                    clients.insert(id, tx);
+                }
+                ClientEvent::Arming(id, arming::Request::Ask { .. }) if latched.is_some() => {
+                    if let Some(tx) = clients.get(&id) {
+                        let _ = tx.try_send(String::from("N the e-stop is latched"));
+                    }
+                }
+                ClientEvent::Arming(id, arming::Request::Ask { operator, text }) => {
+                    withdraw(&mut asks, &clients, |ask| ask.client == id);
+                    next_token += 1;
+                    log(&format!("{operator} (client {id}) asks to confirm `{text}`"));
+                    for (other, tx) in &clients {
+                        if *other != id {
+                            let _ = tx.try_send(format!("C {next_token} {operator} {text}"));
+                        }
+                    }
+                    let deadline = Instant::now() + arming.timeout;
+                    asks.insert(next_token, Ask { client: id, operator, text, deadline });
+                }
+                ClientEvent::Arming(id, arming::Request::Confirm { token, operator }) => {
+                    let reply = |msg: &str| {
+                        if let Some(tx) = clients.get(&id) {
+                            let _ = tx.try_send(format!("S {msg}"));
+                        }
+                    };
+                    match asks.get(&token) {
+                        None => reply("that request is no longer open"),
+                        Some(ask) if ask.client == id || ask.operator == operator => {
+                            reply("a second operator has to confirm")
+                        }
+                        Some(_) => {
+                            let ask = asks.remove(&token).unwrap();
+                            log(&format!("{operator} (client {id}) confirmed {}'s `{}`", ask.operator, ask.text));
+                            armed.insert(ask.client);
+                            if let Some(tx) = clients.get(&ask.client) {
+                                let _ = tx.try_send(format!("G {operator}"));
+                            }
+                            broadcast(&clients, format!("X {token}"));
+                        }
+                    }
+                }
+                ClientEvent::Frame(id, cmd) => {
+                    let refusal = if latched.is_some() {
+                        Some(LATCHED)
+                    } else if arming.two_person && !armed.contains(&id) {
+                        Some(NOT_ARMED)
+                    } else {
+                        None
+                    };
+                    let cmd = match refusal.filter(|_| !is_stop(cmd)) {
+                        Some(why) => {
+                            let actuator = cmd.actuator();
+                            log(&format!("stopped {actuator:?} in place of `{cmd}` from client {id}: {why}"));
+                            if let Some(tx) = clients.get(&id) {
+                                let refused = StatusEvent::ConstraintStop(actuator, String::from(why));
+                                let _ = tx.try_send(format!("E {}", refused.encode()));
+                            }
+                            ActuatorCommand::SetSpeed(0, actuator)
+                        }
+                        None => cmd,
+                    };
+                    queued += 1;
+                    owners.insert(queued, Some(id));
+                    let _ = link.commands.send(cmd.into()).await;
+                }
+                // already latched, so nothing's moving
+                ClientEvent::EStop(_, estop::Event::Tripped(_)) if latched.is_some() => {}
+                ClientEvent::EStop(id, estop::Event::Tripped(why)) => {
+                    let why = format!("client {id}: {}", if why.is_empty() { "e-stop" } else { &why });
+                    log(&format!("e-stop latched by {why}, stopping both actuators"));
+                    broadcast(&clients, format!("Z {why}"));
+                    latched = Some(why);
+                    armed.clear();
+                    withdraw(&mut asks, &clients, |_| true);
+                    for actuator in [Actuator::M1, Actuator::M2] {
+                        queued += 1;
+                        owners.insert(queued, None);
+                        let _ = link.commands.send(ActuatorCommand::SetSpeed(0, actuator).into()).await;
+                    }
+                }
+                ClientEvent::EStop(id, estop::Event::Reset(operator)) => match latched.take() {
+                    Some(why) => {
+                        log(&format!("{operator} (client {id}) reset the e-stop (was: {why})"));
+                        broadcast(&clients, format!("U {operator}"));
+                    }
+                    None => {
+                        if let Some(tx) = clients.get(&id) {
+                            let _ = tx.try_send(String::from("S the e-stop isn't latched"));
+                        }
+                    }
+                },
+                // not a frame, so nobody's waiting on an ack for it
+                ClientEvent::StopAfter(actuator, after) => {
+                    let _ = link.commands.send(Request::StopAfter(actuator, after)).await;
+                }
+                ClientEvent::Gone { id, detached: true } => {
+                    log(&format!("client {id} detached"));
+                    clients.remove(&id);
+                    armed.remove(&id);
+                    withdraw(&mut asks, &clients, |ask| ask.client == id);
+                }
+                ClientEvent::Gone { id, detached: false } => {
+                    log(&format!("lost client {id}, stopping both actuators"));
+                    clients.remove(&id);
+                    armed.remove(&id);
+                    withdraw(&mut asks, &clients, |ask| ask.client == id);
+                    for actuator in [Actuator::M1, Actuator::M2] {
+                        queued += 1;
+                        owners.insert(queued, None);
+                        let _ = link.commands.send(ActuatorCommand::SetSpeed(0, actuator).into()).await;
+                    }
+                }
+            },
+            Some(report) = link.telemetry.recv() => {
+                if report.schema != schema {
+                    schema = Arc::clone(&report.schema);
+                    broadcast(&clients, format!("L {}", schema.encode()));
+                }
+                let values: Vec<String> = report.values.iter().map(f64::to_string).collect();
+                broadcast(&clients, format!("T {} {}", report.actuator as u8, values.join(" ")));
+            }
+            Some(status) = link.status.recv() => {
+                log(&status.to_string());
+                broadcast(&clients, format!("E {}", status.encode()));
+            }
+            Ok((seq, outcome)) = outcomes.recv() => {
+                // any before it were missed while this loop fell behind, and
+                // can only be reported as failed
+                let later = owners.split_off(&seq);
+                let missed = std::mem::replace(&mut owners, later).into_values().map(|id| (id, Outcome::Failed));
+                for (id, outcome) in missed.chain(owners.remove(&seq).map(|id| (id, outcome))) {
+                    if let Some(tx) = id.and_then(|id| clients.get(&id)) {
+                        let _ = tx.try_send(format!("A {}", ack(outcome)));
+                    }
+                }
+            }
+            _ = sleep_until(next_deadline.unwrap_or_else(Instant::now).into()), if next_deadline.is_some() => {
+                let now = Instant::now();
+                for (_, ask) in asks.iter().filter(|(_, ask)| ask.deadline <= now) {
+                    log(&format!("nobody confirmed {}'s `{}`", ask.operator, ask.text));
+                    if let Some(tx) = clients.get(&ask.client) {
+                        let _ = tx.try_send(format!("N nobody confirmed within {:?}", arming.timeout));
+                    }
+                }
+                withdraw(&mut asks, &clients, |ask| ask.deadline <= now);
+            }
+            _ = tokio::signal::ctrl_c() => break Ok(()),
+            _ = terminate.recv() => break Ok(()),
+        }
+    };
+
+    log("shutting down, stopping both actuators");
+    let _ = std::fs::remove_file(path);
+    for actuator in [Actuator::M1, Actuator::M2] {
+        let _ = link.commands.send(ActuatorCommand::SetSpeed(0, actuator).into()).await;
+    }
+    link.close().await;
+    result
+}
+
+/// Why a move was stopped instead while the e-stop is latched.
+const LATCHED: &str = "the e-stop is latched; reset it first";
+
+/// Why a disarmed client's move was stopped instead.
+const NOT_ARMED: &str = "not armed; a second operator has to confirm one of this client's requests first";
+
+/// Whether `cmd` only stops, which a client may send armed or not.
+fn is_stop(cmd: ActuatorCommand) -> bool {
+    matches!(cmd, ActuatorCommand::SetSpeed(0, _))
+}
+
+/// How an `A` line spells `outcome`.
+fn ack(outcome: Outcome) -> &'static str {
+    match outcome {
+        Outcome::Written => "1",
+        Outcome::Failed => "0",
+        Outcome::Coalesced => "c",
+        Outcome::Dropped => "d",
+    }
+}
+
+/// Drops the requests matching `which` and tells everyone they're gone.
+fn withdraw(asks: &mut HashMap<u64, Ask>, clients: &HashMap<u64, mpsc::Sender<String>>, which: impl Fn(&Ask) -> bool) {
+    asks.retain(|token, ask| {
+        if which(ask) {
+            broadcast(clients, format!("X {token}"));
+            false
+        } else {
+            true
+        }
+    });
+}
+
+/// Slow clients miss messages rather than holding everyone else up.
+fn broadcast(clients: &HashMap<u64, mpsc::Sender<String>>, line: String) {
+    for tx in clients.values() {
+        let _ = tx.try_send(line.clone());
+    }
+}
+
+async fn client(id: u64, stream: UnixStream, events: mpsc::Sender<ClientEvent>) {
+    let (r, mut w) = stream.into_split();
+    let (tx, mut rx) = mpsc::channel::<String>(256);
+    if events.send(ClientEvent::Attached(id, tx.clone())).await.is_err() {
+        return;
+    }
+    let mut writer = tokio::spawn(async move {
+        while let Some(line) = rx.recv().await {
+            if w.write_all(format!("{line}\n").as_bytes()).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut r = BufReader::new(r);
+    let mut detached = false;
+    loop {
+        let line = match read_line(&mut r).await {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(e) => {
+                let _ = tx.try_send(format!("S closing: {e}"));
+                // a moment for the writer to pass that on before it's cut off
+                let _ = timeout(Duration::from_millis(100), &mut writer).await;
+                break;
+            }
+        };
+        let event = match parse_line(&line) {
+            Ok(Line::Frame(cmd)) => ClientEvent::Frame(id, cmd),
+            Ok(Line::StopAfter(actuator, after)) => ClientEvent::StopAfter(actuator, after),
+            Ok(Line::Detach) => {
+                detached = true;
+                break;
+            }
+            Ok(Line::Arming(request)) => ClientEvent::Arming(id, request),
+            Ok(Line::EStop(event)) => ClientEvent::EStop(id, event),
+            Err(e) => {
+                let _ = tx.try_send(format!("S ignored `{line}`: {e}"));
+                continue;
+            }
+        };
+        let _ = events.send(event).await;
+    }
+    writer.abort();
+    let _ = events.send(ClientEvent::Gone { id, detached }).await;
+}
+
+/// Reads a line of up to [`MAX_LINE`] bytes, without its newline, or `None`
+/// at the end.
+async fn read_line(r: &mut (impl AsyncBufRead + Unpin)) -> io::Result<Option<String>> {
+    let mut line = Vec::new();
+    if (&mut *r).take(MAX_LINE as u64 + 1).read_until(b'\n', &mut line).await? == 0 {
+        return Ok(None);
+    }
+    if line.last() == Some(&b'\n') {
+        line.pop();
+    } else if line.len() > MAX_LINE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("no newline after {MAX_LINE} bytes")));
+    }
+    String::from_utf8(line).map(Some).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Connects to a daemon serving on `path`.
+pub async fn attach(path: &Path) -> io::Result<Link> {
+    let (r, mut w) = UnixStream::connect(path).await?.into_split();
+
+    let (tx, mut rx) = mpsc::channel::<Request>(100);
+    let (status_tx, status_rx) = mpsc::channel::<StatusEvent>(100);
+    let reports = Reports::new();
+    let source = stream::Source::of(&reports);
+    let (ledger, acks_rx) = Ledger::new();
+    let outcomes = ledger.outcomes();
+    let (requests_tx, mut requests_rx) = mpsc::channel::<arming::Request>(10);
+    let (arming_tx, arming_rx) = mpsc::channel::<arming::Event>(10);
+    let (estop_requests_tx, mut estop_requests_rx) = mpsc::channel::<estop::Event>(10);
+    let (estop_tx, estop_rx) = mpsc::channel::<estop::Event>(10);
+
+    let reader_ledger = ledger.clone();
+    let mut lines = BufReader::new(r).lines();
+    let policy = lines.next_line().await?;
+    match policy.as_deref().and_then(|l| l.strip_prefix("P ")).and_then(|ms| ms.parse::<u64>().ok()) {
+        Some(0) => {}
+        Some(ms) => {
+            let _ = arming_tx.send(arming::Event::Required(Duration::from_millis(ms))).await;
+        }
+        None => return Err(io::Error::new(io::ErrorKind::InvalidData, "not an actuator_controller daemon")),
+    }
+
+    tokio::spawn(async move {
+        let mut schema = Arc::new(Schema::default());
+        while let Ok(Some(line)) = lines.next_line().await {
+            let (kind, rest) = line.split_once(' ').unwrap_or((&line, ""));
+            match kind {
+                "L" => match Schema::parse(rest) {
+                    Ok(advertised) => schema = Arc::new(advertised),
+                    Err(e) => {
+                        let _ = status_tx.send(StatusEvent::Message(format!("Ignoring the daemon's field list: {e}"))).await;
+                    }
+                },
+                "T" => {
+                    let mut words = rest.split_whitespace();
+                    let actuator = words.next().and_then(|a| a.parse::<u8>().ok()).and_then(|a| Actuator::try_from(a).ok());
+                    let values: Option<Vec<f64>> = words.map(|v| v.parse().ok()).collect();
+                    let values = values.filter(|values| values.len() == schema.fields().len());
+                    if let (Some(actuator), Some(values)) = (actuator, values)
+                        && !reports.publish(Report { actuator, schema: Arc::clone(&schema), values })
+                    {
+                        break;
+                    }
+                }
+                "E" => {
+                    let event = StatusEvent::decode(rest).unwrap_or_else(|_| StatusEvent::Message(rest.to_string()));
+                    let _ = status_tx.send(event).await;
+                }
+                "S" => {
+                    let _ = status_tx.send(StatusEvent::Message(rest.to_string())).await;
+                }
+                "A" => reader_ledger.record(match rest {
+                    "1" => Outcome::Written,
+                    "c" => Outcome::Coalesced,
+                    "d" => Outcome::Dropped,
+                    _ => Outcome::Failed,
+                }),
+                "C" => {
+                    let mut parts = rest.splitn(3, ' ');
+                    if let (Some(Ok(token)), Some(operator), Some(text)) =
+                        (parts.next().map(str::parse), parts.next(), parts.next())
+                    {
+                        let event = arming::Event::Requested { token, operator: operator.to_string(), text: text.to_string() };
+                        let _ = arming_tx.send(event).await;
+                    }
+                }
+                "X" => {
+                    if let Ok(token) = rest.parse() {
+                        let _ = arming_tx.send(arming::Event::Withdrawn(token)).await;
+                    }
+                }
+                "G" => {
+                    let _ = arming_tx.send(arming::Event::Granted { operator: rest.to_string() }).await;
+                }
+                "N" => {
+                    let _ = arming_tx.send(arming::Event::Denied(rest.to_string())).await;
+                }
+                "Z" => {
+                    let _ = estop_tx.send(estop::Event::Tripped(rest.to_string())).await;
+                }
+                "U" => {
+                    let _ = estop_tx.send(estop::Event::Reset(rest.to_string())).await;
+                }
+                _ => {}
+            }
+        }
+        let _ = status_tx.send(StatusEvent::Disconnected).await;
+    });
+    let writer = tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                request = rx.recv() => match request {
+                    Some(Request::Frame(cmd)) => {
+                        let [b0, b1, b2, b3] = cmd.serialize();
+                        if w.write_all(format!("F {b0} {b1} {b2} {b3}\n").as_bytes()).await.is_err() {
+                            ledger.record(Outcome::Failed);
+                        }
+                    }
+                    Some(Request::StopAfter(actuator, after)) => {
+                        let _ = w.write_all(format!("W {} {}\n", actuator as u8, after.as_millis()).as_bytes()).await;
+                    }
+                    None => break,
+                },
+                Some(request) = requests_rx.recv() => {
+                    let line = match request {
+                        arming::Request::Ask { operator, text } => format!("R {operator} {}\n", text.replace('\n', " ")),
+                        arming::Request::Confirm { token, operator } => format!("Y {token} {operator}\n"),
+                    };
+                    let _ = w.write_all(line.as_bytes()).await;
+                }
+                Some(event) = estop_requests_rx.recv() => {
+                    let line = match event {
+                        estop::Event::Tripped(why) => format!("Z {}\n", why.replace('\n', " ")),
+                        estop::Event::Reset(operator) => format!("U {operator}\n"),
+                    };
+                    let _ = w.write_all(line.as_bytes()).await;
+                }
+            }
+        }
+        // every sender is gone, so this is a deliberate detach
+        let _ = w.write_all(b"D\n").await;
+        let _ = w.shutdown().await;
+    });
+
+    Ok(Link {
+        commands: tx,
+        status: status_rx,
+        telemetry: source.subscribe(),
+        acks: acks_rx,
+        outcomes,
+        health: watch::channel(Vec::new()).1,
+        arming: Some(arming::Channel { requests: requests_tx, events: arming_rx }),
+        estop: Some(estop::Channel { requests: estop_requests_tx, events: estop_rx }),
+        writer,
+        reports: source,
+        _lock: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::Lines,
+        net::unix::{OwnedReadHalf, OwnedWriteHalf},
+        time::{sleep, timeout},
+    };
+
+    use super::*;
+    use crate::{
+        config::{Config, Table},
+        identify::Model,
+        sim::{self, Profile},
+    };
+
+    /// A daemon over the simulator, on a socket of its own.
+    async fn daemon(name: &str, arming: ArmingConfig) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("actuator_controller-{}-{name}.sock", std::process::id()));
+        let config = Config::from_table(&Table::default()).unwrap();
+        let model = Model { gain: 1e-5, time_constant: 0.1, dead_time: 0.0 };
+        let profile = Profile { models: [model; 2], noise: [0.0; 2], period: Duration::from_millis(20), start: [0.1; 2] };
+        let (link, _) = sim::open(profile, [0.3; 2], config.safety().constraints(), Vec::new());
+        let _ = std::fs::remove_file(&path);
+        tokio::spawn({
+            let path = path.clone();
+            async move { serve(link, &path, arming, |_| {}).await }
+        });
+        while !path.exists() {
+            sleep(Duration::from_millis(10)).await;
+        }
+        path
+    }
+
+    /// A client speaking the line protocol directly, as a raw socket user would.
+    struct Raw {
+        lines: Lines<BufReader<OwnedReadHalf>>,
+        w: OwnedWriteHalf,
+    }
+
+    impl Raw {
+        async fn connect(path: &Path) -> Raw {
+            let (r, w) = UnixStream::connect(path).await.unwrap().into_split();
+            Raw { lines: BufReader::new(r).lines(), w }
+        }
+
+        async fn send(&mut self, line: &str) {
+            self.w.write_all(format!("{line}\n").as_bytes()).await.unwrap();
+        }
+
+        /// Every line up to and including the next starting with `prefix`.
+        async fn until(&mut self, prefix: &str) -> Vec<String> {
+            let mut seen = Vec::new();
+            loop {
+                let line = timeout(Duration::from_secs(5), self.lines.next_line()).await;
+                let line = line.unwrap_or_else(|_| panic!("no `{prefix}` line after {seen:?}")).unwrap().expect("daemon hung up");
+                let done = line.starts_with(prefix);
+                seen.push(line);
+                if done {
+                    return seen;
+                }
+            }
+        }
+
+        async fn next(&mut self, prefix: &str) -> String {
+            self.until(prefix).await.pop().unwrap()
+        }
+    }
+
+    /// Whether any of `lines` says a move was stopped instead.
+    fn stopped(lines: &[String]) -> bool {
+        lines.iter().any(|line| line.starts_with("E constraint_stop"))
+    }
+
+    #[tokio::test]
+    async fn arming_is_enforced() {
+        let arming = ArmingConfig { two_person: true, timeout: Duration::from_secs(10) };
+        let path = daemon("arming", arming).await;
+        let mut alice = Raw::connect(&path).await;
+        assert_eq!(alice.next("P").await, "P 10000");
+
+        // a move before anyone confirms is stopped, a stop goes through
+        alice.send("F 0 0 128 0").await;
+        let seen = alice.until("A").await;
+        assert!(stopped(&seen) && seen.last().unwrap() == "A 1", "{seen:?}");
+        alice.send("F 0 0 0 0").await;
+        let seen = alice.until("A").await;
+        assert!(!stopped(&seen), "{seen:?}");
+
+        let mut bob = Raw::connect(&path).await;
+        alice.send("R alice arm").await;
+        assert_eq!(bob.next("C").await, "C 1 alice arm");
+        // nor can alice confirm her own, from another client or this one
+        alice.send("Y 1 alice").await;
+        assert!(alice.next("S").await.contains("second operator"));
+        alice.send("F 0 0 128 0").await;
+        assert!(stopped(&alice.until("A").await));
+
+        bob.send("Y 1 bob").await;
+        assert_eq!(alice.next("G").await, "G bob");
+        alice.send("F 0 0 128 0").await;
+        let seen = alice.until("A").await;
+        assert!(!stopped(&seen) && seen.last().unwrap() == "A 1", "{seen:?}");
+        // bob wasn't armed by confirming
+        bob.send("F 0 0 128 1").await;
+        assert!(stopped(&bob.until("A").await));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn estop_latches_for_everyone() {
+        let path = daemon("estop", ArmingConfig::default()).await;
+        let mut alice = Raw::connect(&path).await;
+        assert_eq!(alice.next("P").await, "P 0");
+        let mut bob = Raw::connect(&path).await;
+        assert_eq!(bob.next("P").await, "P 0");
+        bob.send("F 0 0 128 0").await;
+        assert!(!stopped(&bob.until("A").await));
+
+        alice.send("Z e-stop key").await;
+        let latched = bob.next("Z").await;
+        assert!(latched.ends_with(": e-stop key"), "{latched}");
+        bob.send("F 0 0 128 0").await;
+        let seen = bob.until("A").await;
+        assert!(seen.iter().any(|line| line.starts_with("E constraint_stop 0 the e-stop is latched")), "{seen:?}");
+        bob.send("F 0 0 0 0").await;
+        assert!(!stopped(&bob.until("A").await));
+        // nor does tripping it again, or attaching afterwards, get around it
+        bob.send("Z").await;
+        let mut carol = Raw::connect(&path).await;
+        assert_eq!(carol.next("Z").await, latched);
+        carol.send("F 1 0 0 1").await;
+        assert!(stopped(&carol.until("A").await));
+
+        bob.send("U bob").await;
+        assert_eq!(alice.next("U").await, "U bob");
+        assert_eq!(carol.next("U").await, "U bob");
+        carol.send("F 0 0 128 0").await;
+        assert!(!stopped(&carol.until("A").await));
+        bob.send("U bob").await;
+        assert!(bob.next("S").await.contains("isn't latched"));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn lines() {
+        assert_eq!(parse_line("F 0 0 128 0"), Ok(Line::Frame(ActuatorCommand::SetSpeed(32768, Actuator::M1))));
+        assert_eq!(parse_line("W 1 2500"), Ok(Line::StopAfter(Actuator::M2, Duration::from_millis(2500))));
+        assert_eq!(parse_line("D"), Ok(Line::Detach));
+        assert_eq!(
+            parse_line("R alice lift goto 0.30"),
+            Ok(Line::Arming(arming::Request::Ask { operator: String::from("alice"), text: String::from("lift goto 0.30") }))
+        );
+        assert_eq!(parse_line("Y 7 bob"), Ok(Line::Arming(arming::Request::Confirm { token: 7, operator: String::from("bob") })));
+        assert_eq!(parse_line("Z gpio  pin 4"), Ok(Line::EStop(estop::Event::Tripped(String::from("gpio  pin 4")))));
+        assert_eq!(parse_line("Z"), Ok(Line::EStop(estop::Event::Tripped(String::new()))));
+        assert_eq!(parse_line("U bob"), Ok(Line::EStop(estop::Event::Reset(String::from("bob")))));
+    }
+
+    #[test]
+    fn operators_split_alike() {
+        // a tab between them, which split_once(' ') used to take as part of the name
+        let Ok(Line::Arming(arming::Request::Ask { operator, text })) = parse_line("R\talice\tangle 35") else { panic!() };
+        assert_eq!((operator.as_str(), text.as_str()), ("alice", "angle 35"));
+        let Ok(Line::Arming(arming::Request::Confirm { operator: confirmer, .. })) = parse_line(&format!("Y 1 {operator}")) else { panic!() };
+        assert_eq!(confirmer, operator);
+        assert!(matches!(parse_line("R alice"), Ok(Line::Arming(arming::Request::Ask { text, .. })) if text.is_empty()));
+    }
+
+    #[test]
+    fn malformed_lines() {
+        for line in [
+            "", "  ", "Q", "f 0 0 128 0", "F", "F 0 0 128", "F 0 0 128 0 0", "F 0 x 0 128 0", "F 0 0 256 0", "F 0 0 128 9", "F 9 0 0 0",
+            "W", "W 0", "W 2 100", "W 0 -1", "W 0 1.5", "W 0 100 200", "R", "R  \t ", "Y", "Y x bob", "Y 7", "Y 7 bob smith",
+            "U", "U bob smith", "z",
+        ] {
+            assert!(parse_line(line).is_err(), "{line:?} parsed");
+        }
+    }
+
+    #[test]
+    fn over_long_stops() {
+        let max = MAX_DURATION.as_millis();
+        assert_eq!(parse_line(&format!("W 0 {max}")), Ok(Line::StopAfter(Actuator::M1, MAX_DURATION)));
+        for ms in [(max + 1).to_string(), u64::MAX.to_string(), String::from("99999999999999999999999")] {
+            assert!(parse_line(&format!("W 0 {ms}")).is_err(), "W 0 {ms} parsed");
+        }
+    }
+
+    #[tokio::test]
+    async fn oversized_lines() {
+        let longest = format!("R alice {}", "x".repeat(MAX_LINE - 8));
+        let mut src = format!("{longest}\nD\nlast").into_bytes();
+        let mut r = src.as_slice();
+        assert_eq!(read_line(&mut r).await.unwrap().as_deref(), Some(longest.as_str()));
+        assert_eq!(read_line(&mut r).await.unwrap().as_deref(), Some("D"));
+        assert_eq!(read_line(&mut r).await.unwrap().as_deref(), Some("last"));
+        assert_eq!(read_line(&mut r).await.unwrap(), None);
+
+        src = format!("R alice {}\n", "x".repeat(1 << 20)).into_bytes();
+        assert!(read_line(&mut src.as_slice()).await.is_err());
+        src = vec![b'F'; MAX_LINE + 1];
+        assert!(read_line(&mut src.as_slice()).await.is_err());
+        assert!(read_line(&mut &b"F \xff\n"[..]).await.is_err());
+
+        // and the daemon hangs up on whoever sends one
+        let path = daemon("oversized", ArmingConfig::default()).await;
+        let mut alice = Raw::connect(&path).await;
+        // short enough to be read whole, so hanging up doesn't reset the connection
+        alice.send(&format!("R alice {}", "x".repeat(4 * MAX_LINE))).await;
+        assert!(alice.next("S").await.contains(&format!("no newline after {MAX_LINE} bytes")));
+        let closed = timeout(Duration::from_secs(5), async {
+            while let Ok(Some(_)) = alice.lines.next_line().await {}
+        });
+        assert!(closed.await.is_ok(), "still attached");
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,160 @@
+//! A Wireshark dissector, in Lua, for `.pcapng` [captures](crate::capture)
+//! (`generate wireshark`), built from the same types that talk to the
+//! firmware, so it can't fall behind the frames.
+//!
+//! Written frames are decoded in full. Reads are decoded a value at a time
+//! in the encoding chosen in the protocol's preferences, as `[telemetry]
+//! encoding` would, or as the handshake or debug text they start. Which
+//! field of a report a value is depends on the reads before it, which a
+//! packet-at-a-time dissector doesn't know; `frames` works that out.
+
+use std::fmt::Write;
+
+use crate::{
+    capture::Chunk,
+    commands::{Actuator, ActuatorCommand, Direction},
+    telemetry::{DEBUG_TEXT, Encoding, HANDSHAKE, Number},
+};
+
+/// A Lua table from numbers to names.
+fn table(entries: impl IntoIterator<Item = (u8, String)>) -> String {
+    let entries: Vec<String> = entries.into_iter().map(|(n, name)| format!("[{n}] = \"{name}\"")).collect();
+    format!("{{ {} }}", entries.join(", "))
+}
+
+pub fn generate() -> String {
+    let chunks = [Chunk::Bytes(Vec::new()), Chunk::Sent(Vec::new()), Chunk::Short, Chunk::Reopen];
+    let kinds = table(chunks.iter().map(|chunk| {
+        let name = match chunk {
+            Chunk::Bytes(_) => "rx",
+            Chunk::Sent(_) => "tx",
+            Chunk::Short => "short read",
+            Chunk::Reopen => "port reopened",
+        };
+        (chunk.kind(), name.to_string())
+    }));
+    let [rx, tx, short, reopen] = chunks.map(|chunk| chunk.kind());
+    let speed = ActuatorCommand::SetSpeed(0, Actuator::M1).serialize()[0];
+    let direction = ActuatorCommand::SetDirection(Direction::Forward, Actuator::M1).serialize()[0];
+    let hold = ActuatorCommand::HoldPosition(Actuator::M1).serialize()[0];
+    let tags = table([(speed, "SetSpeed"), (direction, "SetDirection"), (hold, "HoldPosition")].map(|(n, name)| (n, name.to_string())));
+    let actuators = table([Actuator::M1, Actuator::M2].map(|a| (a as u8, format!("{a:?}"))));
+    let directions = table([Direction::Forward, Direction::Backward].map(|d| {
+        let byte = ActuatorCommand::SetDirection(d, Actuator::M1).serialize()[1];
+        (byte, format!("{d:?}"))
+    }));
+    let mut encodings = String::new();
+    for (i, name) in Encoding::NAMES.iter().enumerate() {
+        let encoding = Encoding::parse(name, 1.0).expect("every name parses");
+        let number = match encoding.number {
+            Number::F64 | Number::F32 => "float",
+            Number::I32 | Number::I16 => "int",
+            Number::U32 | Number::U16 => "uint",
+        };
+        let _ = write!(encodings, "\n    {{ {}, \"{name}\", {}, \"{number}\", {} }},", i + 1, encoding.width(), encoding.big_endian);
+    }
+
+    format!(
+        r#"-- Dissector for actuator_controller `--capture *.pcapng` files.
+-- Generated by `actuator_controller generate wireshark`; regenerate rather than edit.
+-- Copy it to Wireshark's personal Lua plugins folder (Help > About > Folders).
+
+local proto = Proto("actuator", "actuator_controller serial")
+
+local kinds = {kinds}
+local tags = {tags}
+local actuators = {actuators}
+local directions = {directions}
+local RX, TX, SHORT, REOPEN = {rx}, {tx}, {short}, {reopen}
+local SET_SPEED, SET_DIRECTION = {speed}, {direction}
+local HANDSHAKE, DEBUG_TEXT = "{handshake}", "{debug_text}"
+
+-- number, name, width, kind of number, big endian
+local encodings = {{{encodings}
+}}
+local choices = {{}}
+for _, e in ipairs(encodings) do
+    choices[#choices + 1] = {{ e[1], e[2], e[1] }}
+end
+
+local f = {{
+    kind = ProtoField.uint8("actuator.kind", "Kind", base.DEC, kinds),
+    tag = ProtoField.uint8("actuator.command", "Command", base.DEC, tags),
+    speed = ProtoField.uint16("actuator.speed", "Speed", base.DEC),
+    direction = ProtoField.uint8("actuator.direction", "Direction", base.DEC, directions),
+    actuator = ProtoField.uint8("actuator.actuator", "Actuator", base.DEC, actuators),
+    value = ProtoField.double("actuator.value", "Value"),
+    text = ProtoField.string("actuator.text", "Text"),
+    data = ProtoField.bytes("actuator.data", "Data"),
+}}
+proto.fields = {{ f.kind, f.tag, f.speed, f.direction, f.actuator, f.value, f.text, f.data }}
+
+proto.prefs.encoding = Pref.enum("Encoding", 1, "How the firmware sends each value, as [telemetry] encoding", choices, false)
+proto.prefs.scale = Pref.string("Scale", "1", "What each value is multiplied by, as [telemetry] scale")
+
+local function value(range, e)
+    if e[4] == "float" then
+        return e[5] and range:float() or range:le_float()
+    elseif e[4] == "int" then
+        return e[5] and range:int() or range:le_int()
+    end
+    return e[5] and range:uint() or range:le_uint()
+end
+
+function proto.dissector(tvb, pinfo, tree)
+    if tvb:len() < 1 then return end
+    pinfo.cols.protocol = "ACTUATOR"
+    local kind = tvb(0, 1):uint()
+    local t = tree:add(proto, tvb())
+    t:add(f.kind, tvb(0, 1))
+    local n = tvb:len() - 1
+    if kind == TX and n == 4 then
+        local tag = tvb(1, 1):uint()
+        local actuator = actuators[tvb(4, 1):uint()] or "?"
+        t:add(f.tag, tvb(1, 1))
+        local info = (tags[tag] or "unknown command") .. " " .. actuator
+        if tag == SET_SPEED then
+            t:add_le(f.speed, tvb(2, 2))
+            info = info .. " " .. tvb(2, 2):le_uint()
+        elseif tag == SET_DIRECTION then
+            t:add(f.direction, tvb(2, 1))
+            info = info .. " " .. (directions[tvb(2, 1):uint()] or "?")
+        end
+        t:add(f.actuator, tvb(4, 1))
+        pinfo.cols.info = "tx " .. info
+    elseif kind == RX and n > 0 then
+        local body = tvb(1):string()
+        local e = encodings[proto.prefs.encoding] or encodings[1]
+        if body == HANDSHAKE or body == DEBUG_TEXT then
+            t:add(f.text, tvb(1))
+            pinfo.cols.info = "rx " .. (body == HANDSHAKE and "field list follows" or "debug text follows")
+        elseif n % e[3] == 0 then
+            local scale = tonumber(proto.prefs.scale) or 1
+            local values = {{}}
+            for at = 1, n, e[3] do
+                local v = value(tvb(at, e[3]), e) * scale
+                t:add(f.value, tvb(at, e[3]), v)
+                values[#values + 1] = string.format("%.6g", v)
+            end
+            pinfo.cols.info = "rx " .. table.concat(values, ", ")
+        else
+            t:add(f.data, tvb(1))
+            local text = body:gsub("[^%g ]", ".")
+            pinfo.cols.info = "rx " .. n .. " byte(s): " .. text
+        end
+    elseif kind == SHORT then
+        pinfo.cols.info = "short read: the reader threw away what it had"
+    elseif kind == REOPEN then
+        pinfo.cols.info = "the watchdog reopened the port"
+    elseif n > 0 then
+        t:add(f.data, tvb(1))
+    end
+end
+
+local encap = wtap_encaps or wtap
+DissectorTable.get("wtap_encap"):add(encap.USER0, proto)
+"#,
+        handshake = String::from_utf8_lossy(&HANDSHAKE),
+        debug_text = String::from_utf8_lossy(&DEBUG_TEXT),
+    )
+}
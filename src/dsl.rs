@@ -0,0 +1,528 @@
+//! One-line motion commands, e.g. `m1 fwd 50% for 2s` or `lift goto 0.30`.
+//!
+//! ```text
+//! [speed] <actuator> <direction> <speed> [for <duration>]
+//! [speed] <actuator> <direction> <speed> until <current>
+//! <actuator> goto <meters>
+//! <actuator> stop | stop
+//! estop
+//! <actuator> hold
+//! <group> <direction> <speed> | <group> goto <meters> | <group> stop | <group> hold
+//! angle <degrees> [<height>]
+//! mark <note>
+//! ```
+//!
+//! Actuators are `m1`/`lift` and `m2`/`bucket`. Groups are named in the
+//! config, see [`crate::groups`], and only read by the `_with` parsers. Speeds are either a percentage
+//! of full speed (`50%`) or a raw value (`32000`), durations take `ms`, `s` or
+//! `min` (`1500ms`, `2s`). A timed drive (`for`) is stopped by the link
+//! itself once its time is up, see [`crate::link::Request::StopAfter`], unless
+//! something stops it sooner. A push (`until`) drives until the actuator draws
+//! more than the given current, in the unit of the firmware's `current` field.
+//!
+//! The same commands can be given as JSON objects, see [`from_json`].
+
+use std::{borrow::Cow, fmt, time::Duration};
+
+use crate::{
+    aliases::Aliases,
+    commands::{Actuator, Direction},
+    groups::{Group, Groups},
+    json,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Drive {
+        actuator: Actuator,
+        direction: Direction,
+        speed: u16,
+        /// stop again after this long
+        duration: Option<Duration>,
+    },
+    /// drive until the actuator draws more than `limit` current, then back off
+    Push {
+        actuator: Actuator,
+        direction: Direction,
+        speed: u16,
+        limit: f64,
+    },
+    Goto { actuator: Actuator, len: f64 },
+    /// `None` stops everything
+    Stop(Option<Actuator>),
+    /// stop everything and latch the e-stop, see [`crate::estop`]
+    EStop,
+    /// have the firmware hold the actuator where it is, see
+    /// [`ActuatorCommand::HoldPosition`](crate::commands::ActuatorCommand::HoldPosition)
+    Hold(Actuator),
+    Angle { degrees: f64, height: Option<f64> },
+    /// a note for the logs, e.g. `mark hit rock`; nothing moves
+    Mark(String),
+    /// follow the trajectory in a CSV file, see [`crate::trajectory`]
+    Follow(String),
+    /// the same stop, hold, goto or drive for each actuator in a group, all
+    /// started at once
+    Group { name: String, commands: Vec<Command> },
+}
+
+impl Command {
+    /// Moves that run on their own once started, which two-person arming
+    /// holds and only an admin may start over the bridge.
+    pub fn is_autonomous(&self) -> bool {
+        matches!(
+            self,
+            Command::Drive { duration: Some(_), .. }
+                | Command::Push { .. }
+                | Command::Goto { .. }
+                | Command::Angle { .. }
+                | Command::Follow(_)
+        ) || matches!(self, Command::Group { commands, .. } if commands.iter().any(Command::is_autonomous))
+    }
+
+    /// Whether all it does is stop.
+    pub fn is_stop(&self) -> bool {
+        match self {
+            Command::Stop(_) | Command::EStop => true,
+            Command::Group { commands, .. } => commands.iter().all(Command::is_stop),
+            _ => false,
+        }
+    }
+}
+
+/// Back to the text form, e.g. for logs. Parsing it gives the same command.
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = |a: &Actuator| format!("{a:?}").to_lowercase();
+        let dir = |d: &Direction| match d {
+            Direction::Forward => "fwd",
+            Direction::Backward => "back",
+        };
+        match self {
+            Command::Drive { actuator, direction, speed, duration } => {
+                write!(f, "{} {} {speed}", name(actuator), dir(direction))?;
+                if let Some(duration) = duration {
+                    write!(f, " for {}ms", duration.as_millis())?;
+                }
+                Ok(())
+            }
+            Command::Push { actuator, direction, speed, limit } => {
+                write!(f, "{} {} {speed} until {limit}", name(actuator), dir(direction))
+            }
+            Command::Goto { actuator, len } => write!(f, "{} goto {len}", name(actuator)),
+            Command::Stop(None) => f.write_str("stop"),
+            Command::Stop(Some(actuator)) => write!(f, "{} stop", name(actuator)),
+            Command::EStop => f.write_str("estop"),
+            Command::Hold(actuator) => write!(f, "{} hold", name(actuator)),
+            Command::Angle { degrees, height: None } => write!(f, "angle {degrees}"),
+            Command::Angle { degrees, height: Some(height) } => write!(f, "angle {degrees} {height}"),
+            Command::Mark(note) => write!(f, "mark {note}"),
+            Command::Follow(path) => write!(f, "follow {path}"),
+            Command::Group { name, commands } => {
+                // what each member does, without its name
+                let each = commands.first().map(Command::to_string).unwrap_or_default();
+                write!(f, "{name} {}", each.split_once(' ').map_or("", |(_, verb)| verb))
+            }
+        }
+    }
+}
+
+/// The longest a timed drive or any other duration may be, so a typo or a
+/// hostile client can't ask for one that overflows.
+pub const MAX_DURATION: Duration = Duration::from_secs(24 * 3600);
+
+const USAGE: &str = "try `m1 fwd 50% for 2s`, `lift goto 0.30`, `stop`, `angle 35`, `follow dig.csv` or `mark hit rock`";
+
+pub fn parse(line: &str) -> Result<Command, String> {
+    parse_with(line, &Groups::default())
+}
+
+/// [`parse`], also taking commands for any of `groups`.
+pub fn parse_with(line: &str, groups: &Groups) -> Result<Command, String> {
+    let mut words = line.split_whitespace();
+    let Some(first) = words.next() else {
+        return Err(format!("empty command; {USAGE}"));
+    };
+
+    let command = match first.to_ascii_lowercase().as_str() {
+        "mark" => {
+            let note = words.collect::<Vec<_>>().join(" ");
+            return if note.is_empty() { Err(String::from("`mark` needs a note after it")) } else { Ok(Command::Mark(note)) };
+        }
+        "follow" => {
+            // the rest of the line as written, spaces and all
+            let path = line.trim_start()[first.len()..].trim();
+            return if path.is_empty() {
+                Err(String::from("`follow` needs the path of a trajectory CSV after it"))
+            } else {
+                Ok(Command::Follow(path.to_string()))
+            };
+        }
+        // `speed m1 fwd 50%` reads the same as `m1 fwd 50%`; strip every
+        // leading `speed` here rather than recursing once per word
+        "speed" => {
+            let mut rest = line.trim_start()[first.len()..].trim_start();
+            while let Some(word) = rest.split_whitespace().next().filter(|w| w.eq_ignore_ascii_case("speed")) {
+                rest = rest[word.len()..].trim_start();
+            }
+            let drive = |command: &Command| matches!(command, Command::Drive { .. } | Command::Push { .. });
+            return match parse_with(rest.trim_end(), groups)? {
+                command if drive(&command) => Ok(command),
+                Command::Group { name, commands } if commands.iter().all(drive) => Ok(Command::Group { name, commands }),
+                _ => Err(String::from("`speed` needs an actuator, a direction and a speed after it")),
+            };
+        }
+        "stop" => match words.next() {
+            None => Command::Stop(None),
+            Some(w) => Command::Stop(Some(actuator(w)?)),
+        },
+        "estop" => Command::EStop,
+        "angle" => Command::Angle {
+            degrees: number(words.next(), "an angle in degrees after `angle`")?,
+            height: words.next().map(|w| number(Some(w), "a height in meters")).transpose()?,
+        },
+        name => {
+            let Some(actuator) = parse_actuator(name) else {
+                if let Some(group) = groups.get(name) {
+                    let verb = &line.trim_start()[first.len()..];
+                    return for_group(group, |member| parse(&format!("{member:?}{verb}")));
+                }
+                return Err(format!("unknown command or actuator `{first}`; {USAGE}"));
+            };
+            let Some(verb) = words.next() else {
+                return Err(format!("`{first}` needs a direction, `goto`, `stop` or `hold` after it"));
+            };
+            match verb.to_ascii_lowercase().as_str() {
+                "stop" => Command::Stop(Some(actuator)),
+                "hold" => Command::Hold(actuator),
+                "goto" => Command::Goto {
+                    actuator,
+                    len: number(words.next(), "a length in meters after `goto`")?,
+                },
+                dir => {
+                    let Some(direction) = parse_direction(dir) else {
+                        return Err(format!(
+                            "unknown direction `{verb}`; expected fwd/back, extend/retract, goto, stop or hold"
+                        ));
+                    };
+                    let speed = speed(words.next())?;
+                    match words.next() {
+                        None => Command::Drive { actuator, direction, speed, duration: None },
+                        Some(w) if w.eq_ignore_ascii_case("for") => {
+                            Command::Drive { actuator, direction, speed, duration: Some(duration(words.next())?) }
+                        }
+                        Some(w) if w.eq_ignore_ascii_case("until") => {
+                            Command::Push { actuator, direction, speed, limit: limit(words.next())? }
+                        }
+                        Some(w) => {
+                            return Err(format!(
+                                "expected `for <duration>` or `until <current>` after the speed, found `{w}`"
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+    };
+
+    match words.next() {
+        None => Ok(command),
+        Some(extra) => Err(format!("unexpected `{extra}` at the end of the command")),
+    }
+}
+
+/// The words a config adds: its groups and aliases.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Vocabulary {
+    pub groups: Groups,
+    pub aliases: Aliases,
+}
+
+impl Vocabulary {
+    /// Either form of command, as [`parse_line_with`], after expanding an alias.
+    pub fn parse(&self, line: &str) -> Result<Command, String> {
+        let expanded = self.aliases.expand(line);
+        parse_line_with(&expanded, &self.groups).map_err(|e| match expanded {
+            Cow::Owned(expanded) => format!("`{expanded}`: {e}"),
+            Cow::Borrowed(_) => e,
+        })
+    }
+}
+
+/// Either form: a line starting with `{` is JSON, anything else a one-line command.
+pub fn parse_line(line: &str) -> Result<Command, String> {
+    parse_line_with(line, &Groups::default())
+}
+
+/// [`parse_line`], also taking commands for any of `groups`.
+pub fn parse_line_with(line: &str, groups: &Groups) -> Result<Command, String> {
+    if line.starts_with('{') {
+        json::parse(line).and_then(|v| from_json_with(&v, groups))
+    } else {
+        parse_with(line, groups)
+    }
+}
+
+/// A command written as JSON:
+///
+/// ```text
+/// {"cmd": "drive", "actuator": "m1", "direction": "fwd", "speed": "50%", "duration_ms": 2000}
+/// {"cmd": "push", "actuator": "m2", "direction": "fwd", "speed": "30%", "limit": 4}
+/// {"cmd": "goto", "actuator": "lift", "len": 0.30}
+/// {"cmd": "stop"}  {"cmd": "stop", "actuator": "m2"}
+/// {"cmd": "estop"}
+/// {"cmd": "hold", "actuator": "m1"}
+/// {"cmd": "angle", "degrees": 35, "height": 0.5}
+/// {"cmd": "mark", "note": "hit rock"}
+/// {"cmd": "follow", "path": "dig.csv"}
+/// ```
+///
+/// With [`from_json_with`], `"group": "both"` can stand in for `"actuator"`.
+pub fn from_json(value: &json::Value) -> Result<Command, String> {
+    from_json_with(value, &Groups::default())
+}
+
+pub fn from_json_with(value: &json::Value, groups: &Groups) -> Result<Command, String> {
+    let field = |key: &str| value.get(key).filter(|v| **v != json::Value::Null);
+    let text = |key: &str| match field(key) {
+        None => Err(format!("missing `{key}`")),
+        Some(v) => v.as_str().ok_or_else(|| format!("`{key}` must be a string")),
+    };
+    let num = |key: &str| match field(key) {
+        None => Err(format!("missing `{key}`")),
+        Some(v) => v.as_f64().filter(|n| n.is_finite()).ok_or_else(|| format!("`{key}` must be a number")),
+    };
+    let Some(cmd) = field("cmd") else {
+        return Err(String::from(r#"expected an object with a "cmd" field"#));
+    };
+    if let (Some(_), json::Value::Object(fields)) = (field("group"), value) {
+        let name = text("group")?;
+        let group = groups.get(name).ok_or_else(|| format!("unknown group `{name}`"))?;
+        return for_group(group, |member| {
+            let mut fields: Vec<_> = fields.iter().filter(|(key, _)| key != "group" && key != "actuator").cloned().collect();
+            fields.push((String::from("actuator"), json::Value::String(format!("{member:?}"))));
+            from_json(&json::Value::Object(fields))
+        });
+    }
+
+    let direction = || {
+        let dir = text("direction")?;
+        parse_direction(&dir.to_ascii_lowercase())
+            .ok_or_else(|| format!("unknown direction `{dir}`; expected fwd/back or extend/retract"))
+    };
+    let json_speed = || match field("speed") {
+        Some(json::Value::Number(n)) if n.fract() == 0.0 && (0.0..=u16::MAX as f64).contains(n) => Ok(*n as u16),
+        Some(json::Value::String(s)) => speed(Some(s)),
+        _ => Err(format!("`speed` must be a percentage string or a whole number up to {}", u16::MAX)),
+    };
+
+    match cmd.as_str() {
+        Some("drive") => Ok(Command::Drive {
+            actuator: actuator(text("actuator")?)?,
+            direction: direction()?,
+            speed: json_speed()?,
+            duration: match field("duration_ms") {
+                None => None,
+                Some(_) => {
+                    let ms = num("duration_ms")?;
+                    if ms < 0.0 {
+                        return Err(String::from("`duration_ms` can't be negative"));
+                    }
+                    let limit = MAX_DURATION.as_millis();
+                    Some(seconds(ms / 1000.0).ok_or_else(|| format!("`duration_ms` can't be more than {limit}, not {ms}"))?)
+                }
+            },
+        }),
+        Some("push") => Ok(Command::Push {
+            actuator: actuator(text("actuator")?)?,
+            direction: direction()?,
+            speed: json_speed()?,
+            limit: match num("limit")? {
+                limit if limit > 0.0 => limit,
+                limit => return Err(format!("`limit` must be a positive current, not {limit}")),
+            },
+        }),
+        Some("goto") => Ok(Command::Goto {
+            actuator: actuator(text("actuator")?)?,
+            len: num("len")?,
+        }),
+        Some("stop") => match field("actuator") {
+            None => Ok(Command::Stop(None)),
+            Some(_) => Ok(Command::Stop(Some(actuator(text("actuator")?)?))),
+        },
+        Some("estop") => Ok(Command::EStop),
+        Some("hold") => Ok(Command::Hold(actuator(text("actuator")?)?)),
+        Some("angle") => Ok(Command::Angle {
+            degrees: num("degrees")?,
+            height: field("height").map(|_| num("height")).transpose()?,
+        }),
+        Some("mark") => match text("note")?.split_whitespace().collect::<Vec<_>>().join(" ") {
+            note if note.is_empty() => Err(String::from("`note` can't be empty")),
+            note => Ok(Command::Mark(note)),
+        },
+        Some("follow") => match text("path")?.trim() {
+            "" => Err(String::from("`path` can't be empty")),
+            path => Ok(Command::Follow(path.to_string())),
+        },
+        Some(other) => Err(format!("unknown cmd `{other}`; expected drive, push, goto, stop, estop, hold, angle, mark or follow")),
+        None => Err(String::from("`cmd` must be a string")),
+    }
+}
+
+/// `each` for every member of `group`, as long as it's something a group can do.
+fn for_group(group: &Group, each: impl Fn(Actuator) -> Result<Command, String>) -> Result<Command, String> {
+    let commands = group.members.iter().map(|&member| each(member)).collect::<Result<Vec<_>, _>>()?;
+    let ok = |cmd: &Command| {
+        matches!(cmd, Command::Stop(Some(_)) | Command::Hold(_) | Command::Goto { .. } | Command::Drive { duration: None, .. })
+    };
+    if !commands.iter().all(ok) {
+        return Err(format!("`{}` is a group, which can only stop, hold, goto or drive without `for` or `until`", group.name));
+    }
+    Ok(Command::Group { name: group.name.clone(), commands })
+}
+
+pub fn parse_actuator(word: &str) -> Option<Actuator> {
+    match word.to_ascii_lowercase().as_str() {
+        "m1" | "lift" => Some(Actuator::M1),
+        "m2" | "bucket" => Some(Actuator::M2),
+        _ => None,
+    }
+}
+
+/// A speed like `50%` or `32000`.
+pub fn parse_speed(word: &str) -> Result<u16, String> {
+    speed(Some(word))
+}
+
+/// A duration like `2s` or `1500ms`.
+pub fn parse_duration(word: &str) -> Result<Duration, String> {
+    duration(Some(word))
+}
+
+fn parse_direction(word: &str) -> Option<Direction> {
+    match word {
+        "fwd" | "forward" | "extend" | "out" => Some(Direction::Forward),
+        "back" | "backward" | "rev" | "reverse" | "retract" | "in" => Some(Direction::Backward),
+        _ => None,
+    }
+}
+
+fn actuator(word: &str) -> Result<Actuator, String> {
+    parse_actuator(word).ok_or_else(|| format!("unknown actuator `{word}`; expected m1, m2, lift or bucket"))
+}
+
+fn number(word: Option<&str>, expected: &str) -> Result<f64, String> {
+    let Some(word) = word else {
+        return Err(format!("expected {expected}"));
+    };
+    match word.parse::<f64>() {
+        Ok(n) if n.is_finite() => Ok(n),
+        _ => Err(format!("expected {expected}, found `{word}`")),
+    }
+}
+
+fn limit(word: Option<&str>) -> Result<f64, String> {
+    match number(word, "a current limit after `until`")? {
+        limit if limit > 0.0 => Ok(limit),
+        limit => Err(format!("the current limit must be positive, not {limit}")),
+    }
+}
+
+fn speed(word: Option<&str>) -> Result<u16, String> {
+    let Some(word) = word else {
+        return Err(String::from("expected a speed like `50%` or `32000`"));
+    };
+    if let Some(pct) = word.strip_suffix('%') {
+        return match pct.parse::<f64>() {
+            Ok(p) if (0.0..=100.0).contains(&p) => Ok((p / 100.0 * u16::MAX as f64).round() as u16),
+            _ => Err(format!("speed `{word}` must be between 0% and 100%")),
+        };
+    }
+    word.parse::<u16>()
+        .map_err(|_| format!("speed `{word}` must be a percentage or a whole number up to {}", u16::MAX))
+}
+
+fn duration(word: Option<&str>) -> Result<Duration, String> {
+    let Some(word) = word else {
+        return Err(String::from("expected a duration like `2s` or `1500ms` after `for`"));
+    };
+    let split = word.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(word.len());
+    let (value, unit) = word.split_at(split);
+    let scale = match unit {
+        "ms" => 0.001,
+        "s" | "" => 1.0,
+        "min" => 60.0,
+        _ => return Err(format!("unknown time unit `{unit}` in `{word}`; use ms, s or min")),
+    };
+    match value.parse::<f64>() {
+        Ok(v) if v >= 0.0 && v.is_finite() => {
+            seconds(v * scale).ok_or_else(|| format!("`{word}` is longer than the {} h allowed", MAX_DURATION.as_secs() / 3600))
+        }
+        _ => Err(format!("can't read `{word}` as a duration")),
+    }
+}
+
+/// `secs` as a duration, if it's one of up to [`MAX_DURATION`].
+fn seconds(secs: f64) -> Option<Duration> {
+    Duration::try_from_secs_f64(secs).ok().filter(|d| *d <= MAX_DURATION)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timed_drive() {
+        let cmd = parse("m1 fwd 50% for 2s").unwrap();
+        let Command::Drive { duration, .. } = cmd else { panic!("not a drive: {cmd:?}") };
+        assert_eq!(duration, Some(Duration::from_secs(2)));
+        assert_eq!(parse_duration("1.5min"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("250ms"), Ok(Duration::from_millis(250)));
+    }
+
+    #[test]
+    fn over_long_durations() {
+        assert!(parse("m1 fwd 50% for 99999999999999999999999s").is_err());
+        assert!(parse_duration("1e300min").is_err());
+        assert!(parse_duration("25h").is_err());
+        assert!(parse_duration(&format!("{}s", MAX_DURATION.as_secs() + 1)).is_err());
+        assert_eq!(parse_duration(&format!("{}s", MAX_DURATION.as_secs())), Ok(MAX_DURATION));
+    }
+
+    #[test]
+    fn malformed() {
+        for line in ["", "   ", "m1", "m1 fwd", "m1 fwd 50% for", "m1 fwd 50% for -2s", "m1 fwd 50% for NaNs", "m3 fwd 50%", "mark", "fly"] {
+            assert!(parse(line).is_err(), "{line:?} parsed");
+        }
+        for word in ["", "s", "infs", "-1s", "2 s", "2sec"] {
+            assert!(parse_duration(word).is_err(), "{word:?} parsed");
+        }
+    }
+
+    #[test]
+    fn json_drive() {
+        let drive = |ms: &str| parse_line(&format!(r#"{{"cmd": "drive", "actuator": "m1", "direction": "fwd", "speed": "50%", "duration_ms": {ms}}}"#));
+        assert!(matches!(drive("2000"), Ok(Command::Drive { duration: Some(d), .. }) if d == Duration::from_secs(2)));
+        for ms in ["1e30", "1e400", "-1", "\"2s\"", "86400001"] {
+            assert!(drive(ms).is_err(), "duration_ms {ms} parsed");
+        }
+        for line in [r#"{"cmd": "drive"}"#, r#"{"cmd": "fly"}"#, r#"{"actuator": "m1"}"#, "[]", "{", r#"{"cmd": "stop"} x"#] {
+            assert!(parse_line(line).is_err(), "{line:?} parsed");
+        }
+        assert!(parse_line(&format!("{}{}", "{\"a\":".repeat(100_000), "1")).is_err());
+    }
+
+    #[test]
+    fn oversized_line() {
+        let line = format!("mark {}", "x".repeat(1 << 20));
+        assert!(matches!(parse(&line), Ok(Command::Mark(note)) if note.len() == 1 << 20));
+        assert!(parse(&"m1 ".repeat(100_000)).is_err());
+    }
+
+    #[test]
+    fn repeated_speed() {
+        assert!(parse(&"speed ".repeat(100_000)).is_err());
+        let line = format!("{}m1 fwd 50%", "speed ".repeat(100_000));
+        assert!(matches!(parse(&line), Ok(Command::Drive { .. })));
+        assert!(parse("speed stop").is_err());
+    }
+}
@@ -0,0 +1,94 @@
+//! The software e-stop: once tripped, by the `!` key, an `estop` command from
+//! any input source, or an alarm set to stop, both actuators are stopped and
+//! stay stopped. Anything that would move them is refused until the operator
+//! resets it on purpose, in two steps: `E` to ask, then `y` within
+//! [`RESET_WINDOW`] to confirm. Tripping it again while it's latched keeps
+//! the first reason; the reset clears everything at once.
+//!
+//! Attached to a daemon, the daemon keeps the latch for every client: a trip
+//! or reset on one is passed on to all the others, and while it's latched the
+//! daemon stops anything any of them tries to move.
+
+use std::time::{Duration, Instant};
+
+use tokio::sync::mpsc;
+
+/// How long a reset asked for waits for its confirmation.
+pub const RESET_WINDOW: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Latch {
+    /// why it was tripped, while it's latched
+    tripped: Option<String>,
+    /// when the operator asked to reset it
+    reset_asked: Option<Instant>,
+    /// news for the daemon, sent at the top of the next loop
+    pub outbox: Vec<Event>,
+}
+
+/// Between a client and its daemon, either way.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    /// latched, for this reason
+    Tripped(String),
+    /// reset by this operator
+    Reset(String),
+}
+
+/// The e-stop half of a link to a daemon.
+pub struct Channel {
+    pub requests: mpsc::Sender<Event>,
+    pub events: mpsc::Receiver<Event>,
+}
+
+impl Latch {
+    /// Latches it, for `why`. Returns whether it wasn't already.
+    pub fn trip(&mut self, why: &str) -> bool {
+        self.reset_asked = None;
+        if self.tripped.is_some() {
+            return false;
+        }
+        self.tripped = Some(why.to_string());
+        true
+    }
+
+    pub fn is_latched(&self) -> bool {
+        self.tripped.is_some()
+    }
+
+    /// What tripped it, while it's latched.
+    pub fn why(&self) -> Option<&str> {
+        self.tripped.as_deref()
+    }
+
+    /// The first step of a reset.
+    pub fn ask_reset(&mut self, now: Instant) -> Result<(), String> {
+        if self.tripped.is_none() {
+            return Err(String::from("the e-stop isn't latched"));
+        }
+        self.reset_asked = Some(now);
+        Ok(())
+    }
+
+    /// Whether a reset has been asked for and can still be confirmed.
+    pub fn is_asking(&self, now: Instant) -> bool {
+        self.reset_asked.is_some_and(|asked| now.duration_since(asked) < RESET_WINDOW)
+    }
+
+    /// The second step, giving back what had tripped it.
+    pub fn confirm_reset(&mut self, now: Instant) -> Result<String, String> {
+        if !self.is_asking(now) {
+            self.reset_asked = None;
+            return Err(String::from("no reset to confirm; press E first"));
+        }
+        self.reset_asked = None;
+        self.tripped.take().ok_or_else(|| String::from("the e-stop isn't latched"))
+    }
+
+    /// Unlatches it without the two steps, for a reset made elsewhere, giving
+    /// back what had tripped it if it was latched.
+    pub fn clear(&mut self) -> Option<String> {
+        self.reset_asked = None;
+        self.tripped.take()
+    }
+}
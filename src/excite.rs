@@ -0,0 +1,88 @@
+//! `--excite`: drive each actuator with a test signal for a while, printing
+//! every speed sent alongside the telemetry it caused, for system
+//! identification.
+//!
+//! With `--output json` the whole run is one log: `drive` events for what
+//! was commanded and `telemetry` events for what came back, on the same
+//! clock.
+
+use std::time::{Duration, Instant};
+
+use actuator_controller::{
+    app::App,
+    commands::{Actuator, Drive},
+    link::Link,
+    signal::Signal,
+    update::Msg,
+};
+use tokio::time::{interval, MissedTickBehavior};
+
+use crate::output::{Event, Output};
+
+/// How often the signals are sampled: as often as the link writes a frame.
+pub const SAMPLE: Duration = Duration::from_millis(50);
+
+/// Runs the signals for `duration`, or until Ctrl-C, then stops both
+/// actuators. Returns the process exit code.
+pub async fn run(link: &mut Link, mut app: App, signals: &[Signal], duration: Duration, out: &Output) -> i32 {
+    if let Some(channel) = &mut link.arming {
+        app.sync_arming(channel);
+    }
+    if app.arming.required() {
+        let message = "two-person arming is on, and a test signal can't wait for a second operator";
+        out.emit(Event::Error { command: "excite", message });
+        out.emit(Event::Done { failures: 1 });
+        return 1;
+    }
+    for signal in signals {
+        app.audit.record(&format!("excite {signal} for {duration:?}"));
+    }
+
+    let start = Instant::now();
+    let mut last: [Option<i32>; 2] = [None; 2];
+    let mut ticks = interval(SAMPLE);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let mut failures = 0;
+    loop {
+        tokio::select! {
+            _ = ticks.tick() => {}
+            Some(report) = link.telemetry.recv() => {
+                out.emit(Event::Telemetry(&report));
+                app.update(Msg::Telemetry(report));
+                continue;
+            }
+            Some(status) = link.status.recv() => {
+                if status.is_error() {
+                    failures += 1;
+                }
+                out.emit(Event::Status(&status));
+                app.update(Msg::Status(status));
+                continue;
+            }
+            _ = tokio::signal::ctrl_c() => break,
+            else => break,
+        }
+        let elapsed = start.elapsed();
+        if elapsed >= duration {
+            break;
+        }
+        for signal in signals {
+            let speed = signal.speed(elapsed);
+            if last[signal.actuator as usize] != Some(speed) {
+                last[signal.actuator as usize] = Some(speed);
+                out.emit(Event::Drive { actuator: signal.actuator, speed });
+                app.pending.push(Drive::new(signal.actuator, speed));
+            }
+        }
+        app.flush(&link.commands).await;
+    }
+
+    for actuator in [Actuator::M1, Actuator::M2] {
+        out.emit(Event::Drive { actuator, speed: 0 });
+        app.pending.push(Drive::stop(actuator));
+    }
+    app.flush(&link.commands).await;
+    app.audit.record("excite done");
+    out.emit(Event::Done { failures });
+    if failures > 0 { 1 } else { 0 }
+}
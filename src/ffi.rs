@@ -0,0 +1,292 @@
+//! `extern "C"` bindings over [`ActuatorClient`] for programs that can't link
+//! Rust directly. The matching header is `include/actuator_controller.h`.
+//!
+//! Every call blocks until the command has finished, exactly like
+//! [`ActuatorClient::run`]. Functions returning `int` give 0 on success and
+//! -1 on failure, with the reason available from `ac_last_error`.
+
+use std::{
+    ffi::{CStr, CString, c_char, c_int},
+    path::Path,
+    ptr,
+    time::Duration,
+};
+
+use tokio::runtime::Runtime;
+
+use crate::{
+    client::ActuatorClient,
+    commands::{Actuator, Direction},
+    config::{self, Config},
+};
+
+pub struct AcClient {
+    runtime: Runtime,
+    client: ActuatorClient,
+    last_error: CString,
+}
+
+impl AcClient {
+    fn result(&mut self, result: Result<(), String>) -> c_int {
+        match result {
+            Ok(()) => 0,
+            Err(e) => {
+                self.last_error = CString::new(e.replace('\0', "")).unwrap_or_default();
+                -1
+            }
+        }
+    }
+}
+
+unsafe fn str_arg<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        return None;
+    }
+    unsafe { CStr::from_ptr(s) }.to_str().ok()
+}
+
+/// Opens the port and returns a client, or NULL if the port or config
+/// couldn't be opened.
+///
+/// # Safety
+/// `port_path` must be a NUL-terminated string. `config_path` must be one
+/// too or NULL, in which case `actuator.toml` is used if it exists.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ac_connect(port_path: *const c_char, config_path: *const c_char) -> *mut AcClient {
+    let Some(port_path) = (unsafe { str_arg(port_path) }) else {
+        return ptr::null_mut();
+    };
+    let config_path = unsafe { str_arg(config_path) }.unwrap_or(config::DEFAULT_PATH);
+    let Ok(config) = Config::load(Path::new(config_path)) else {
+        return ptr::null_mut();
+    };
+    let Ok(runtime) = tokio::runtime::Builder::new_multi_thread().enable_all().build() else {
+        return ptr::null_mut();
+    };
+    let Ok(client) = runtime.block_on(async { ActuatorClient::connect(port_path, &config) }) else {
+        return ptr::null_mut();
+    };
+    Box::into_raw(Box::new(AcClient {
+        runtime,
+        client,
+        last_error: CString::default(),
+    }))
+}
+
+/// Stops both actuators and frees the client.
+///
+/// # Safety
+/// `client` must come from `ac_connect` and not be used afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ac_free(client: *mut AcClient) {
+    if client.is_null() {
+        return;
+    }
+    let AcClient { runtime, mut client, .. } = *unsafe { Box::from_raw(client) };
+    runtime.block_on(async {
+        let _ = client.stop(None).await;
+        client.close().await;
+    });
+}
+
+/// Message for the last failed call on `client`. Valid until the next call.
+///
+/// # Safety
+/// `client` must be a live pointer from `ac_connect`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ac_last_error(client: *const AcClient) -> *const c_char {
+    unsafe { &*client }.last_error.as_ptr()
+}
+
+/// How long a call may take before it's stopped and fails.
+///
+/// # Safety
+/// `client` must be a live pointer from `ac_connect`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ac_set_timeout_ms(client: *mut AcClient, timeout_ms: u32) {
+    unsafe { &mut *client }.client.set_timeout(Duration::from_millis(timeout_ms as u64));
+}
+
+/// `actuator` is 0 for M1 and 1 for M2, `direction` 0 for forward and 1 for backward.
+///
+/// # Safety
+/// `client` must be a live pointer from `ac_connect`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ac_set_speed(client: *mut AcClient, actuator: u8, direction: u8, speed: u16) -> c_int {
+    let c = unsafe { &mut *client };
+    let direction = match direction {
+        0 => Direction::Forward,
+        1 => Direction::Backward,
+        _ => return c.result(Err(String::from("Invalid direction value"))),
+    };
+    let result = match Actuator::try_from(actuator) {
+        Ok(actuator) => c.runtime.block_on(c.client.set_speed(actuator, direction, speed)).into_result().map(drop),
+        Err(e) => Err(e.to_string()),
+    };
+    c.result(result)
+}
+
+/// Moves `actuator` to `len` meters and waits until it gets there.
+///
+/// # Safety
+/// `client` must be a live pointer from `ac_connect`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ac_goto(client: *mut AcClient, actuator: u8, len: f64) -> c_int {
+    let c = unsafe { &mut *client };
+    let result = match Actuator::try_from(actuator) {
+        Ok(actuator) => c.runtime.block_on(c.client.goto(actuator, len)).into_result().map(drop),
+        Err(e) => Err(e.to_string()),
+    };
+    c.result(result)
+}
+
+/// Stops `actuator` (0 or 1), or both if it's anything else.
+///
+/// # Safety
+/// `client` must be a live pointer from `ac_connect`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ac_stop(client: *mut AcClient, actuator: c_int) -> c_int {
+    let c = unsafe { &mut *client };
+    let actuator = u8::try_from(actuator).ok().and_then(|a| Actuator::try_from(a).ok());
+    let result = c.runtime.block_on(c.client.stop(actuator)).into_result().map(drop);
+    c.result(result)
+}
+
+/// Runs a command in the `:` prompt syntax, e.g. `"m1 fwd 50% for 2s"`.
+///
+/// # Safety
+/// `client` must be a live pointer from `ac_connect` and `command` a
+/// NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ac_run(client: *mut AcClient, command: *const c_char) -> c_int {
+    let c = unsafe { &mut *client };
+    let Some(command) = (unsafe { str_arg(command) }) else {
+        return c.result(Err(String::from("command must be valid UTF-8")));
+    };
+    let result = match c.client.parse(command) {
+        Ok(cmd) => c.runtime.block_on(c.client.run(cmd)).map(drop),
+        Err(e) => Err(e),
+    };
+    c.result(result)
+}
+
+/// Writes the last reported length of `actuator` to `len`. Returns 0 if
+/// there is one, -1 if nothing has been reported yet.
+///
+/// # Safety
+/// `client` must be a live pointer from `ac_connect` and `len` writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ac_length(client: *mut AcClient, actuator: u8, len: *mut f64) -> c_int {
+    let c = unsafe { &mut *client };
+    match Actuator::try_from(actuator).ok().and_then(|a| c.client.length(a)) {
+        Some(l) => {
+            unsafe { *len = l };
+            0
+        }
+        None => c.result(Err(String::from("no length reported yet"))),
+    }
+}
+
+/// Waits up to `timeout_ms` for the next length report. Returns 0 and fills
+/// in `actuator` and `len` if one arrived, -1 otherwise.
+///
+/// # Safety
+/// `client` must be a live pointer from `ac_connect` and `actuator` and
+/// `len` writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn ac_next_telemetry(
+    client: *mut AcClient,
+    timeout_ms: u32,
+    actuator: *mut u8,
+    len: *mut f64,
+) -> c_int {
+    let c = unsafe { &mut *client };
+    let next = c.runtime.block_on(async {
+        tokio::time::timeout(Duration::from_millis(timeout_ms as u64), c.client.telemetry()).await
+    });
+    match next {
+        Ok(Some(report)) => {
+            unsafe {
+                *actuator = report.actuator as u8;
+                *len = report.length();
+            }
+            0
+        }
+        Ok(None) => c.result(Err(String::from("port closed"))),
+        Err(_) => c.result(Err(String::from("no telemetry before the timeout"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        app::App,
+        config::Table,
+        identify::Model,
+        sim::{self, Profile},
+    };
+
+    /// A client over the simulator, as `ac_connect` would give.
+    fn simulated() -> *mut AcClient {
+        let config = Config::from_table(&Table::default()).unwrap();
+        let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build().unwrap();
+        let model = Model { gain: 1e-5, time_constant: 0.1, dead_time: 0.0 };
+        let profile = Profile { models: [model; 2], noise: [0.0; 2], period: Duration::from_millis(20), start: [0.1; 2] };
+        let client = runtime.block_on(async {
+            let (link, _) = sim::open(profile, [0.3; 2], config.safety().constraints(), Vec::new());
+            ActuatorClient::new(link, App::new(&config))
+        });
+        Box::into_raw(Box::new(AcClient { runtime, client, last_error: CString::default() }))
+    }
+
+    fn last_error(client: *mut AcClient) -> String {
+        unsafe { CStr::from_ptr(ac_last_error(client)) }.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn commands() {
+        let client = simulated();
+        let stop = CString::new("stop").unwrap();
+        assert_eq!(unsafe { ac_run(client, stop.as_ptr()) }, 0);
+        unsafe { ac_free(client) };
+    }
+
+    #[test]
+    fn malformed_commands() {
+        let client = simulated();
+        assert_eq!(unsafe { ac_run(client, ptr::null()) }, -1);
+        assert_eq!(last_error(client), "command must be valid UTF-8");
+        let invalid = [0xff, 0xfe, b'x', 0];
+        assert_eq!(unsafe { ac_run(client, invalid.as_ptr().cast()) }, -1);
+        let oversized = format!("m1 fwd 50% for 1s {}", "x".repeat(1 << 20));
+        for command in ["", "fly", "m1 fwd 50% for 99999999999999999999999s", r#"{"cmd": "drive", "duration_ms": 1e30}"#, &oversized] {
+            let command = CString::new(command).unwrap();
+            assert_eq!(unsafe { ac_run(client, command.as_ptr()) }, -1);
+            assert!(!last_error(client).is_empty());
+        }
+        let nested = CString::new(r#"{"a":"#.repeat(200_000)).unwrap();
+        assert_eq!(unsafe { ac_run(client, nested.as_ptr()) }, -1);
+        unsafe { ac_free(client) };
+    }
+
+    #[test]
+    fn bad_arguments() {
+        let client = simulated();
+        assert_eq!(unsafe { ac_set_speed(client, 0, 2, 100) }, -1);
+        assert_eq!(last_error(client), "Invalid direction value");
+        assert_eq!(unsafe { ac_set_speed(client, 7, 0, 100) }, -1);
+        assert_eq!(unsafe { ac_goto(client, 7, 0.2) }, -1);
+        let mut len = 0.0;
+        assert_eq!(unsafe { ac_length(client, 7, &mut len) }, -1);
+        unsafe { ac_free(client) };
+    }
+
+    #[test]
+    fn errors_with_nul() {
+        let client = simulated();
+        assert_eq!(unsafe { &mut *client }.result(Err(String::from("bad\0 news"))), -1);
+        assert_eq!(last_error(client), "bad news");
+        unsafe { ac_free(client) };
+    }
+}
@@ -0,0 +1,297 @@
+//! `frames`: stepping through a [capture](crate::capture) frame by frame,
+//! decoded the way the link's reader decodes the port, to see where a bad
+//! length came from.
+//!
+//! The reader takes one value's worth of bytes at a time, in the
+//! [`Encoding`] the config gives. If they're the
+//! [handshake](crate::telemetry::HANDSHAKE) it reads a field list up to a
+//! newline, and if they're [debug text](crate::telemetry::DEBUG_TEXT) a line
+//! of text the same way; otherwise they're the length, and it reads the
+//! report's other fields after it. A read that comes up short loses what it
+//! had, and the reader starts over on the next value's bytes, wherever they
+//! fall. [`Decoder`]
+//! goes through the same states, so a frame it decodes wrongly is one the
+//! reader decoded wrongly too, and it says what looks off: a length outside
+//! the stroke, a handshake partway through a report, or where the reports
+//! would line up again.
+
+use std::{fmt, ops::RangeInclusive};
+
+use crate::{
+    capture::{Chunk, Entry},
+    telemetry::{DEBUG_TEXT, Encoding, HANDSHAKE, Schema},
+};
+
+/// The longest field list or line of debug text the reader takes before
+/// giving up on it.
+const MAX_FIELDS_TEXT: usize = 1024;
+
+/// How many reports in a row have to look right for a guess at where they
+/// line up again.
+const REALIGN_REPORTS: usize = 3;
+
+/// Nothing reports a length this close to zero but zero itself (m), while
+/// the bytes of a report read out of step often come to one.
+const TINY: f64 = 1e-9;
+
+/// What the reader is doing when the next byte comes in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum State {
+    /// reading the first value, a length or the handshake
+    Start,
+    /// reading the field list after the handshake
+    Fields,
+    /// reading a line of debug text
+    Text,
+    /// reading field `field` (from 1) of a report with `of`
+    Rest { field: usize, of: usize },
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            State::Start => f.write_str("start"),
+            State::Fields => f.write_str("field list"),
+            State::Text => f.write_str("debug text"),
+            State::Rest { field, of } => write!(f, "field {field}/{of}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Kind {
+    /// a report, one value per field of the schema it was decoded with
+    Report(Schema, Vec<f64>),
+    /// a field list after the handshake, or why it was refused
+    Fields(Result<Schema, String>),
+    /// a line of debug text from the firmware
+    Text(String),
+    /// bytes the reader threw away after a short read
+    Dropped,
+    /// the watchdog reopened the port here
+    Reopened,
+    /// the capture ended partway through a frame
+    Incomplete,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    /// where its first byte is in the stream
+    pub offset: usize,
+    /// when the read that finished it came in, if the capture says
+    pub at: Option<f64>,
+    pub bytes: Vec<u8>,
+    /// every state the reader went through for it, from the one it started in
+    pub path: Vec<State>,
+    pub kind: Kind,
+    /// anything that looks wrong with it
+    pub warnings: Vec<String>,
+}
+
+/// Decodes a capture a frame at a time.
+pub struct Decoder {
+    /// every byte received, in order
+    stream: Vec<u8>,
+    /// when the read each byte came in was, by its offset
+    times: Vec<Option<f64>>,
+    /// `short` and `reopen` marks, with the offset of the byte they came before
+    marks: Vec<(usize, Chunk, Option<f64>)>,
+    next_mark: usize,
+    pos: usize,
+    state: State,
+    schema: Schema,
+    encoding: Encoding,
+    /// lengths that can be right, in m
+    lengths: RangeInclusive<f64>,
+    /// the frame a reopen came partway through: its offset, path and warnings so far
+    partial: Option<(usize, Vec<State>, Vec<String>)>,
+}
+
+impl Decoder {
+    /// Starts with `schema`, as the reader does until the firmware advertises
+    /// another, and warns of lengths outside `lengths`.
+    pub fn new(entries: &[Entry], schema: Schema, encoding: Encoding, lengths: RangeInclusive<f64>) -> Decoder {
+        let (mut stream, mut times, mut marks) = (Vec::new(), Vec::new(), Vec::new());
+        for entry in entries {
+            match &entry.chunk {
+                Chunk::Bytes(bytes) => {
+                    stream.extend_from_slice(bytes);
+                    times.extend(std::iter::repeat_n(entry.at, bytes.len()));
+                }
+                // the reader never sees what's written
+                Chunk::Sent(_) => {}
+                mark => marks.push((stream.len(), mark.clone(), entry.at)),
+            }
+        }
+        Decoder { stream, times, marks, next_mark: 0, pos: 0, state: State::Start, schema, encoding, lengths, partial: None }
+    }
+
+    /// How many bytes the capture holds.
+    pub fn len(&self) -> usize {
+        self.stream.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stream.is_empty()
+    }
+
+    /// The fields reports are being decoded with.
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    fn frame(&self, offset: usize, path: Vec<State>, kind: Kind, warnings: Vec<String>) -> Frame {
+        let at = self.pos.checked_sub(1).and_then(|last| self.times[last]);
+        Frame { offset, at, bytes: self.stream[offset..self.pos].to_vec(), path, kind, warnings }
+    }
+
+    /// The report that ends at the current position.
+    fn decode(&mut self, offset: usize, mut path: Vec<State>, mut warnings: Vec<String>) -> Frame {
+        let values = self.encoding.decode_all(&self.stream[offset..self.pos]);
+        self.state = State::Start;
+        path.push(self.state);
+        warnings.extend(self.check_report(offset, &values));
+        self.frame(offset, path, Kind::Report(self.schema.clone(), values), warnings)
+    }
+
+    /// What looks wrong with the report at `offset`.
+    fn check_report(&self, offset: usize, values: &[f64]) -> Vec<String> {
+        let mut warnings = Vec::new();
+        let (lo, hi) = (*self.lengths.start(), *self.lengths.end());
+        let length = values[0];
+        if !length.is_finite() {
+            warnings.push(format!("length is {length}"));
+        } else if !self.lengths.contains(&length) {
+            warnings.push(format!("length {length:.3e} m is outside {lo} to {hi} m"));
+        } else if !self.is_length(length) {
+            warnings.push(format!("length {length:.3e} m is too close to zero to be one"));
+        }
+        for (field, value) in self.schema.fields().iter().zip(values).skip(1) {
+            if !value.is_finite() {
+                warnings.push(format!("{} is {value}", field.name));
+            }
+        }
+        let size = self.encoding.width() * values.len();
+        // the handshake can straddle the end of the report
+        let window = &self.stream[offset..(offset + size + HANDSHAKE.len() - 1).min(self.stream.len())];
+        if let Some(k) = window.windows(HANDSHAKE.len()).position(|w| w == HANDSHAKE).filter(|&k| k > 0)
+            && self.encoding.has_handshake()
+        {
+            warnings.push(format!("the handshake starts {k} bytes in, so the firmware restarted partway through a report"));
+        }
+        if !warnings.is_empty()
+            && let Some(shift) = self.realign(offset, size)
+        {
+            warnings.push(format!(
+                "{shift} bytes out of step: from offset {:#06x} the next {REALIGN_REPORTS} lengths look right",
+                offset + shift
+            ));
+        }
+        warnings
+    }
+
+    /// How many bytes on from `offset` the reports look right again, if
+    /// they do within one report's `size`.
+    fn realign(&self, offset: usize, size: usize) -> Option<usize> {
+        (1..size).find(|shift| {
+            (0..REALIGN_REPORTS).all(|i| {
+                let at = offset + shift + i * size;
+                let Some(bytes) = self.stream.get(at..at + self.encoding.width()) else { return false };
+                bytes != HANDSHAKE && bytes != DEBUG_TEXT && self.is_length(self.encoding.decode(bytes))
+            })
+        })
+    }
+
+    fn is_length(&self, length: f64) -> bool {
+        self.lengths.contains(&length) && (length == 0.0 || length.abs() >= TINY)
+    }
+}
+
+impl Iterator for Decoder {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        let (offset, mut path, mut warnings) = self.partial.take().unwrap_or_else(|| (self.pos, vec![self.state], Vec::new()));
+        loop {
+            // a mark comes before the byte at its offset
+            if let Some((at, mark, time)) = self.marks.get(self.next_mark).cloned()
+                && at == self.pos
+            {
+                self.next_mark += 1;
+                match mark {
+                    Chunk::Short => {
+                        self.state = State::Start;
+                        path.push(self.state);
+                        return Some(self.frame(offset, path, Kind::Dropped, warnings));
+                    }
+                    // the reader carries on where it was, so any frame it
+                    // came partway through is picked up again after it
+                    _ => {
+                        if self.pos > offset {
+                            warnings.push(format!("the port was reopened after {} bytes of this frame", self.pos - offset));
+                            self.partial = Some((offset, path, warnings));
+                        }
+                        let path = vec![self.state];
+                        return Some(Frame { offset: self.pos, at: time, bytes: Vec::new(), path, kind: Kind::Reopened, warnings: Vec::new() });
+                    }
+                }
+            }
+            if self.pos == self.stream.len() {
+                return (self.pos > offset).then(|| self.frame(offset, path, Kind::Incomplete, warnings));
+            }
+            self.pos += 1;
+            let read = self.pos - offset;
+            match self.state {
+                State::Fields if self.stream[self.pos - 1] == b'\n' => {
+                    let text = String::from_utf8_lossy(&self.stream[offset + HANDSHAKE.len()..self.pos - 1]).into_owned();
+                    let fields = Schema::parse(&text);
+                    match &fields {
+                        Ok(schema) => self.schema = schema.clone(),
+                        Err(e) => warnings.push(e.clone()),
+                    }
+                    self.state = State::Start;
+                    path.push(self.state);
+                    return Some(self.frame(offset, path, Kind::Fields(fields), warnings));
+                }
+                State::Fields if read - HANDSHAKE.len() > MAX_FIELDS_TEXT => {
+                    let e = format!("no newline after {MAX_FIELDS_TEXT} bytes");
+                    warnings.push(e.clone());
+                    self.state = State::Start;
+                    path.push(self.state);
+                    return Some(self.frame(offset, path, Kind::Fields(Err(e)), warnings));
+                }
+                State::Text if self.stream[self.pos - 1] == b'\n' => {
+                    let text = String::from_utf8_lossy(&self.stream[offset + DEBUG_TEXT.len()..self.pos - 1]).into_owned();
+                    self.state = State::Start;
+                    path.push(self.state);
+                    return Some(self.frame(offset, path, Kind::Text(text), warnings));
+                }
+                State::Text if read - DEBUG_TEXT.len() > MAX_FIELDS_TEXT => {
+                    warnings.push(format!("no newline after {MAX_FIELDS_TEXT} bytes"));
+                    self.state = State::Start;
+                    path.push(self.state);
+                    return Some(self.frame(offset, path, Kind::Dropped, warnings));
+                }
+                State::Fields | State::Text => {}
+                _ if !read.is_multiple_of(self.encoding.width()) => {}
+                State::Start if self.encoding.has_handshake() && self.stream[offset..self.pos] == HANDSHAKE => {
+                    self.state = State::Fields;
+                    path.push(self.state);
+                }
+                State::Start if self.encoding.has_handshake() && self.stream[offset..self.pos] == DEBUG_TEXT => {
+                    self.state = State::Text;
+                    path.push(self.state);
+                }
+                _ => {
+                    let of = self.schema.fields().len();
+                    let field = read / self.encoding.width() + 1;
+                    if field > of {
+                        return Some(self.decode(offset, path, warnings));
+                    }
+                    self.state = State::Rest { field, of };
+                    path.push(self.state);
+                }
+            }
+        }
+    }
+}
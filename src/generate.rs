@@ -0,0 +1,154 @@
+//! `generate`: shell completions and a man page, built from [`cli::OPTIONS`]
+//! so they never drift from `--help`, the wire-format test vectors (see
+//! [`actuator_controller::vectors`]) and a Wireshark dissector for captures
+//! (see [`actuator_controller::dissector`]).
+
+use crate::{cli::{self, Opt}, output::Format};
+
+pub const TARGETS: &[&str] = &["bash", "zsh", "fish", "man", "vectors", "wireshark"];
+
+const NAME: &str = "actuator_controller";
+
+pub fn run(target: &str) -> Result<String, String> {
+    match target {
+        "bash" => Ok(bash()),
+        "zsh" => Ok(zsh()),
+        "fish" => Ok(fish()),
+        "man" => Ok(man()),
+        "vectors" => Ok(format!("{}\n", actuator_controller::vectors::generate())),
+        "wireshark" => Ok(actuator_controller::dissector::generate()),
+        _ => Err(format!("can't generate `{target}`; expected one of {}", TARGETS.join(", "))),
+    }
+}
+
+/// What an option's value completes to.
+enum Values {
+    None,
+    Files,
+    Words(&'static [&'static str]),
+}
+
+fn values(opt: &Opt) -> Values {
+    match opt.value {
+        None => Values::None,
+        Some("PATH") => Values::Files,
+        Some("FORMAT") => Values::Words(Format::NAMES),
+        // endpoints, numbers: nothing sensible to offer
+        Some(_) => Values::Words(&[]),
+    }
+}
+
+fn bash() -> String {
+    let mut cases = String::new();
+    for opt in cli::OPTIONS {
+        match values(opt) {
+            Values::None => {}
+            Values::Files => cases += &format!("        {}) COMPREPLY=($(compgen -f -- \"$cur\")); return ;;\n", opt.long),
+            Values::Words(words) => {
+                cases += &format!("        {}) COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")); return ;;\n", opt.long, words.join(" "))
+            }
+        }
+    }
+    let flags: Vec<_> = cli::OPTIONS.iter().map(|o| o.long).collect();
+    format!(
+        r#"_{NAME}() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}" prev="${{COMP_WORDS[COMP_CWORD-1]}}"
+    if [[ "${{COMP_WORDS[1]}}" == generate ]]; then
+        [[ $COMP_CWORD -eq 2 ]] && COMPREPLY=($(compgen -W "{targets}" -- "$cur"))
+        return
+    fi
+    case "$prev" in
+{cases}    esac
+    if [[ "$cur" == -* ]]; then
+        COMPREPLY=($(compgen -W "{flags}" -- "$cur"))
+    elif [[ $COMP_CWORD -eq 1 ]]; then
+        COMPREPLY=($(compgen -W "generate" -- "$cur") $(compgen -f -- "$cur"))
+    else
+        COMPREPLY=($(compgen -f -- "$cur"))
+    fi
+}}
+complete -F _{NAME} {NAME}
+"#,
+        targets = TARGETS.join(" "),
+        flags = flags.join(" "),
+    )
+}
+
+fn zsh() -> String {
+    // inside '...' with [help] brackets
+    let quote = |s: &str| s.replace('\'', "'\\''").replace('[', "\\[").replace(']', "\\]");
+    let mut specs = String::new();
+    for opt in cli::OPTIONS {
+        let action = match values(opt) {
+            Values::None => String::new(),
+            Values::Files => format!(":{}:_files", opt.value.unwrap_or_default()),
+            Values::Words(words) => format!(":{}:({})", opt.value.unwrap_or_default(), words.join(" ")),
+        };
+        specs += &format!("    '{}[{}]{action}' \\\n", opt.long, quote(opt.help));
+    }
+    format!(
+        "#compdef {NAME}\n\nif [[ $words[2] == generate ]]; then\n    _arguments '2:target:({targets})'\n    return\nfi\n_arguments \\\n{specs}    '1:device path:_files'\n",
+        targets = TARGETS.join(" "),
+    )
+}
+
+fn fish() -> String {
+    let quote = |s: &str| format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'"));
+    let mut out = format!(
+        "complete -c {NAME} -n '__fish_use_subcommand' -a generate -d 'print shell completions or a man page'\n\
+         complete -c {NAME} -n '__fish_seen_subcommand_from generate' -x -a {}\n",
+        quote(&TARGETS.join(" ")),
+    );
+    for opt in cli::OPTIONS {
+        let values = match values(opt) {
+            Values::None => String::new(),
+            Values::Files => String::from(" -r -F"),
+            Values::Words(words) => format!(" -x -a {}", quote(&words.join(" "))),
+        };
+        out += &format!(
+            "complete -c {NAME} -n 'not __fish_seen_subcommand_from generate' -l {}{values} -d {}\n",
+            opt.long.trim_start_matches("--"),
+            quote(opt.help),
+        );
+    }
+    out
+}
+
+fn man() -> String {
+    let roff = |s: &str| {
+        let s = s.replace('\\', "\\e").replace('-', "\\-");
+        if s.starts_with(['.', '\'']) { format!("\\&{s}") } else { s }
+    };
+    let mut out = format!(
+        ".TH {} 1\n\
+         .SH NAME\n\
+         {NAME} \\- control the pico actuator controller firmware over serial\n\
+         .SH SYNOPSIS\n\
+         .B {NAME}\n\
+         [\\fIOPTIONS\\fR] \\fIdevice\\fR\n\
+         .br\n\
+         .B {NAME}\n\
+         \\-\\-attach [\\fIOPTIONS\\fR]\n\
+         .br\n\
+         .B {NAME} generate\n\
+         \\fI{}\\fR\n\
+         .SH DESCRIPTION\n\
+         Opens \\fIdevice\\fR (e.g. /dev/ttyACM0) and shows a terminal interface for driving the two actuators. \
+         With \\-\\-stdin, \\-\\-monitor, \\-\\-daemon or the \\-\\-zmq options it runs headless instead. \
+         \\fBgenerate\\fR prints shell completions or this page.\n\
+         .SH OPTIONS\n",
+        NAME.to_uppercase(),
+        TARGETS.join("\\fR|\\fI"),
+    );
+    for opt in cli::OPTIONS {
+        out += ".TP\n";
+        out += &match opt.value {
+            Some(value) => format!(".BI \"{} \" {value}\n", roff(opt.long)),
+            None => format!(".B {}\n", roff(opt.long)),
+        };
+        out += &format!("{}\n", roff(opt.help));
+    }
+    out += ".SH FILES\n.TP\n.I actuator.toml\nsettings, read from the working directory unless \\-\\-config is given\n";
+    out += ".SH EXIT STATUS\n0 on success, 1 if a \\-\\-stdin command failed, 2 for bad arguments, config or port.\n";
+    out
+}
@@ -0,0 +1,52 @@
+//! Named sets of actuators, e.g. `both = m1, m2`, that `stop`, `hold`,
+//! `goto` and open-ended drives can target as one: `both goto 0.30` sets
+//! both targets in the same update, so neither starts before the other.
+
+use crate::{commands::Actuator, dsl};
+
+/// Words a group can't be called, since commands already start with them.
+pub(crate) const RESERVED: [&str; 9] = ["m1", "m2", "lift", "bucket", "stop", "angle", "mark", "follow", "speed"];
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Group {
+    pub name: String,
+    pub members: Vec<Actuator>,
+}
+
+impl Group {
+    /// Reads `<name> = <actuator>, <actuator>...`.
+    pub fn parse(text: &str) -> Result<Group, String> {
+        let (name, list) = text.split_once('=').ok_or("expected `<name> = <actuator>, <actuator>`")?;
+        let name = name.trim().to_ascii_lowercase();
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+            return Err(format!("`{name}` isn't a name; use letters, digits, `_` and `-`"));
+        }
+        if RESERVED.contains(&name.as_str()) {
+            return Err(format!("`{name}` already means something in commands"));
+        }
+        let mut members = Vec::new();
+        for word in list.split(',').map(str::trim).filter(|w| !w.is_empty()) {
+            let actuator = dsl::parse_actuator(word)
+                .ok_or_else(|| format!("unknown actuator `{word}`; expected m1, m2, lift or bucket"))?;
+            if members.contains(&actuator) {
+                return Err(format!("{actuator:?} is in it twice"));
+            }
+            members.push(actuator);
+        }
+        if members.is_empty() {
+            return Err(format!("`{name}` has no actuators"));
+        }
+        Ok(Group { name, members })
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Groups {
+    pub groups: Vec<Group>,
+}
+
+impl Groups {
+    pub fn get(&self, name: &str) -> Option<&Group> {
+        self.groups.iter().find(|group| group.name.eq_ignore_ascii_case(name))
+    }
+}
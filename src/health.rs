@@ -0,0 +1,315 @@
+//! A soak test's bookkeeping: what the actuators did over hours of the same
+//! motions, whether anything about it calls for stopping, and the health
+//! report written at the end, as Markdown or HTML.
+//!
+//! Every report feeds the peak temperature (the `temp` field) and current of
+//! its actuator, per hour of the run. Every move that settles is matched up
+//! with the same move in the cycles before by where it is in the cycle, and
+//! its repeatability is the spread of the lengths it settled at. Going over
+//! any of [`SoakConfig`]'s limits is an anomaly, and the first one halts the
+//! test.
+
+use std::{
+    fmt::Write,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use crate::{audit::clock, commands::Actuator, dsl, position::Settle, telemetry::Report};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SoakConfig {
+    /// run in order, over and over
+    pub cycle: Vec<dsl::Command>,
+    /// how long to keep cycling
+    pub duration: Duration,
+    /// °C; 0 for no limit
+    pub max_temp: f64,
+    /// A; 0 for no limit
+    pub max_current: f64,
+    /// widest spread (m) of lengths a move may settle at; 0 for no limit
+    pub repeatability: f64,
+    /// HTML if it ends in `.html`, Markdown otherwise
+    pub report: PathBuf,
+}
+
+impl Default for SoakConfig {
+    fn default() -> SoakConfig {
+        SoakConfig {
+            cycle: Vec::new(),
+            duration: Duration::from_secs(4 * 3600),
+            max_temp: 0.0,
+            max_current: 0.0,
+            repeatability: 0.005,
+            report: PathBuf::from("soak.md"),
+        }
+    }
+}
+
+/// Peaks over one hour of the run, indexed by `Actuator as usize`.
+#[derive(Clone, Copy, Debug, Default)]
+struct Hour {
+    cycles: u64,
+    temp: [Option<f64>; 2],
+    current: [Option<f64>; 2],
+}
+
+/// One of the report's tables.
+struct Table {
+    heading: &'static str,
+    header: Vec<&'static str>,
+    rows: Vec<Vec<String>>,
+}
+
+/// One move of the cycle, and where it settled each time.
+#[derive(Clone, Debug)]
+struct Spot {
+    step: usize,
+    actuator: Actuator,
+    target: f64,
+    lengths: Vec<f64>,
+}
+
+impl Spot {
+    fn spread(&self) -> f64 {
+        let (min, max) = self.lengths.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &len| (min.min(len), max.max(len)));
+        max - min
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Health {
+    limits: SoakConfig,
+    start: Instant,
+    /// how long it ran, once it's over
+    took: Option<Duration>,
+    cycles: u64,
+    hours: Vec<Hour>,
+    spots: Vec<Spot>,
+    anomaly: Option<String>,
+    /// why it stopped short, if not for an anomaly
+    stopped: Option<String>,
+}
+
+impl Health {
+    pub fn new(limits: &SoakConfig, now: Instant) -> Health {
+        Health {
+            limits: limits.clone(),
+            start: now,
+            took: None,
+            cycles: 0,
+            hours: vec![Hour::default()],
+            spots: Vec::new(),
+            anomaly: None,
+            stopped: None,
+        }
+    }
+
+    fn hour(&mut self, now: Instant) -> &mut Hour {
+        let n = (now.duration_since(self.start).as_secs() / 3600) as usize;
+        if self.hours.len() <= n {
+            self.hours.resize(n + 1, Hour::default());
+        }
+        &mut self.hours[n]
+    }
+
+    /// The first anomaly, which halts the test.
+    pub fn anomaly(&self) -> Option<&str> {
+        self.anomaly.as_deref()
+    }
+
+    fn flag(&mut self, why: String) {
+        self.anomaly.get_or_insert(why);
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Takes in a report, flagging a temperature or current over its limit.
+    pub fn observe(&mut self, report: &Report, now: Instant) {
+        let i = report.actuator as usize;
+        let (temp, current) = (report.field("temp").filter(|t| !t.is_nan()), report.current().filter(|c| !c.is_nan()));
+        let hour = self.hour(now);
+        if let Some(temp) = temp {
+            hour.temp[i] = Some(hour.temp[i].map_or(temp, |peak| peak.max(temp)));
+        }
+        if let Some(current) = current {
+            hour.current[i] = Some(hour.current[i].map_or(current, |peak| peak.max(current)));
+        }
+        if let Some(temp) = temp
+            && self.limits.max_temp > 0.0
+            && temp > self.limits.max_temp
+        {
+            self.flag(format!("{:?} reached {temp:.1} °C, over the {} °C limit", report.actuator, self.limits.max_temp));
+        }
+        if let Some(current) = current
+            && self.limits.max_current > 0.0
+            && current > self.limits.max_current
+        {
+            self.flag(format!("{:?} drew {current:.2} A, over the {} A limit", report.actuator, self.limits.max_current));
+        }
+    }
+
+    /// A move at `step` of the cycle settled, flagging it if it's strayed
+    /// too far from where the same move settled before.
+    pub fn settled(&mut self, step: usize, settle: &Settle) {
+        let length = settle.target + settle.error;
+        let at = self.spots.iter().position(|spot| spot.step == step && spot.actuator == settle.actuator);
+        let spot = match at {
+            Some(at) => &mut self.spots[at],
+            None => {
+                self.spots.push(Spot { step, actuator: settle.actuator, target: settle.target, lengths: Vec::new() });
+                self.spots.last_mut().unwrap()
+            }
+        };
+        spot.lengths.push(length);
+        let (spread, target) = (spot.spread(), spot.target);
+        if self.limits.repeatability > 0.0 && spread > self.limits.repeatability {
+            self.flag(format!(
+                "{:?} settled {:.1} mm apart going to {target:.3} m, more than the {:.1} mm allowed",
+                settle.actuator,
+                spread * 1000.0,
+                self.limits.repeatability * 1000.0
+            ));
+        }
+    }
+
+    /// Something outside the readings went wrong, e.g. a command failed.
+    pub fn fail(&mut self, why: String) {
+        self.flag(why);
+    }
+
+    pub fn cycle_done(&mut self, now: Instant) {
+        self.cycles += 1;
+        self.hour(now).cycles += 1;
+    }
+
+    /// The test is over at `now`, early for `stopped` if that's given.
+    pub fn finish(&mut self, now: Instant, stopped: Option<String>) {
+        self.took = Some(now.duration_since(self.start));
+        self.stopped = stopped;
+    }
+
+    /// Whether it ran its whole time without an anomaly.
+    pub fn passed(&self) -> bool {
+        self.took.is_some() && self.anomaly.is_none() && self.stopped.is_none()
+    }
+
+    /// One line saying how it went.
+    pub fn verdict(&self) -> String {
+        let took = clock(self.took.unwrap_or_default());
+        match (&self.anomaly, &self.stopped) {
+            (Some(why), _) => format!("FAIL: halted after {} cycles ({took}): {why}", self.cycles),
+            (None, Some(why)) => format!("INCOMPLETE: stopped after {} cycles ({took}): {why}", self.cycles),
+            (None, None) => format!("PASS: {} cycles in {took}", self.cycles),
+        }
+    }
+
+    fn tables(&self) -> [Table; 2] {
+        let reading = |value: Option<f64>, decimals: usize| value.map_or(String::from("-"), |v| format!("{v:.decimals$}"));
+        let hours = self
+            .hours
+            .iter()
+            .enumerate()
+            .map(|(n, hour)| {
+                vec![
+                    (n + 1).to_string(),
+                    hour.cycles.to_string(),
+                    reading(hour.temp[0], 1),
+                    reading(hour.current[0], 2),
+                    reading(hour.temp[1], 1),
+                    reading(hour.current[1], 2),
+                ]
+            })
+            .collect();
+        let cycle: Vec<String> = self.limits.cycle.iter().map(ToString::to_string).collect();
+        let spots = self
+            .spots
+            .iter()
+            .map(|spot| {
+                let mean = spot.lengths.iter().sum::<f64>() / spot.lengths.len() as f64;
+                vec![
+                    format!("{}: `{}`", spot.step + 1, cycle.get(spot.step).map_or("", String::as_str)),
+                    format!("{:?}", spot.actuator),
+                    format!("{:.3}", spot.target),
+                    spot.lengths.len().to_string(),
+                    format!("{mean:.4}"),
+                    format!("{:.1}", spot.spread() * 1000.0),
+                ]
+            })
+            .collect();
+        [
+            Table { heading: "Peaks by hour", header: vec!["hour", "cycles", "M1 °C", "M1 A", "M2 °C", "M2 A"], rows: hours },
+            Table {
+                heading: "Repeatability",
+                header: vec!["move", "actuator", "target (m)", "settles", "mean (m)", "spread (mm)"],
+                rows: spots,
+            },
+        ]
+    }
+
+    fn limits(&self) -> Vec<String> {
+        let limit = |value: f64, unit: &str| if value > 0.0 { format!("{value} {unit}") } else { String::from("none") };
+        vec![
+            format!("cycle: {}", self.limits.cycle.iter().map(ToString::to_string).collect::<Vec<_>>().join("; ")),
+            format!("duration: {}", clock(self.limits.duration)),
+            format!("max temperature: {}", limit(self.limits.max_temp, "°C")),
+            format!("max current: {}", limit(self.limits.max_current, "A")),
+            format!("repeatability: {}", limit(self.limits.repeatability * 1000.0, "mm")),
+        ]
+    }
+
+    pub fn markdown(&self, title: &str) -> String {
+        let mut out = format!("# {title}\n\n**{}**\n\n", self.verdict());
+        for line in self.limits() {
+            let _ = writeln!(out, "- {line}");
+        }
+        for Table { heading, header, rows } in self.tables() {
+            let _ = write!(out, "\n## {heading}\n\n| {} |\n|{}\n", header.join(" | "), " --- |".repeat(header.len()));
+            for row in rows {
+                let _ = writeln!(out, "| {} |", row.join(" | "));
+            }
+        }
+        out
+    }
+
+    pub fn html(&self, title: &str) -> String {
+        let escape = |s: &str| s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;");
+        let colour = match (&self.anomaly, &self.stopped) {
+            (Some(_), _) => "#c00",
+            (None, Some(_)) => "#a60",
+            (None, None) => "#070",
+        };
+        let mut out = format!(
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{title}</title>\n\
+             <style>body {{ font-family: sans-serif }} table {{ border-collapse: collapse }} \
+             td, th {{ border: 1px solid #999; padding: 2px 8px; text-align: right }}</style></head>\n\
+             <body>\n<h1>{title}</h1>\n<p style=\"color: {colour}\"><b>{}</b></p>\n<ul>\n",
+            escape(&self.verdict()),
+            title = escape(title)
+        );
+        for line in self.limits() {
+            let _ = writeln!(out, "<li>{}</li>", escape(&line));
+        }
+        out += "</ul>\n";
+        for Table { heading, header, rows } in self.tables() {
+            let _ = writeln!(out, "<h2>{heading}</h2>\n<table>\n<tr><th>{}</th></tr>", header.join("</th><th>"));
+            for row in rows {
+                let cells: Vec<String> = row.iter().map(|cell| escape(&cell.replace('`', ""))).collect();
+                let _ = writeln!(out, "<tr><td>{}</td></tr>", cells.join("</td><td>"));
+            }
+            out += "</table>\n";
+        }
+        out += "</body></html>\n";
+        out
+    }
+
+    /// The report in the format `limits.report` asks for.
+    pub fn report(&self, title: &str) -> String {
+        match self.limits.report.extension().and_then(|ext| ext.to_str()) {
+            Some("html" | "htm") => self.html(title),
+            _ => self.markdown(title),
+        }
+    }
+}
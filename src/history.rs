@@ -0,0 +1,38 @@
+//! The high-level actions run so far, newest last, so `.` can repeat the
+//! last one and `u` can undo it.
+
+use std::collections::VecDeque;
+
+use crate::{app::Action, dsl};
+
+/// Older entries are dropped past this many.
+const CAPACITY: usize = 100;
+
+pub struct Entry {
+    pub action: Action,
+    /// what `u` runs: the opposite move, or a stop when there isn't one
+    pub undo: dsl::Command,
+}
+
+#[derive(Default)]
+pub struct History {
+    entries: VecDeque<Entry>,
+}
+
+impl History {
+    pub fn push(&mut self, entry: Entry) {
+        if self.entries.len() == CAPACITY {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    pub fn last(&self) -> Option<&Entry> {
+        self.entries.back()
+    }
+
+    /// Oldest first.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Entry> + ExactSizeIterator {
+        self.entries.iter()
+    }
+}
@@ -0,0 +1,99 @@
+//! Telemetry hooks: commands run of their own accord when a reading crosses
+//! a threshold, configured under `[hooks]` as
+//! `when <field> > <value> run <command>` (or `<`), e.g.
+//! `when lift.len > 0.40 run retract_bucket`.
+//!
+//! The field is `len` or any other the reports carry, derived channels
+//! included. With `m1.` or `m2.` (or `lift.`, `bucket.`) in front only that
+//! actuator's reports count; without, either's do. A hook fires as its
+//! reading crosses the threshold, not on the first reading if that's
+//! already past it, and can't fire again until a reading has come back.
+//! Fired hooks wait in [`Hooks::take`] for the app to run them, with the
+//! same checks as any typed command.
+
+use std::collections::VecDeque;
+
+use crate::{checklist::Check, commands::Actuator, dsl, telemetry::Report};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hook {
+    /// how it was written, for the log
+    pub text: String,
+    /// `None` for either actuator's reports
+    pub of: Option<Actuator>,
+    pub check: Check,
+    pub command: dsl::Command,
+}
+
+impl Hook {
+    /// Reads `when <field> > <value> run <command>`, the command with
+    /// `vocabulary`'s groups and aliases.
+    pub fn parse(text: &str, vocabulary: &dsl::Vocabulary) -> Result<Hook, String> {
+        let text = text.trim();
+        let rest = text.strip_prefix("when ").ok_or("expected `when <field> > <value> run <command>`")?;
+        let (condition, command) = rest.split_once(" run ").ok_or("expected `run <command>` after the condition")?;
+        let at = condition.find(['>', '<']).ok_or_else(|| format!("`{}` compares nothing; use > or <", condition.trim()))?;
+        let (of, field) = match condition[..at].trim().split_once('.') {
+            Some((actuator, field)) => {
+                let actuator = dsl::parse_actuator(actuator)
+                    .ok_or_else(|| format!("unknown actuator `{actuator}`; expected m1, m2, lift or bucket"))?;
+                (Some(actuator), field)
+            }
+            None => (None, condition[..at].trim()),
+        };
+        if field.is_empty() || !field.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(format!("expected a telemetry field before `{}`, found `{field}`", &condition[at..at + 1]));
+        }
+        // the unit is only there for whoever reads the config
+        let value = condition[at + 1..].trim().trim_end_matches(|c: char| c.is_ascii_alphabetic()).trim();
+        let threshold = value.parse::<f64>().map_err(|_| format!("expected a number after `{}`", &condition[at..at + 1]))?;
+        let check = Check { field: field.to_string(), above: condition[at..].starts_with('>'), threshold };
+        let command = vocabulary.parse(command.trim()).map_err(|e| format!("`{}`: {e}", command.trim()))?;
+        Ok(Hook { text: text.to_string(), of, check, command })
+    }
+
+    /// This hook's reading in `report`, if it's one the hook watches.
+    fn reading(&self, report: &Report) -> Option<f64> {
+        if self.of.is_some_and(|of| of != report.actuator) {
+            return None;
+        }
+        match self.check.field.as_str() {
+            "len" => Some(report.length()),
+            field => report.field(field),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Hooks {
+    pub list: Vec<Hook>,
+    /// whether each hook's condition held at its last reading, once it's had one
+    holding: Vec<Option<bool>>,
+    /// indices into `list` of the hooks that have fired and not been taken
+    fired: VecDeque<usize>,
+}
+
+impl Hooks {
+    pub fn new(list: Vec<Hook>) -> Hooks {
+        let n = list.len();
+        Hooks { list, holding: vec![None; n], fired: VecDeque::new() }
+    }
+
+    /// Takes in a report, firing every hook whose condition it makes true.
+    pub fn observe(&mut self, report: &Report) {
+        for (i, hook) in self.list.iter().enumerate() {
+            // NaN, e.g. a channel that can't be worked out yet, says nothing
+            let Some(value) = hook.reading(report).filter(|value| !value.is_nan()) else { continue };
+            let holds = hook.check.passes(value);
+            if holds && self.holding[i] == Some(false) {
+                self.fired.push_back(i);
+            }
+            self.holding[i] = Some(holds);
+        }
+    }
+
+    /// The oldest hook that's fired and hasn't been run.
+    pub fn take(&mut self) -> Option<Hook> {
+        self.fired.pop_front().map(|i| self.list[i].clone())
+    }
+}
@@ -0,0 +1,221 @@
+//! `identify`: fitting a model to a logged run and suggesting gains for the
+//! position controller.
+//!
+//! The log is `--excite --output json`: `drive` events for the speeds sent and
+//! `telemetry` events for the lengths that came back. Every time an
+//! actuator's speed changes is a step, and the rod's velocity answers it
+//! like a first-order system with dead time:
+//!
+//! ```text
+//! v(t) = v0 + gain * du * (1 - exp(-(t - dead_time) / time_constant))
+//! ```
+//!
+//! Each step is fitted with the two-point method (the times the velocity
+//! gets 28.3% and 63.2% of the way), and the median of an actuator's steps
+//! taken. Length is that velocity integrated, so the position controller is
+//! tuned with the SIMC rules for an integrating process with a lag.
+
+use std::fmt;
+
+use crate::{commands::Actuator, dsl, json};
+
+/// Reports either side of a sample used to estimate its velocity.
+const SPAN: usize = 2;
+/// A step needs at least this many velocity estimates to be fitted.
+const MIN_SAMPLES: usize = 8;
+/// Half the link's 50 ms frame period, added to the dead time since a speed
+/// can wait that long on average to go out.
+const HOLD_DELAY: f64 = 0.025;
+
+/// First order plus dead time, from speed units to m/s.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Model {
+    /// steady velocity (m/s) per speed unit
+    pub gain: f64,
+    /// seconds
+    pub time_constant: f64,
+    /// seconds
+    pub dead_time: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pid {
+    /// speed units per meter of error
+    pub kp: f64,
+    /// speed units per meter-second
+    pub ki: f64,
+    /// speed units per meter per second
+    pub kd: f64,
+}
+
+impl Model {
+    /// SIMC gains for holding a length, with the closed-loop time constant
+    /// set to the dead time.
+    pub fn pid(&self) -> Pid {
+        let dead_time = self.dead_time + HOLD_DELAY;
+        let closed_loop = dead_time;
+        let kp = 1.0 / (self.gain * (closed_loop + dead_time));
+        let integral_time = 4.0 * (closed_loop + dead_time);
+        Pid { kp, ki: kp / integral_time, kd: kp * self.time_constant }
+    }
+}
+
+/// One actuator's part of the run.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Trace {
+    /// when each speed was sent
+    pub(crate) drives: Vec<(f64, i32)>,
+    /// when each length came in
+    pub(crate) lengths: Vec<(f64, f64)>,
+}
+
+/// What `identify` prints: a model and gains per actuator it found steps for.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Identification {
+    /// steps fitted, and the median model, indexed by `Actuator as usize`
+    pub models: [(usize, Option<Model>); 2],
+}
+
+/// Fits a model for each actuator in the log `src`.
+pub fn identify(src: &str) -> Result<Identification, String> {
+    let traces = traces(src)?;
+    let models = traces.each_ref().map(|trace| {
+        let mut fits: Vec<Model> = trace.steps().filter_map(|step| step.fit()).collect();
+        let count = fits.len();
+        let mut median = |field: fn(&Model) -> f64| {
+            fits.sort_by(|a, b| field(a).total_cmp(&field(b)));
+            field(&fits[count / 2])
+        };
+        let model = (count > 0).then(|| Model {
+            gain: median(|m| m.gain),
+            time_constant: median(|m| m.time_constant),
+            dead_time: median(|m| m.dead_time),
+        });
+        (count, model)
+    });
+    Ok(Identification { models })
+}
+
+/// Splits the log `src` by actuator, indexed by `Actuator as usize`.
+pub(crate) fn traces(src: &str) -> Result<[Trace; 2], String> {
+    let mut traces: [Trace; 2] = Default::default();
+    for (n, line) in src.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let event = json::parse(line).map_err(|e| format!("line {}: {e}", n + 1))?;
+        let number = |key: &str| event.get(key).and_then(json::Value::as_f64);
+        let actuator = event.get("actuator").and_then(json::Value::as_str).and_then(dsl::parse_actuator);
+        let (Some(t), Some(actuator)) = (number("t"), actuator) else {
+            continue;
+        };
+        let trace = &mut traces[actuator as usize];
+        match event.get("event").and_then(json::Value::as_str) {
+            Some("drive") => {
+                let speed = number("speed").ok_or(format!("line {}: a drive event needs a `speed`", n + 1))?;
+                trace.drives.push((t, speed as i32));
+            }
+            Some("telemetry") => {
+                let len = number("len").ok_or(format!("line {}: a telemetry event needs a `len`", n + 1))?;
+                trace.lengths.push((t, len));
+            }
+            _ => {}
+        }
+    }
+    if traces.iter().all(|trace| trace.drives.is_empty()) {
+        return Err(String::from("no drive events; record the run with `--excite ... --output json`"));
+    }
+    Ok(traces)
+}
+
+/// A speed held from `start` until the next one, and the one before it.
+struct Step {
+    start: f64,
+    from: i32,
+    to: i32,
+    /// velocity estimates since the previous step, and since this one
+    before: Vec<(f64, f64)>,
+    after: Vec<(f64, f64)>,
+}
+
+impl Trace {
+    /// Velocity between the reports `SPAN` either side of each report.
+    fn velocities(&self) -> Vec<(f64, f64)> {
+        self.lengths
+            .windows(2 * SPAN + 1)
+            .filter(|w| w[2 * SPAN].0 > w[0].0)
+            .map(|w| (w[SPAN].0, (w[2 * SPAN].1 - w[0].1) / (w[2 * SPAN].0 - w[0].0)))
+            .collect()
+    }
+
+    fn steps(&self) -> impl Iterator<Item = Step> + '_ {
+        let velocities = self.velocities();
+        let between = move |from: f64, to: f64| -> Vec<(f64, f64)> {
+            velocities.iter().copied().filter(|(t, _)| *t >= from && *t < to).collect()
+        };
+        (0..self.drives.len()).filter_map(move |i| {
+            let (start, to) = self.drives[i];
+            let (previous, from) = if i == 0 { (f64::NEG_INFINITY, 0) } else { self.drives[i - 1] };
+            let end = self.drives.get(i + 1).map_or(f64::INFINITY, |d| d.0);
+            (to != from).then(|| Step { start, from, to, before: between(previous, start), after: between(start, end) })
+        })
+    }
+}
+
+impl Step {
+    fn fit(&self) -> Option<Model> {
+        if self.after.len() < MIN_SAMPLES {
+            return None;
+        }
+        // where the velocity settled: the last quarter of each side
+        let settled = |v: &[(f64, f64)]| {
+            let tail = &v[v.len() - (v.len() / 4).max(1)..];
+            tail.iter().map(|(_, v)| v).sum::<f64>() / tail.len() as f64
+        };
+        let v0 = if self.before.is_empty() { 0.0 } else { settled(&self.before) };
+        let change = settled(&self.after) - v0;
+        let du = (self.to - self.from) as f64;
+        if change.abs() < f64::EPSILON || change / du <= 0.0 {
+            return None;
+        }
+        let reached = |fraction: f64| {
+            self.after.iter().find(|(_, v)| (v - v0) / change >= fraction).map(|(t, _)| t - self.start)
+        };
+        let (t28, t63) = (reached(0.283)?, reached(0.632)?);
+        let time_constant = 1.5 * (t63 - t28);
+        Some(Model { gain: change / du, time_constant, dead_time: (t63 - time_constant).max(0.0) })
+    }
+}
+
+impl fmt::Display for Identification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let header = ["steps", "gain (m/s/u)", "tau (s)", "dead (s)", "Kp (u/m)", "Ki (u/m/s)", "Kd (u.s/m)"];
+        writeln!(f, "{:<4} {:>5} {:>14} {:>10} {:>10} {:>12} {:>12} {:>12}", "", header[0], header[1], header[2], header[3], header[4], header[5], header[6])?;
+        for actuator in [Actuator::M1, Actuator::M2] {
+            match self.models[actuator as usize] {
+                (steps, Some(model)) => {
+                    let pid = model.pid();
+                    writeln!(
+                        f,
+                        "{:<4} {steps:>5} {:>14.3e} {:>10.3} {:>10.3} {:>12.0} {:>12.0} {:>12.0}",
+                        format!("{actuator:?}"),
+                        model.gain,
+                        model.time_constant,
+                        model.dead_time,
+                        pid.kp,
+                        pid.ki,
+                        pid.kd
+                    )?;
+                }
+                (_, None) => writeln!(f, "{:<4} {:>5} no step responses to fit", format!("{actuator:?}"), 0)?,
+            }
+        }
+        // one gain is shared, so the gentler of the two
+        let kp = self.models.iter().filter_map(|(_, m)| m.map(|m| m.pid().kp)).min_by(f64::total_cmp);
+        if let Some(kp) = kp {
+            writeln!(f, "\nThe position controller is proportional only; for both actuators:\n\n[position]\ngain = {kp:.0}")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,288 @@
+//! Where the terminal UI's control inputs come from, and which of them to
+//! take when more than one is driving at once.
+//!
+//! Each [`InputSource`] hands over the [`Input`]s it has waiting. The keys
+//! and the on-screen joystick are [`Queued`], filled by the UI as events
+//! come in. A named pipe and a script file are [`Lines`] of commands, read
+//! on a thread of their own. [`Merge`] then decides, by the `[input]`
+//! priority, whether each [`ControlEvent`] goes through: after an input
+//! from one source, those ranked below it are ignored for `hold`. A stop
+//! from any source always goes through, so the keyboard's Space or `s`
+//! stops the actuators whatever else is in control.
+
+use std::{
+    collections::VecDeque,
+    fmt, fs,
+    io,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{dsl, update::Input};
+
+/// How long a source keeps the ones below it out after its last input.
+pub const DEFAULT_HOLD: f64 = 1.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Source {
+    Keyboard,
+    /// the on-screen pad dragged with the mouse
+    Joystick,
+    /// the `[input] listen` PULL socket
+    Network,
+    Fifo,
+    Script,
+}
+
+impl Source {
+    /// in the default priority, highest first
+    pub const ALL: [Source; 5] = [Source::Keyboard, Source::Joystick, Source::Network, Source::Fifo, Source::Script];
+
+    pub fn parse(name: &str) -> Option<Source> {
+        Source::ALL.into_iter().find(|source| source.to_string().eq_ignore_ascii_case(name))
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Source::Keyboard => "keyboard",
+            Source::Joystick => "joystick",
+            Source::Network => "network",
+            Source::Fifo => "fifo",
+            Source::Script => "script",
+        })
+    }
+}
+
+/// One input, with where it came from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ControlEvent {
+    pub source: Source,
+    pub input: Input,
+}
+
+impl ControlEvent {
+    /// Stops are taken from anyone, whoever is in control.
+    pub fn is_stop(&self, vocabulary: &dsl::Vocabulary) -> bool {
+        match &self.input {
+            Input::Stop | Input::StopAll | Input::EStop | Input::Abort => true,
+            Input::Run(line) => vocabulary.parse(line).is_ok_and(|cmd| cmd.is_stop()),
+            _ => false,
+        }
+    }
+}
+
+pub trait InputSource {
+    fn source(&self) -> Source;
+
+    /// The next input waiting, without blocking.
+    fn next(&mut self) -> Option<Input>;
+
+    /// Everything waiting, in order.
+    fn drain(&mut self) -> Vec<ControlEvent> {
+        let source = self.source();
+        std::iter::from_fn(|| self.next()).map(|input| ControlEvent { source, input }).collect()
+    }
+}
+
+/// Inputs the caller pushes as its events come in, e.g. key presses.
+pub struct Queued {
+    source: Source,
+    inputs: VecDeque<Input>,
+}
+
+impl Queued {
+    pub fn new(source: Source) -> Queued {
+        Queued { source, inputs: VecDeque::new() }
+    }
+
+    pub fn push(&mut self, input: Input) {
+        self.inputs.push_back(input);
+    }
+}
+
+impl InputSource for Queued {
+    fn source(&self) -> Source {
+        self.source
+    }
+
+    fn next(&mut self) -> Option<Input> {
+        self.inputs.pop_front()
+    }
+}
+
+/// Commands read a line at a time on a thread of their own, each becoming
+/// an [`Input::Run`] in either form [`dsl::parse_line`] takes.
+pub struct Lines {
+    source: Source,
+    rx: mpsc::Receiver<String>,
+}
+
+impl Lines {
+    /// Whatever is written to the named pipe at `path`, made if it isn't
+    /// there. The pipe is opened again each time a writer closes it, so
+    /// `echo stop > path` works as often as it's run.
+    #[cfg(unix)]
+    pub fn fifo(path: &Path) -> io::Result<Lines> {
+        use std::{
+            io::{BufRead, BufReader},
+            os::unix::{ffi::OsStrExt, fs::FileTypeExt},
+        };
+
+        match fs::metadata(path) {
+            Ok(meta) if meta.file_type().is_fifo() => {}
+            Ok(_) => return Err(io::Error::other("already exists and isn't a named pipe")),
+            Err(_) => {
+                let c_path = std::ffi::CString::new(path.as_os_str().as_bytes()).map_err(io::Error::other)?;
+                if unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) } != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+        }
+        let (tx, rx) = mpsc::channel();
+        let path = path.to_path_buf();
+        thread::spawn(move || {
+            // blocks until something opens the pipe to write
+            while let Ok(file) = fs::File::open(&path) {
+                for line in BufReader::new(file).lines() {
+                    let Ok(line) = line else { break };
+                    if tx.send(line).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+        Ok(Lines { source: Source::Fifo, rx })
+    }
+
+    #[cfg(not(unix))]
+    pub fn fifo(_path: &Path) -> io::Result<Lines> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, format!("named pipes need Unix, not {}", std::env::consts::OS)))
+    }
+
+    /// The commands in the file at `path`, one per line, sent as fast as
+    /// they're taken except where a `wait <duration>` line holds the rest
+    /// back. Blank lines and ones starting with `#` are skipped.
+    pub fn script(path: &Path) -> Result<Lines, String> {
+        let src = fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let mut steps = Vec::new();
+        for (i, line) in src.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            // `None` for a command, or how long to wait
+            let wait = match line.strip_prefix("wait ") {
+                Some(wait) => Some(dsl::parse_duration(wait.trim()).map_err(|e| format!("{}:{}: {e}", path.display(), i + 1))?),
+                None => None,
+            };
+            steps.push((line.to_string(), wait));
+        }
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for (line, wait) in steps {
+                match wait {
+                    Some(wait) => thread::sleep(wait),
+                    None if tx.send(line).is_err() => return,
+                    None => {}
+                }
+            }
+        });
+        Ok(Lines { source: Source::Script, rx })
+    }
+}
+
+impl InputSource for Lines {
+    fn source(&self) -> Source {
+        self.source
+    }
+
+    fn next(&mut self) -> Option<Input> {
+        loop {
+            let line = self.rx.try_recv().ok()?;
+            if !line.trim().is_empty() {
+                return Some(Input::Run(line.trim().to_string()));
+            }
+        }
+    }
+}
+
+/// Which sources win when more than one is driving.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Policy {
+    /// highest first; every source is in it once
+    pub priority: Vec<Source>,
+    /// how long a source keeps the ones below it out; zero takes everything
+    pub hold: Duration,
+}
+
+impl Default for Policy {
+    fn default() -> Policy {
+        Policy { priority: Source::ALL.to_vec(), hold: Duration::from_secs_f64(DEFAULT_HOLD) }
+    }
+}
+
+impl Policy {
+    /// Reads a comma-separated ranking. Sources left out go after the ones
+    /// named, in their usual order.
+    pub fn rank(list: &str) -> Result<Vec<Source>, String> {
+        let mut priority = Vec::new();
+        for name in list.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            let source = Source::parse(name).ok_or_else(|| {
+                format!("unknown source `{name}`; expected keyboard, joystick, network, fifo or script")
+            })?;
+            if priority.contains(&source) {
+                return Err(format!("`{name}` is listed twice"));
+            }
+            priority.push(source);
+        }
+        let rest: Vec<Source> = Source::ALL.into_iter().filter(|source| !priority.contains(source)).collect();
+        priority.extend(rest);
+        Ok(priority)
+    }
+}
+
+/// Everything `[input]` sets up.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InputConfig {
+    pub policy: Policy,
+    /// named pipe to take commands from
+    pub fifo: Option<PathBuf>,
+    /// ZeroMQ endpoint to bind a PULL socket on
+    pub listen: Option<String>,
+    /// file of commands to run once the UI starts
+    pub script: Option<PathBuf>,
+}
+
+/// Applies a [`Policy`] to events from every source.
+pub struct Merge {
+    policy: Policy,
+    /// for telling a group's or alias's `stop` from anything else
+    vocabulary: dsl::Vocabulary,
+    /// when each source last got an input through
+    last: [Option<Instant>; Source::ALL.len()],
+}
+
+impl Merge {
+    pub fn new(policy: Policy, vocabulary: dsl::Vocabulary) -> Merge {
+        Merge { policy, vocabulary, last: [None; Source::ALL.len()] }
+    }
+
+    /// Whether `event` goes through at `now`, or else the source in control.
+    pub fn admit(&mut self, event: &ControlEvent, now: Instant) -> Result<(), Source> {
+        let above = self.policy.priority.iter().take_while(|&&source| source != event.source);
+        let holder = above.copied().find(|&source| {
+            self.last[source as usize].is_some_and(|at| now.duration_since(at) < self.policy.hold)
+        });
+        match holder {
+            Some(holder) if !event.is_stop(&self.vocabulary) => Err(holder),
+            _ => {
+                self.last[event.source as usize] = Some(now);
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,100 @@
+//! Stopping before reversing, enforced by the serial writer itself.
+//!
+//! Whatever queued a frame, be it the terminal UI, a script, the FFI or a
+//! client attached to a daemon, a direction change only reaches the firmware
+//! once the actuator has been sent a stop and `dwell` has passed since. A
+//! reversal that arrives while the actuator is moving gets the stop slipped
+//! in ahead of it.
+
+use std::time::{Duration, Instant};
+
+use crate::commands::{Actuator, ActuatorCommand, Direction};
+
+/// How long an actuator is left stopped before its direction may change,
+/// unless the config says otherwise.
+pub const DEFAULT_DWELL: Duration = Duration::from_millis(250);
+
+#[derive(Clone, Debug)]
+pub struct Interlock {
+    dwell: Duration,
+    /// what each actuator was last sent, indexed by `Actuator as usize`;
+    /// the firmware starts up stopped, with no direction we can rely on
+    speed: [u16; 2],
+    direction: [Option<Direction>; 2],
+    /// when each actuator was last brought to a stop
+    stopped_at: [Option<Instant>; 2],
+}
+
+impl Interlock {
+    pub fn new(dwell: Duration) -> Interlock {
+        Interlock {
+            dwell,
+            speed: [0; 2],
+            direction: [None; 2],
+            stopped_at: [None; 2],
+        }
+    }
+
+    /// The actuator that has to be sent a stop ahead of `cmd`, if it's a
+    /// reversal of one that's still moving.
+    pub fn stop_first(&self, cmd: ActuatorCommand) -> Option<Actuator> {
+        match cmd {
+            ActuatorCommand::SetDirection(dir, actuator)
+                if self.reverses(dir, actuator) && self.speed[actuator as usize] != 0 =>
+            {
+                Some(actuator)
+            }
+            _ => None,
+        }
+    }
+
+    /// When `cmd` may be written, if it has to wait out the dwell.
+    pub fn hold_until(&self, cmd: ActuatorCommand) -> Option<Instant> {
+        match cmd {
+            ActuatorCommand::SetDirection(dir, actuator) if self.reverses(dir, actuator) => {
+                self.stopped_at[actuator as usize].map(|t| t + self.dwell)
+            }
+            _ => None,
+        }
+    }
+
+    /// The speed `actuator` was last sent.
+    pub fn speed(&self, actuator: Actuator) -> u16 {
+        self.speed[actuator as usize]
+    }
+
+    /// Whether `actuator` was last sent a speed other than zero.
+    pub fn is_moving(&self, actuator: Actuator) -> bool {
+        self.speed[actuator as usize] != 0
+    }
+
+    /// Whether either actuator was last sent a speed other than zero.
+    pub fn moving(&self) -> bool {
+        self.speed.iter().any(|&speed| speed != 0)
+    }
+
+    /// Records a frame the firmware was sent at `now`.
+    pub fn written(&mut self, cmd: ActuatorCommand, now: Instant) {
+        match cmd {
+            ActuatorCommand::SetSpeed(speed, actuator) => {
+                let i = actuator as usize;
+                if speed == 0 && self.speed[i] != 0 {
+                    self.stopped_at[i] = Some(now);
+                }
+                self.speed[i] = speed;
+            }
+            ActuatorCommand::SetDirection(dir, actuator) => self.direction[actuator as usize] = Some(dir),
+            // the firmware stops driving it to hold it
+            ActuatorCommand::HoldPosition(actuator) => self.written(ActuatorCommand::SetSpeed(0, actuator), now),
+        }
+    }
+
+    /// The direction `actuator` was last sent, if it's been sent one.
+    pub fn direction(&self, actuator: Actuator) -> Option<Direction> {
+        self.direction[actuator as usize]
+    }
+
+    fn reverses(&self, dir: Direction, actuator: Actuator) -> bool {
+        self.direction[actuator as usize] != Some(dir)
+    }
+}
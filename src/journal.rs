@@ -0,0 +1,162 @@
+//! `--journal`: where a running sequence, dig cycle or trajectory is up to,
+//! kept on disk so that after a crash the next start can say which step was
+//! running and offer to resume it or abort it for good.
+//!
+//! The file is one JSON object, written whenever a step starts and every
+//! [`PERIOD`] while a trajectory plays, and removed once the sequence
+//! finishes or is aborted. Each write goes to a file alongside that is
+//! synced and then renamed over it, so a crash part way through leaves the
+//! last whole entry behind rather than half of the new one.
+//!
+//! ```json
+//! {"command":"follow dig.csv","running":"follow dig.csv: 12 s (1/1)","step":0,"played":4.5,"at":"2026-03-02T18:04:11.532Z"}
+//! ```
+//!
+//! A dig cycle has `"dig":[<lift>,<bucket>]`, the lengths it started from,
+//! in place of `command`.
+
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{audit, json::{self, Value}, sequence::Runner};
+
+/// How often the place in a trajectory is written while it plays.
+pub const PERIOD: Duration = Duration::from_secs(1);
+
+/// What started a sequence, so it can be started again.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Origin {
+    /// a command, as typed
+    Command(String),
+    /// the dig cycle, from these lift and bucket lengths
+    Dig([f64; 2]),
+}
+
+/// Where a sequence was up to when it was last written.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub origin: Origin,
+    /// what the status line said
+    pub running: String,
+    /// counting from 0
+    pub step: usize,
+    /// how far into the step's trajectory (s), or 0
+    pub played: f64,
+    /// when it was written, see [`audit::timestamp`]
+    pub at: String,
+}
+
+impl Entry {
+    fn to_json(&self) -> Value {
+        let origin = match &self.origin {
+            Origin::Command(line) => (String::from("command"), Value::String(line.clone())),
+            Origin::Dig(start) => (String::from("dig"), Value::Array(start.iter().map(|&len| Value::Number(len)).collect())),
+        };
+        Value::Object(vec![
+            origin,
+            (String::from("running"), Value::String(self.running.clone())),
+            (String::from("step"), Value::Number(self.step as f64)),
+            (String::from("played"), Value::Number(self.played)),
+            (String::from("at"), Value::String(self.at.clone())),
+        ])
+    }
+
+    fn from_json(value: &Value) -> Result<Entry, String> {
+        let origin = match (value.get("command"), value.get("dig")) {
+            (Some(line), _) => Origin::Command(line.as_str().ok_or("`command` isn't a string")?.to_string()),
+            (None, Some(Value::Array(start))) => match start.iter().map(Value::as_f64).collect::<Option<Vec<f64>>>() {
+                Some(start) if start.len() == 2 => Origin::Dig([start[0], start[1]]),
+                _ => return Err(String::from("`dig` isn't two lengths")),
+            },
+            _ => return Err(String::from("no `command` or `dig`")),
+        };
+        let text = |key: &str| value.get(key).and_then(Value::as_str).map(str::to_string).ok_or(format!("no `{key}`"));
+        let number = |key: &str| value.get(key).and_then(Value::as_f64).ok_or(format!("no `{key}`"));
+        Ok(Entry { origin, running: text("running")?, step: number("step")? as usize, played: number("played")?, at: text("at")? })
+    }
+}
+
+pub struct Journal {
+    path: PathBuf,
+    /// what's running, with the step and when it was last written
+    running: Option<(Origin, usize, Instant)>,
+    /// whether a write has failed since the last that didn't, so it's only said once
+    failing: bool,
+}
+
+impl Journal {
+    pub fn new(path: &Path) -> Journal {
+        Journal { path: path.to_path_buf(), running: None, failing: false }
+    }
+
+    /// Journals a sequence started from `origin`, now running as `runner`.
+    pub fn start(&mut self, origin: Origin, runner: &Runner, now: Instant) -> Result<(), String> {
+        self.running = Some((origin, runner.step(), now));
+        self.write(runner)
+    }
+
+    /// Writes where `runner` is up to, if it's on a new step or it's time to.
+    pub fn update(&mut self, runner: &Runner, now: Instant) -> Result<(), String> {
+        let Some((_, step, written)) = &mut self.running else { return Ok(()) };
+        if *step == runner.step() && !(runner.is_tracking() && now.duration_since(*written) >= PERIOD) {
+            return Ok(());
+        }
+        (*step, *written) = (runner.step(), now);
+        self.write(runner)
+    }
+
+    /// Forgets the sequence, which finished or was aborted.
+    pub fn finish(&mut self) -> Result<(), String> {
+        self.running = None;
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => self.failed(e),
+            _ => Ok(()),
+        }
+    }
+
+    fn write(&mut self, runner: &Runner) -> Result<(), String> {
+        let Some((origin, step, _)) = &self.running else { return Ok(()) };
+        let entry = Entry {
+            origin: origin.clone(),
+            running: runner.describe(),
+            step: *step,
+            played: runner.played(),
+            at: audit::timestamp(SystemTime::now()),
+        };
+        let partial = self.path.with_extension("partial");
+        let written = File::create(&partial)
+            .and_then(|mut file| {
+                writeln!(file, "{}", entry.to_json())?;
+                file.sync_all()
+            })
+            .and_then(|_| fs::rename(&partial, &self.path));
+        match written {
+            Ok(()) => {
+                self.failing = false;
+                Ok(())
+            }
+            Err(e) => self.failed(e),
+        }
+    }
+
+    fn failed(&mut self, e: io::Error) -> Result<(), String> {
+        if std::mem::replace(&mut self.failing, true) {
+            return Ok(());
+        }
+        Err(format!("couldn't write the journal {}: {e}", self.path.display()))
+    }
+}
+
+/// What the journal at `path` says was running, if anything was.
+pub fn read(path: &Path) -> Result<Option<Entry>, String> {
+    let src = match fs::read_to_string(path) {
+        Ok(src) => src,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("{}: {e}", path.display())),
+    };
+    json::parse(src.trim()).and_then(|value| Entry::from_json(&value)).map(Some).map_err(|e| format!("{}: {e}", path.display()))
+}
@@ -0,0 +1,62 @@
+//! An on-screen pad for driving the selected actuator with the mouse: drag
+//! up to extend, down to retract, further from the middle to go faster.
+//! Letting go snaps back to a stop.
+
+use ratatui::{
+    layout::{Alignment, Rect},
+    text::{Line, Text},
+    widgets::{Block, Borders, Paragraph},
+};
+
+#[derive(Default)]
+pub struct Pad {
+    /// where the pad was last drawn, for hit-testing the mouse
+    pub area: Rect,
+    /// a drag started on the pad and hasn't been released yet
+    pub dragging: bool,
+}
+
+impl Pad {
+    fn inner(&self) -> Rect {
+        Block::default().borders(Borders::ALL).inner(self.area)
+    }
+
+    /// The row the middle (zero speed) is drawn on, relative to the inside of the pad.
+    fn center(&self) -> u16 {
+        self.inner().height.saturating_sub(1) / 2
+    }
+
+    pub fn contains(&self, column: u16, row: u16) -> bool {
+        let inner = self.inner();
+        (inner.x..inner.x + inner.width).contains(&column) && (inner.y..inner.y + inner.height).contains(&row)
+    }
+
+    /// The signed speed for the mouse at screen row `row`, top being `max` forward.
+    pub fn value_at(&self, row: u16, max: u32) -> i32 {
+        let inner = self.inner();
+        let center = self.center();
+        if center == 0 {
+            return 0;
+        }
+        let rel = row.clamp(inner.y, inner.y + 2 * center) - inner.y;
+        (center as i64 - rel as i64) as i32 * max as i32 / center as i32
+    }
+
+    pub fn widget(&self, value: i32, max: u32) -> Paragraph<'static> {
+        let inner = self.inner();
+        let center = self.center() as i32;
+        let marker = if max == 0 { center } else { center - (value as i64 * center as i64 / max as i64) as i32 };
+        let width = inner.width as usize;
+        let half = "━".repeat(width.saturating_sub(1) / 2);
+        let lines = (0..inner.height as i32)
+            .map(|row| match row {
+                _ if row == marker => Line::from(format!("{half}●{half}")),
+                _ if row == center => Line::from("─".repeat(width)),
+                _ => Line::from(""),
+            })
+            .collect::<Vec<_>>();
+        Paragraph::new(Text::from(lines))
+            .alignment(Alignment::Center)
+            .block(Block::default().title("Joystick (drag)").borders(Borders::ALL))
+    }
+}
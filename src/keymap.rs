@@ -0,0 +1,142 @@
+//! Per-actuator key bindings: the key that selects each actuator, and how
+//! far each speed key moves the speed.
+//!
+//! The lift needs much finer control than the bucket, so every actuator has
+//! its own steps, falling back to the `[keys]` ones.
+//!
+//! The arrow, speed and space keys are read in one of a few [`Layer`]s, so
+//! they can do more than one job without one getting in another's way. Tab
+//! latches the next layer; holding Shift or Alt with a key reads just that
+//! key in the fine or sequence layer instead.
+
+use std::fmt;
+
+use crate::commands::Actuator;
+
+/// A key that can be bound in the config, written `"F1"` or `"1"`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Key {
+    F(u8),
+    Char(char),
+}
+
+/// Keys the terminal interface already uses for something else.
+const RESERVED: &str = "qsghyunel.:+-[]mpcxfkiwadWSAD<>!ERX";
+
+impl Key {
+    pub fn parse(src: &str) -> Result<Key, String> {
+        let mut chars = src.chars();
+        let key = match (chars.next(), chars.next()) {
+            (Some(c), None) if !c.is_whitespace() => Key::Char(c),
+            _ => match src.strip_prefix('F').and_then(|n| n.parse().ok()) {
+                Some(n @ 1..=12) => Key::F(n),
+                _ => return Err(format!("`{src}` isn't a key; expected F1 to F12 or a single character")),
+            },
+        };
+        match key {
+            Key::Char(c) if RESERVED.contains(c) => Err(format!("`{c}` is already bound to something else")),
+            key => Ok(key),
+        }
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Key::F(n) => write!(f, "F{n}"),
+            Key::Char(c) => write!(f, "{c}"),
+        }
+    }
+}
+
+/// Speed units added or taken by one press.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Steps {
+    /// Shift+↑/↓
+    pub fine: u32,
+    /// ↑/↓
+    pub normal: u32,
+    /// +/-
+    pub coarse: u32,
+}
+
+impl Default for Steps {
+    fn default() -> Self {
+        Steps {
+            fine: 100,
+            normal: 1000,
+            coarse: 5000,
+        }
+    }
+}
+
+/// A set of meanings for the arrow, speed and space keys. Every other key
+/// means the same in all of them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Layer {
+    /// ↑/↓ and `+`/`-` change the speed by the step and coarse step
+    #[default]
+    Normal,
+    /// ↑/↓ and `+`/`-` change the speed by the fine step and the step
+    Fine,
+    /// ←/→ play a running trajectory slower or faster, space pauses it, and
+    /// ↑/↓ do nothing, so watching a sequence can't stop it by accident
+    Sequence,
+}
+
+impl Layer {
+    pub fn label(self) -> &'static str {
+        match self {
+            Layer::Normal => "normal",
+            Layer::Fine => "fine",
+            Layer::Sequence => "sequence",
+        }
+    }
+
+    /// The one Tab latches after this.
+    pub fn next(self) -> Layer {
+        match self {
+            Layer::Normal => Layer::Fine,
+            Layer::Fine => Layer::Sequence,
+            Layer::Sequence => Layer::Normal,
+        }
+    }
+
+    /// The layer a key is read in when pressed with `latched` latched: the
+    /// fine one with Shift held, the sequence one with Alt, else `latched`.
+    pub fn momentary(latched: Layer, shift: bool, alt: bool) -> Layer {
+        match (shift, alt) {
+            (_, true) => Layer::Sequence,
+            (true, false) => Layer::Fine,
+            (false, false) => latched,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Keymap {
+    /// what selects each actuator, indexed by `Actuator as usize`
+    pub select: [Key; 2],
+    /// indexed by `Actuator as usize`
+    pub steps: [Steps; 2],
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Keymap {
+            select: [Key::F(1), Key::F(2)],
+            steps: [Steps::default(); 2],
+        }
+    }
+}
+
+impl Keymap {
+    /// The actuator `key` selects, if any.
+    pub fn selects(&self, key: Key) -> Option<Actuator> {
+        [Actuator::M1, Actuator::M2].into_iter().find(|&a| self.select[a as usize] == key)
+    }
+
+    pub fn steps(&self, actuator: Actuator) -> Steps {
+        self.steps[actuator as usize]
+    }
+}
@@ -0,0 +1,141 @@
+//! Geometry of the lift arm and bucket linkage.
+//!
+//! Each actuator forms a triangle with the pivot of the joint it drives, so
+//! the joint angle follows from its length by the law of cosines.
+
+use crate::commands::Actuator;
+
+/// One actuator spanning a joint.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Joint {
+    /// distance from the joint pivot to the actuator's base pin (m)
+    pub base: f64,
+    /// distance from the joint pivot to the actuator's rod-end pin (m)
+    pub rod: f64,
+    /// pin-to-pin length of the actuator when fully retracted (m)
+    pub retracted: f64,
+    /// how far the actuator extends from fully retracted (m)
+    pub max_stroke: f64,
+    /// how far the lead screw moves the rod per turn (m)
+    pub lead: f64,
+    /// motor turns per turn of the lead screw
+    pub gear_ratio: f64,
+    /// angle at the pivot between the two pins when the joint reads zero (rad)
+    pub offset: f64,
+}
+
+impl Joint {
+    /// Joint angle in radians for the given stroke, or `None` if the
+    /// geometry can't produce that actuator length.
+    pub fn angle(&self, stroke: f64) -> Option<f64> {
+        let len = self.retracted + stroke;
+        let cos = (self.base.powi(2) + self.rod.powi(2) - len.powi(2)) / (2.0 * self.base * self.rod);
+        if !(-1.0..=1.0).contains(&cos) {
+            return None;
+        }
+        Some(cos.acos() - self.offset)
+    }
+
+    /// How much of the full stroke `stroke` is, as a percentage.
+    pub fn percent_extended(&self, stroke: f64) -> f64 {
+        stroke / self.max_stroke * 100.0
+    }
+
+    /// Motor speed (rpm) that moves the rod at `velocity` (m/s), signed the
+    /// same way.
+    pub fn motor_rpm(&self, velocity: f64) -> f64 {
+        velocity / self.lead * self.gear_ratio * 60.0
+    }
+
+    /// Stroke that puts the joint at `angle` radians, or `None` if that's
+    /// outside what the actuator can reach.
+    pub fn stroke(&self, angle: f64) -> Option<f64> {
+        let pins = angle + self.offset;
+        if !(0.0..=std::f64::consts::PI).contains(&pins) {
+            return None;
+        }
+        let len = (self.base.powi(2) + self.rod.powi(2) - 2.0 * self.base * self.rod * pins.cos()).sqrt();
+        let stroke = len - self.retracted;
+        (stroke >= 0.0).then_some(stroke)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Linkage {
+    /// M1, raising the arm
+    pub lift: Joint,
+    /// M2, curling the bucket relative to the arm
+    pub bucket: Joint,
+    /// distance from the arm pivot to the bucket pivot (m)
+    pub arm_length: f64,
+    /// height of the arm pivot above the ground (m)
+    pub pivot_height: f64,
+}
+
+impl Default for Linkage {
+    fn default() -> Self {
+        Linkage {
+            lift: Joint {
+                base: 0.25,
+                rod: 0.40,
+                retracted: 0.30,
+                max_stroke: 0.30,
+                lead: 0.002,
+                gear_ratio: 10.0,
+                offset: 60f64.to_radians(),
+            },
+            bucket: Joint {
+                base: 0.20,
+                rod: 0.15,
+                retracted: 0.25,
+                max_stroke: 0.20,
+                lead: 0.002,
+                gear_ratio: 10.0,
+                offset: 90f64.to_radians(),
+            },
+            arm_length: 0.80,
+            pivot_height: 0.30,
+        }
+    }
+}
+
+/// What the operator actually cares about.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pose {
+    /// bucket angle relative to the ground (degrees)
+    pub bucket_angle: f64,
+    /// height of the bucket pivot above the ground (m)
+    pub bucket_height: f64,
+}
+
+impl Linkage {
+    /// The joint `actuator` drives.
+    pub fn joint(&self, actuator: Actuator) -> &Joint {
+        match actuator {
+            Actuator::M1 => &self.lift,
+            Actuator::M2 => &self.bucket,
+        }
+    }
+
+    /// Pose implied by the lift and bucket strokes (as reported by telemetry).
+    pub fn forward(&self, lift_stroke: f64, bucket_stroke: f64) -> Option<Pose> {
+        let arm = self.lift.angle(lift_stroke)?;
+        let bucket = self.bucket.angle(bucket_stroke)?;
+        Some(Pose {
+            bucket_angle: (arm + bucket).to_degrees(),
+            bucket_height: self.pivot_height + self.arm_length * arm.sin(),
+        })
+    }
+
+    /// Lift and bucket strokes that produce `pose`.
+    pub fn inverse(&self, pose: Pose) -> Option<(f64, f64)> {
+        let sin = (pose.bucket_height - self.pivot_height) / self.arm_length;
+        if !(-1.0..=1.0).contains(&sin) {
+            return None;
+        }
+        let arm = sin.asin();
+        let lift = self.lift.stroke(arm)?;
+        let bucket = self.bucket.stroke(pose.bucket_angle.to_radians() - arm)?;
+        Some((lift, bucket))
+    }
+}
@@ -0,0 +1,185 @@
+//! `--profile`: how long a frame spends in each stage between the key press
+//! that caused it and the UI seeing it acknowledged.
+//!
+//! Frames go through the command channel in order, so a trace is started
+//! for each one as the app queues it and picked up, oldest first, by the
+//! writer. Frames that never go out (coalesced, dropped or failed) have
+//! their trace thrown away unrecorded.
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// What end-to-end latency (key to ack) is meant to stay under.
+pub const TARGET: Duration = Duration::from_millis(30);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stage {
+    /// key or mouse event to the frame being put on the command channel
+    Queue,
+    /// on the channel until the writer takes it off
+    Channel,
+    /// taken off until serialized and ready to write, including any
+    /// interlock stop and dwell ahead of it
+    Serialize,
+    Write,
+    /// written until the UI sees the ack
+    Ack,
+    /// key or mouse event to ack
+    Total,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 6] = [Stage::Queue, Stage::Channel, Stage::Serialize, Stage::Write, Stage::Ack, Stage::Total];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Stage::Queue => "input -> queue",
+            Stage::Channel => "queue -> writer",
+            Stage::Serialize => "writer -> serialize",
+            Stage::Write => "serialize -> write",
+            Stage::Ack => "write -> ack",
+            Stage::Total => "input -> ack",
+        }
+    }
+}
+
+/// When one frame reached each stage.
+#[derive(Clone, Copy, Debug)]
+pub struct Trace {
+    /// `None` for frames the app queued on its own, e.g. position control
+    input: Option<Instant>,
+    queued: Instant,
+    dequeued: Option<Instant>,
+    serialized: Option<Instant>,
+    written: Option<Instant>,
+}
+
+impl Trace {
+    pub fn serialized(&mut self) {
+        self.serialized = Some(Instant::now());
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    /// queued by the app, not yet taken off by the writer
+    queued: VecDeque<Trace>,
+    /// written, not yet acked
+    written: VecDeque<Trace>,
+    samples: [Vec<Duration>; Stage::ALL.len()],
+}
+
+/// Shared by the app, which starts and finishes traces, and the writer.
+#[derive(Default)]
+pub struct Profiler {
+    inner: Mutex<Inner>,
+}
+
+impl Profiler {
+    /// A frame caused by the input at `input` went onto the channel.
+    pub fn queued(&self, input: Option<Instant>) {
+        let trace = Trace { input, queued: Instant::now(), dequeued: None, serialized: None, written: None };
+        self.inner.lock().unwrap().queued.push_back(trace);
+    }
+
+    /// The writer took the oldest frame off the channel.
+    pub fn dequeued(&self) -> Option<Trace> {
+        let mut trace = self.inner.lock().unwrap().queued.pop_front()?;
+        trace.dequeued = Some(Instant::now());
+        Some(trace)
+    }
+
+    /// The writer threw away the `n` oldest frames without writing them.
+    pub fn discard(&self, n: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        let n = (n as usize).min(inner.queued.len());
+        inner.queued.drain(..n);
+    }
+
+    /// The writer wrote the frame `trace` is for.
+    pub fn written(&self, mut trace: Trace) {
+        trace.written = Some(Instant::now());
+        self.inner.lock().unwrap().written.push_back(trace);
+    }
+
+    /// The UI saw `n` more frames acknowledged.
+    pub fn acked(&self, n: u64) {
+        let now = Instant::now();
+        let mut inner = self.inner.lock().unwrap();
+        for _ in 0..n {
+            let Some(trace) = inner.written.pop_front() else { break };
+            let stamps = [
+                (Stage::Queue, trace.input, Some(trace.queued)),
+                (Stage::Channel, Some(trace.queued), trace.dequeued),
+                (Stage::Serialize, trace.dequeued, trace.serialized),
+                (Stage::Write, trace.serialized, trace.written),
+                (Stage::Ack, trace.written, Some(now)),
+                (Stage::Total, trace.input, Some(now)),
+            ];
+            for (stage, from, to) in stamps {
+                if let (Some(from), Some(to)) = (from, to) {
+                    inner.samples[stage as usize].push(to.duration_since(from));
+                }
+            }
+        }
+    }
+
+    pub fn stats(&self, stage: Stage) -> Option<Stats> {
+        Stats::of(&self.inner.lock().unwrap().samples[stage as usize])
+    }
+
+    /// One line per stage, and whether the end-to-end target was met.
+    pub fn report(&self) -> String {
+        let mut out = format!("{:<20} {:>6} {:>8} {:>8} {:>8} {:>8}\n", "stage", "frames", "mean", "p50", "p95", "max");
+        for stage in Stage::ALL {
+            match self.stats(stage) {
+                Some(stats) => out += &format!("{:<20} {stats}\n", stage.label()),
+                None => out += &format!("{:<20} {:>6}\n", stage.label(), 0),
+            }
+        }
+        match self.stats(Stage::Total) {
+            Some(stats) if stats.p95 <= TARGET => out += &format!("p95 input -> ack is within the {TARGET:?} target\n"),
+            Some(_) => out += &format!("p95 input -> ack MISSES the {TARGET:?} target\n"),
+            None => out += "no keyed frames were acknowledged\n",
+        }
+        out
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stats {
+    pub count: usize,
+    pub mean: Duration,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub max: Duration,
+}
+
+impl Stats {
+    pub(crate) fn of(samples: &[Duration]) -> Option<Stats> {
+        if samples.is_empty() {
+            return None;
+        }
+        let mut sorted = samples.to_vec();
+        sorted.sort();
+        let at = |q: f64| sorted[((sorted.len() - 1) as f64 * q).round() as usize];
+        Some(Stats {
+            count: sorted.len(),
+            mean: sorted.iter().sum::<Duration>() / sorted.len() as u32,
+            p50: at(0.5),
+            p95: at(0.95),
+            max: sorted[sorted.len() - 1],
+        })
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ms = |d: Duration| format!("{:.2}ms", d.as_secs_f64() * 1000.0);
+        write!(f, "{:>6} {:>8} {:>8} {:>8} {:>8}", self.count, ms(self.mean), ms(self.p50), ms(self.p95), ms(self.max))
+    }
+}
@@ -0,0 +1,68 @@
+//! Host-side control of the actuator controller firmware, shared by the
+//! terminal UI and anything else that wants to drive the actuators.
+
+pub mod aliases;
+pub mod app;
+pub mod arming;
+pub mod audit;
+pub mod checklist;
+pub mod bode;
+pub mod budget;
+pub mod cadence;
+pub mod capture;
+pub mod channels;
+pub mod client;
+pub mod commands;
+pub mod compare;
+pub mod config;
+pub mod constraints;
+pub mod countdown;
+#[cfg(unix)]
+pub mod daemon;
+pub mod dissector;
+pub mod dsl;
+pub mod estop;
+pub mod frames;
+pub mod groups;
+pub mod ffi;
+pub mod health;
+pub mod history;
+pub mod hooks;
+pub mod identify;
+pub mod input;
+pub mod interlock;
+pub mod journal;
+pub mod json;
+pub mod keymap;
+pub mod kinematics;
+pub mod latency;
+pub mod limits;
+pub mod link;
+pub mod load;
+pub mod mode;
+pub mod platform;
+pub mod position;
+#[cfg(unix)]
+pub mod portlock;
+pub mod roles;
+pub mod safety;
+pub mod sensor;
+pub mod sequence;
+pub mod session;
+#[cfg(unix)]
+pub mod shm;
+pub mod signal;
+pub mod sim;
+pub mod skew;
+pub mod softstart;
+pub mod standstill;
+pub mod status;
+pub mod stream;
+pub mod supervise;
+pub mod telemetry;
+pub mod throttle;
+pub mod toast;
+pub mod trajectory;
+pub mod trip;
+pub mod update;
+pub mod vectors;
@@ -0,0 +1,46 @@
+//! Speed caps for each way each actuator runs, enforced by the serial writer
+//! alongside the [interlock](crate::interlock).
+//!
+//! Under load an actuator can retract far faster than it should extend (or
+//! the other way around), so each direction gets its own cap. Whatever queued
+//! a frame, a `SetSpeed` above the cap for the direction the actuator was
+//! last sent goes out at the cap instead.
+
+use crate::commands::{Actuator, ActuatorCommand, Direction};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpeedLimits {
+    /// fastest each actuator may extend (`Forward`), indexed by `Actuator as usize`
+    pub extend: [u16; 2],
+    /// fastest each actuator may retract (`Backward`)
+    pub retract: [u16; 2],
+}
+
+impl Default for SpeedLimits {
+    fn default() -> SpeedLimits {
+        SpeedLimits { extend: [u16::MAX; 2], retract: [u16::MAX; 2] }
+    }
+}
+
+impl SpeedLimits {
+    /// The fastest `actuator` may run in `direction`; the lower of its two
+    /// caps while there's no telling which way it'll go.
+    pub fn cap(&self, actuator: Actuator, direction: Option<Direction>) -> u16 {
+        let i = actuator as usize;
+        match direction {
+            Some(Direction::Forward) => self.extend[i],
+            Some(Direction::Backward) => self.retract[i],
+            None => self.extend[i].min(self.retract[i]),
+        }
+    }
+
+    /// `cmd`, with its speed brought down to the cap if it's over.
+    pub fn apply(&self, cmd: ActuatorCommand, direction: Option<Direction>) -> ActuatorCommand {
+        match cmd {
+            ActuatorCommand::SetSpeed(speed, actuator) => {
+                ActuatorCommand::SetSpeed(speed.min(self.cap(actuator, direction)), actuator)
+            }
+            cmd => cmd,
+        }
+    }
+}
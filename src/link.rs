@@ -0,0 +1,743 @@
+//! The background tasks that talk to the pico over serial.
+
+use std::{
+    collections::VecDeque,
+    io::{self, Read},
+    sync::{
+        Arc,
+        atomic::{AtomicU8, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    sync::{Mutex, broadcast, mpsc, watch},
+    task::JoinHandle,
+    time::{sleep, sleep_until},
+};
+use tokio_serial::{SerialPortBuilderExt, SerialStream};
+
+use crate::{
+    arming, estop,
+    capture::{self, Chunk},
+    channels::Channels,
+    commands::{Actuator, ActuatorCommand},
+    latency::Profiler,
+    safety::{self, Safety},
+    status::{Fault, StatusEvent},
+    stream::{self, Reports, Subscription},
+    supervise::{Supervisor, TaskHealth},
+    telemetry::{self, Encoding, Report, Schema},
+};
+#[cfg(unix)]
+use crate::{
+    portlock::{self, PortLock},
+    shm,
+};
+
+/// Running totals of frames the writer has dealt with, so callers can tell
+/// when everything they queued has actually gone out.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Acks {
+    pub written: u64,
+    pub failed: u64,
+    /// speeds skipped because a newer one for the same actuator was already
+    /// queued behind them
+    pub coalesced: u64,
+    /// thrown away unwritten, e.g. when the watchdog found the link hung
+    pub dropped: u64,
+}
+
+impl Acks {
+    pub fn total(&self) -> u64 {
+        self.written + self.failed + self.coalesced + self.dropped
+    }
+
+    /// Counts one more frame as `outcome`, returning its sequence number.
+    pub fn record(&mut self, outcome: Outcome) -> u64 {
+        match outcome {
+            Outcome::Written => self.written += 1,
+            Outcome::Failed => self.failed += 1,
+            Outcome::Coalesced => self.coalesced += 1,
+            Outcome::Dropped => self.dropped += 1,
+        }
+        self.total()
+    }
+}
+
+/// What became of one frame, each counted in its own field of [`Acks`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Outcome {
+    Written,
+    Failed,
+    Coalesced,
+    Dropped,
+}
+
+/// How many outcomes a receiver from [`Link::outcomes`] can fall behind by
+/// before it misses some.
+pub const OUTCOMES: usize = 256;
+
+/// Where whatever writes frames counts what became of them: in the totals
+/// and, numbered, to anyone waiting on particular ones.
+#[derive(Clone)]
+pub(crate) struct Ledger {
+    acks: watch::Sender<Acks>,
+    outcomes: broadcast::Sender<(u64, Outcome)>,
+}
+
+impl Ledger {
+    pub(crate) fn new() -> (Ledger, watch::Receiver<Acks>) {
+        let (acks, acks_rx) = watch::channel(Acks::default());
+        let outcomes = broadcast::channel(OUTCOMES).0;
+        (Ledger { acks, outcomes }, acks_rx)
+    }
+
+    pub(crate) fn record(&self, outcome: Outcome) {
+        let mut seq = 0;
+        self.acks.send_modify(|acks| seq = acks.record(outcome));
+        let _ = self.outcomes.send((seq, outcome));
+    }
+
+    pub(crate) fn outcomes(&self) -> broadcast::Sender<(u64, Outcome)> {
+        self.outcomes.clone()
+    }
+}
+
+/// What the writer is given to do, in order.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Request {
+    Frame(ActuatorCommand),
+    /// stop the actuator this long after the frames ahead of this have been
+    /// written, unless a speed or hold for it is written first
+    StopAfter(Actuator, Duration),
+}
+
+impl From<ActuatorCommand> for Request {
+    fn from(cmd: ActuatorCommand) -> Request {
+        Request::Frame(cmd)
+    }
+}
+
+pub struct Link {
+    pub commands: mpsc::Sender<Request>,
+    pub status: mpsc::Receiver<StatusEvent>,
+    /// the link's own subscription to reports as they arrive, each at least
+    /// an actuator length; see [`Link::subscribe`] for more
+    pub telemetry: Subscription,
+    pub acks: watch::Receiver<Acks>,
+    pub(crate) outcomes: broadcast::Sender<(u64, Outcome)>,
+    /// how the background tasks are doing; empty when attached to a daemon,
+    /// which reports its own as status events
+    pub health: watch::Receiver<Vec<TaskHealth>>,
+    /// only when attached to a daemon
+    pub arming: Option<arming::Channel>,
+    /// only when attached to a daemon
+    pub estop: Option<estop::Channel>,
+    pub(crate) writer: JoinHandle<()>,
+    pub(crate) reports: stream::Source,
+    /// held until the link is dropped, with `--lock`
+    #[cfg(unix)]
+    pub(crate) _lock: Option<PortLock>,
+}
+
+impl Link {
+    /// Adds `channels` to every report on its way out, whichever link it is,
+    /// see [`crate::channels`].
+    pub fn derive(&self, channels: Channels) {
+        if !channels.is_empty() {
+            self.reports.derive(channels);
+        }
+    }
+
+    /// Another subscription to reports, from the next one on, independent of
+    /// [`Link::telemetry`] and every other; see [`crate::stream`].
+    pub fn subscribe(&self) -> Subscription {
+        self.reports.subscribe()
+    }
+
+    /// What becomes of each frame from now on, by sequence number: the nth
+    /// frame handed to the link, counting from 1, is number n, so the
+    /// outcomes of frames queued once [`Acks::total`] was `t` come numbered
+    /// after `t`, in order.
+    pub fn outcomes(&self) -> broadcast::Receiver<(u64, Outcome)> {
+        self.outcomes.subscribe()
+    }
+
+    /// The latest report of each actuator, indexed by `Actuator as usize`.
+    pub fn latest(&self) -> watch::Receiver<[Option<Report>; 2]> {
+        self.reports.latest()
+    }
+
+    /// Waits for everything already queued to be written, then drops the
+    /// link. Every clone of `commands` has to be gone first. Attached to a
+    /// daemon, this is also what tells it we're detaching on purpose.
+    pub async fn close(self) {
+        let Link { commands, writer, .. } = self;
+        drop(commands);
+        let _ = writer.await;
+    }
+}
+
+const BAUD_RATE: u32 = 9600;
+
+/// How long frames may keep failing while an actuator is moving before the
+/// link is assumed hung, unless the config says otherwise.
+pub const DEFAULT_WATCHDOG: Duration = Duration::from_millis(500);
+
+/// Extras for [`open_with`].
+#[derive(Default)]
+pub struct Options {
+    /// also publish every length report here
+    #[cfg(unix)]
+    pub shm: Option<shm::Writer>,
+    /// the checks every frame goes through, see [`crate::safety`]
+    pub safety: safety::Settings,
+    /// time each frame through the writer, see [`crate::latency`]
+    pub profiler: Option<Arc<Profiler>>,
+    /// record every byte read and frame written, see [`crate::capture`]
+    pub capture: Option<Arc<capture::Writer>>,
+    /// how the firmware sends each value, see [`telemetry::Encoding`]
+    pub encoding: Encoding,
+    /// refuse the port if another instance has it locked, see
+    /// [`crate::portlock`]; an error off Unix
+    pub lock: bool,
+}
+
+/// Opens the port and starts the reader and writer tasks.
+pub fn open(port_path: &str) -> Result<Link, tokio_serial::Error> {
+    open_with(port_path, Options::default())
+}
+
+pub fn open_with(port_path: &str, options: Options) -> Result<Link, tokio_serial::Error> {
+    #[cfg(not(unix))]
+    if options.lock {
+        let why = format!("locking the port needs Unix, not {}", std::env::consts::OS);
+        return Err(tokio_serial::Error::new(tokio_serial::ErrorKind::Io(io::ErrorKind::Unsupported), why));
+    }
+    #[cfg(unix)]
+    let lock = match options.lock {
+        true => Some(PortLock::acquire(port_path).map_err(|e| {
+            tokio_serial::Error::new(tokio_serial::ErrorKind::Io(io::ErrorKind::ResourceBusy), e.to_string())
+        })?),
+        false => None,
+    };
+    // the port refuses a second open of its own, but doesn't say by whom
+    let port = tokio_serial::new(port_path, BAUD_RATE).open_native_async();
+    #[cfg(unix)]
+    let port = port.map_err(|e| {
+        let busy = e.kind == tokio_serial::ErrorKind::Io(io::ErrorKind::ResourceBusy);
+        match portlock::holder(port_path).filter(|_| busy) {
+            Some(holder) => tokio_serial::Error::new(e.kind, format!("{e}; port busy by {holder}")),
+            None => e,
+        }
+    });
+    let port = port?;
+
+    let (tx, rx) = mpsc::channel::<Request>(100);
+    let (status_tx, status_rx) = mpsc::channel::<StatusEvent>(100);
+    let reports = Reports::new();
+    let source = stream::Source::of(&reports);
+    let (ledger, acks_rx) = Ledger::new();
+    let outcomes = ledger.outcomes();
+
+    let port = Arc::new(Mutex::new(port));
+
+    // The firmware reports the length of whichever actuator it was last
+    // commanded to move, so the writer records that for the reader.
+    let last_actuator = Arc::new(AtomicU8::new(Actuator::M1 as u8));
+    // and the reader passes the lengths and currents back for the writer's
+    // safety policies
+    let (lengths_tx, lengths_rx) = watch::channel([None; 2]);
+    let lengths_tx = Arc::new(lengths_tx);
+    let (currents_tx, currents_rx) = watch::channel([None; 2]);
+    let currents_tx = Arc::new(currents_tx);
+
+    let supervisor = Supervisor::new(status_tx.clone());
+    // The firmware only advertises its fields once, so a restarted reader
+    // has to remember them.
+    let schema = Arc::new(Mutex::new(Arc::new(Schema::default())));
+    let reader = {
+        let port = Arc::clone(&port);
+        let last_actuator = Arc::clone(&last_actuator);
+        #[cfg(unix)]
+        let shm = options.shm.map(Arc::new);
+        let capture = options.capture.clone();
+        let encoding = options.encoding;
+        let status_tx = status_tx.clone();
+        move || {
+            let port = Arc::clone(&port);
+            let last_actuator = Arc::clone(&last_actuator);
+            #[cfg(unix)]
+            let shm = shm.clone();
+            let capture = capture.clone();
+            let reports = Arc::clone(&reports);
+            let status_tx = status_tx.clone();
+            let schema = Arc::clone(&schema);
+            let lengths_tx = Arc::clone(&lengths_tx);
+            let currents_tx = Arc::clone(&currents_tx);
+            async move {
+                loop {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                    let mut buf = vec![0u8; encoding.width()];
+                    let val = read_frame(&mut *port.lock().await, &mut buf, capture.as_deref());
+                    // nothing read is nothing lost
+                    if val.is_err_and(|read| read > 0) && let Some(capture) = &capture {
+                        capture.record(Chunk::Short);
+                    }
+                    if val.is_err() {
+                        continue;
+                    }
+                    if encoding.has_handshake() && buf == telemetry::HANDSHAKE {
+                        let event = match read_line(&port, capture.as_deref()).await.and_then(|text| Schema::parse(&text)) {
+                            Ok(advertised) => {
+                                let event = StatusEvent::Message(format!("Firmware reports {advertised}"));
+                                *schema.lock().await = Arc::new(advertised);
+                                event
+                            }
+                            Err(e) => {
+                                if let Some(capture) = &capture {
+                                    capture.record(Chunk::Short);
+                                }
+                                StatusEvent::SerialError(format!("bad field list from the firmware: {e}"))
+                            }
+                        };
+                        let _ = status_tx.send(event).await;
+                        continue;
+                    }
+                    if encoding.has_handshake() && buf == telemetry::DEBUG_TEXT {
+                        let event = match read_line(&port, capture.as_deref()).await {
+                            Ok(text) => StatusEvent::FirmwareLog(text),
+                            Err(e) => {
+                                if let Some(capture) = &capture {
+                                    capture.record(Chunk::Short);
+                                }
+                                StatusEvent::SerialError(format!("bad debug text from the firmware: {e}"))
+                            }
+                        };
+                        let _ = status_tx.send(event).await;
+                        continue;
+                    }
+                    let schema = Arc::clone(&*schema.lock().await);
+                    let mut values = vec![encoding.decode(&buf)];
+                    let mut rest = vec![0u8; encoding.width() * (schema.fields().len() - 1)];
+                    if read_frame(&mut *port.lock().await, &mut rest, capture.as_deref()).is_err() {
+                        // the length is lost with whatever was read of the rest
+                        if let Some(capture) = &capture {
+                            capture.record(Chunk::Short);
+                        }
+                        continue;
+                    }
+                    values.extend(encoding.decode_all(&rest));
+                    let actuator = Actuator::try_from(last_actuator.load(Ordering::Relaxed)).unwrap_or(Actuator::M1);
+                    #[cfg(unix)]
+                    if let Some(shm) = &shm {
+                        shm.publish(actuator, values[0]);
+                    }
+                    lengths_tx.send_modify(|lengths| lengths[actuator as usize] = Some(values[0]));
+                    let report = Report { actuator, schema, values };
+                    if let Some(current) = report.current() {
+                        currents_tx.send_modify(|currents| currents[actuator as usize] = Some(current));
+                    }
+                    if !reports.publish(report) {
+                        // nobody's listening any more
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    };
+    supervisor.spawn("reader", reader);
+
+    let writer = SerialWriter {
+        port,
+        port_path: port_path.into(),
+        queue: Arc::new(Mutex::new(Queue { rx, backlog: VecDeque::new() })),
+        ledger,
+        status: status_tx,
+        last_actuator,
+        safety: options.safety,
+        lengths: lengths_rx,
+        currents: currents_rx,
+        profiler: options.profiler,
+        capture: options.capture,
+    };
+    let mut restarted = false;
+    let writer = supervisor.spawn("writer", move || {
+        let run = writer.clone().run(restarted);
+        restarted = true;
+        run
+    });
+
+    Ok(Link {
+        commands: tx,
+        status: status_rx,
+        telemetry: source.subscribe(),
+        acks: acks_rx,
+        outcomes,
+        health: supervisor.health(),
+        arming: None,
+        estop: None,
+        writer,
+        reports: source,
+        #[cfg(unix)]
+        _lock: lock,
+    })
+}
+
+struct Queue {
+    rx: mpsc::Receiver<Request>,
+    /// taken off the channel but not written yet, so later frames can be looked ahead at
+    backlog: VecDeque<Request>,
+}
+
+/// Everything the writer task works with, kept outside it so a restarted
+/// writer carries on from the same queue.
+#[derive(Clone)]
+struct SerialWriter {
+    port: Arc<Mutex<SerialStream>>,
+    port_path: Arc<str>,
+    queue: Arc<Mutex<Queue>>,
+    ledger: Ledger,
+    status: mpsc::Sender<StatusEvent>,
+    last_actuator: Arc<AtomicU8>,
+    safety: safety::Settings,
+    /// the last length reported for each actuator
+    lengths: watch::Receiver<[Option<f64>; 2]>,
+    /// the last current reported for each actuator, by firmware that reports one
+    currents: watch::Receiver<[Option<f64>; 2]>,
+    profiler: Option<Arc<Profiler>>,
+    capture: Option<Arc<capture::Writer>>,
+}
+
+impl SerialWriter {
+    /// Writes frames until every sender is gone and any timed stop still to
+    /// come has been made. After a restart there's no telling what the last
+    /// run left the actuators doing, so both are stopped first.
+    async fn run(self, restarted: bool) -> Result<(), String> {
+        let mut queue = self.queue.lock().await;
+        let mut safety = Safety::new(self.safety.clone());
+        let mut lengths = self.lengths.clone();
+        let mut currents = self.currents.clone();
+        // when each actuator is due to be stopped, from a `StopAfter`
+        let mut stop_at: [Option<Instant>; 2] = [None; 2];
+        if restarted {
+            self.stop_both(&mut safety).await;
+        }
+        loop {
+            if lengths.has_changed().unwrap_or(false) {
+                let now = *lengths.borrow_and_update();
+                self.enforce(now, &mut safety).await;
+            }
+            self.stop_timed(&mut stop_at, &mut safety).await;
+            if safety.watches_currents() && currents.has_changed().unwrap_or(false) {
+                let now = *currents.borrow_and_update();
+                self.rebudget(now, &mut safety).await;
+            }
+            self.raise(&mut safety).await;
+            while let Ok(request) = queue.rx.try_recv() {
+                queue.backlog.push_back(request);
+            }
+            let next_stop = stop_at.iter().flatten().copied().chain(safety.next_due()).min();
+            let request = match queue.backlog.pop_front() {
+                Some(request) => request,
+                None => tokio::select! {
+                    request = queue.rx.recv() => match (request, next_stop) {
+                        (Some(request), _) => request,
+                        // nobody can cancel a timed stop or a soft start any
+                        // more, so it's waited out rather than left for the next run
+                        (None, Some(at)) => {
+                            sleep_until(at.into()).await;
+                            continue;
+                        }
+                        (None, None) => return Ok(()),
+                    },
+                    // nothing to write, but a move already under way may
+                    // have just broken a rule
+                    Ok(()) = lengths.changed(), if safety.watches_lengths() => continue,
+                    Ok(()) = currents.changed(), if safety.watches_currents() => continue,
+                    _ = sleep_until(next_stop.unwrap_or_else(Instant::now).into()), if next_stop.is_some() => continue,
+                },
+            };
+            let cmd = match request {
+                Request::Frame(cmd) => cmd,
+                Request::StopAfter(actuator, after) => {
+                    // never, if it's too far off to say when
+                    stop_at[actuator as usize] = Instant::now().checked_add(after);
+                    continue;
+                }
+            };
+            let mut trace = self.profiler.as_ref().and_then(|p| p.dequeued());
+            if superseded(cmd, &queue.backlog) {
+                self.ledger.record(Outcome::Coalesced);
+                continue;
+            }
+            let actuator = cmd.actuator();
+            if let Some(until) = safety.wait_until(cmd, Instant::now()) {
+                sleep_until(until.into()).await;
+                // a newer speed may have come in while this one waited
+                while let Ok(later) = queue.rx.try_recv() {
+                    queue.backlog.push_back(later);
+                }
+                if superseded(cmd, &queue.backlog) {
+                    self.ledger.record(Outcome::Coalesced);
+                    continue;
+                }
+            }
+            let checked = safety.check(cmd, *lengths.borrow());
+            if let Some(event) = checked.blocked.clone() {
+                let _ = self.status.send(event).await;
+            }
+            let cmd = checked.cmd;
+            let deadline = safety.deadline(cmd, Instant::now());
+            let result = async {
+                if let Some(actuator) = safety.stop_first(cmd) {
+                    // never reverse something we couldn't stop
+                    let stop = ActuatorCommand::SetSpeed(0, actuator);
+                    write_frame(&self.port, stop, deadline, self.capture.as_deref()).await?;
+                    safety.written(stop, Instant::now());
+                    let _ = self.status.send(StatusEvent::InterlockStop(actuator)).await;
+                }
+                if let Some(until) = safety.hold_until(cmd) {
+                    sleep_until(until.into()).await;
+                }
+                if let Some(trace) = &mut trace {
+                    trace.serialized();
+                }
+                write_frame(&self.port, cmd, deadline, self.capture.as_deref()).await
+            }
+            .await;
+            let hung = match result {
+                Ok(()) => {
+                    safety.sent(&checked, Instant::now());
+                    if !matches!(cmd, ActuatorCommand::SetDirection(..)) {
+                        // whatever comes next for it replaces the timed move
+                        stop_at[actuator as usize] = None;
+                    }
+                    self.last_actuator.store(actuator as u8, Ordering::Relaxed);
+                    if let (Some(profiler), Some(trace)) = (&self.profiler, trace) {
+                        profiler.written(trace);
+                    }
+                    self.ledger.record(Outcome::Written);
+                    let _ = self.status.send(StatusEvent::CommandAcked(cmd)).await;
+                    false
+                }
+                Err(e) => {
+                    self.ledger.record(Outcome::Failed);
+                    let _ = self.status.send(StatusEvent::SerialError(e.to_string())).await;
+                    deadline.is_some_and(|deadline| Instant::now() >= deadline)
+                }
+            };
+            if hung {
+                self.recover(&mut queue, &mut safety).await;
+            }
+            sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    /// The watchdog's way out of a hung link: throw away what's queued,
+    /// reopen the port and stop both actuators.
+    async fn recover(&self, queue: &mut Queue, safety: &mut Safety) {
+        let waited = safety.watchdog().unwrap_or_default();
+        let _ = self.status.send(StatusEvent::FaultRaised(Fault::LinkHung(waited))).await;
+        // whatever queued up behind the hang is stale by now
+        while let Ok(request) = queue.rx.try_recv() {
+            queue.backlog.push_back(request);
+        }
+        let stale = queue.backlog.iter().filter(|request| matches!(request, Request::Frame(_))).count() as u64;
+        queue.backlog.clear();
+        if let Some(profiler) = &self.profiler {
+            profiler.discard(stale);
+        }
+        for _ in 0..stale {
+            self.ledger.record(Outcome::Dropped);
+        }
+        {
+            let mut port = self.port.lock().await;
+            // the old handle keeps the port locked against reopening
+            #[cfg(unix)]
+            let _ = port.set_exclusive(false);
+            let event = match tokio_serial::new(&*self.port_path, BAUD_RATE).open_native_async() {
+                Ok(reopened) => {
+                    *port = reopened;
+                    if let Some(capture) = &self.capture {
+                        capture.record(Chunk::Reopen);
+                    }
+                    StatusEvent::Reconnected
+                }
+                Err(e) => StatusEvent::FaultRaised(Fault::ReopenFailed(e.to_string())),
+            };
+            let _ = self.status.send(event).await;
+        }
+        self.stop_both(safety).await;
+    }
+
+    /// Stops whichever actuator is moving the way a rule says it mustn't,
+    /// given the latest `lengths`.
+    async fn enforce(&self, lengths: [Option<f64>; 2], safety: &mut Safety) {
+        for (actuator, rule) in safety.enforce(lengths) {
+            let stop = ActuatorCommand::SetSpeed(0, actuator);
+            let event = match write_frame(&self.port, stop, None, self.capture.as_deref()).await {
+                Ok(()) => {
+                    safety.written(stop, Instant::now());
+                    StatusEvent::ConstraintStop(actuator, rule)
+                }
+                Err(e) => StatusEvent::SerialError(e.to_string()),
+            };
+            let _ = self.status.send(event).await;
+        }
+    }
+
+    /// Stops each actuator whose time in `stop_at` is up, if it's still moving.
+    async fn stop_timed(&self, stop_at: &mut [Option<Instant>; 2], safety: &mut Safety) {
+        let now = Instant::now();
+        for actuator in [Actuator::M1, Actuator::M2] {
+            if stop_at[actuator as usize].is_none_or(|at| at > now) {
+                continue;
+            }
+            stop_at[actuator as usize] = None;
+            if !safety.is_moving(actuator) {
+                continue;
+            }
+            let stop = ActuatorCommand::SetSpeed(0, actuator);
+            let event = match write_frame(&self.port, stop, None, self.capture.as_deref()).await {
+                Ok(()) => {
+                    safety.written(stop, Instant::now());
+                    StatusEvent::TimedStop(actuator)
+                }
+                Err(e) => StatusEvent::SerialError(e.to_string()),
+            };
+            let _ = self.status.send(event).await;
+        }
+    }
+
+    /// Sends each actuator whose soft start is over the speed it was started
+    /// for, if it's still moving at the soft one.
+    async fn raise(&self, safety: &mut Safety) {
+        for cmd in safety.due(Instant::now()) {
+            self.send(cmd, safety).await;
+        }
+    }
+
+    /// Rescales for the latest `currents`, sending each actuator that's
+    /// moving (and not still starting softly) the speed it was asked for,
+    /// scaled, if that's changed.
+    async fn rebudget(&self, currents: [Option<f64>; 2], safety: &mut Safety) {
+        let Some((event, speeds)) = safety.rebudget(currents) else { return };
+        let _ = self.status.send(event).await;
+        for cmd in speeds {
+            self.send(cmd, safety).await;
+        }
+    }
+
+    /// Writes a speed `safety` called for of its own accord.
+    async fn send(&self, cmd: ActuatorCommand, safety: &mut Safety) {
+        let event = match write_frame(&self.port, cmd, None, self.capture.as_deref()).await {
+            Ok(()) => {
+                safety.written(cmd, Instant::now());
+                StatusEvent::CommandAcked(cmd)
+            }
+            Err(e) => StatusEvent::SerialError(e.to_string()),
+        };
+        let _ = self.status.send(event).await;
+    }
+
+    /// Stops both actuators, starting `safety` over from there.
+    async fn stop_both(&self, safety: &mut Safety) {
+        safety.restart();
+        for actuator in [Actuator::M1, Actuator::M2] {
+            let stop = ActuatorCommand::SetSpeed(0, actuator);
+            match write_frame(&self.port, stop, None, self.capture.as_deref()).await {
+                Ok(()) => safety.written(stop, Instant::now()),
+                Err(e) => {
+                    let fault = Fault::StopFailed(actuator, e.to_string());
+                    let _ = self.status.send(StatusEvent::FaultRaised(fault)).await;
+                }
+            }
+        }
+    }
+}
+
+/// Whether `cmd` is a speed that a later one for the same actuator, with no
+/// direction change in between, makes pointless to send. Stops always go out.
+fn superseded(cmd: ActuatorCommand, backlog: &VecDeque<Request>) -> bool {
+    let ActuatorCommand::SetSpeed(speed, actuator) = cmd else {
+        return false;
+    };
+    if speed == 0 {
+        return false;
+    }
+    for later in backlog {
+        let Request::Frame(later) = *later else { continue };
+        match later {
+            ActuatorCommand::SetSpeed(_, a) if a == actuator => return true,
+            ActuatorCommand::SetDirection(_, a) | ActuatorCommand::HoldPosition(a) if a == actuator => return false,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Reads the text that follows [`telemetry::HANDSHAKE`] or
+/// [`telemetry::DEBUG_TEXT`] up to a newline, waiting a little for bytes that
+/// haven't arrived yet.
+async fn read_line(port: &Mutex<SerialStream>, capture: Option<&capture::Writer>) -> Result<String, String> {
+    let deadline = Instant::now() + Duration::from_secs(1);
+    let mut text = Vec::new();
+    loop {
+        let mut byte = [0u8];
+        match read_frame(&mut *port.lock().await, &mut byte, capture).map_err(|_| io::ErrorKind::WouldBlock) {
+            Ok(()) if byte[0] == b'\n' => break,
+            Ok(()) if text.len() < 1024 => text.push(byte[0]),
+            Ok(()) => return Err(String::from("no newline after 1024 bytes")),
+            Err(_) if Instant::now() < deadline => sleep(Duration::from_millis(1)).await,
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+    Ok(String::from_utf8_lossy(&text).into_owned())
+}
+
+/// Fills `buf` from the port, as `read_exact` does, passing what it reads
+/// on to `capture`. If the port runs dry first, what it read so far is
+/// lost; the error is how many bytes that was.
+fn read_frame(port: &mut impl Read, buf: &mut [u8], capture: Option<&capture::Writer>) -> Result<(), usize> {
+    let mut read = 0;
+    let result = loop {
+        if read == buf.len() {
+            break Ok(());
+        }
+        match port.read(&mut buf[read..]) {
+            Ok(0) => break Err(read),
+            Ok(n) => read += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(_) => break Err(read),
+        }
+    };
+    if let Some(capture) = capture {
+        capture.record(Chunk::Bytes(buf[..read].to_vec()));
+    }
+    result
+}
+
+/// Writes one frame, retrying until `deadline` if there is one, and passes
+/// it on to `capture` once it's gone out.
+async fn write_frame(
+    port: &Mutex<SerialStream>,
+    cmd: ActuatorCommand,
+    deadline: Option<Instant>,
+    capture: Option<&capture::Writer>,
+) -> io::Result<()> {
+    let frame = cmd.serialize();
+    loop {
+        let result = port.lock().await.try_write(&frame).map(drop);
+        match deadline {
+            Some(deadline) if result.is_err() && Instant::now() < deadline => sleep(Duration::from_millis(10)).await,
+            _ => {
+                if result.is_ok() && let Some(capture) = capture {
+                    capture.record(Chunk::Sent(frame.to_vec()));
+                }
+                return result;
+            }
+        }
+    }
+}
@@ -0,0 +1,43 @@
+//! A load cell in the linkage, for digging tests.
+//!
+//! The firmware reads the load cell (through an HX711 or the like) and
+//! reports it as a [telemetry field](crate::telemetry) named `force`, in
+//! whatever units it counts in. Here it's turned into newtons and checked
+//! against an alarm threshold.
+
+/// The telemetry field the load cell comes in as.
+pub const FIELD: &str = "force";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LoadCell {
+    /// newtons per unit the firmware reports
+    pub scale: f64,
+    /// what the firmware reports with no load on the linkage
+    pub tare: f64,
+    /// force (N) above which the operator is warned; zero never warns
+    pub alarm: f64,
+    /// also stop both actuators when the alarm goes off
+    pub stop: bool,
+}
+
+impl Default for LoadCell {
+    fn default() -> Self {
+        LoadCell {
+            scale: 1.0,
+            tare: 0.0,
+            alarm: 0.0,
+            stop: false,
+        }
+    }
+}
+
+impl LoadCell {
+    /// The force (N) a reported value stands for.
+    pub fn force(&self, reported: f64) -> f64 {
+        (reported - self.tare) * self.scale
+    }
+
+    pub fn is_excessive(&self, force: f64) -> bool {
+        self.alarm > 0.0 && force.abs() > self.alarm
+    }
+}
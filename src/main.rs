@@ -1,257 +1,398 @@
-use std::{env::args, io::{self, Read}, sync::Arc, time::Duration};
-use tokio::{io::{AsyncBufReadExt, BufStream}, sync::{mpsc, RwLock}, time::sleep};
-use tokio_serial::{SerialPortBuilderExt, SerialStream};
-use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode},
-    execute,
-    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
-};
-use ratatui::{
-    backend::CrosstermBackend, layout::{Constraint, Layout}, style::{Color, Modifier, Style}, text::Text, widgets::{Block, Borders, Cell, Paragraph, Row, Table}, Terminal
-};
+use std::{env::args, io, path::Path, process::exit, sync::Arc, time::Duration};
 
-mod commands;
-use commands::*;
+use actuator_controller::{app::App, capture, identify, audit::AuditLog, client::ActuatorClient, journal, platform, compare::Recording, config::{self, Config}, latency, link, session, sim};
+#[cfg(unix)]
+use actuator_controller::{daemon, shm};
 
-struct App {
-    speed: u32,
-    direction: commands::Direction, // true = forward, false = backward
-    max_speed: u32,
-    status_message: String,
-    actuator: commands::Actuator,
-    actuator_len_meters: f64
-}
+#[cfg(feature = "tui")]
+mod about;
+mod batch;
+#[cfg(feature = "bridge")]
+mod bridge;
+mod cli;
+mod excite;
+mod generate;
+#[cfg(feature = "tui")]
+mod joystick;
+#[cfg(feature = "mdns")]
+mod mdns;
+mod monitor;
+#[cfg(all(feature = "tui", feature = "bridge"))]
+mod network;
+mod output;
+#[cfg(feature = "tui")]
+mod picker;
+#[cfg(feature = "tui")]
+mod preflight;
+#[cfg(all(feature = "tui", feature = "bridge"))]
+mod remote;
+mod replay;
+#[cfg(feature = "tui")]
+mod scope;
+mod soak;
+mod step;
+mod sweep;
+#[cfg(feature = "tui")]
+mod tui;
+#[cfg(feature = "bridge")]
+// the PUSH and SUB ends are only for `remote`
+#[cfg_attr(not(feature = "tui"), allow(dead_code))]
+mod zmtp;
 
-impl App {
-    fn new() -> App {
-        App {
-            speed: 0,
-            direction: commands::Direction::Forward,
-            max_speed: 65535, // Adjust based on the motor's capabilities
-            status_message: String::from("Ready"),
-            actuator: commands::Actuator::M1,
-            actuator_len_meters: 0.0
+#[tokio::main]
+async fn main() -> Result<(), io::Error> {
+    if args().nth(1).as_deref() == Some("generate") {
+        match args().nth(2).ok_or_else(|| format!("generate needs one of {}", generate::TARGETS.join(", "))).and_then(|t| generate::run(&t)) {
+            Ok(text) => print!("{text}"),
+            Err(e) => {
+                eprintln!("{e}");
+                exit(2);
+            }
         }
+        return Ok(());
     }
-
-    fn increase_speed(&mut self, amount: u32) {
-        self.speed = (self.speed + amount).min(self.max_speed);
+    if args().nth(1).as_deref() == Some("config") {
+        match config_command(&args().skip(2).collect::<Vec<_>>()) {
+            Ok(text) => print!("{text}"),
+            Err(e) => {
+                eprintln!("{e}");
+                exit(2);
+            }
+        }
+        return Ok(());
     }
-
-    fn decrease_speed(&mut self, amount: u32) {
-        self.speed = self.speed.saturating_sub(amount);
+    if args().nth(1).as_deref() == Some("identify") {
+        let path = args().nth(2).ok_or_else(|| String::from("identify needs the path of an --excite --output json log"));
+        match path.and_then(|p| std::fs::read_to_string(&p).map_err(|e| format!("{p}: {e}"))).and_then(|src| identify::identify(&src)) {
+            Ok(identification) => print!("{identification}"),
+            Err(e) => {
+                eprintln!("{e}");
+                exit(2);
+            }
+        }
+        return Ok(());
     }
-
-    fn set_direction(&mut self, dir: commands::Direction) {
-        self.direction = dir;
+    if args().nth(1).as_deref() == Some("frames") {
+        if let Err(e) = step::run(&args().skip(2).collect::<Vec<_>>()) {
+            eprintln!("{e}");
+            exit(2);
+        }
+        return Ok(());
     }
-}
-
-#[tokio::main]
-async fn main() -> Result<(), io::Error> {
-    enable_raw_mode()?;
-
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
-
-    let (tx, mut rx) = mpsc::channel::<ActuatorCommand>(100);
-    let (status_tx, mut status_rx) = mpsc::channel::<String>(100);
-    let (actuator_tx, mut actuator_rx) = mpsc::channel::<f64>(10);
-
-    let binding = args().collect::<Vec<String>>();
-    let Some(port_path) = binding.get(1) else {
-        // Restore terminal
-        disable_raw_mode()?;
-        execute!(
-            terminal.backend_mut(),
-            LeaveAlternateScreen,
-            DisableMouseCapture
-        )?;
-        terminal.show_cursor()?;
-        eprintln!("supply path argument. Example: /dev/ttyACM0");
+    if args().nth(1).as_deref() == Some("discover") {
+        #[cfg(not(feature = "mdns"))]
+        {
+            eprintln!("discover needs the mdns feature, which this build was made without");
+            exit(2);
+        }
+        #[cfg(feature = "mdns")]
+        {
+            let wait = match args().nth(2).map(|s| cli::seconds(&s)) {
+                None => std::time::Duration::from_secs(2),
+                Some(Some(wait)) => wait,
+                Some(None) => {
+                    eprintln!("discover takes how many seconds to listen for, e.g. 2, up to {}", actuator_controller::dsl::MAX_DURATION.as_secs());
+                    exit(2);
+                }
+            };
+            match mdns::discover(wait).await {
+                Ok(found) if found.is_empty() => {
+                    eprintln!("No bridges answered on {} within {} s", mdns::SERVICE, wait.as_secs_f64());
+                    exit(1);
+                }
+                Ok(found) => {
+                    for bridge in found {
+                        let endpoints: Vec<String> = [bridge.pull, bridge.publish].into_iter().flatten().collect();
+                        println!("{} {}", bridge.instance, endpoints.join(" "));
+                    }
+                }
+                Err(e) => {
+                    eprintln!("{e}");
+                    exit(2);
+                }
+            }
+            return Ok(());
+        }
+    }
+    if args().nth(1).as_deref() == Some("platform") {
+        print!("{}", platform::report());
         return Ok(());
+    }
+    if args().nth(1).as_deref() == Some("remote") {
+        #[cfg(not(all(feature = "tui", feature = "bridge")))]
+        {
+            eprintln!("remote needs the tui and bridge features, which this build was made without");
+            exit(2);
+        }
+        #[cfg(all(feature = "tui", feature = "bridge"))]
+        {
+            let mut words: Vec<String> = args().skip(2).collect();
+            let key = match words.iter().position(|w| w == "--key") {
+                Some(i) if i + 1 < words.len() => Some(words.drain(i..i + 2).nth(1).unwrap()),
+                Some(_) => {
+                    eprintln!("--key needs a value");
+                    exit(2);
+                }
+                None => None,
+            };
+            let Some(push) = words.first() else {
+                eprintln!("remote needs the endpoint of the bridge's PULL socket, e.g. tcp://robot:5557");
+                exit(2);
+            };
+            let keys = match Config::load_default() {
+                Ok(config) => config.keys,
+                Err(e) => {
+                    eprintln!("Couldn't load config: {e}");
+                    exit(2);
+                }
+            };
+            exit(remote::run(push, words.get(1).map(String::as_str), key.as_deref(), keys).await);
+        }
+    }
+    let args = match cli::parse(args().skip(1)) {
+        Ok(args) => args,
+        Err(e) if e.is_empty() => {
+            print!("{}", cli::usage());
+            return Ok(());
+        }
+        Err(e) => {
+            eprintln!("{e}\n\n{}", cli::usage());
+            exit(2);
+        }
     };
-
-    
-    let mut port = match tokio_serial::new(port_path, 9600).open_native_async() {
-        Ok(p) => p,
+    let config = match Config::load_with(&args.config_path, &args.sets) {
+        Ok(config) => config,
         Err(e) => {
-            // Restore terminal
-            disable_raw_mode()?;
-            execute!(
-                terminal.backend_mut(),
-                LeaveAlternateScreen,
-                DisableMouseCapture
-            )?;
-        terminal.show_cursor()?;
-            eprintln!("Couldn't open {port_path}: {e}");
-            return Ok(());
+            eprintln!("Couldn't load config: {e}");
+            exit(2);
         }
     };
-
-    let port = Arc::new(RwLock::new(port));
-
-    let status_tx_clone = status_tx.clone();
-    let port_clone = Arc::clone(&port);
-    tokio::spawn(async move {
-        loop {
-            tokio::time::sleep(Duration::from_millis(10)).await;
-            let mut buf = [0u8;8];
-            let val = port_clone.write().await.read_exact(&mut buf);
-            if let Ok(_) = val {
-                actuator_tx.send(f64::from_le_bytes(buf)).await.unwrap();
+    if args.soak && config.soak.cycle.is_empty() {
+        eprintln!("soak has nothing to cycle; list the motions in soak.cycle, e.g. \"m1 goto 0.30; m1 goto 0.05\"");
+        exit(2);
+    }
+    let recording = args.replay.as_ref().map(|log| {
+        match std::fs::read_to_string(log).map_err(|e| e.to_string()).and_then(|src| Recording::parse(&src)) {
+            Ok(recording) => recording,
+            Err(e) => {
+                eprintln!("Couldn't read {}: {e}", log.display());
+                exit(2);
             }
         }
     });
-    tokio::spawn(async move {
-        // let mut port = port;
-        while let Some(cmd) = rx.recv().await {
-            match cmd {
-                ActuatorCommand::SetSpeed(speed, actuator) => {
-                    let bytes = ActuatorCommand::SetSpeed(speed, actuator).serialize();
-                    if let Err(e) = port.write().await.try_write(&bytes) {
-                        let _ = status_tx_clone.send(format!("Serial error: {}", e)).await;
-                    } else {
-                        let _ = status_tx_clone.send(format!("Set speed to {}", speed)).await;
-                    }
+    let mut app = App::new(&config);
+    app.audit = match AuditLog::open(args.audit.as_deref(), &args.operator) {
+        Ok(audit) => audit,
+        Err(e) => {
+            eprintln!("Couldn't open audit log: {e}");
+            exit(2);
+        }
+    };
+    // so a log says what it was recorded with
+    let snapshot = session::snapshot(&config, &args.config_path);
+    app.audit.record(&format!("build {}", session::build()));
+    app.audit.record(&format!("config {}: {}", args.config_path.display(), config.snapshot()));
+    if let Some(path) = &args.journal {
+        app.interrupted = match journal::read(path) {
+            Ok(entry) => entry,
+            Err(e) => {
+                eprintln!("Couldn't read the journal: {e}");
+                exit(2);
+            }
+        };
+        app.journal = Some(journal::Journal::new(path));
+    }
+    #[cfg(unix)]
+    let shm = match &args.shm {
+        Some(path) => match shm::Writer::create(path) {
+            Ok(writer) => Some(writer),
+            Err(e) => {
+                eprintln!("Couldn't create {}: {e}", path.display());
+                exit(2);
+            }
+        },
+        None => None,
+    };
+    let capture = match &args.capture {
+        Some(path) => match capture::Writer::create(path) {
+            Ok(writer) => Some(Arc::new(writer)),
+            Err(e) => {
+                eprintln!("Couldn't create {}: {e}", path.display());
+                exit(2);
+            }
+        },
+        None => None,
+    };
+    let profiler = args.profile.then(|| Arc::new(latency::Profiler::default()));
+    app.profiler = profiler.clone();
+    let options = link::Options {
+        #[cfg(unix)]
+        shm,
+        safety: config.safety(),
+        profiler: profiler.clone(),
+        capture,
+        encoding: config.encoding,
+        lock: args.lock,
+    };
+    // for `:fault` under `--sim`
+    let mut faults = None;
+    let mut link = match (&args.sim, &args.port_path) {
+        (Some(log), _) => {
+            match std::fs::read_to_string(log).map_err(|e| e.to_string()).and_then(|src| sim::Profile::from_log(&src)) {
+                Ok(profile) => {
+                    let strokes = [config.linkage.lift.max_stroke, config.linkage.bucket.max_stroke];
+                    let (link, injections) = sim::open(profile, strokes, options.safety.constraints(), config.sim_faults.clone());
+                    faults = Some(injections);
+                    link
                 }
-                ActuatorCommand::SetDirection(dir, actuator) => {
-                    let bytes = ActuatorCommand::SetDirection(dir, actuator).serialize();
-                    if let Err(e) = port.write().await.try_write(&bytes) {
-                        let _ = status_tx_clone.send(format!("Serial error: {}", e)).await;
-                    } else {
-                        let dir_str = if dir == commands::Direction::Forward { "forward" } else { "backward" };
-                        let _ = status_tx_clone.send(format!("Set direction to {}", dir_str)).await;
-                    }
+                Err(e) => {
+                    eprintln!("Couldn't model {}: {e}", log.display());
+                    exit(2);
                 }
             }
-            sleep(Duration::from_millis(50)).await;
         }
-    });
+        (None, Some(port_path)) => match link::open_with(port_path, options) {
+            Ok(link) => link,
+            Err(e) => {
+                let ports = match platform::serial_ports() {
+                    Ok(ports) if ports.is_empty() => String::from("no serial ports found"),
+                    Ok(ports) => format!("serial ports found: {}", ports.join(", ")),
+                    Err(e) => e,
+                };
+                eprintln!("Couldn't open {port_path}: {e} ({ports})");
+                exit(2);
+            }
+        },
+        #[cfg(unix)]
+        (None, None) => match daemon::attach(&args.socket).await {
+            Ok(link) => link,
+            Err(e) => {
+                eprintln!("Couldn't attach to {}: {e}", args.socket.display());
+                exit(2);
+            }
+        },
+        // refused by cli::parse
+        #[cfg(not(unix))]
+        (None, None) => unreachable!("--attach needs Unix"),
+    };
+    link.derive(config.channels.clone());
 
-    let mut app = App::new();
+    #[cfg(unix)]
+    if args.daemon {
+        if let Err(e) = daemon::serve(link, &args.socket, config.arming, |msg| eprintln!("{msg}")).await {
+            eprintln!("Couldn't serve {}: {e}", args.socket.display());
+            exit(1);
+        }
+        return Ok(());
+    }
+    app.arming.connected = link.arming.is_some();
 
-    loop {
-        if let Ok(msg) = status_rx.try_recv() {
-            app.status_message = msg;
+    if args.soak {
+        let duration = args.duration.unwrap_or(config.soak.duration);
+        exit(soak::run(link, app, &config, duration, args.timeout).await);
+    }
+    if args.stdin {
+        let out = output::Output::new(args.output, args.output == output::Format::Json);
+        out.emit(output::Event::Session(&snapshot));
+        let mut client = ActuatorClient::new(link, app);
+        client.set_timeout(args.timeout);
+        let code = batch::run(client, &config.startup, &out).await;
+        exit(code);
+    }
+    #[cfg(feature = "bridge")]
+    if args.zmq_pub.is_some() || args.zmq_pull.is_some() {
+        let publisher = match &args.zmq_pub {
+            Some(endpoint) => Some(zmtp::Publisher::bind(endpoint).await),
+            None => None,
         }
-        if let Ok(msg) = actuator_rx.try_recv() {
-            app.actuator_len_meters = msg;
+        .transpose();
+        let commands = match &args.zmq_pull {
+            Some(endpoint) => Some(zmtp::bind_pull(endpoint).await),
+            None => None,
         }
-        
-        terminal.draw(|f| {
-            
-            let chunks = Layout::default()
-                .direction(ratatui::layout::Direction::Vertical)
-                .margin(1)
-                .constraints([
-                    Constraint::Percentage(25),
-                    Constraint::Percentage(25),
-                    Constraint::Percentage(25),
-                    Constraint::Percentage(25),
-                ].as_ref())
-                .split(f.area());
-
-            let dir_str = if app.direction == Direction::Forward {"Forward"} else {"Backward"};
-            
-            let speed_text = Text::from(format!("Speed: {} / {}", app.speed, app.max_speed));
-            let speed_paragraph = Paragraph::new(speed_text)
-                .block(Block::default().title("Motor Speed").borders(Borders::ALL));
-            f.render_widget(speed_paragraph, chunks[0]);
-            
-            let dir_text = Text::from(format!("Direction: {}", dir_str));
-            let dir_paragraph = Paragraph::new(dir_text)
-                .block(Block::default().title("Motor Direction").borders(Borders::ALL));
-            f.render_widget(dir_paragraph, chunks[1]);
-
-            let status_text = format!("Status: {} | {:?}", app.status_message, app.actuator);
-            let actuator_len_text = format!("Actuator len (m): {}",app.actuator_len_meters);
-
-            let status_table_rows = [
-                Row::new(vec![Cell::new(status_text),Cell::new(actuator_len_text)])
-            ];
-            let status_table = Table::new(status_table_rows, [Constraint::Percentage(50),Constraint::Percentage(50)])
-                .block(Block::default().title("Info").borders(Borders::ALL));
-            
-            f.render_widget(status_table, chunks[2]);
-            
-            let help_text = Text::from(
-                "↑/↓: Change speed | ←/→: Switch Direction | q: Quit\n\
-                 s: Stop motor | +/-: Increase/decrease speed by 5000 | a: Change actuator (bucket or lift)"
-            );
-            let help_paragraph = Paragraph::new(help_text)
-                .block(Block::default().title("Controls").borders(Borders::ALL));
-            f.render_widget(help_paragraph, chunks[3]);
-        })?;
-
-        if event::poll(Duration::from_millis(100))? {
-            if let Event::Key(key) = event::read()? {
-                match key.code {
-                    KeyCode::Char('q') => break,
-                    KeyCode::Char('s') => {
-                        app.speed = 0;
-                        let _ = tx.send(ActuatorCommand::SetSpeed(0, app.actuator)).await;
-                    },
-                    KeyCode::Up => {
-                        app.increase_speed(1000);
-                        let _ = tx.send(ActuatorCommand::SetSpeed(app.speed as u16, app.actuator)).await;
-                    },
-                    KeyCode::Down => {
-                        app.decrease_speed(1000);
-                        let _ = tx.send(ActuatorCommand::SetSpeed(app.speed as u16, app.actuator)).await;
-                    },
-                    KeyCode::Left => {
-                        app.set_direction(commands::Direction::Backward);
-                        let _ = tx.send(ActuatorCommand::SetDirection(
-                            commands::Direction::Backward,
-                            app.actuator
-                        )).await; 
-                    }
-                    
-                    KeyCode::Right => {
-                        app.set_direction(commands::Direction::Forward);
-                        let _ = tx.send(ActuatorCommand::SetDirection(
-                            commands::Direction::Forward,
-                            app.actuator
-                        )).await;
-                    },
-                    KeyCode::Char('+') => {
-                        app.increase_speed(5000);
-                        let _ = tx.send(ActuatorCommand::SetSpeed(app.speed as u16, app.actuator)).await;
-                    },
-                    KeyCode::Char('-') => {
-                        app.decrease_speed(5000);
-                        let _ = tx.send(ActuatorCommand::SetSpeed(app.speed as u16, app.actuator)).await;
-                    },
-                    KeyCode::Char('a') => {
-                        app.speed = 0;
-                        let _ = tx.send(ActuatorCommand::SetSpeed(
-                            app.speed as u16,
-                            app.actuator
-                        )).await;
-                        if app.actuator == Actuator::M1 {
-                            app.actuator = Actuator::M2;
-                        } else {
-                            app.actuator = Actuator::M1;
-                        }
-                        app.status_message = format!("Switched to {:?}",app.actuator);
-                    }
-                    _ => {}
-                }
+        .transpose();
+        let (publisher, commands) = match (publisher, commands) {
+            (Ok(publisher), Ok(commands)) => (publisher, commands),
+            (Err(e), _) | (_, Err(e)) => {
+                eprintln!("{e}");
+                exit(2);
+            }
+        };
+        #[cfg(feature = "mdns")]
+        if let Some(instance) = &args.advertise {
+            let port = |endpoint: &Option<String>| endpoint.as_deref()?.rsplit(':').next()?.parse().ok();
+            let service = mdns::Service { instance: instance.clone(), pull: port(&args.zmq_pull), publish: port(&args.zmq_pub) };
+            if let Err(e) = mdns::advertise(service).await {
+                eprintln!("{e}");
+                exit(2);
             }
         }
+        let mut client = ActuatorClient::new(link, app);
+        client.set_timeout(args.timeout);
+        let out = output::Output::new(args.output, args.output == output::Format::Json);
+        out.emit(output::Event::Session(&snapshot));
+        bridge::run(client, publisher, commands, &config, &out).await;
+        return Ok(());
+    }
+    if !args.excite.is_empty() {
+        let out = output::Output::new(args.output, true);
+        out.emit(output::Event::Session(&snapshot));
+        let code = excite::run(&mut link, app, &args.excite, args.duration.unwrap_or(Duration::from_secs(10)), &out).await;
+        link.close().await;
+        exit(code);
+    }
+    if let Some(sweep) = args.sweep {
+        let code = sweep::run(&mut link, app, sweep, config.sweep).await;
+        link.close().await;
+        exit(code);
+    }
+    if let Some(recording) = &recording {
+        let out = output::Output::new(args.output, args.output == output::Format::Json);
+        out.emit(output::Event::Session(&snapshot));
+        let code = replay::run(&mut link, app, recording, config.replay, &out).await;
+        link.close().await;
+        exit(code);
+    }
+    if args.monitor {
+        let out = output::Output::new(args.output, true);
+        out.emit(output::Event::Session(&snapshot));
+        monitor::run(&mut link, &out).await;
+        link.close().await;
+        return Ok(());
     }
 
-    // Restore terminal
-    disable_raw_mode()?;
-    execute!(
-        terminal.backend_mut(),
-        LeaveAlternateScreen,
-        DisableMouseCapture
-    )?;
-    terminal.show_cursor()?;
+    #[cfg(feature = "tui")]
+    {
+        tui::run(&args, &config, app, link, profiler, faults).await
+    }
+    #[cfg(not(feature = "tui"))]
+    {
+        // `cli::parse` refuses to get this far
+        drop((app, link, profiler, faults));
+        unreachable!("no mode to run without the terminal UI")
+    }
+}
 
-    Ok(())
+/// `config schema` or `config migrate [PATH]`.
+fn config_command(args: &[String]) -> Result<String, String> {
+    match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["schema"] => Ok(format!("{}\n", Config::schema())),
+        ["migrate"] => migrate_config(Path::new(config::DEFAULT_PATH)),
+        ["migrate", path] => migrate_config(Path::new(path)),
+        _ => Err(String::from("usage: actuator_controller config schema | config migrate [PATH]")),
+    }
 }
+
+/// Upgrades the config at `path` in place, keeping the original as `PATH.bak`.
+fn migrate_config(path: &Path) -> Result<String, String> {
+    let src = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    let (from, upgraded) = config::migrate(&src).map_err(|e| format!("{}: {e}", path.display()))?;
+    if from == config::VERSION {
+        return Ok(format!("{} is already at version {from}\n", path.display()));
+    }
+    let backup = std::path::PathBuf::from(format!("{}.bak", path.display()));
+    std::fs::write(&backup, &src).map_err(|e| format!("{}: {e}", backup.display()))?;
+    std::fs::write(path, upgraded).map_err(|e| format!("{}: {e}", path.display()))?;
+    Ok(format!("Upgraded {} from version {from} to {}, the original is in {}\n", path.display(), config::VERSION, backup.display()))
+}
+
@@ -0,0 +1,471 @@
+//! Just enough multicast DNS (<https://www.rfc-editor.org/rfc/rfc6762>) and
+//! DNS-SD for driver stations to find the bridge without knowing its
+//! address: `--advertise` answers for [`SERVICE`], and `discover` asks.
+//!
+//! An instance is advertised as `<name>._actuatorctl._tcp.local` with the
+//! PULL socket's port in its SRV record, and the PUB socket's as `pub=<port>`
+//! in its TXT record.
+
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket as StdUdpSocket},
+    time::Duration,
+};
+#[cfg(unix)]
+use std::{mem::size_of, os::fd::FromRawFd};
+
+use tokio::{net::UdpSocket, time::timeout_at};
+
+pub const SERVICE: &str = "_actuatorctl._tcp.local";
+
+const GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+const PORT: u16 = 5353;
+const TTL: u32 = 120;
+
+const A: u16 = 1;
+const PTR: u16 = 12;
+const TXT: u16 = 16;
+const SRV: u16 = 33;
+const ANY: u16 = 255;
+const IN: u16 = 1;
+/// the top bit of a question's class asks for a unicast answer, of a
+/// record's says it replaces what's cached
+const TOP: u16 = 0x8000;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Data {
+    Ptr(String),
+    Srv { port: u16, target: String },
+    Txt(Vec<String>),
+    A(Ipv4Addr),
+    Other,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Record {
+    name: String,
+    data: Data,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Message {
+    id: u16,
+    response: bool,
+    /// name, type and whether a unicast answer is wanted
+    questions: Vec<(String, u16, bool)>,
+    /// answers and additional records alike
+    records: Vec<Record>,
+}
+
+fn put_name(out: &mut Vec<u8>, name: &str) {
+    for label in name.split('.') {
+        out.push(label.len() as u8);
+        out.extend(label.as_bytes());
+    }
+    out.push(0);
+}
+
+impl Message {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        let flags: u16 = if self.response { 0x8400 } else { 0 };
+        for n in [self.id, flags, self.questions.len() as u16, self.records.len() as u16, 0, 0] {
+            out.extend(n.to_be_bytes());
+        }
+        for (name, kind, unicast) in &self.questions {
+            put_name(&mut out, name);
+            out.extend(kind.to_be_bytes());
+            out.extend((IN | if *unicast { TOP } else { 0 }).to_be_bytes());
+        }
+        for record in &self.records {
+            put_name(&mut out, &record.name);
+            let mut data = Vec::new();
+            let kind = match &record.data {
+                Data::Ptr(name) => {
+                    put_name(&mut data, name);
+                    PTR
+                }
+                Data::Srv { port, target } => {
+                    data.extend([0, 0, 0, 0]);
+                    data.extend(port.to_be_bytes());
+                    put_name(&mut data, target);
+                    SRV
+                }
+                Data::Txt(strings) => {
+                    if strings.is_empty() {
+                        // a TXT record holds at least one string, if empty
+                        data.push(0);
+                    }
+                    for s in strings {
+                        data.push(s.len() as u8);
+                        data.extend(s.as_bytes());
+                    }
+                    TXT
+                }
+                Data::A(ip) => {
+                    data.extend(ip.octets());
+                    A
+                }
+                Data::Other => continue,
+            };
+            // PTRs are shared between every instance, the rest are ours alone
+            let class = if kind == PTR { IN } else { IN | TOP };
+            out.extend(kind.to_be_bytes());
+            out.extend(class.to_be_bytes());
+            out.extend(TTL.to_be_bytes());
+            out.extend((data.len() as u16).to_be_bytes());
+            out.extend(data);
+        }
+        out
+    }
+
+    /// `None` for anything malformed, which on a shared network is best
+    /// ignored.
+    fn parse(buf: &[u8]) -> Option<Message> {
+        let u16_at = |at: usize| Some(u16::from_be_bytes(buf.get(at..at + 2)?.try_into().ok()?));
+        let mut msg = Message { id: u16_at(0)?, response: u16_at(2)? & 0x8000 != 0, ..Message::default() };
+        let questions = u16_at(4)?;
+        let records = u16_at(6)? as usize + u16_at(8)? as usize + u16_at(10)? as usize;
+        let mut at = 12;
+        for _ in 0..questions {
+            let (name, next) = read_name(buf, at)?;
+            msg.questions.push((name, u16_at(next)?, u16_at(next + 2)? & TOP != 0));
+            at = next + 4;
+        }
+        for _ in 0..records {
+            let (name, next) = read_name(buf, at)?;
+            let kind = u16_at(next)?;
+            let len = u16_at(next + 8)? as usize;
+            let start = next + 10;
+            let body = buf.get(start..start + len)?;
+            let data = match kind {
+                PTR => Data::Ptr(read_name(buf, start)?.0),
+                SRV => Data::Srv { port: u16_at(start + 4)?, target: read_name(buf, start + 6)?.0 },
+                TXT => {
+                    let mut strings = Vec::new();
+                    let mut rest = body;
+                    while let Some((&n, tail)) = rest.split_first() {
+                        let s = tail.get(..n as usize)?;
+                        strings.push(String::from_utf8_lossy(s).into_owned());
+                        rest = &tail[n as usize..];
+                    }
+                    Data::Txt(strings)
+                }
+                A if len == 4 => Data::A(Ipv4Addr::new(body[0], body[1], body[2], body[3])),
+                _ => Data::Other,
+            };
+            msg.records.push(Record { name, data });
+            at = start + len;
+        }
+        Some(msg)
+    }
+}
+
+/// The name at `at`, following compression pointers, and where whatever
+/// comes after it starts.
+fn read_name(buf: &[u8], mut at: usize) -> Option<(String, usize)> {
+    let mut labels: Vec<String> = Vec::new();
+    let mut after = None;
+    // enough for any real name, and stops pointer loops
+    for _ in 0..64 {
+        let len = *buf.get(at)? as usize;
+        match len {
+            0 => return Some((labels.join("."), after.unwrap_or(at + 1))),
+            _ if len & 0xC0 == 0xC0 => {
+                after.get_or_insert(at + 2);
+                at = (len & 0x3F) << 8 | *buf.get(at + 1)? as usize;
+            }
+            _ => {
+                labels.push(String::from_utf8_lossy(buf.get(at + 1..at + 1 + len)?).into_owned());
+                at += 1 + len;
+            }
+        }
+    }
+    None
+}
+
+/// The bridge's sockets, as advertised under `instance`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Service {
+    pub instance: String,
+    pub pull: Option<u16>,
+    pub publish: Option<u16>,
+}
+
+impl Service {
+    fn full_name(&self) -> String {
+        format!("{}.{SERVICE}", self.instance)
+    }
+
+    fn host(&self) -> String {
+        format!("{}.local", self.instance)
+    }
+
+    fn records(&self, ip: Ipv4Addr) -> Vec<Record> {
+        let mut txt: Vec<String> = self.publish.map(|port| format!("pub={port}")).into_iter().collect();
+        if self.pull.is_none() {
+            // the SRV record has to name some port
+            txt.push(String::from("pull=none"));
+        }
+        vec![
+            Record { name: SERVICE.to_string(), data: Data::Ptr(self.full_name()) },
+            Record {
+                name: self.full_name(),
+                data: Data::Srv { port: self.pull.or(self.publish).unwrap_or(0), target: self.host() },
+            },
+            Record { name: self.full_name(), data: Data::Txt(txt) },
+            Record { name: self.host(), data: Data::A(ip) },
+        ]
+    }
+
+    fn is_asked_for(&self, name: &str) -> bool {
+        [SERVICE.to_string(), self.full_name(), self.host()].iter().any(|ours| ours.eq_ignore_ascii_case(name))
+    }
+}
+
+/// The address other machines on the network reach this one by: the one
+/// multicast goes out from.
+fn local_ip() -> io::Result<Ipv4Addr> {
+    let probe = StdUdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    probe.connect((GROUP, PORT))?;
+    match probe.local_addr()? {
+        SocketAddr::V4(addr) => Ok(*addr.ip()),
+        SocketAddr::V6(_) => Err(io::Error::other("no IPv4 address")),
+    }
+}
+
+/// A socket on port 5353 that shares it with any other responder on the
+/// machine, such as Avahi, which `bind` alone won't do.
+#[cfg(unix)]
+fn bind_shared() -> io::Result<UdpSocket> {
+    // SAFETY: plain socket calls on a descriptor that's closed on error or
+    // handed to the std socket, which owns it from then on
+    unsafe {
+        let fd = libc::socket(libc::AF_INET, libc::SOCK_DGRAM, 0);
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let socket = StdUdpSocket::from_raw_fd(fd);
+        // not SOCK_CLOEXEC, which macOS doesn't have
+        if libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let on: libc::c_int = 1;
+        for option in [libc::SO_REUSEADDR, libc::SO_REUSEPORT] {
+            let ptr = (&on as *const libc::c_int).cast();
+            if libc::setsockopt(fd, libc::SOL_SOCKET, option, ptr, size_of::<libc::c_int>() as libc::socklen_t) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        // zeroed rather than written out, as the BSDs have an extra `sin_len`
+        let mut addr: libc::sockaddr_in = std::mem::zeroed();
+        #[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd", target_os = "openbsd", target_os = "netbsd"))]
+        {
+            addr.sin_len = size_of::<libc::sockaddr_in>() as u8;
+        }
+        addr.sin_family = libc::AF_INET as libc::sa_family_t;
+        addr.sin_port = PORT.to_be();
+        addr.sin_addr = libc::in_addr { s_addr: u32::from(Ipv4Addr::UNSPECIFIED).to_be() };
+        let ptr = (&addr as *const libc::sockaddr_in).cast();
+        if libc::bind(fd, ptr, size_of::<libc::sockaddr_in>() as libc::socklen_t) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        socket.join_multicast_v4(&GROUP, &Ipv4Addr::UNSPECIFIED)?;
+        socket.set_nonblocking(true)?;
+        UdpSocket::from_std(socket)
+    }
+}
+
+/// Port 5353 without sharing it, so only when nothing else on the machine
+/// is answering mDNS.
+#[cfg(not(unix))]
+fn bind_shared() -> io::Result<UdpSocket> {
+    let socket = StdUdpSocket::bind((Ipv4Addr::UNSPECIFIED, PORT))?;
+    socket.join_multicast_v4(&GROUP, &Ipv4Addr::UNSPECIFIED)?;
+    socket.set_nonblocking(true)?;
+    UdpSocket::from_std(socket)
+}
+
+/// Announces `service` and answers queries for it in the background, for as
+/// long as the process runs.
+pub async fn advertise(service: Service) -> Result<(), String> {
+    let socket = bind_shared().map_err(|e| format!("couldn't advertise on mDNS: {e}"))?;
+    let group = SocketAddr::V4(SocketAddrV4::new(GROUP, PORT));
+    let ours = service.clone();
+    let answer = move |id: u16, questions: Vec<(String, u16, bool)>| -> Option<Vec<u8>> {
+        let records = service.records(local_ip().ok()?);
+        Some(Message { id, response: true, questions, records }.encode())
+    };
+    tokio::spawn(async move {
+        // twice a second apart, as a responder starting up should
+        for _ in 0..2 {
+            if let Some(packet) = answer(0, Vec::new()) {
+                let _ = socket.send_to(&packet, group).await;
+            }
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+        let mut buf = [0; 9000];
+        while let Ok((len, from)) = socket.recv_from(&mut buf).await {
+            let Some(query) = Message::parse(&buf[..len]) else { continue };
+            let asked = query.questions.iter().any(|(name, kind, _)| {
+                matches!(*kind, PTR | SRV | TXT | A | ANY) && ours.is_asked_for(name)
+            });
+            if query.response || !asked {
+                continue;
+            }
+            // a querier not on 5353 is a one-shot resolver expecting a
+            // plain DNS answer back, its question and ID included
+            let legacy = from.port() != PORT;
+            let unicast = legacy || query.questions.iter().any(|&(_, _, unicast)| unicast);
+            let packet = if legacy { answer(query.id, query.questions) } else { answer(0, Vec::new()) };
+            if let Some(packet) = packet {
+                let _ = socket.send_to(&packet, if unicast { from } else { group }).await;
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Asks for every bridge on the network and collects the answers that come
+/// in within `wait`.
+pub async fn discover(wait: Duration) -> Result<Vec<Found>, String> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await.map_err(|e| e.to_string())?;
+    let query = Message { id: std::process::id() as u16, questions: vec![(SERVICE.to_string(), PTR, false)], ..Message::default() };
+    socket
+        .send_to(&query.encode(), (GROUP, PORT))
+        .await
+        .map_err(|e| format!("couldn't send an mDNS query: {e}"))?;
+
+    let deadline = tokio::time::Instant::now() + wait;
+    let mut records = Vec::new();
+    let mut buf = [0; 9000];
+    while let Ok(Ok((len, from))) = timeout_at(deadline, socket.recv_from(&mut buf)).await {
+        if let Some(msg) = Message::parse(&buf[..len])
+            && msg.response
+        {
+            records.extend(msg.records.into_iter().map(|record| (record, from.ip())));
+        }
+    }
+
+    let mut found: Vec<Found> = Vec::new();
+    for (record, _) in &records {
+        let Data::Ptr(full_name) = &record.data else { continue };
+        if !record.name.eq_ignore_ascii_case(SERVICE) || found.iter().any(|f| f.full_name == *full_name) {
+            continue;
+        }
+        let of = |name: &str| -> Vec<&(Record, IpAddr)> {
+            records.iter().filter(|(r, _)| r.name.eq_ignore_ascii_case(name)).collect()
+        };
+        let Some((port, target, from)) = of(full_name).into_iter().find_map(|(r, from)| match &r.data {
+            Data::Srv { port, target } => Some((*port, target.clone(), *from)),
+            _ => None,
+        }) else {
+            continue;
+        };
+        let txt: Vec<&String> = of(full_name).into_iter().filter_map(|(r, _)| if let Data::Txt(t) = &r.data { Some(t) } else { None }).flatten().collect();
+        let value = |key: &str| txt.iter().find_map(|s| s.strip_prefix(key)?.strip_prefix('='));
+        let host = of(&target)
+            .into_iter()
+            .find_map(|(r, _)| if let Data::A(ip) = r.data { Some(ip.to_string()) } else { None })
+            .unwrap_or_else(|| from.to_string());
+        found.push(Found {
+            instance: full_name.strip_suffix(&format!(".{SERVICE}")).unwrap_or(full_name).to_string(),
+            full_name: full_name.clone(),
+            pull: (value("pull") != Some("none")).then(|| format!("tcp://{host}:{port}")),
+            publish: value("pub").map(|port| format!("tcp://{host}:{port}")),
+        });
+    }
+    Ok(found)
+}
+
+/// A bridge that answered, with endpoints `remote` takes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Found {
+    pub instance: String,
+    full_name: String,
+    pub pull: Option<String>,
+    pub publish: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service() -> Service {
+        Service { instance: String::from("rig"), pull: Some(5557), publish: Some(5556) }
+    }
+
+    #[test]
+    fn round_trip() {
+        let query = Message { id: 7, questions: vec![(SERVICE.to_string(), PTR, true)], ..Message::default() };
+        assert_eq!(Message::parse(&query.encode()), Some(query));
+        let answer = Message { id: 0, response: true, questions: Vec::new(), records: service().records(Ipv4Addr::new(10, 0, 0, 12)) };
+        assert_eq!(Message::parse(&answer.encode()), Some(answer));
+    }
+
+    #[test]
+    fn compressed_names() {
+        // "rig._actuatorctl._tcp.local" as a label, then a pointer to the service name at 12
+        let mut buf = vec![0; 12];
+        put_name(&mut buf, SERVICE);
+        let at = buf.len();
+        buf.extend([3, b'r', b'i', b'g', 0xC0, 12]);
+        assert_eq!(read_name(&buf, at), Some((format!("rig.{SERVICE}"), at + 6)));
+    }
+
+    #[test]
+    fn malformed() {
+        let good = Message { id: 1, response: true, questions: Vec::new(), records: service().records(Ipv4Addr::LOCALHOST) }.encode();
+        // every truncation either parses as less or not at all, without panicking
+        for len in 0..good.len() {
+            let _ = Message::parse(&good[..len]);
+        }
+        assert_eq!(Message::parse(&[]), None);
+        assert_eq!(Message::parse(&[0; 11]), None);
+        // claiming questions and records that aren't there
+        assert_eq!(Message::parse(&[0, 0, 0x84, 0, 0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0]), None);
+        // a label running off the end, a pointer past the end
+        assert_eq!(read_name(&[5, b'a', b'b'], 0), None);
+        assert_eq!(read_name(&[0xC0, 0xff], 0), None);
+        // a TXT string longer than its record
+        let mut txt = vec![0, 0, 0x84, 0, 0, 0, 0, 1, 0, 0, 0, 0];
+        put_name(&mut txt, "x.local");
+        txt.extend(TXT.to_be_bytes());
+        txt.extend(IN.to_be_bytes());
+        txt.extend(TTL.to_be_bytes());
+        txt.extend(3u16.to_be_bytes());
+        txt.extend([9, b'a', b'b']);
+        assert_eq!(Message::parse(&txt), None);
+    }
+
+    #[test]
+    fn pointer_loops() {
+        // a pointer to itself, and two pointing at each other
+        assert_eq!(read_name(&[0xC0, 0], 0), None);
+        assert_eq!(read_name(&[0xC0, 2, 0xC0, 0], 0), None);
+        // a label then a pointer to the next, a hundred times over: deeper
+        // than any real name
+        let mut deep = Vec::new();
+        for i in 1..=100u16 {
+            let next = 4 * i;
+            deep.extend([1, b'a', 0xC0 | (next >> 8) as u8, next as u8]);
+        }
+        deep.push(0);
+        assert_eq!(read_name(&deep, 0), None);
+        assert_eq!(read_name(&deep, 4 * 90).map(|(name, _)| name.len()), Some(19));
+    }
+
+    #[test]
+    fn oversized() {
+        let big = vec![0xff; 9000];
+        assert!(Message::parse(&big).is_none());
+        let mut many = vec![0, 0, 0x84, 0, 0, 0, 0xff, 0xff, 0, 0, 0, 0];
+        for _ in 0..2000 {
+            put_name(&mut many, "a");
+            many.extend([0, 99, 0, 1, 0, 0, 0, 0, 0, 0]);
+        }
+        assert_eq!(Message::parse(&many), None);
+        many[6..8].copy_from_slice(&2000u16.to_be_bytes());
+        assert_eq!(Message::parse(&many).map(|msg| msg.records.len()), Some(2000));
+    }
+}
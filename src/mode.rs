@@ -0,0 +1,70 @@
+//! How manual speed/direction input fans out to the actuators.
+
+use crate::commands::{Actuator, Drive};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    /// only the selected actuator moves
+    Single,
+    /// both actuators get every command, speeds scaled by their trim and
+    /// skewed by the offset like in differential mode
+    Mirror,
+    /// both actuators get every command but M2 runs the opposite way
+    Differential,
+    /// W/S drives M1 and ↑/↓ drives M2, independently and at the same time
+    Split,
+}
+
+impl Mode {
+    pub fn label(self) -> &'static str {
+        match self {
+            Mode::Single => "single",
+            Mode::Mirror => "mirror",
+            Mode::Differential => "differential",
+            Mode::Split => "split",
+        }
+    }
+
+    /// What the arrow keys do in this mode.
+    pub fn arrow_help(self) -> &'static str {
+        match self {
+            Mode::Single => "↑/↓: Change speed | ←/→: Switch Direction",
+            Mode::Mirror => "↑/↓: Change speed of both | ←/→: Switch direction of both",
+            Mode::Differential => "↑/↓: Change tilt speed | ←/→: Switch tilt direction | [/]: Skew M1/M2",
+            Mode::Split => "W/S, A/D: M1 speed, direction | ↑/↓, ←/→: M2 speed, direction | Space: Stop both",
+        }
+    }
+
+    pub fn next(self) -> Mode {
+        match self {
+            Mode::Single => Mode::Mirror,
+            Mode::Mirror => Mode::Differential,
+            Mode::Differential => Mode::Split,
+            Mode::Split => Mode::Single,
+        }
+    }
+
+    /// The drives to send for a manual drive of the selected actuator.
+    /// `trim` is a speed multiplier per actuator, indexed by `Actuator as usize`,
+    /// and `offset` is added to M1's speed and taken from M2's.
+    pub fn expand(self, drive: Drive, trim: [f64; 2], offset: i32) -> Vec<Drive> {
+        if matches!(self, Mode::Single | Mode::Split) {
+            return vec![drive];
+        }
+        let speed = drive.speed.unsigned_abs() as i32;
+        [Actuator::M1, Actuator::M2]
+            .into_iter()
+            .map(|actuator| {
+                let skewed = if actuator == Actuator::M1 { speed + offset } else { speed - offset };
+                // a stop stays a stop no matter the skew
+                let skewed = if speed == 0 { 0 } else { skewed };
+                let speed = (skewed as f64 * trim[actuator as usize]).clamp(0.0, u16::MAX as f64) as u32;
+                let direction = match self {
+                    Mode::Differential if actuator == Actuator::M2 => drive.direction().reversed(),
+                    _ => drive.direction(),
+                };
+                Drive::new(actuator, direction.signed(speed))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,18 @@
+//! `--monitor`: print telemetry and status as it arrives, without sending anything.
+
+use actuator_controller::link::Link;
+
+use crate::output::{Event, Output};
+
+/// Runs until Ctrl-C or the link goes away.
+pub async fn run(link: &mut Link, out: &Output) {
+    loop {
+        tokio::select! {
+            Some(report) = link.telemetry.recv() => out.emit(Event::Telemetry(&report)),
+            Some(status) = link.status.recv() => out.emit(Event::Status(&status)),
+            _ = tokio::signal::ctrl_c() => break,
+            else => break,
+        }
+    }
+    out.emit(Event::Done { failures: 0 });
+}
@@ -0,0 +1,48 @@
+//! The `[input] listen` PULL socket: commands from the network while the
+//! terminal UI runs, one per message in any form `--stdin` takes, as the
+//! bridge takes them (see `bridge`). With `[roles]` set, the first frame is
+//! the sender's key; nothing is sent back, so a command the key's role
+//! doesn't allow is just dropped.
+
+use std::net::SocketAddr;
+
+use actuator_controller::{
+    dsl,
+    input::{InputSource, Source},
+    roles::Roles,
+    update::Input,
+};
+use tokio::sync::mpsc;
+
+use crate::zmtp;
+
+pub struct Network {
+    commands: mpsc::Receiver<(SocketAddr, Vec<Vec<u8>>)>,
+    roles: Roles,
+    vocabulary: dsl::Vocabulary,
+}
+
+impl Network {
+    pub async fn bind(endpoint: &str, roles: Roles, vocabulary: dsl::Vocabulary) -> Result<Network, String> {
+        Ok(Network { commands: zmtp::bind_pull(endpoint).await?, roles, vocabulary })
+    }
+}
+
+impl InputSource for Network {
+    fn source(&self) -> Source {
+        Source::Network
+    }
+
+    fn next(&mut self) -> Option<Input> {
+        loop {
+            let (_, parts) = self.commands.try_recv().ok()?;
+            let Some((msg, rest)) = parts.split_last() else { continue };
+            let key = rest.last().map(|key| String::from_utf8_lossy(key).into_owned());
+            let line = String::from_utf8_lossy(msg);
+            let Ok(cmd) = self.vocabulary.parse(&line) else { continue };
+            if self.roles.check(key.as_deref(), &cmd).is_ok() {
+                return Some(Input::Run(line.trim().to_string()));
+            }
+        }
+    }
+}
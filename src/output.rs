@@ -0,0 +1,196 @@
+//! How the headless modes (`--stdin`, `--monitor`, `--excite`, `--replay`,
+//! the ZeroMQ bridge) report what happens.
+//!
+//! Text output is for people: results on stdout, errors on stderr. JSON
+//! output is one object per line on stdout, each with an `event` name and
+//! `t`, the seconds since startup:
+//!
+//! ```text
+//! {"event":"session","t":0.0,"version":"0.1.0","commit":"3f68cce","built":"2026-03-01T09:12:44.000Z","features":[],"config_path":"actuator.toml","config":{...}}
+//! {"event":"telemetry","t":0.52,"actuator":"m1","len":0.1234}
+//! {"event":"telemetry","t":0.53,"actuator":"m1","len":0.1235,"current":1.42}
+//! {"event":"status","t":0.61,"kind":"acked","message":"Set speed to 32768"}
+//! {"event":"drive","t":0.55,"actuator":"m1","speed":-26214}
+//! {"event":"queue","t":1.02,"depth":4,"coalesced":3,"dropped":0}
+//! {"event":"ack","t":2.70,"command":"m1 fwd 50% for 2s","message":"Driving M1 Forward at 32768"}
+//! {"event":"error","t":2.71,"command":"m3 fwd","message":"unknown command or actuator `m3`; ..."}
+//! {"event":"settle","t":4.31,"actuator":"m1","target":0.15,"time":1.84,"overshoot":0.0012,"error":-0.0008}
+//! {"event":"compare","t":12.4,"actuator":"m1","samples":240,"rms":0.0011,"max":0.0042,"at":3.1,"ok":true}
+//! {"event":"source","t":9.10,"client":"10.0.0.7:51234","run":120,"coalesced":31}
+//! {"event":"done","t":2.90,"failures":1}
+//! ```
+
+use std::time::Instant;
+
+use actuator_controller::{
+    commands::Actuator, compare::Deviation, json::Value, position::Settle, status::StatusEvent, telemetry::Report,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+impl Format {
+    pub const NAMES: &[&str] = &["text", "json"];
+
+    pub fn parse(name: &str) -> Option<Format> {
+        match name {
+            "text" => Some(Format::Text),
+            "json" => Some(Format::Json),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Event<'a> {
+    /// what the session is running with, see [`actuator_controller::session`]
+    Session(&'a Value),
+    /// with any fields the firmware advertised after the length
+    Telemetry(&'a Report),
+    Status(&'a StatusEvent),
+    /// a signed speed was sent, negative when backward
+    Drive { actuator: Actuator, speed: i32 },
+    /// frames still queued, and the running totals skipped or thrown away
+    Queue { depth: u64, coalesced: u64, dropped: u64 },
+    /// a move to a target settled: how long it took (s), how far it overshot
+    /// and how far off it ended up (m)
+    Settle(&'a Settle),
+    /// a command finished successfully
+    Ack { command: &'a str, message: &'a str },
+    Error { command: &'a str, message: &'a str },
+    Done { failures: u32 },
+    /// how many of a bridge client's commands have been run, and how many
+    /// were replaced by newer ones before their turn
+    #[cfg_attr(not(feature = "bridge"), allow(dead_code))]
+    Source { client: &'a str, run: u64, coalesced: u64 },
+    /// how far an actuator strayed from a recording, and whether that's within tolerance
+    Compare { actuator: Actuator, deviation: Deviation, ok: bool },
+}
+
+impl Event<'_> {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::Session(_) => "session",
+            Event::Telemetry(_) => "telemetry",
+            Event::Status(_) => "status",
+            Event::Drive { .. } => "drive",
+            Event::Queue { .. } => "queue",
+            Event::Settle(_) => "settle",
+            Event::Ack { .. } => "ack",
+            Event::Error { .. } => "error",
+            Event::Done { .. } => "done",
+            Event::Source { .. } => "source",
+            Event::Compare { .. } => "compare",
+        }
+    }
+}
+
+pub struct Output {
+    format: Format,
+    /// whether telemetry and status updates are worth printing at all
+    stream: bool,
+    start: Instant,
+}
+
+impl Output {
+    pub fn new(format: Format, stream: bool) -> Output {
+        Output {
+            format,
+            stream,
+            start: Instant::now(),
+        }
+    }
+
+    pub fn emit(&self, event: Event) {
+        if !self.stream && matches!(event, Event::Telemetry(_) | Event::Status(_) | Event::Drive { .. } | Event::Queue { .. }) {
+            return;
+        }
+        match self.format {
+            Format::Text => match event {
+                Event::Session(_) => {}
+                Event::Telemetry(report) => {
+                    let mut line = format!("{:?} len (m): {}", report.actuator, report.length());
+                    for (field, value) in report.extras() {
+                        line += &format!(" | {field}: {value}");
+                    }
+                    println!("{line}");
+                }
+                Event::Status(status) if status.is_error() => eprintln!("status: {status}"),
+                Event::Status(status) => println!("status: {status}"),
+                Event::Drive { actuator, speed } => println!("drive: {actuator:?} {speed}"),
+                Event::Queue { depth, coalesced, dropped } => {
+                    println!("queue: {depth} queued, {coalesced} coalesced, {dropped} dropped")
+                }
+                Event::Settle(settle) => println!("settle: {settle}"),
+                Event::Ack { command, message } => println!("ok: {command}: {message}"),
+                Event::Error { command, message } => eprintln!("error: {command}: {message}"),
+                Event::Done { .. } => {}
+                Event::Source { client, run, coalesced } => println!("client {client}: {run} run, {coalesced} coalesced"),
+                Event::Compare { actuator, deviation, ok } => println!(
+                    "{actuator:?}: {} reports, rms {:.4} m, max {:.4} m at {:.2} s: {}",
+                    deviation.samples,
+                    deviation.rms,
+                    deviation.max,
+                    deviation.at,
+                    if ok { "ok" } else { "REGRESSION" }
+                ),
+            },
+            Format::Json => println!("{}", self.to_json(&event)),
+        }
+    }
+
+    pub fn to_json(&self, event: &Event) -> Value {
+        let text = |s: &str| Value::String(s.to_string());
+        let mut fields = match *event {
+            Event::Session(Value::Object(snapshot)) => snapshot.iter().map(|(k, v)| (k.as_str(), v.clone())).collect(),
+            Event::Session(_) => Vec::new(),
+            Event::Telemetry(report) => {
+                let mut fields = vec![("actuator", text(&format!("{:?}", report.actuator).to_lowercase()))];
+                for (field, value) in report.schema.fields().iter().zip(&report.values) {
+                    fields.push((field.name.as_str(), Value::Number(*value)));
+                }
+                fields
+            }
+            Event::Status(status) => vec![("kind", text(status.kind())), ("message", text(&status.to_string()))],
+            Event::Drive { actuator, speed } => vec![
+                ("actuator", text(&format!("{actuator:?}").to_lowercase())),
+                ("speed", Value::Number(speed as f64)),
+            ],
+            Event::Queue { depth, coalesced, dropped } => vec![
+                ("depth", Value::Number(depth as f64)),
+                ("coalesced", Value::Number(coalesced as f64)),
+                ("dropped", Value::Number(dropped as f64)),
+            ],
+            Event::Settle(settle) => vec![
+                ("actuator", text(&format!("{:?}", settle.actuator).to_lowercase())),
+                ("target", Value::Number(settle.target)),
+                ("time", Value::Number(settle.time.as_secs_f64())),
+                ("overshoot", Value::Number(settle.overshoot)),
+                ("error", Value::Number(settle.error)),
+            ],
+            Event::Ack { command, message } | Event::Error { command, message } => {
+                vec![("command", text(command)), ("message", text(message))]
+            }
+            Event::Done { failures } => vec![("failures", Value::Number(failures as f64))],
+            Event::Source { client, run, coalesced } => vec![
+                ("client", text(client)),
+                ("run", Value::Number(run as f64)),
+                ("coalesced", Value::Number(coalesced as f64)),
+            ],
+            Event::Compare { actuator, deviation, ok } => vec![
+                ("actuator", text(&format!("{actuator:?}").to_lowercase())),
+                ("samples", Value::Number(deviation.samples as f64)),
+                ("rms", Value::Number(deviation.rms)),
+                ("max", Value::Number(deviation.max)),
+                ("at", Value::Number(deviation.at)),
+                ("ok", Value::Bool(ok)),
+            ],
+        };
+        fields.insert(0, ("t", Value::Number(self.start.elapsed().as_secs_f64())));
+        fields.insert(0, ("event", text(event.name())));
+        Value::Object(fields.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+    }
+}
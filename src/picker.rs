@@ -0,0 +1,121 @@
+//! The `f` popup: pick a trajectory to `follow` from the `[files]`
+//! directories instead of typing its path at the `:` prompt.
+//!
+//! The ones followed most recently this session come first, marked `*`.
+//! Typing narrows the list to the paths containing what's typed.
+
+use std::path::Path;
+
+use ratatui::{
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    text::{Line, Text},
+    widgets::{Block, Borders, Clear, Paragraph},
+    Frame,
+};
+
+use crossterm::event::KeyCode;
+
+pub struct Picker {
+    /// paths as they'd be typed after `follow`, and whether each was used recently
+    entries: Vec<(String, bool)>,
+    filter: String,
+    /// index into the filtered list
+    selected: usize,
+}
+
+/// What a key press in the picker came to.
+pub enum Picked {
+    Path(String),
+    Cancelled,
+}
+
+impl Picker {
+    /// Lists the CSV files directly inside each of `dirs`, after `recent`
+    /// (newest first). Recent paths are kept even if they're elsewhere.
+    pub fn open(dirs: &[String], recent: &[String]) -> Picker {
+        let mut entries: Vec<(String, bool)> = Vec::new();
+        for path in recent {
+            if !entries.iter().any(|(p, _)| p == path) {
+                entries.push((path.clone(), true));
+            }
+        }
+        let mut found = Vec::new();
+        for dir in dirs {
+            let Ok(listing) = std::fs::read_dir(dir) else { continue };
+            for entry in listing.flatten() {
+                let path = entry.path();
+                if path.is_file() && path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("csv")) {
+                    // `./dig.csv` reads better as `dig.csv`
+                    let path = path.strip_prefix(".").unwrap_or(&path);
+                    found.push(path.display().to_string());
+                }
+            }
+        }
+        found.sort();
+        for path in found {
+            if !entries.iter().any(|(p, _)| Path::new(p) == Path::new(&path)) {
+                entries.push((path, false));
+            }
+        }
+        Picker { entries, filter: String::new(), selected: 0 }
+    }
+
+    fn shown(&self) -> Vec<&(String, bool)> {
+        let filter = self.filter.to_lowercase();
+        self.entries.iter().filter(|(path, _)| path.to_lowercase().contains(&filter)).collect()
+    }
+
+    /// `None` while the picker stays open.
+    pub fn key(&mut self, code: KeyCode) -> Option<Picked> {
+        let count = self.shown().len();
+        match code {
+            KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down => self.selected = (self.selected + 1).min(count.saturating_sub(1)),
+            KeyCode::Char(c) => {
+                self.filter.push(c);
+                self.selected = 0;
+            }
+            KeyCode::Backspace => {
+                self.filter.pop();
+                self.selected = 0;
+            }
+            KeyCode::Enter => return self.shown().get(self.selected).map(|(path, _)| Picked::Path(path.clone())),
+            KeyCode::Esc => return Some(Picked::Cancelled),
+            _ => {}
+        }
+        None
+    }
+
+    pub fn render(&self, f: &mut Frame, area: Rect) {
+        let shown = self.shown();
+        let rows = area.height.saturating_sub(3) as usize;
+        // keep the selection in view
+        let first = self.selected.saturating_sub(rows.saturating_sub(1));
+        let mut lines: Vec<Line> = vec![Line::from(format!("Find: {}", self.filter))];
+        if shown.is_empty() {
+            lines.push(Line::styled("No trajectories here; see [files] dirs", Style::default().fg(Color::DarkGray)));
+        }
+        for (i, (path, recent)) in shown.iter().enumerate().skip(first).take(rows) {
+            let text = format!("{} {path}", if *recent { "*" } else { " " });
+            let style = if i == self.selected {
+                Style::default().fg(Color::Black).bg(Color::Cyan).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            lines.push(Line::styled(text, style));
+        }
+        let width = (lines.iter().map(Line::width).max().unwrap_or(0) as u16 + 4).clamp(40.min(area.width), area.width);
+        let height = (lines.len() as u16 + 2).min(area.height);
+        let popup = Rect {
+            x: area.x + (area.width - width) / 2,
+            y: area.y + (area.height - height) / 2,
+            width,
+            height,
+        };
+        let picker = Paragraph::new(Text::from(lines))
+            .block(Block::default().title("Follow (*: recent)").borders(Borders::ALL));
+        f.render_widget(Clear, popup);
+        f.render_widget(picker, popup);
+    }
+}
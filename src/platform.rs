@@ -0,0 +1,144 @@
+//! What this build can do on the machine it's running on, so that it says
+//! what's missing there instead of failing part way through. It builds for
+//! Linux, macOS and Windows, but only Linux has everything.
+//!
+//! Serial ports are listed the same way on all three. The daemon, shared
+//! memory, `--lock` and the FIFO input source need Unix, and on Windows
+//! `--advertise` can't share the mDNS port with another responder. Gamepads
+//! are found through the Linux joystick devices (`/dev/input/js*`) only with
+//! the `gamepad` feature, and GPIO chips (`/dev/gpiochip*`) only with the
+//! Linux-only `gpio` feature; elsewhere they're reported as unavailable,
+//! with why.
+
+use std::{fmt, path::PathBuf};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Capability {
+    Serial,
+    Gamepad,
+    Gpio,
+    Daemon,
+    SharedMemory,
+    PortLock,
+    Fifo,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 7] = [
+        Capability::Serial,
+        Capability::Gamepad,
+        Capability::Gpio,
+        Capability::Daemon,
+        Capability::SharedMemory,
+        Capability::PortLock,
+        Capability::Fifo,
+    ];
+
+    /// Whether it's there, with what was found or why not.
+    pub fn detect(self) -> Support {
+        match self {
+            Capability::Serial => match serial_ports() {
+                Ok(ports) if ports.is_empty() => Support::Available(String::from("no ports found")),
+                Ok(ports) => Support::Available(ports.join(", ")),
+                Err(e) => Support::Unavailable(e),
+            },
+            Capability::Gamepad => found(gamepads(), "no gamepads found"),
+            Capability::Gpio => found(gpio_chips(), "no GPIO chips found"),
+            Capability::Daemon | Capability::SharedMemory | Capability::PortLock | Capability::Fifo => match cfg!(unix) {
+                true => Support::Available(String::new()),
+                false => Support::Unavailable(format!("needs Unix, not {}", std::env::consts::OS)),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Capability::Serial => "serial",
+            Capability::Gamepad => "gamepad",
+            Capability::Gpio => "GPIO",
+            Capability::Daemon => "daemon",
+            Capability::SharedMemory => "shared memory",
+            Capability::PortLock => "port lock",
+            Capability::Fifo => "FIFO input",
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Support {
+    /// with what was found, if there's anything to say
+    Available(String),
+    /// with why not
+    Unavailable(String),
+}
+
+impl Support {
+    pub fn is_available(&self) -> bool {
+        matches!(self, Support::Available(_))
+    }
+}
+
+/// Every capability, one per line, e.g. `serial: /dev/ttyACM0` or
+/// `GPIO: unavailable (built without the gpio feature)`.
+pub fn report() -> String {
+    let mut out = format!("{} {}\n", std::env::consts::OS, std::env::consts::ARCH);
+    for capability in Capability::ALL {
+        out += &match capability.detect() {
+            Support::Available(found) if found.is_empty() => format!("{capability}: available\n"),
+            Support::Available(found) => format!("{capability}: {found}\n"),
+            Support::Unavailable(why) => format!("{capability}: unavailable ({why})\n"),
+        };
+    }
+    out
+}
+
+/// The serial ports the OS knows of, by the path or name to open them with.
+pub fn serial_ports() -> Result<Vec<String>, String> {
+    let ports = tokio_serial::available_ports().map_err(|e| format!("couldn't list serial ports: {e}"))?;
+    Ok(ports.into_iter().map(|port| port.port_name).collect())
+}
+
+/// The gamepads plugged in, by their joystick device.
+pub fn gamepads() -> Result<Vec<PathBuf>, String> {
+    if !cfg!(target_os = "linux") {
+        return Err(format!("gamepads are only found on Linux, not {}", std::env::consts::OS));
+    }
+    if !cfg!(feature = "gamepad") {
+        return Err(String::from("built without the gamepad feature"));
+    }
+    Ok(devices("/dev/input", "js"))
+}
+
+/// The GPIO chips there are, by their character device.
+pub fn gpio_chips() -> Result<Vec<PathBuf>, String> {
+    if !cfg!(target_os = "linux") {
+        return Err(format!("GPIO is only supported on Linux, not {}", std::env::consts::OS));
+    }
+    if !cfg!(feature = "gpio") {
+        return Err(String::from("built without the gpio feature"));
+    }
+    Ok(devices("/dev", "gpiochip"))
+}
+
+/// What's available, or else "unavailable" and why.
+fn found(devices: Result<Vec<PathBuf>, String>, none: &str) -> Support {
+    match devices {
+        Ok(devices) if devices.is_empty() => Support::Available(none.to_string()),
+        Ok(devices) => Support::Available(devices.iter().map(|d| d.display().to_string()).collect::<Vec<_>>().join(", ")),
+        Err(e) => Support::Unavailable(e),
+    }
+}
+
+/// The entries of `dir` whose names start with `prefix`, in order.
+fn devices(dir: &str, prefix: &str) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else { return Vec::new() };
+    let mut found: Vec<PathBuf> = entries
+        .flatten()
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with(prefix))
+        .map(|entry| entry.path())
+        .collect();
+    found.sort();
+    found
+}
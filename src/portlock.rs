@@ -0,0 +1,118 @@
+//! `--lock`: an advisory lock on the serial port, so a second instance on the
+//! same port says who has it instead of both writing frames into it at once.
+//!
+//! The lock is an `fcntl` write lock on a file named after the port in the
+//! temporary directory, which, unlike a `flock`, the kernel can name the
+//! holder of. The file also holds the holder's PID, for `cat`. The kernel
+//! lets go of the lock when its holder exits, however it ends, so a file left
+//! behind locks nothing.
+
+use std::{
+    fmt,
+    fs::{File, OpenOptions, Permissions},
+    io::{self, Write},
+    os::{fd::AsRawFd, unix::fs::{OpenOptionsExt, PermissionsExt}},
+    path::{Path, PathBuf},
+};
+
+/// Whoever holds the lock on a port.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Holder {
+    pub pid: i32,
+    /// the holder's command name, if it can be read
+    pub name: Option<String>,
+}
+
+impl fmt::Display for Holder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PID {}", self.pid)?;
+        if let Some(name) = &self.name {
+            write!(f, " ({name})")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum LockError {
+    Busy(Holder),
+    Io(PathBuf, io::Error),
+}
+
+impl fmt::Display for LockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LockError::Busy(holder) => write!(f, "port busy by {holder}"),
+            LockError::Io(path, e) => write!(f, "couldn't lock {}: {e}", path.display()),
+        }
+    }
+}
+
+/// Held for as long as the port is open.
+#[derive(Debug)]
+pub struct PortLock {
+    _file: File,
+}
+
+impl PortLock {
+    pub fn acquire(port_path: &str) -> Result<PortLock, LockError> {
+        let path = lock_path(port_path);
+        let io = |e| LockError::Io(path.clone(), e);
+        // not truncated until it's locked, which would lose the holder's PID
+        let mut file =
+            OpenOptions::new().read(true).write(true).create(true).truncate(false).mode(0o666).open(&path).map_err(io)?;
+        // past the umask, so whoever else runs on the port can lock it too;
+        // only the file's owner can, which is fine
+        let _ = file.set_permissions(Permissions::from_mode(0o666));
+        if let Err(e) = fcntl(&file, libc::F_SETLK, libc::F_WRLCK as libc::c_short) {
+            if !matches!(e.raw_os_error(), Some(libc::EAGAIN | libc::EACCES)) {
+                return Err(io(e));
+            }
+            // it may have been let go of in between
+            return match holder_of(&file).map_err(io)? {
+                Some(holder) => Err(LockError::Busy(holder)),
+                None => PortLock::acquire(port_path),
+            };
+        }
+        file.set_len(0).map_err(io)?;
+        writeln!(file, "{}", std::process::id()).map_err(io)?;
+        Ok(PortLock { _file: file })
+    }
+}
+
+/// Whoever holds the lock on the port at `port_path`, if anyone, for
+/// saying who when the port won't open.
+pub fn holder(port_path: &str) -> Option<Holder> {
+    let file = File::open(lock_path(port_path)).ok()?;
+    holder_of(&file).ok().flatten()
+}
+
+/// The lock file for the port at `port_path`, the same one for every path to it.
+pub fn lock_path(port_path: &str) -> PathBuf {
+    let port = Path::new(port_path).canonicalize().unwrap_or_else(|_| PathBuf::from(port_path));
+    let name = port.to_string_lossy().trim_start_matches('/').replace('/', "_");
+    std::env::temp_dir().join(format!("actuator_controller.{name}.lock"))
+}
+
+fn holder_of(file: &File) -> io::Result<Option<Holder>> {
+    let lock = fcntl(file, libc::F_GETLK, libc::F_WRLCK as libc::c_short)?;
+    if lock.l_type == libc::F_UNLCK as libc::c_short {
+        return Ok(None);
+    }
+    let name = std::fs::read_to_string(format!("/proc/{}/comm", lock.l_pid)).ok().map(|name| name.trim().to_string());
+    Ok(Some(Holder { pid: lock.l_pid, name }))
+}
+
+/// `cmd` with a lock of `kind` over the whole file, giving back what the
+/// kernel filled in.
+fn fcntl(file: &File, cmd: libc::c_int, kind: libc::c_short) -> io::Result<libc::flock> {
+    // SAFETY: every field of `flock` is an integer, for which zero is valid
+    let mut lock: libc::flock = unsafe { std::mem::zeroed() };
+    lock.l_type = kind;
+    lock.l_whence = libc::SEEK_SET as libc::c_short;
+    // SAFETY: `lock` outlives the call, which only reads and writes it
+    if unsafe { libc::fcntl(file.as_raw_fd(), cmd, &mut lock) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(lock)
+}
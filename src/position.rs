@@ -0,0 +1,260 @@
+//! Closed-loop length control on top of signed-speed drives.
+//!
+//! A plain proportional controller: the further an actuator is from its
+//! target the faster it's driven, down to `min_speed` so it doesn't stall
+//! short of the target, and it's stopped once inside `tolerance`. The move
+//! is done once it has stayed inside for `settle`, and how long that took
+//! from the start and how far it overshot are kept as a [`Settle`]. A
+//! target being tracked (a trajectory) isn't done once reached: the actuator
+//! waits there until it moves on.
+//!
+//! A worn lead screw has play, so where the rod ends up for a given reading
+//! depends on which way it was last driven. Lengths are taken to be as read
+//! while extending; with `backlash` set, a target approached while
+//! retracting is aimed that much shorter, so it's reached from either side
+//! alike. Which way an actuator was last driven is kept whoever drove it,
+//! see [`PositionController::driven`].
+
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    cadence,
+    commands::{Actuator, Direction, Drive},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Gains {
+    /// speed units per meter of error
+    pub gain: f64,
+    pub min_speed: u16,
+    pub max_speed: u16,
+    /// error (m) at which a target counts as reached
+    pub tolerance: f64,
+    /// how long a target has to stay reached for the move to be done
+    pub settle: Duration,
+    /// play (m) in each actuator's drive, taken up whenever it reverses
+    pub backlash: [f64; 2],
+    /// how many times a second the closed loop runs, see [`crate::cadence`]
+    pub rate: f64,
+}
+
+impl Default for Gains {
+    fn default() -> Self {
+        Gains {
+            gain: 400_000.0,
+            min_speed: 8000,
+            max_speed: 65535,
+            tolerance: 0.005,
+            settle: Duration::ZERO,
+            backlash: [0.0; 2],
+            rate: cadence::DEFAULT_RATE,
+        }
+    }
+}
+
+/// Commanded speeds only change in steps of this much, so a slowly shrinking
+/// error doesn't turn into a new frame every tick.
+const SPEED_STEP: u16 = 1000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Target {
+    len: f64,
+    max_speed: u16,
+    /// kept once reached, see [`PositionController::track`]
+    tracking: bool,
+    /// when the move started and from what length, from the first report after
+    from: Option<(Instant, f64)>,
+    /// furthest past the target (m) so far, the way it was approached
+    overshoot: f64,
+    /// since when it's been within tolerance, while it waits to settle
+    inside: Option<Instant>,
+}
+
+impl Target {
+    fn new(len: f64, max_speed: u16, tracking: bool) -> Target {
+        Target { len, max_speed, tracking, from: None, overshoot: 0.0, inside: None }
+    }
+}
+
+/// How a move to a target went, once it settled.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Settle {
+    pub actuator: Actuator,
+    /// m
+    pub target: f64,
+    /// from the start of the move until it settled
+    pub time: Duration,
+    /// furthest past the target (m) on the way, 0 if it never got there early
+    pub overshoot: f64,
+    /// how far off (m) it settled, positive being long
+    pub error: f64,
+}
+
+impl fmt::Display for Settle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} settled at {:.3} m in {:.2} s, overshoot {:.1} mm, off by {:+.1} mm",
+            self.actuator,
+            self.target,
+            self.time.as_secs_f64(),
+            self.overshoot * 1000.0,
+            self.error * 1000.0
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct PositionController {
+    gains: Gains,
+    targets: [Option<Target>; 2],
+    /// last speed asked of each actuator, so only changes go out
+    sent: [Option<i32>; 2],
+    /// which way each actuator was last driven, so which side its play is on
+    side: [Option<Direction>; 2],
+    /// moves that have settled since they were last taken
+    settled: Vec<Settle>,
+}
+
+impl PositionController {
+    pub fn new(gains: Gains) -> PositionController {
+        PositionController {
+            gains,
+            ..Default::default()
+        }
+    }
+
+    pub fn set_target(&mut self, actuator: Actuator, len: f64) {
+        self.set_target_capped(actuator, len, self.gains.max_speed);
+    }
+
+    /// Like `set_target`, but never drives faster than `max_speed` on the way.
+    pub fn set_target_capped(&mut self, actuator: Actuator, len: f64, max_speed: u16) {
+        self.targets[actuator as usize] = Some(Target::new(len, max_speed.min(self.gains.max_speed), false));
+    }
+
+    /// Moves the target along a trajectory. Unlike `set_target`, reaching it
+    /// doesn't end the move, so the target can go on moving from there.
+    pub fn track(&mut self, actuator: Actuator, len: f64) {
+        self.targets[actuator as usize] = Some(Target::new(len, self.gains.max_speed, true));
+    }
+
+    /// Turns tracked targets into ordinary ones, done once settled. How they
+    /// settle is timed from here.
+    pub fn settle(&mut self) {
+        for target in self.targets.iter_mut().flatten() {
+            *target = Target::new(target.len, target.max_speed, false);
+        }
+    }
+
+    /// The moves that have settled since this was last called, oldest first.
+    pub fn take_settled(&mut self) -> Vec<Settle> {
+        std::mem::take(&mut self.settled)
+    }
+
+    /// Where `actuator` is being moved to, if anywhere.
+    pub fn target(&self, actuator: Actuator) -> Option<f64> {
+        self.targets[actuator as usize].map(|target| target.len)
+    }
+
+    /// Notes that `actuator` was sent `speed`, by this or anything else.
+    pub fn driven(&mut self, actuator: Actuator, speed: i32) {
+        if speed != 0 {
+            self.side[actuator as usize] = Some(Direction::of(speed));
+        }
+    }
+
+    /// The reading that puts `actuator` at `len` when it's driven `direction`.
+    fn aim(&self, actuator: Actuator, len: f64, direction: Direction) -> f64 {
+        match direction {
+            Direction::Forward => len,
+            Direction::Backward => len - self.gains.backlash[actuator as usize],
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.targets.iter().any(Option::is_some)
+    }
+
+    /// Forgets all targets, returning the stops for any actuator that was
+    /// still being driven.
+    pub fn cancel(&mut self) -> Vec<Drive> {
+        let stops = [Actuator::M1, Actuator::M2]
+            .into_iter()
+            .filter(|&a| self.targets[a as usize].is_some())
+            .map(Drive::stop)
+            .collect();
+        self.targets = [None; 2];
+        self.sent = [None; 2];
+        stops
+    }
+
+    /// Commands needed to move toward the targets given the latest lengths.
+    pub fn update(&mut self, lengths: [Option<f64>; 2], now: Instant) -> Vec<Drive> {
+        let mut out = Vec::new();
+        for actuator in [Actuator::M1, Actuator::M2] {
+            let i = actuator as usize;
+            let (Some(mut target), Some(len)) = (self.targets[i], lengths[i]) else {
+                continue;
+            };
+            let (started, start_len) = *target.from.get_or_insert((now, len));
+            // carry on the way it was last driven unless that means going
+            // past the target, and only then take up the play the other way
+            let toward = |error: f64| if error > 0.0 { Direction::Forward } else { Direction::Backward };
+            let error = self.aim(actuator, target.len, self.side[i].unwrap_or(Direction::Forward)) - len;
+            let error = match self.side[i] {
+                _ if error.abs() <= self.gains.tolerance => error,
+                Some(side) if toward(error) == side => error,
+                _ => self.aim(actuator, target.len, toward(error)) - len,
+            };
+            let approach = if target.len >= start_len { 1.0 } else { -1.0 };
+            target.overshoot = target.overshoot.max(-error * approach);
+
+            if error.abs() <= self.gains.tolerance && target.tracking {
+                if self.sent[i] != Some(0) {
+                    out.push(Drive::stop(actuator));
+                }
+                self.sent[i] = Some(0);
+                self.targets[i] = Some(target);
+                continue;
+            }
+            if error.abs() <= self.gains.tolerance {
+                if self.sent[i] != Some(0) {
+                    out.push(Drive::stop(actuator));
+                }
+                let inside = *target.inside.get_or_insert(now);
+                if now.duration_since(inside) < self.gains.settle {
+                    self.sent[i] = Some(0);
+                    self.targets[i] = Some(target);
+                    continue;
+                }
+                self.targets[i] = None;
+                self.sent[i] = None;
+                self.settled.push(Settle {
+                    actuator,
+                    target: target.len,
+                    time: now.duration_since(started),
+                    overshoot: target.overshoot,
+                    error: -error,
+                });
+                continue;
+            }
+            // drifted out before it settled, so it starts over
+            target.inside = None;
+            self.targets[i] = Some(target);
+
+            let min_speed = self.gains.min_speed.min(target.max_speed);
+            let speed = (error.abs() * self.gains.gain).clamp(min_speed as f64, target.max_speed as f64) as u16;
+            let direction = if error > 0.0 { Direction::Forward } else { Direction::Backward };
+            let speed = direction.signed((speed / SPEED_STEP * SPEED_STEP).max(min_speed) as u32);
+            if self.sent[i] != Some(speed) {
+                out.push(Drive::new(actuator, speed));
+            }
+            self.sent[i] = Some(speed);
+        }
+        out
+    }
+}
@@ -0,0 +1,78 @@
+//! The `k` popup: the pre-flight checklist, ticked off before arming for
+//! full speed (see `actuator_controller::checklist`).
+
+use actuator_controller::{checklist::Checklist, update::Input};
+use ratatui::{
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    text::{Line, Text},
+    widgets::{Block, Borders, Clear, Paragraph},
+    Frame,
+};
+
+use crossterm::event::KeyCode;
+
+pub struct Preflight {
+    selected: usize,
+}
+
+/// What a key press in the popup came to.
+pub enum Pressed {
+    Input(Input),
+    Closed,
+}
+
+impl Preflight {
+    pub fn open() -> Preflight {
+        Preflight { selected: 0 }
+    }
+
+    /// `None` while the popup stays open with nothing to do.
+    pub fn key(&mut self, code: KeyCode, checklist: &Checklist) -> Option<Pressed> {
+        match code {
+            KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down => self.selected = (self.selected + 1).min(checklist.items.len().saturating_sub(1)),
+            KeyCode::Char(' ') => return Some(Pressed::Input(Input::CheckOff(self.selected))),
+            KeyCode::Enter => return Some(Pressed::Input(Input::Arm)),
+            KeyCode::Esc | KeyCode::Char('k') => return Some(Pressed::Closed),
+            _ => {}
+        }
+        None
+    }
+
+    pub fn render(&self, f: &mut Frame, area: Rect, checklist: &Checklist) {
+        let mut lines: Vec<Line> = Vec::new();
+        for (i, item) in checklist.items.iter().enumerate() {
+            let mark = if checklist.is_done(i) { "[x]" } else { "[ ]" };
+            let text = match (&item.check, checklist.reading(i)) {
+                (Some(_), Some(value)) => format!("{mark} {} (now {value:.2})", item.text),
+                (Some(check), None) => format!("{mark} {} (no `{}` yet)", item.text, check.field),
+                (None, _) => format!("{mark} {}", item.text),
+            };
+            let style = if i == self.selected {
+                Style::default().fg(Color::Black).bg(Color::Cyan).add_modifier(Modifier::BOLD)
+            } else if checklist.is_done(i) {
+                Style::default().fg(Color::Green)
+            } else {
+                Style::default()
+            };
+            lines.push(Line::styled(text, style));
+        }
+        let title = if checklist.is_armed() {
+            String::from("Checklist: armed")
+        } else {
+            format!("Checklist: {}/{} done", checklist.done(), checklist.items.len())
+        };
+        let width = (lines.iter().map(Line::width).max().unwrap_or(0) as u16 + 4).clamp(40.min(area.width), area.width);
+        let height = (lines.len() as u16 + 2).min(area.height);
+        let popup = Rect {
+            x: area.x + (area.width - width) / 2,
+            y: area.y + (area.height - height) / 2,
+            width,
+            height,
+        };
+        let list = Paragraph::new(Text::from(lines)).block(Block::default().title(title).borders(Borders::ALL));
+        f.render_widget(Clear, popup);
+        f.render_widget(list, popup);
+    }
+}
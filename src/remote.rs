@@ -0,0 +1,262 @@
+//! `remote <push endpoint> [<pub endpoint>] [--key <key>]`: drive from
+//! another machine through the ZeroMQ bridge, so a second operator can take
+//! over.
+//!
+//! Keys are turned into the same commands `--stdin` takes and pushed to the
+//! bridge's PULL socket, one per change. The bridge's PUB socket, if given,
+//! keeps a single status line up to date with the lengths and the answer to
+//! the last command. The key, if given, goes in a frame before each command
+//! for a bridge with `[roles]` set. Everything runs on the bridge's side,
+//! interlock and arming included; this end only remembers the speed and heading it asked
+//! for.
+
+use std::{
+    io::{self, Write},
+    time::Duration,
+};
+
+use actuator_controller::{
+    commands::{Actuator, Direction},
+    dsl::Command,
+    json,
+    keymap::{Key, Keymap},
+};
+use crossterm::{
+    cursor::MoveToColumn,
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    execute,
+    terminal::{self, Clear, ClearType},
+};
+use tokio::sync::mpsc;
+
+use crate::zmtp::{self, Pusher};
+
+struct Remote {
+    keys: Keymap,
+    actuator: Actuator,
+    /// what each actuator was last asked for, negative when backward
+    speeds: [i32; 2],
+    heading: [Direction; 2],
+    lengths: [Option<f64>; 2],
+    /// the bridge's answer to the last command, or what went wrong
+    last: String,
+    /// text typed after `:`
+    prompt: Option<String>,
+    /// what's on the status line, so it's only redrawn when it changes
+    drawn: String,
+}
+
+/// Runs until `q`, which stops both actuators on the way out. Returns the
+/// process exit code.
+pub async fn run(push: &str, sub: Option<&str>, key: Option<&str>, keys: Keymap) -> i32 {
+    let mut pusher = match Pusher::connect(push).await {
+        Ok(pusher) => pusher,
+        Err(e) => {
+            eprintln!("{e}");
+            return 2;
+        }
+    };
+    let mut updates = match sub.map(zmtp::connect_sub) {
+        Some(connecting) => match connecting.await {
+            Ok(updates) => Some(updates),
+            Err(e) => {
+                eprintln!("{e}");
+                return 2;
+            }
+        },
+        None => None,
+    };
+    let mut remote = Remote {
+        keys,
+        actuator: Actuator::M1,
+        speeds: [0; 2],
+        heading: [Direction::Forward; 2],
+        lengths: [None; 2],
+        last: format!("Connected to {push}"),
+        prompt: None,
+        drawn: String::new(),
+    };
+
+    if let Err(e) = terminal::enable_raw_mode() {
+        eprintln!("{e}");
+        return 2;
+    }
+    let mut send = async |line: &str| match key {
+        Some(key) => pusher.send(&[key.as_bytes(), line.as_bytes()]).await,
+        None => pusher.send(&[line.as_bytes()]).await,
+    };
+    let code = loop {
+        if let Some(updates) = &mut updates {
+            remote.take(updates);
+        }
+        remote.draw();
+        let key = match event::poll(Duration::from_millis(100)) {
+            Ok(true) => match event::read() {
+                Ok(Event::Key(key)) if key.kind != KeyEventKind::Release => key,
+                Ok(_) => continue,
+                Err(e) => break Err(e.to_string()),
+            },
+            Ok(false) => continue,
+            Err(e) => break Err(e.to_string()),
+        };
+        match remote.key(key) {
+            Some(Ok(line)) => {
+                if let Err(e) = send(&line).await {
+                    break Err(format!("lost {push}: {e}"));
+                }
+                remote.last = format!("sent: {line}");
+            }
+            Some(Err(())) => break Ok(()),
+            None => {}
+        }
+    };
+    // whoever's at the robot shouldn't be left with something still moving
+    let _ = send(&Command::Stop(None).to_string()).await;
+    let _ = terminal::disable_raw_mode();
+    println!();
+    match code {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{e}");
+            1
+        }
+    }
+}
+
+impl Remote {
+    /// Picks up what the bridge published since last time.
+    fn take(&mut self, updates: &mut mpsc::Receiver<(String, String)>) {
+        while let Ok((topic, body)) = updates.try_recv() {
+            let Ok(event) = json::parse(&body) else { continue };
+            let text = |key: &str| event.get(key).and_then(json::Value::as_str).unwrap_or_default().to_string();
+            match topic.as_str() {
+                "telemetry" => {
+                    let i = match event.get("actuator").and_then(json::Value::as_str) {
+                        Some("m1") => 0,
+                        Some("m2") => 1,
+                        _ => continue,
+                    };
+                    self.lengths[i] = event.get("len").and_then(json::Value::as_f64);
+                }
+                "ack" => self.last = format!("ok: {}: {}", text("command"), text("message")),
+                "error" => self.last = format!("error: {}: {}", text("command"), text("message")),
+                "status" if text("kind") != "acked" => self.last = format!("status: {}", text("message")),
+                _ => {}
+            }
+        }
+    }
+
+    fn draw(&mut self) {
+        let line = match &self.prompt {
+            Some(prompt) => format!(":{prompt}"),
+            None => {
+                let len = |len: Option<f64>| len.map_or(String::from("-"), |len| format!("{len:.4}"));
+                format!(
+                    "{:?} {:+} | M1 {} m, M2 {} m | {} | q: Quit",
+                    self.actuator,
+                    self.speeds[self.actuator as usize],
+                    len(self.lengths[0]),
+                    len(self.lengths[1]),
+                    self.last
+                )
+            }
+        };
+        let width = match terminal::size() {
+            Ok((width, _)) if width > 0 => width as usize,
+            _ => 80,
+        };
+        let line: String = line.chars().take(width - 1).collect();
+        if line == self.drawn {
+            return;
+        }
+        let mut stdout = io::stdout();
+        let _ = execute!(stdout, MoveToColumn(0), Clear(ClearType::CurrentLine));
+        let _ = write!(stdout, "{line}");
+        let _ = stdout.flush();
+        self.drawn = line;
+    }
+
+    /// The command line a key asks for, `Err` to quit.
+    fn key(&mut self, key: KeyEvent) -> Option<Result<String, ()>> {
+        if let Some(prompt) = &mut self.prompt {
+            match key.code {
+                KeyCode::Char(c) => prompt.push(c),
+                KeyCode::Backspace => {
+                    prompt.pop();
+                }
+                KeyCode::Esc => self.prompt = None,
+                KeyCode::Enter => return self.prompt.take().filter(|line| !line.trim().is_empty()).map(Ok),
+                _ => {}
+            }
+            return None;
+        }
+        let bound = match key.code {
+            KeyCode::F(n) => Some(Key::F(n)),
+            KeyCode::Char(c) => Some(Key::Char(c)),
+            _ => None,
+        };
+        if let Some(actuator) = bound.and_then(|k| self.keys.selects(k)) {
+            if actuator == self.actuator {
+                return None;
+            }
+            // as at the terminal UI, switching stops the one left behind
+            let stop = self.drive(self.actuator, 0);
+            self.actuator = actuator;
+            return Some(Ok(stop));
+        }
+        let steps = self.keys.steps(self.actuator);
+        let step = if key.modifiers.contains(KeyModifiers::SHIFT) { steps.fine } else { steps.normal } as i32;
+        let i = self.actuator as usize;
+        let line = match key.code {
+            KeyCode::Char('q') => return Some(Err(())),
+            KeyCode::Char(':') => {
+                self.prompt = Some(String::new());
+                return None;
+            }
+            KeyCode::Up => self.change_speed(step),
+            KeyCode::Down => self.change_speed(-step),
+            KeyCode::Char('+') => self.change_speed(steps.coarse as i32),
+            KeyCode::Char('-') => self.change_speed(-(steps.coarse as i32)),
+            KeyCode::Left | KeyCode::Right => {
+                let direction = if key.code == KeyCode::Left { Direction::Backward } else { Direction::Forward };
+                self.heading[i] = direction;
+                if self.speeds[i] == 0 {
+                    return None;
+                }
+                self.drive(self.actuator, direction.signed(self.speeds[i].unsigned_abs()))
+            }
+            KeyCode::Char('s') => self.drive(self.actuator, 0),
+            KeyCode::Char('h') => {
+                self.speeds[i] = 0;
+                Command::Hold(self.actuator).to_string()
+            }
+            KeyCode::Char(' ') | KeyCode::Esc => {
+                self.speeds = [0; 2];
+                Command::Stop(None).to_string()
+            }
+            _ => return None,
+        };
+        Some(Ok(line))
+    }
+
+    fn change_speed(&mut self, delta: i32) -> String {
+        let i = self.actuator as usize;
+        let speed = (self.speeds[i].unsigned_abs() as i32 + delta).clamp(0, u16::MAX as i32);
+        self.drive(self.actuator, self.heading[i].signed(speed as u32))
+    }
+
+    /// Remembers `speed` and returns the command for it.
+    fn drive(&mut self, actuator: Actuator, speed: i32) -> String {
+        self.speeds[actuator as usize] = speed;
+        let cmd = match speed {
+            0 => Command::Stop(Some(actuator)),
+            speed => Command::Drive {
+                actuator,
+                direction: Direction::of(speed),
+                speed: speed.unsigned_abs() as u16,
+                duration: None,
+            },
+        };
+        cmd.to_string()
+    }
+}
@@ -0,0 +1,117 @@
+//! `--replay`: send the speeds of a recorded run again, on the same clock,
+//! and compare the lengths that come back with the recording's (see
+//! [`actuator_controller::compare`]).
+//!
+//! With `--output json` the replay prints the same `drive` and `telemetry`
+//! events as `--excite`, so its log can be the next run's recording.
+
+use std::time::Instant;
+
+use actuator_controller::{
+    app::App,
+    commands::{Actuator, Drive},
+    compare::{Recording, Tolerance},
+    link::Link,
+    update::Msg,
+};
+use tokio::time::{interval, MissedTickBehavior};
+
+use crate::{
+    excite::SAMPLE,
+    output::{Event, Output},
+};
+
+/// Replays `recording` until it's over, or until Ctrl-C, then stops both
+/// actuators and reports how far each strayed. Returns the process exit
+/// code: 1 if either is out of `tolerance`.
+pub async fn run(link: &mut Link, mut app: App, recording: &Recording, tolerance: Tolerance, out: &Output) -> i32 {
+    if let Some(channel) = &mut link.arming {
+        app.sync_arming(channel);
+    }
+    if app.arming.required() {
+        let message = "two-person arming is on, and a replay can't wait for a second operator";
+        out.emit(Event::Error { command: "replay", message });
+        out.emit(Event::Done { failures: 1 });
+        return 1;
+    }
+    let duration = recording.duration();
+    app.audit.record(&format!("replay for {duration:?}"));
+
+    let drives = recording.drives();
+    let mut next = 0;
+    let mut replayed: [Vec<(f64, f64)>; 2] = Default::default();
+    let start = Instant::now();
+    let mut ticks = interval(SAMPLE);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let mut failures = 0;
+    let mut interrupted = false;
+    loop {
+        tokio::select! {
+            _ = ticks.tick() => {}
+            Some(report) = link.telemetry.recv() => {
+                out.emit(Event::Telemetry(&report));
+                replayed[report.actuator as usize].push((start.elapsed().as_secs_f64(), report.length()));
+                app.update(Msg::Telemetry(report));
+                continue;
+            }
+            Some(status) = link.status.recv() => {
+                if status.is_error() {
+                    failures += 1;
+                }
+                out.emit(Event::Status(&status));
+                app.update(Msg::Status(status));
+                continue;
+            }
+            _ = tokio::signal::ctrl_c() => {
+                interrupted = true;
+                break;
+            }
+            else => break,
+        }
+        let elapsed = start.elapsed();
+        if elapsed >= duration {
+            break;
+        }
+        while let Some(&(at, actuator, speed)) = drives.get(next)
+            && at <= elapsed
+        {
+            next += 1;
+            out.emit(Event::Drive { actuator, speed });
+            app.pending.push(Drive::new(actuator, speed));
+        }
+        app.flush(&link.commands).await;
+    }
+
+    for actuator in [Actuator::M1, Actuator::M2] {
+        out.emit(Event::Drive { actuator, speed: 0 });
+        app.pending.push(Drive::stop(actuator));
+    }
+    app.flush(&link.commands).await;
+    app.audit.record("replay done");
+    if interrupted {
+        out.emit(Event::Error { command: "replay", message: "interrupted, so not compared" });
+        out.emit(Event::Done { failures: failures + 1 });
+        return 1;
+    }
+
+    for (actuator, deviation) in [Actuator::M1, Actuator::M2].into_iter().zip(recording.compare(&replayed)) {
+        match deviation {
+            Some(deviation) => {
+                let ok = deviation.within(tolerance);
+                if !ok {
+                    failures += 1;
+                }
+                out.emit(Event::Compare { actuator, deviation, ok });
+            }
+            // only an actuator the recording has reports for can regress
+            None if recording.reported(actuator) => {
+                failures += 1;
+                let message = format!("{actuator:?} has no reports to compare between the recording and the replay");
+                out.emit(Event::Error { command: "replay", message: &message });
+            }
+            None => {}
+        }
+    }
+    out.emit(Event::Done { failures });
+    if failures > 0 { 1 } else { 0 }
+}
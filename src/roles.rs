@@ -0,0 +1,79 @@
+//! Who may do what over the ZeroMQ bridge, for when more than one laptop can
+//! reach the robot.
+//!
+//! Each client sends a key with every command (see `bridge`), and the key
+//! decides its role. With no keys configured the bridge takes commands from
+//! anyone, as it always has.
+
+use std::fmt;
+
+use crate::dsl::Command;
+
+/// In order of what they may do; each may do everything the ones before it
+/// can.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    /// watches, drops markers, and may always stop
+    Viewer,
+    /// drives
+    Operator,
+    /// also starts autonomous moves, see [`Command::is_autonomous`]
+    Admin,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Role::Viewer => "viewer",
+            Role::Operator => "operator",
+            Role::Admin => "admin",
+        })
+    }
+}
+
+impl Role {
+    /// The least a client must be to run `cmd`. Anyone may stop, whatever
+    /// their role.
+    pub fn needed(cmd: &Command) -> Role {
+        match cmd {
+            Command::Mark(_) => Role::Viewer,
+            cmd if cmd.is_stop() => Role::Viewer,
+            cmd if cmd.is_autonomous() => Role::Admin,
+            _ => Role::Operator,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Roles {
+    /// every known key with its role; empty lets anyone do anything
+    pub keys: Vec<(String, Role)>,
+}
+
+impl Roles {
+    pub fn enabled(&self) -> bool {
+        !self.keys.is_empty()
+    }
+
+    pub fn of(&self, key: &str) -> Option<Role> {
+        self.keys.iter().find(|(k, _)| k == key).map(|&(_, role)| role)
+    }
+
+    /// Whether the client with `key` may run `cmd`, and if not, why.
+    pub fn check(&self, key: Option<&str>, cmd: &Command) -> Result<(), String> {
+        if !self.enabled() {
+            return Ok(());
+        }
+        let Some(key) = key else {
+            return Err(String::from("no key; send it as a frame before the command"));
+        };
+        let Some(role) = self.of(key) else {
+            return Err(String::from("unknown key"));
+        };
+        let needed = Role::needed(cmd);
+        if role < needed {
+            return Err(format!("{needed}s only; this key is for {role}s"));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,416 @@
+//! Every check the serial writer makes before a frame reaches the firmware,
+//! whoever queued it, as one set of policies that can each be switched off.
+//!
+//! [`Safety`] keeps what the policies need to know (what each actuator was
+//! last sent, when, and what it was asked for) and decides what's written
+//! in place of each frame, what has to go ahead of it, and what has to
+//! follow on its own. The writer only does the writing.
+//!
+//! | Policy | What it does |
+//! | --- | --- |
+//! | `interlock` | stops an actuator and waits out a dwell before it reverses, see [`crate::interlock`] |
+//! | `limits` | caps each actuator's speed each way, see [`crate::limits`] |
+//! | `constraints` | stops moves that break a rule tying the lengths together, see [`crate::constraints`] |
+//! | `throttle` | holds speeds back to a rate per actuator, see [`crate::throttle`] |
+//! | `softstart` | starts an actuator slowly from rest, see [`crate::softstart`] |
+//! | `power` | scales speeds to a total current, see [`crate::budget`] |
+//! | `watchdog` | reopens a hung link and stops both actuators, see [`crate::link::Options`] |
+//!
+//! A profile is a named set of policies that are on. The built-in
+//! [`DEFAULT_PROFILE`] has them all; others, e.g. one for the bench with the
+//! power budget off, are defined in the config.
+
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    budget::PowerBudget,
+    commands::{Actuator, ActuatorCommand},
+    constraints::Constraints,
+    interlock::{self, Interlock},
+    limits::SpeedLimits,
+    softstart::SoftStart,
+    status::StatusEvent,
+    throttle::Throttle,
+};
+
+/// The profile with every policy on, used unless the config picks another.
+pub const DEFAULT_PROFILE: &str = "full";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Policy {
+    Interlock,
+    Limits,
+    Constraints,
+    Throttle,
+    SoftStart,
+    Power,
+    Watchdog,
+}
+
+impl Policy {
+    pub const ALL: [Policy; 7] = [
+        Policy::Interlock,
+        Policy::Limits,
+        Policy::Constraints,
+        Policy::Throttle,
+        Policy::SoftStart,
+        Policy::Power,
+        Policy::Watchdog,
+    ];
+
+    /// The name it goes by in the config, which is its section's.
+    pub fn name(self) -> &'static str {
+        match self {
+            Policy::Interlock => "interlock",
+            Policy::Limits => "limits",
+            Policy::Constraints => "constraints",
+            Policy::Throttle => "throttle",
+            Policy::SoftStart => "softstart",
+            Policy::Power => "power",
+            Policy::Watchdog => "watchdog",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Policy> {
+        Policy::ALL.into_iter().find(|policy| policy.name() == name)
+    }
+}
+
+impl fmt::Display for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Which policies are on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Policies(u8);
+
+impl Default for Policies {
+    fn default() -> Policies {
+        Policies::ALL
+    }
+}
+
+impl Policies {
+    pub const ALL: Policies = Policies((1 << Policy::ALL.len()) - 1);
+    pub const NONE: Policies = Policies(0);
+
+    pub fn contains(self, policy: Policy) -> bool {
+        self.0 & (1 << policy as u8) != 0
+    }
+
+    pub fn with(self, policy: Policy) -> Policies {
+        Policies(self.0 | (1 << policy as u8))
+    }
+
+    pub fn without(self, policy: Policy) -> Policies {
+        Policies(self.0 & !(1 << policy as u8))
+    }
+
+    /// The policies that aren't on.
+    pub fn off(self) -> Vec<Policy> {
+        Policy::ALL.into_iter().filter(|&policy| !self.contains(policy)).collect()
+    }
+
+    /// A comma-separated list of policy names, or `all` or `none`.
+    pub fn parse(list: &str) -> Result<Policies, String> {
+        match list.trim() {
+            "all" => return Ok(Policies::ALL),
+            "none" => return Ok(Policies::NONE),
+            _ => {}
+        }
+        let mut policies = Policies::NONE;
+        for name in list.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            let Some(policy) = Policy::parse(name) else {
+                let names: Vec<&str> = Policy::ALL.iter().map(|policy| policy.name()).collect();
+                return Err(format!("unknown policy `{name}`; expected all, none or some of {}", names.join(", ")));
+            };
+            policies = policies.with(policy);
+        }
+        Ok(policies)
+    }
+}
+
+impl fmt::Display for Policies {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Policies::ALL => f.write_str("all"),
+            Policies::NONE => f.write_str("none"),
+            policies => {
+                let on: Vec<&str> = Policy::ALL.into_iter().filter(|&p| policies.contains(p)).map(Policy::name).collect();
+                f.write_str(&on.join(", "))
+            }
+        }
+    }
+}
+
+/// What each policy is set to, and which are on.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    pub policies: Policies,
+    /// how long an actuator stays stopped before it may reverse
+    pub dwell: Duration,
+    /// fastest each actuator may run each way
+    pub limits: SpeedLimits,
+    /// rules tying the lengths together
+    pub constraints: Constraints,
+    /// most speeds a second for each actuator
+    pub actuator_rate: Option<f64>,
+    /// start each actuator slowly from rest
+    pub soft_start: Option<SoftStart>,
+    /// most current (A) the actuators may draw together
+    pub power_budget: Option<f64>,
+    /// reopen the port and stop both actuators if frames can't be written
+    /// for this long while one is moving; `None` just reports the errors
+    pub watchdog: Option<Duration>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            policies: Policies::ALL,
+            dwell: interlock::DEFAULT_DWELL,
+            limits: SpeedLimits::default(),
+            constraints: Constraints::default(),
+            actuator_rate: None,
+            soft_start: None,
+            power_budget: None,
+            watchdog: Some(crate::link::DEFAULT_WATCHDOG),
+        }
+    }
+}
+
+impl Settings {
+    /// The constraints, if that policy is on.
+    pub fn constraints(&self) -> Constraints {
+        match self.policies.contains(Policy::Constraints) {
+            true => self.constraints.clone(),
+            false => Constraints::default(),
+        }
+    }
+
+    /// The watchdog's timeout, if that policy is on.
+    pub fn watchdog(&self) -> Option<Duration> {
+        self.watchdog.filter(|_| self.policies.contains(Policy::Watchdog))
+    }
+
+    fn soft_start(&self) -> Option<SoftStart> {
+        self.soft_start.filter(|_| self.policies.contains(Policy::SoftStart))
+    }
+}
+
+/// A frame as it's to be written.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Checked {
+    pub cmd: ActuatorCommand,
+    /// why a move went out as a stop, if a rule turned it into one
+    pub blocked: Option<StatusEvent>,
+    /// the speed it was asked for, before a soft start or the budget cut it
+    asked: Option<u16>,
+    /// whether it's the soft start of `asked`
+    soft: bool,
+}
+
+/// The policies' state, for one run of the writer.
+#[derive(Clone, Debug)]
+pub struct Safety {
+    settings: Settings,
+    interlock: Interlock,
+    throttles: [Throttle; 2],
+    budget: Option<PowerBudget>,
+    /// when each actuator started softly is due its full speed, and what that is
+    raise_at: [Option<(Instant, u16)>; 2],
+    /// the speed each actuator was last asked for, before the budget scaled it
+    asked: [u16; 2],
+}
+
+impl Safety {
+    pub fn new(settings: Settings) -> Safety {
+        let on = |policy| settings.policies.contains(policy);
+        let rate = settings.actuator_rate.filter(|_| on(Policy::Throttle));
+        Safety {
+            interlock: Interlock::new(settings.dwell),
+            throttles: [Throttle::new(rate); 2],
+            budget: settings.power_budget.filter(|_| on(Policy::Power)).map(PowerBudget::new),
+            raise_at: [None; 2],
+            asked: [0; 2],
+            settings,
+        }
+    }
+
+    fn on(&self, policy: Policy) -> bool {
+        self.settings.policies.contains(policy)
+    }
+
+    /// Forgets what the actuators were sent, as after stopping both when
+    /// there's no telling what they were doing.
+    pub fn restart(&mut self) {
+        self.interlock = Interlock::new(self.settings.dwell);
+        self.raise_at = [None; 2];
+    }
+
+    pub fn is_moving(&self, actuator: Actuator) -> bool {
+        self.interlock.is_moving(actuator)
+    }
+
+    /// The watchdog's timeout, if that policy is on.
+    pub fn watchdog(&self) -> Option<Duration> {
+        self.settings.watchdog()
+    }
+
+    /// Whether new lengths can call for a stop.
+    pub fn watches_lengths(&self) -> bool {
+        self.on(Policy::Constraints) && !self.settings.constraints.is_empty()
+    }
+
+    /// Whether new currents can call for new speeds.
+    pub fn watches_currents(&self) -> bool {
+        self.budget.is_some()
+    }
+
+    /// When `cmd` may go, if the throttle holds it back past `now`.
+    pub fn wait_until(&self, cmd: ActuatorCommand, now: Instant) -> Option<Instant> {
+        match cmd {
+            ActuatorCommand::SetSpeed(speed, actuator) if speed != 0 => self.throttles[actuator as usize].wait_until(now),
+            _ => None,
+        }
+    }
+
+    /// What to write for `cmd` given the last reported `lengths`.
+    pub fn check(&self, cmd: ActuatorCommand, lengths: [Option<f64>; 2]) -> Checked {
+        let ActuatorCommand::SetSpeed(speed, actuator) = cmd else {
+            return Checked { cmd, blocked: None, asked: None, soft: false };
+        };
+        let direction = self.interlock.direction(actuator);
+        let mut speed = match self.on(Policy::Limits) {
+            true => speed.min(self.settings.limits.cap(actuator, direction)),
+            false => speed,
+        };
+        let mut blocked = None;
+        if speed != 0
+            && self.on(Policy::Constraints)
+            && let Some(rule) = self.settings.constraints.blocking(actuator, direction, lengths)
+        {
+            blocked = Some(StatusEvent::ConstraintStop(actuator, rule.to_string()));
+            speed = 0;
+        }
+        let asked = speed;
+        let soft = self.settings.soft_start().and_then(|soft| soft.split(ActuatorCommand::SetSpeed(speed, actuator), self.is_moving(actuator)));
+        let mut cmd = soft.map_or(ActuatorCommand::SetSpeed(speed, actuator), |(start, _)| start);
+        if let Some(budget) = &self.budget {
+            cmd = budget.apply(cmd);
+        }
+        Checked { cmd, blocked, asked: Some(asked), soft: soft.is_some() }
+    }
+
+    /// The actuator to stop ahead of `cmd`, if it's a reversal of one that's
+    /// still moving.
+    pub fn stop_first(&self, cmd: ActuatorCommand) -> Option<Actuator> {
+        self.interlock.stop_first(cmd).filter(|_| self.on(Policy::Interlock))
+    }
+
+    /// When `cmd` may be written, if it has to wait out the dwell.
+    pub fn hold_until(&self, cmd: ActuatorCommand) -> Option<Instant> {
+        self.interlock.hold_until(cmd).filter(|_| self.on(Policy::Interlock))
+    }
+
+    /// How long `cmd` may keep failing to write before the link counts as
+    /// hung, from `now`, if the watchdog is on and anything's moving.
+    pub fn deadline(&self, cmd: ActuatorCommand, now: Instant) -> Option<Instant> {
+        let moving = self.interlock.moving() || matches!(cmd, ActuatorCommand::SetSpeed(speed, _) if speed != 0);
+        self.watchdog().filter(|_| moving).map(|watchdog| now + watchdog)
+    }
+
+    /// Records a frame `check` let through, written at `now`.
+    pub fn sent(&mut self, checked: &Checked, now: Instant) {
+        let cmd = checked.cmd;
+        self.written(cmd, now);
+        let actuator = cmd.actuator();
+        let i = actuator as usize;
+        if !matches!(cmd, ActuatorCommand::SetDirection(..)) {
+            // whatever comes next replaces the speed a soft start was working up to
+            self.raise_at[i] = None;
+            self.asked[i] = checked.asked.unwrap_or(0);
+        }
+        if let (true, Some(asked), Some(soft)) = (checked.soft, checked.asked, self.settings.soft_start()) {
+            self.raise_at[i] = Some((now + soft.time, asked));
+        }
+        if matches!(cmd, ActuatorCommand::SetSpeed(speed, _) if speed != 0) {
+            self.throttles[i].sent(now);
+        }
+    }
+
+    /// Records any other frame written at `now`, e.g. a stop the writer made
+    /// of its own accord.
+    pub fn written(&mut self, cmd: ActuatorCommand, now: Instant) {
+        self.interlock.written(cmd, now);
+    }
+
+    /// The rules moving actuators are breaking, given the latest `lengths`,
+    /// so they're to be stopped.
+    pub fn enforce(&self, lengths: [Option<f64>; 2]) -> Vec<(Actuator, String)> {
+        if !self.on(Policy::Constraints) {
+            return Vec::new();
+        }
+        [Actuator::M1, Actuator::M2]
+            .into_iter()
+            .filter(|&actuator| self.is_moving(actuator))
+            .filter_map(|actuator| {
+                let rule = self.settings.constraints.blocking(actuator, self.interlock.direction(actuator), lengths)?;
+                Some((actuator, rule.to_string()))
+            })
+            .collect()
+    }
+
+    /// When a soft start is next due to finish.
+    pub fn next_due(&self) -> Option<Instant> {
+        self.raise_at.iter().flatten().map(|&(at, _)| at).min()
+    }
+
+    /// The speeds of soft starts that are over by `now`, for actuators still
+    /// moving at the soft one.
+    pub fn due(&mut self, now: Instant) -> Vec<ActuatorCommand> {
+        let mut out = Vec::new();
+        for actuator in [Actuator::M1, Actuator::M2] {
+            let i = actuator as usize;
+            let Some((_, speed)) = self.raise_at[i].filter(|&(at, _)| at <= now) else {
+                continue;
+            };
+            self.raise_at[i] = None;
+            // a rule may have stopped it since
+            if self.is_moving(actuator) {
+                let cmd = ActuatorCommand::SetSpeed(speed, actuator);
+                out.push(self.budget.map_or(cmd, |budget| budget.apply(cmd)));
+            }
+        }
+        out
+    }
+
+    /// Rescales for the latest `currents`: the budget's new scale and draw,
+    /// if that changed, and the speeds that changed with it for actuators
+    /// moving (and not still starting softly).
+    pub fn rebudget(&mut self, currents: [Option<f64>; 2]) -> Option<(StatusEvent, Vec<ActuatorCommand>)> {
+        let budget = self.budget.as_mut()?;
+        // an idle actuator's last reading is from before it stopped
+        let draw: f64 = [Actuator::M1, Actuator::M2]
+            .into_iter()
+            .filter(|&actuator| self.interlock.is_moving(actuator))
+            .filter_map(|actuator| currents[actuator as usize])
+            .sum();
+        if !budget.observe(draw) {
+            return None;
+        }
+        let budget = *budget;
+        let speeds = [Actuator::M1, Actuator::M2]
+            .into_iter()
+            .filter(|&actuator| self.is_moving(actuator) && self.raise_at[actuator as usize].is_none())
+            .map(|actuator| budget.apply(ActuatorCommand::SetSpeed(self.asked[actuator as usize], actuator)))
+            .filter(|&cmd| cmd != ActuatorCommand::SetSpeed(self.interlock.speed(cmd.actuator()), cmd.actuator()))
+            .collect();
+        Some((StatusEvent::BudgetScaled(budget.scale(), draw), speeds))
+    }
+}
@@ -0,0 +1,409 @@
+//! The Telemetry chart: the last few seconds of the selected actuator's
+//! length, velocity, current, commanded speed, the load cell's force and one
+//! [derived channel](actuator_controller::channels), laid over one another.
+//!
+//! The series are in different units, so each is stretched to fill the
+//! chart's height on its own, and the legend says what range that covers.
+//! `c` opens a picker to choose which are shown. Limits, targets and alarm
+//! thresholds are drawn across the chart in grey, and count toward the
+//! range of their series, so they're always in view.
+//!
+//! A lane under the chart marks commands (◆), faults (✖), reconnects (↻)
+//! and `mark` notes (✎) at the time they happened, and spells out the
+//! latest one in view.
+//!
+//! `x` freezes the chart and puts a cursor on it, like an oscilloscope's:
+//! the arrow keys move it, and it reads out each series' value there, and
+//! the difference from a mark left behind with Enter.
+
+use std::{collections::VecDeque, time::Instant};
+
+use ratatui::{
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Style},
+    symbols,
+    text::{Line, Span, Text},
+    widgets::{Axis, Block, Borders, Chart, Clear, Dataset, GraphType, LegendPosition, Paragraph},
+    Frame,
+};
+
+use actuator_controller::{app::TimelineEvent, commands::Actuator, telemetry::Field};
+
+/// Seconds of history shown.
+const WINDOW: f64 = 10.0;
+
+/// How far the cursor moves per key press (s).
+pub const CURSOR_STEP: f64 = 0.1;
+/// How far it moves with Shift held.
+pub const CURSOR_FINE_STEP: f64 = 0.01;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Series {
+    Length,
+    Velocity,
+    Current,
+    Speed,
+    Force,
+    /// the one `[channels]` picks for the chart
+    Channel,
+}
+
+impl Series {
+    pub const ALL: [Series; 6] =
+        [Series::Length, Series::Velocity, Series::Current, Series::Speed, Series::Force, Series::Channel];
+
+    fn name(self) -> &'static str {
+        match self {
+            Series::Length => "length",
+            Series::Velocity => "velocity",
+            Series::Current => "current",
+            Series::Speed => "commanded speed",
+            Series::Force => "force",
+            Series::Channel => "channel",
+        }
+    }
+
+    fn unit(self) -> &'static str {
+        match self {
+            Series::Length => "m",
+            Series::Velocity => "m/s",
+            Series::Current => "A",
+            Series::Speed | Series::Channel => "",
+            Series::Force => "N",
+        }
+    }
+
+    /// Decimal places worth showing.
+    fn precision(self) -> usize {
+        match self {
+            Series::Length | Series::Velocity | Series::Channel => 3,
+            Series::Current => 2,
+            Series::Speed | Series::Force => 0,
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            Series::Length => Color::Cyan,
+            Series::Velocity => Color::Yellow,
+            Series::Current => Color::Red,
+            Series::Speed => Color::Green,
+            Series::Force => Color::Magenta,
+            Series::Channel => Color::Blue,
+        }
+    }
+}
+
+/// One moment of an actuator, with a value per [`Series::ALL`] where there is one.
+pub type Sample = [Option<f64>; 6];
+
+/// A value worth drawing a line across the chart at, such as a limit.
+pub struct Reference {
+    pub series: Series,
+    pub value: f64,
+    /// for the legend, shared by lines that go together (like both ends of a range)
+    pub label: &'static str,
+}
+
+/// A frozen copy of the chart being measured.
+struct Cursor {
+    samples: [VecDeque<(f64, Sample)>; 2],
+    events: VecDeque<(f64, TimelineEvent)>,
+    /// seconds since `start` when it was frozen
+    now: f64,
+    /// where the cursor is, seconds since `start`
+    at: f64,
+    /// where the mark was left, to measure from
+    mark: Option<f64>,
+}
+
+pub struct Scope {
+    /// (seconds since `start`, values) for each actuator, oldest first
+    samples: [VecDeque<(f64, Sample)>; 2],
+    /// (seconds since `start`, event) for the timeline, oldest first
+    events: VecDeque<(f64, TimelineEvent)>,
+    start: Instant,
+    /// which of [`Series::ALL`] are drawn
+    pub shown: [bool; 6],
+    /// what [`Series::Channel`] is, if there are any channels
+    pub channel: Option<Field>,
+    /// the series picker is open
+    pub picking: bool,
+    cursor: Option<Cursor>,
+}
+
+impl Default for Scope {
+    fn default() -> Scope {
+        Scope {
+            samples: Default::default(),
+            events: VecDeque::new(),
+            start: Instant::now(),
+            shown: [true, false, false, true, false, false],
+            channel: None,
+            picking: false,
+            cursor: None,
+        }
+    }
+}
+
+impl Scope {
+    pub fn push(&mut self, actuator: Actuator, now: Instant, sample: Sample) {
+        let t = now.duration_since(self.start).as_secs_f64();
+        let samples = &mut self.samples[actuator as usize];
+        samples.push_back((t, sample));
+        while samples.front().is_some_and(|&(at, _)| at < t - WINDOW) {
+            samples.pop_front();
+        }
+        while self.events.front().is_some_and(|&(at, _)| at < t - WINDOW) {
+            self.events.pop_front();
+        }
+    }
+
+    /// Puts `event` on the timeline at `at`.
+    pub fn note(&mut self, at: Instant, event: TimelineEvent) {
+        self.events.push_back((at.duration_since(self.start).as_secs_f64(), event));
+    }
+
+    /// Shows or hides the `n`th of [`Series::ALL`], counting from 1 as the picker does.
+    pub fn toggle(&mut self, n: usize) {
+        if n > self.choices() {
+            return;
+        }
+        if let Some(shown) = n.checked_sub(1).and_then(|i| self.shown.get_mut(i)) {
+            *shown = !*shown;
+        }
+    }
+
+    /// How many of [`Series::ALL`] the picker offers: the channel only if
+    /// there is one.
+    pub fn choices(&self) -> usize {
+        Series::ALL.len() - usize::from(self.channel.is_none())
+    }
+
+    fn name(&self, series: Series) -> &str {
+        match (series, &self.channel) {
+            (Series::Channel, Some(field)) => &field.name,
+            _ => series.name(),
+        }
+    }
+
+    fn unit(&self, series: Series) -> &str {
+        match (series, &self.channel) {
+            (Series::Channel, Some(field)) => &field.unit,
+            _ => series.unit(),
+        }
+    }
+
+    pub fn measuring(&self) -> bool {
+        self.cursor.is_some()
+    }
+
+    /// Freezes the chart with the cursor at its right-hand edge, or lets it
+    /// run again.
+    pub fn toggle_cursor(&mut self, now: Instant) {
+        self.cursor = match self.cursor {
+            Some(_) => None,
+            None => {
+                let now = now.duration_since(self.start).as_secs_f64();
+                Some(Cursor { samples: self.samples.clone(), events: self.events.clone(), now, at: now, mark: None })
+            }
+        };
+    }
+
+    /// Moves the cursor `seconds` later, or earlier if negative, staying on the chart.
+    pub fn move_cursor(&mut self, seconds: f64) {
+        if let Some(cursor) = &mut self.cursor {
+            cursor.at = (cursor.at + seconds).clamp(cursor.now - WINDOW, cursor.now);
+        }
+    }
+
+    /// Leaves the mark where the cursor is.
+    pub fn set_mark(&mut self) {
+        if let Some(cursor) = &mut self.cursor {
+            cursor.mark = Some(cursor.at);
+        }
+    }
+
+    /// Each shown series' value under the cursor, then how far it and the
+    /// time are from the mark, if there is one.
+    pub fn readout(&self, actuator: Actuator) -> String {
+        let Some(cursor) = &self.cursor else {
+            return String::new();
+        };
+        let samples = &cursor.samples[actuator as usize];
+        let shown = || Series::ALL.into_iter().enumerate().filter(|&(i, _)| self.shown[i]);
+        let mut at = format!("{actuator:?} at {:.2} s", cursor.at - cursor.now);
+        for (i, series) in shown() {
+            at += &match value_at(samples, i, cursor.at) {
+                Some(v) => format!(" | {} {v:.p$} {}", self.name(series), self.unit(series), p = series.precision()),
+                None => format!(" | {} -", self.name(series)),
+            };
+        }
+        let Some(mark) = cursor.mark else {
+            return format!("{at}\nEnter: Mark here to measure from");
+        };
+        let mut delta = format!("Δ from mark at {:.2} s: {:+.2} s", mark - cursor.now, cursor.at - mark);
+        for (i, series) in shown() {
+            delta += &match (value_at(samples, i, cursor.at), value_at(samples, i, mark)) {
+                (Some(v), Some(from)) => {
+                    format!(" | {} {:+.p$} {}", self.name(series), v - from, self.unit(series), p = series.precision())
+                }
+                _ => format!(" | {} -", self.name(series)),
+            };
+        }
+        format!("{at}\n{delta}")
+    }
+
+    pub fn render(&self, f: &mut Frame, area: Rect, actuator: Actuator, now: Instant, references: &[Reference]) {
+        let (samples, events, now) = match &self.cursor {
+            Some(cursor) => (&cursor.samples[actuator as usize], &cursor.events, cursor.now),
+            None => (&self.samples[actuator as usize], &self.events, now.duration_since(self.start).as_secs_f64()),
+        };
+        let mut lines = Vec::new();
+        let mut guides: Vec<(&str, [(f64, f64); 2])> = Vec::new();
+        for (i, series) in Series::ALL.into_iter().enumerate().filter(|&(i, _)| self.shown[i]) {
+            let points: Vec<(f64, f64)> = samples.iter().filter_map(|&(t, sample)| sample[i].map(|v| (t, v))).collect();
+            let references: Vec<&Reference> = references.iter().filter(|r| r.series == series).collect();
+            let (low, high) = points
+                .iter()
+                .map(|&(_, v)| v)
+                .chain(references.iter().map(|r| r.value))
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(low, high), v| (low.min(v), high.max(v)));
+            let name = match points.is_empty() {
+                true => format!("{}: -", self.name(series)),
+                false => format!("{} {low:.p$} to {high:.p$} {}", self.name(series), self.unit(series), p = series.precision()),
+            };
+            // a flat line goes through the middle
+            let span = if high > low { high - low } else { 1.0 };
+            let middle = if high > low { 0.0 } else { 0.5 };
+            let scaled: Vec<(f64, f64)> = points.iter().map(|&(t, v)| (t, middle + (v - low) / span)).collect();
+            lines.push((series, name, scaled));
+            for reference in references {
+                let y = middle + (reference.value - low) / span;
+                guides.push((reference.label, [(now - WINDOW, y), (now, y)]));
+            }
+        }
+        let mut datasets: Vec<Dataset> = lines
+            .iter()
+            .map(|(series, name, points)| {
+                Dataset::default()
+                    .name(name.as_str())
+                    .marker(symbols::Marker::Braille)
+                    .graph_type(GraphType::Line)
+                    .style(Style::default().fg(series.color()))
+                    .data(points)
+            })
+            .collect();
+        for (n, (label, line)) in guides.iter().enumerate() {
+            let dataset = Dataset::default()
+                .marker(symbols::Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(Color::DarkGray))
+                .data(line);
+            // one legend entry for each kind of line
+            let first = guides[..n].iter().all(|(other, _)| other != label);
+            datasets.push(if first { dataset.name(*label) } else { dataset });
+        }
+        // unnamed, so they stay out of the legend
+        let cursors: Vec<([(f64, f64); 2], Color)> = match &self.cursor {
+            Some(cursor) => [(Some(cursor.at), Color::White), (cursor.mark, Color::DarkGray)]
+                .into_iter()
+                .filter_map(|(at, color)| at.map(|at| ([(at, 0.0), (at, 1.0)], color)))
+                .collect(),
+            None => Vec::new(),
+        };
+        datasets.extend(cursors.iter().map(|(line, color)| {
+            Dataset::default()
+                .marker(symbols::Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(*color))
+                .data(line)
+        }));
+        let title = match self.cursor {
+            Some(_) => format!("Telemetry: {actuator:?} (frozen, x: Resume)"),
+            None => format!("Telemetry: {actuator:?} (c: Series | x: Cursor)"),
+        };
+        let first_label = format!("-{WINDOW} s");
+        // the chart puts its plot right of the first x label, up to a third of the width
+        let left = (first_label.len() as u16).min(area.width / 3);
+        let chart = Chart::new(datasets)
+            .x_axis(Axis::default().bounds([now - WINDOW, now]).labels([first_label, String::from("now")]))
+            .y_axis(Axis::default().bounds([0.0, 1.0]))
+            .legend_position(Some(LegendPosition::TopLeft))
+            .hidden_legend_constraints((Constraint::Ratio(1, 1), Constraint::Ratio(1, 1)));
+        let block = Block::default().title(title).borders(Borders::ALL);
+        let [plot, lane] = Layout::vertical([Constraint::Min(0), Constraint::Length(2)]).areas(block.inner(area));
+        f.render_widget(block, area);
+        f.render_widget(chart, plot);
+
+        let width = lane.width.saturating_sub(left);
+        let mut glyphs = vec![Span::raw(" "); width as usize];
+        for (at, event) in events.iter().filter(|(at, _)| *at >= now - WINDOW && *at <= now) {
+            let column = ((at - (now - WINDOW)) / WINDOW * width.saturating_sub(1) as f64).round() as usize;
+            if let Some(glyph) = glyphs.get_mut(column) {
+                let (symbol, color) = glyph_for(event);
+                *glyph = Span::styled(symbol, Style::default().fg(color));
+            }
+        }
+        // the latest in view, or the nearest the cursor while measuring
+        let described = match &self.cursor {
+            Some(cursor) => events.iter().min_by(|a, b| (a.0 - cursor.at).abs().total_cmp(&(b.0 - cursor.at).abs())),
+            None => events.iter().rev().find(|(at, _)| *at >= now - WINDOW),
+        };
+        let description = match described {
+            Some((at, event)) => format!("{} {} at {:.1} s", glyph_for(event).0, describe(event), at - now),
+            None => String::new(),
+        };
+        let mut row = vec![Span::raw(" ".repeat(left as usize))];
+        row.extend(glyphs);
+        f.render_widget(Paragraph::new(Text::from(vec![Line::from(row), Line::from(description)])), lane);
+    }
+
+    /// The picker, over the middle of `area`.
+    pub fn render_picker(&self, f: &mut Frame, area: Rect) {
+        let rows: Vec<String> = Series::ALL[..self.choices()]
+            .iter()
+            .enumerate()
+            .map(|(i, &series)| format!("{}: [{}] {}", i + 1, if self.shown[i] { "x" } else { " " }, self.name(series)))
+            .collect();
+        let width = (rows.iter().map(String::len).max().unwrap_or(0) as u16 + 4).min(area.width);
+        let height = (rows.len() as u16 + 2).min(area.height);
+        let popup = Rect {
+            x: area.x + (area.width - width) / 2,
+            y: area.y + (area.height - height) / 2,
+            width,
+            height,
+        };
+        let picker = Paragraph::new(Text::from(rows.join("\n")))
+            .block(Block::default().title("Series").borders(Borders::ALL));
+        f.render_widget(Clear, popup);
+        f.render_widget(picker, popup);
+    }
+}
+
+fn glyph_for(event: &TimelineEvent) -> (&'static str, Color) {
+    match event {
+        TimelineEvent::Command(_) => ("◆", Color::Cyan),
+        TimelineEvent::Fault(_) => ("✖", Color::Red),
+        TimelineEvent::Reconnect => ("↻", Color::Green),
+        TimelineEvent::Note(_) => ("✎", Color::Yellow),
+    }
+}
+
+fn describe(event: &TimelineEvent) -> String {
+    match event {
+        TimelineEvent::Command(text) => text.clone(),
+        TimelineEvent::Fault(fault) => format!("fault: {fault}"),
+        TimelineEvent::Reconnect => String::from("reconnected"),
+        TimelineEvent::Note(note) => format!("note: {note}"),
+    }
+}
+
+/// The `i`th series' value at the sample nearest `t` that has one.
+fn value_at(samples: &VecDeque<(f64, Sample)>, i: usize, t: f64) -> Option<f64> {
+    samples
+        .iter()
+        .filter_map(|&(at, sample)| sample[i].map(|v| ((at - t).abs(), v)))
+        .min_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, v)| v)
+}
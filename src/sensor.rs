@@ -0,0 +1,205 @@
+//! How far each actuator's length sensor (a potentiometer on the current
+//! boards) can be trusted, scored from 0 to 100 over its recent reports, so
+//! a failing one shows up before a run rather than partway through.
+//!
+//! Three things count against it:
+//! - noise: how much the length jitters about a smooth path, from the
+//!   second differences of consecutive reports, which a steady speed doesn't
+//!   change;
+//! - dropouts: reports that didn't come when they should have, while no
+//!   other actuator was reporting instead, and values that aren't numbers;
+//! - rails: lengths of exactly 0 or the full stroke, where a broken wiper or
+//!   lead leaves the reading, which a real end stop never quite gives.
+//!
+//! Each is taken as a share of the limit it's allowed, and the score is
+//! 100 less the worst of them.
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    time::{Duration, Instant},
+};
+
+use crate::commands::Actuator;
+
+/// Reports needed before there's a score at all.
+const MIN_REPORTS: usize = 10;
+
+/// A gap this many report periods long means reports went missing.
+const GAP: f64 = 3.0;
+
+/// Reports are often taken in a batch, once per redraw, so a gap has to be
+/// at least this long as well before it counts as reports going missing.
+const MIN_GAP: Duration = Duration::from_millis(250);
+
+/// How much a score has to come back above `warn` before the next drop warns again.
+const HYSTERESIS: f64 = 10.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SensorConfig {
+    /// how many reports the score is taken over
+    pub window: usize,
+    /// noise (m, standard deviation) that scores 0
+    pub noise: f64,
+    /// share of reports missing that scores 0
+    pub dropout: f64,
+    /// share of reports on a rail that scores 0
+    pub rail: f64,
+    /// score under which the operator is warned
+    pub warn: f64,
+}
+
+impl Default for SensorConfig {
+    fn default() -> Self {
+        SensorConfig { window: 200, noise: 0.002, dropout: 0.2, rail: 0.5, warn: 70.0 }
+    }
+}
+
+/// What's wrong with a sensor, if anything.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Problem {
+    Noisy,
+    Dropouts,
+    Rail,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Problem::Noisy => "noisy",
+            Problem::Dropouts => "dropping out",
+            Problem::Rail => "stuck at a rail",
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Health {
+    /// 0 to 100, higher is better
+    pub score: f64,
+    /// standard deviation of the jitter (m)
+    pub noise: f64,
+    /// share of reports missing
+    pub dropout: f64,
+    /// share of reports on a rail
+    pub rail: f64,
+    /// whichever counts against it most, if anything does
+    pub worst: Option<Problem>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Sample {
+    /// `None` for a value that isn't a number
+    length: Option<f64>,
+    /// reports estimated missing just before it
+    missed: u32,
+    /// whether the one before it isn't the report just before, after
+    /// reports went missing or another actuator reported in between
+    broken: bool,
+    rail: bool,
+}
+
+#[derive(Clone, Debug, Default)]
+struct Sensor {
+    samples: VecDeque<Sample>,
+    last: Option<Instant>,
+    /// usual time between reports
+    period: Option<Duration>,
+    /// whether the operator has been warned of the latest drop
+    warned: bool,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Sensors {
+    pub config: SensorConfig,
+    sensors: [Sensor; 2],
+    /// whose report came last, since only one actuator reports at a time
+    reporting: Option<Actuator>,
+}
+
+impl Sensors {
+    pub fn new(config: SensorConfig) -> Sensors {
+        Sensors { config, ..Sensors::default() }
+    }
+
+    /// Takes in a reported `length` of `actuator`, whose full stroke is `stroke`.
+    pub fn observe(&mut self, actuator: Actuator, length: f64, stroke: f64, now: Instant) {
+        let steady = self.reporting == Some(actuator);
+        self.reporting = Some(actuator);
+        let sensor = &mut self.sensors[actuator as usize];
+        let gap = sensor.last.map(|last| now.duration_since(last));
+        sensor.last = Some(now);
+        let mut missed = 0;
+        if let Some(gap) = gap
+            && steady
+        {
+            match sensor.period {
+                Some(period) if gap > period.mul_f64(GAP).max(MIN_GAP) && !period.is_zero() => {
+                    missed = (gap.as_secs_f64() / period.as_secs_f64()).round() as u32 - 1;
+                }
+                // an average of the usual gaps, slow to follow, which comes
+                // out right however they were batched
+                Some(period) => sensor.period = Some(period.mul_f64(0.95) + gap.mul_f64(0.05)),
+                None => sensor.period = Some(gap),
+            }
+        }
+        let length = length.is_finite().then_some(length);
+        let rail = length.is_some_and(|len| len == 0.0 || len == stroke);
+        if sensor.samples.len() == self.config.window {
+            sensor.samples.pop_front();
+        }
+        sensor.samples.push_back(Sample { length, missed, broken: !steady || missed > 0, rail });
+    }
+
+    /// The score for `actuator`, once it has reported enough.
+    pub fn health(&self, actuator: Actuator) -> Option<Health> {
+        let samples = &self.sensors[actuator as usize].samples;
+        if samples.len() < MIN_REPORTS {
+            return None;
+        }
+        let reports = samples.len() as f64;
+        let missed = samples.iter().map(|s| s.missed as f64 + s.length.is_none() as u8 as f64).sum::<f64>();
+        let dropout = missed / (reports + samples.iter().map(|s| s.missed as f64).sum::<f64>());
+        let rail = samples.iter().filter(|s| s.rail).count() as f64 / reports;
+        // second differences of unbroken runs of three; white noise of σ gives them σ√6
+        let samples: Vec<Sample> = samples.iter().copied().collect();
+        let seconds: Vec<f64> = samples
+            .windows(3)
+            .filter_map(|w| match (w[0].length, w[1].length, w[2].length) {
+                (Some(a), Some(b), Some(c)) if !w[1].broken && !w[2].broken => Some(a - 2.0 * b + c),
+                _ => None,
+            })
+            .collect();
+        let noise = match seconds.len() {
+            0 => 0.0,
+            n => (seconds.iter().map(|d| d * d).sum::<f64>() / n as f64).sqrt() / 6f64.sqrt(),
+        };
+        let shares = [
+            (Problem::Noisy, noise / self.config.noise),
+            (Problem::Dropouts, dropout / self.config.dropout),
+            (Problem::Rail, rail / self.config.rail),
+        ];
+        let (problem, share) = shares.into_iter().max_by(|a, b| a.1.total_cmp(&b.1)).expect("three shares");
+        let share = share.min(1.0);
+        let worst = (share > 0.0).then_some(problem);
+        Some(Health { score: (100.0 * (1.0 - share)).round(), noise, dropout, rail, worst })
+    }
+
+    pub fn is_poor(&self, health: &Health) -> bool {
+        health.score < self.config.warn
+    }
+
+    /// `actuator`'s health if it has just dropped under `warn`, once per drop.
+    pub fn dropped(&mut self, actuator: Actuator) -> Option<Health> {
+        let health = self.health(actuator)?;
+        let sensor = &mut self.sensors[actuator as usize];
+        if health.score >= self.config.warn + HYSTERESIS {
+            sensor.warned = false;
+        }
+        if health.score < self.config.warn && !sensor.warned {
+            sensor.warned = true;
+            return Some(health);
+        }
+        None
+    }
+}
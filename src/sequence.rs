@@ -0,0 +1,319 @@
+//! Multi-step motion routines built out of position moves, timed drives,
+//! pushes that stop at resistance and trajectories.
+
+use std::time::{Duration, Instant};
+
+use crate::{
+    commands::{Actuator, Direction, Drive},
+    position::PositionController,
+    trajectory::Trajectory,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Step {
+    /// Move to the given strokes (`None` leaves that actuator alone), no
+    /// faster than `speed`. Done once the position controller settles.
+    Goto { targets: [Option<f64>; 2], speed: u16 },
+    /// Run open loop for `duration`, then stop.
+    Drive {
+        actuator: Actuator,
+        direction: Direction,
+        speed: u16,
+        duration: Duration,
+    },
+    /// Run open loop until the actuator draws more than `limit` current
+    /// (push until resistance), then back off the other way for `backoff`
+    /// and stop. Gives up and stops after `timeout` without resistance.
+    Push {
+        actuator: Actuator,
+        direction: Direction,
+        speed: u16,
+        limit: f64,
+        backoff: Duration,
+        timeout: Duration,
+    },
+    /// Track the trajectory's targets with the position controller until its
+    /// last point. Done once that's reached.
+    Track(Trajectory),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sequence {
+    pub name: String,
+    /// each step with a short label shown while it runs
+    pub steps: Vec<(String, Step)>,
+}
+
+pub struct Runner {
+    sequence: Sequence,
+    index: usize,
+    step_started: Option<Instant>,
+    /// when the current push step met resistance and started backing off
+    backing_off: Option<Instant>,
+    /// whether the current track step is past its last point, waiting to reach it
+    settling: bool,
+    /// how far into the current track step's trajectory, in its own seconds,
+    /// and when that was worked out
+    played: (f64, Instant),
+    /// how fast trajectories play now, 1 being as written; eases toward
+    /// `pace`, or 0 while paused
+    rate: f64,
+    pace: f64,
+    paused: bool,
+    /// how far into its trajectory the next track step starts, when resuming one
+    resume_from: f64,
+}
+
+/// Speeds `<` and `>` step a trajectory through.
+pub const PACES: [f64; 4] = [0.25, 0.5, 0.75, 1.0];
+
+/// How fast the rate a trajectory plays at can change, per second, so slowing
+/// or pausing it eases the targets to a stop instead of jerking them.
+const RATE_SLEW: f64 = 2.0;
+
+impl Runner {
+    pub fn new(sequence: Sequence) -> Runner {
+        Runner {
+            sequence,
+            index: 0,
+            step_started: None,
+            backing_off: None,
+            settling: false,
+            played: (0.0, Instant::now()),
+            rate: 1.0,
+            pace: 1.0,
+            paused: false,
+            resume_from: 0.0,
+        }
+    }
+
+    /// The step running, counting from 0.
+    pub fn step(&self) -> usize {
+        self.index
+    }
+
+    /// How far into the current step's trajectory (s), or 0 if it isn't
+    /// following one.
+    pub fn played(&self) -> f64 {
+        match self.is_tracking() && self.step_started.is_some() {
+            true => self.played.0,
+            false => 0.0,
+        }
+    }
+
+    /// Skips to `step` before it starts, `played` seconds into its
+    /// trajectory if it follows one, to pick up where an interrupted run of
+    /// the same sequence left off.
+    pub fn skip_to(&mut self, step: usize, played: f64) {
+        self.index = step.min(self.sequence.steps.len());
+        self.step_started = None;
+        self.resume_from = if self.is_tracking() { played } else { 0.0 };
+    }
+
+    /// e.g. `dig cycle: curl (2/4)`, or `follow dig.csv: 3 s (1/1) at 50%`
+    pub fn describe(&self) -> String {
+        let label = self.sequence.steps.get(self.index).map_or("done", |(label, _)| label.as_str());
+        let pace = match (self.paused, self.pace) {
+            _ if !self.is_tracking() => String::new(),
+            (true, _) => String::from(" paused"),
+            (false, 1.0) => String::new(),
+            (false, pace) => format!(" at {:.0}%", pace * 100.0),
+        };
+        format!(
+            "{}: {} ({}/{}){pace}",
+            self.sequence.name,
+            label,
+            (self.index + 1).min(self.sequence.steps.len()),
+            self.sequence.steps.len()
+        )
+    }
+
+    /// Whether the current step is following a trajectory.
+    pub fn is_tracking(&self) -> bool {
+        matches!(self.sequence.steps.get(self.index), Some((_, Step::Track(_))))
+    }
+
+    /// Plays trajectories at `pace` times the speed they were written for.
+    pub fn set_pace(&mut self, pace: f64) {
+        self.pace = pace;
+    }
+
+    pub fn pace(&self) -> f64 {
+        self.pace
+    }
+
+    /// Pauses a trajectory, or resumes it at its pace. Returns whether it's
+    /// now paused.
+    pub fn toggle_pause(&mut self) -> bool {
+        self.paused = !self.paused;
+        self.paused
+    }
+
+    /// Starts or finishes steps as needed, pushing any frames that have to go
+    /// out onto `out`. `current` is each actuator's last reported current.
+    /// Returns `false` once the last step is done.
+    pub fn tick(
+        &mut self,
+        position: &mut PositionController,
+        current: [Option<f64>; 2],
+        now: Instant,
+        out: &mut Vec<Drive>,
+    ) -> bool {
+        loop {
+            let Some((_, step)) = self.sequence.steps.get(self.index) else {
+                return false;
+            };
+            let Some(started) = self.step_started else {
+                match *step {
+                    Step::Goto { targets, speed } => {
+                        for actuator in [Actuator::M1, Actuator::M2] {
+                            if let Some(len) = targets[actuator as usize] {
+                                position.set_target_capped(actuator, len, speed);
+                            }
+                        }
+                    }
+                    Step::Drive { actuator, direction, speed, duration } => {
+                        out.push(Drive::new(actuator, direction.signed(speed as u32)).lasting(duration));
+                    }
+                    Step::Push { actuator, direction, speed, .. } => {
+                        out.push(Drive::new(actuator, direction.signed(speed as u32)));
+                    }
+                    Step::Track(ref trajectory) => {
+                        let from = std::mem::take(&mut self.resume_from).min(trajectory.duration());
+                        self.played = (from, now);
+                        track(position, trajectory, from);
+                    }
+                }
+                self.step_started = Some(now);
+                return true;
+            };
+            match *step {
+                Step::Track(ref trajectory) => {
+                    let (played, at) = self.played;
+                    let dt = now.duration_since(at).as_secs_f64();
+                    let target = if self.paused { 0.0 } else { self.pace };
+                    let rate = self.rate + (target - self.rate).clamp(-RATE_SLEW * dt, RATE_SLEW * dt);
+                    // the trapezoid, so the clock eases along with the rate
+                    let played = played + (self.rate + rate) / 2.0 * dt;
+                    self.rate = rate;
+                    self.played = (played, now);
+                    if played < trajectory.duration() {
+                        track(position, trajectory, played);
+                        return true;
+                    }
+                    if !self.settling {
+                        track(position, trajectory, trajectory.duration());
+                        position.settle();
+                        self.settling = true;
+                    }
+                    if position.is_active() {
+                        return true;
+                    }
+                }
+                Step::Goto { .. } if position.is_active() => return true,
+                Step::Drive { duration, .. } if now.duration_since(started) < duration => return true,
+                Step::Goto { .. } => {}
+                Step::Drive { actuator, .. } => out.push(Drive::stop(actuator)),
+                Step::Push { actuator, direction, speed, limit, backoff, timeout } => match self.backing_off {
+                    None if current[actuator as usize].is_some_and(|c| c.abs() > limit) && !backoff.is_zero() => {
+                        out.push(Drive::new(actuator, direction.reversed().signed(speed as u32)));
+                        self.backing_off = Some(now);
+                        return true;
+                    }
+                    None if current[actuator as usize].is_some_and(|c| c.abs() > limit) => out.push(Drive::stop(actuator)),
+                    None if now.duration_since(started) >= timeout => out.push(Drive::stop(actuator)),
+                    None => return true,
+                    Some(at) if now.duration_since(at) < backoff => return true,
+                    Some(_) => out.push(Drive::stop(actuator)),
+                },
+            }
+            self.index += 1;
+            self.step_started = None;
+            self.backing_off = None;
+            self.settling = false;
+        }
+    }
+
+    /// Stops whatever the current step is driving open loop. Position moves
+    /// are the position controller's to cancel.
+    pub fn abort(self) -> Vec<Drive> {
+        match self.sequence.steps.get(self.index) {
+            Some((_, Step::Drive { actuator, .. } | Step::Push { actuator, .. })) if self.step_started.is_some() => {
+                vec![Drive::stop(*actuator)]
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Points the position controller at where `trajectory` is `t` seconds in.
+fn track(position: &mut PositionController, trajectory: &Trajectory, t: f64) {
+    for (actuator, len) in [Actuator::M1, Actuator::M2].into_iter().zip(trajectory.at(t)) {
+        if let Some(len) = len {
+            position.track(actuator, len);
+        }
+    }
+}
+
+/// How a push (`<actuator> <dir> <speed> until <current>`) finishes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Effort {
+    /// how long to drive the other way once the limit is hit; zero just stops
+    pub backoff: Duration,
+    /// give up and stop after this long without hitting the limit
+    pub timeout: Duration,
+}
+
+impl Default for Effort {
+    fn default() -> Effort {
+        Effort { backoff: Duration::from_millis(200), timeout: Duration::from_secs(10) }
+    }
+}
+
+/// Parameters of the built-in dig cycle. Strokes are in meters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DigCycle {
+    /// lift stroke the bucket is lowered to before digging
+    pub lower_depth: f64,
+    pub lower_speed: u16,
+    /// bucket stroke to curl to once lowered
+    pub curl: f64,
+    pub curl_speed: u16,
+    /// lift stroke to raise the full bucket to
+    pub raise: f64,
+    pub raise_speed: u16,
+    /// speed for heading back to where the cycle started
+    pub return_speed: u16,
+}
+
+impl Default for DigCycle {
+    fn default() -> Self {
+        DigCycle {
+            lower_depth: 0.02,
+            lower_speed: 30000,
+            curl: 0.08,
+            curl_speed: 20000,
+            raise: 0.18,
+            raise_speed: 40000,
+            return_speed: 40000,
+        }
+    }
+}
+
+impl DigCycle {
+    /// Lower, curl, raise, then return to `start` (the strokes when the cycle began).
+    pub fn sequence(&self, start: [f64; 2]) -> Sequence {
+        let step = |label: &str, lift: Option<f64>, bucket: Option<f64>, speed| {
+            (label.to_string(), Step::Goto { targets: [lift, bucket], speed })
+        };
+        Sequence {
+            name: String::from("dig cycle"),
+            steps: vec![
+                step("lower", Some(self.lower_depth), None, self.lower_speed),
+                step("curl", None, Some(self.curl), self.curl_speed),
+                step("raise", Some(self.raise), None, self.raise_speed),
+                step("return", Some(start[0]), Some(start[1]), self.return_speed),
+            ],
+        }
+    }
+}
@@ -0,0 +1,60 @@
+//! What a session ran with, written at its start to the audit log and to
+//! `--output json`, so a recorded run can be made sense of later without
+//! knowing how it was started.
+//!
+//! That's the build (version, commit, date and features), the config file, and every key as it resolved once
+//! environment variables and `--set` were applied, calibrations included.
+//! The firmware doesn't report a version; the fields it advertises follow as
+//! a status event once it sends them.
+
+use std::{
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{audit, config::Config, json::Value};
+
+/// The commit this was built from, as `git describe` put it, or `unknown`
+/// if it wasn't built from a git checkout.
+pub const COMMIT: &str = env!("ACTUATOR_COMMIT");
+
+/// When this was built, as seconds since the Unix epoch; only rebuilt with
+/// the commit, so it's when the commit was built.
+const BUILT: &str = env!("ACTUATOR_BUILT");
+
+/// When this was built, if the build recorded it.
+pub fn built() -> Option<SystemTime> {
+    BUILT.parse().ok().filter(|&secs| secs > 0).map(|secs| UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+/// The optional Cargo features this was built with.
+pub fn features() -> Vec<&'static str> {
+    [
+        ("tui", cfg!(feature = "tui")),
+        ("bridge", cfg!(feature = "bridge")),
+        ("mdns", cfg!(feature = "mdns")),
+        ("gamepad", cfg!(feature = "gamepad")),
+        ("gpio", cfg!(feature = "gpio")),
+    ]
+    .into_iter().filter(|&(_, on)| on).map(|(name, _)| name).collect()
+}
+
+/// The crate version and commit, e.g. `0.1.0 (3f68cce-dirty)`.
+pub fn build() -> String {
+    format!("{} ({COMMIT})", env!("CARGO_PKG_VERSION"))
+}
+
+/// The build, the config file read from `path`, and every key of `config`.
+pub fn snapshot(config: &Config, path: &Path) -> Value {
+    Value::Object(vec![
+        (String::from("version"), Value::String(env!("CARGO_PKG_VERSION").to_string())),
+        (String::from("commit"), Value::String(COMMIT.to_string())),
+        (String::from("built"), built().map_or(Value::Null, |at| Value::String(audit::timestamp(at)))),
+        (
+            String::from("features"),
+            Value::Array(features().into_iter().map(|name| Value::String(name.to_string())).collect()),
+        ),
+        (String::from("config_path"), Value::String(path.display().to_string())),
+        (String::from("config"), config.snapshot()),
+    ])
+}
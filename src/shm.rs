@@ -0,0 +1,177 @@
+//! The latest telemetry in a shared-memory file, for readers on the same
+//! machine that can't afford a socket round trip.
+//!
+//! The file holds one [`Segment`], native-endian, guarded by a seqlock: the
+//! writer makes `seq` odd while it updates the fields and even again when
+//! it's done, so a reader copies the fields between two reads of `seq` and
+//! retries if they differ or are odd. In C:
+//!
+//! ```text
+//! struct segment {
+//!     uint32_t magic;           /* 0x41435431, "ACT1" */
+//!     uint32_t version;         /* 1 */
+//!     uint64_t seq;
+//!     double   len[2];          /* meters, M1 then M2 */
+//!     uint64_t updated_ns[2];   /* CLOCK_MONOTONIC, 0 = never reported */
+//!     uint64_t count[2];        /* reports so far */
+//! };
+//! ```
+
+use std::{
+    ffi::CString,
+    io,
+    os::unix::ffi::OsStrExt,
+    path::Path,
+    sync::atomic::{AtomicU32, AtomicU64, Ordering, fence},
+};
+
+use crate::commands::Actuator;
+
+pub const DEFAULT_PATH: &str = "/dev/shm/actuator_controller";
+
+const MAGIC: u32 = 0x4143_5431;
+const VERSION: u32 = 1;
+
+#[repr(C)]
+pub struct Segment {
+    magic: AtomicU32,
+    version: AtomicU32,
+    seq: AtomicU64,
+    len: [AtomicU64; 2],
+    updated_ns: [AtomicU64; 2],
+    count: [AtomicU64; 2],
+}
+
+/// What a reader sees: the last length and report time for each actuator.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Snapshot {
+    pub len: [Option<f64>; 2],
+    /// `CLOCK_MONOTONIC` nanoseconds of the last report
+    pub updated_ns: [u64; 2],
+    pub count: [u64; 2],
+}
+
+struct Mapping {
+    segment: *mut Segment,
+}
+
+// Everything in the segment is atomic.
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
+impl Mapping {
+    fn open(path: &Path, write: bool) -> io::Result<Mapping> {
+        let c_path = CString::new(path.as_os_str().as_bytes()).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let size = size_of::<Segment>();
+        unsafe {
+            let flags = if write { libc::O_RDWR | libc::O_CREAT } else { libc::O_RDONLY };
+            let fd = libc::open(c_path.as_ptr(), flags | libc::O_CLOEXEC, 0o644);
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if write && libc::ftruncate(fd, size as libc::off_t) != 0 {
+                let e = io::Error::last_os_error();
+                libc::close(fd);
+                return Err(e);
+            }
+            let prot = if write { libc::PROT_READ | libc::PROT_WRITE } else { libc::PROT_READ };
+            let ptr = libc::mmap(std::ptr::null_mut(), size, prot, libc::MAP_SHARED, fd, 0);
+            libc::close(fd);
+            if ptr == libc::MAP_FAILED {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Mapping { segment: ptr.cast() })
+        }
+    }
+
+    fn segment(&self) -> &Segment {
+        unsafe { &*self.segment }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.segment.cast(), size_of::<Segment>()) };
+    }
+}
+
+fn monotonic_ns() -> u64 {
+    let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+    ts.tv_sec as u64 * 1_000_000_000 + ts.tv_nsec as u64
+}
+
+/// The single process that publishes into the segment.
+pub struct Writer {
+    mapping: Mapping,
+}
+
+impl Writer {
+    /// Creates (or takes over) the file at `path` and clears it.
+    pub fn create(path: &Path) -> io::Result<Writer> {
+        let mapping = Mapping::open(path, true)?;
+        let s = mapping.segment();
+        s.magic.store(0, Ordering::Relaxed);
+        s.seq.store(0, Ordering::Relaxed);
+        for i in 0..2 {
+            s.len[i].store(0, Ordering::Relaxed);
+            s.updated_ns[i].store(0, Ordering::Relaxed);
+            s.count[i].store(0, Ordering::Relaxed);
+        }
+        s.version.store(VERSION, Ordering::Relaxed);
+        s.magic.store(MAGIC, Ordering::Release);
+        Ok(Writer { mapping })
+    }
+
+    pub fn publish(&self, actuator: Actuator, len: f64) {
+        let s = self.mapping.segment();
+        let i = actuator as usize;
+        let seq = s.seq.load(Ordering::Relaxed);
+        s.seq.store(seq + 1, Ordering::Relaxed);
+        fence(Ordering::Release);
+        s.len[i].store(len.to_bits(), Ordering::Relaxed);
+        s.updated_ns[i].store(monotonic_ns(), Ordering::Relaxed);
+        s.count[i].fetch_add(1, Ordering::Relaxed);
+        s.seq.store(seq + 2, Ordering::Release);
+    }
+}
+
+pub struct Reader {
+    mapping: Mapping,
+}
+
+impl Reader {
+    pub fn open(path: &Path) -> io::Result<Reader> {
+        let mapping = Mapping::open(path, false)?;
+        let s = mapping.segment();
+        if s.magic.load(Ordering::Acquire) != MAGIC || s.version.load(Ordering::Relaxed) != VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not an actuator telemetry segment"));
+        }
+        Ok(Reader { mapping })
+    }
+
+    /// A consistent copy of the segment. Never blocks for longer than the
+    /// writer takes to update a handful of words.
+    pub fn read(&self) -> Snapshot {
+        let s = self.mapping.segment();
+        loop {
+            let before = s.seq.load(Ordering::Acquire);
+            if before & 1 == 1 {
+                std::hint::spin_loop();
+                continue;
+            }
+            let mut snapshot = Snapshot::default();
+            for i in 0..2 {
+                snapshot.count[i] = s.count[i].load(Ordering::Relaxed);
+                snapshot.updated_ns[i] = s.updated_ns[i].load(Ordering::Relaxed);
+                if snapshot.count[i] > 0 {
+                    snapshot.len[i] = Some(f64::from_bits(s.len[i].load(Ordering::Relaxed)));
+                }
+            }
+            fence(Ordering::Acquire);
+            if s.seq.load(Ordering::Relaxed) == before {
+                return snapshot;
+            }
+        }
+    }
+}
@@ -0,0 +1,90 @@
+//! Test signals for exciting the mechanism during system identification:
+//! a square, sine or triangle wave of speed per actuator, see `--excite`.
+//!
+//! A signal is written `<actuator>:<wave>:<amplitude>:<hz>`, e.g.
+//! `m1:square:40%:0.25`. The amplitude is a speed in the same form the
+//! [`dsl`](crate::dsl) takes, and every wave starts at zero heading forward.
+
+use std::{f64::consts::PI, fmt, time::Duration};
+
+use crate::{commands::Actuator, dsl};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Wave {
+    /// full speed one way for half a period, then the other: a step response
+    /// every half period
+    Square,
+    Sine,
+    Triangle,
+}
+
+impl Wave {
+    pub const NAMES: &[&str] = &["square", "sine", "triangle"];
+
+    pub fn parse(name: &str) -> Option<Wave> {
+        match name.to_ascii_lowercase().as_str() {
+            "square" => Some(Wave::Square),
+            "sine" => Some(Wave::Sine),
+            "triangle" => Some(Wave::Triangle),
+            _ => None,
+        }
+    }
+
+    /// The wave at `phase` periods in, between -1 and 1.
+    pub fn at(self, phase: f64) -> f64 {
+        let phase = phase.fract();
+        match self {
+            Wave::Square if phase < 0.5 => 1.0,
+            Wave::Square => -1.0,
+            Wave::Sine => (2.0 * PI * phase).sin(),
+            Wave::Triangle if phase < 0.25 => 4.0 * phase,
+            Wave::Triangle if phase < 0.75 => 2.0 - 4.0 * phase,
+            Wave::Triangle => 4.0 * phase - 4.0,
+        }
+    }
+}
+
+impl fmt::Display for Wave {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(Wave::NAMES[*self as usize])
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Signal {
+    pub actuator: Actuator,
+    pub wave: Wave,
+    /// peak speed either way
+    pub amplitude: u16,
+    pub frequency: f64,
+}
+
+impl Signal {
+    pub fn parse(spec: &str) -> Result<Signal, String> {
+        let parts: Vec<&str> = spec.split(':').collect();
+        let [actuator, wave, amplitude, frequency] = parts[..] else {
+            return Err(format!("expected <actuator>:<wave>:<amplitude>:<hz>, e.g. m1:square:40%:0.25, not `{spec}`"));
+        };
+        let actuator = dsl::parse_actuator(actuator)
+            .ok_or_else(|| format!("unknown actuator `{actuator}`; expected m1, m2, lift or bucket"))?;
+        let wave = Wave::parse(wave).ok_or_else(|| format!("unknown wave `{wave}`; expected {}", Wave::NAMES.join(", ")))?;
+        let amplitude = dsl::parse_speed(amplitude)?;
+        let frequency = match frequency.trim_end_matches("Hz").trim_end_matches("hz").parse::<f64>() {
+            Ok(hz) if hz > 0.0 && hz.is_finite() => hz,
+            _ => return Err(format!("frequency `{frequency}` must be a positive number of Hz")),
+        };
+        Ok(Signal { actuator, wave, amplitude, frequency })
+    }
+
+    /// The signed speed to drive at `elapsed` into the signal.
+    pub fn speed(&self, elapsed: Duration) -> i32 {
+        (self.wave.at(elapsed.as_secs_f64() * self.frequency) * self.amplitude as f64).round() as i32
+    }
+}
+
+impl fmt::Display for Signal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let actuator = format!("{:?}", self.actuator).to_lowercase();
+        write!(f, "{actuator}:{}:{}:{}", self.wave, self.amplitude, self.frequency)
+    }
+}
@@ -0,0 +1,519 @@
+//! A stand-in for the firmware, for `--sim`, that behaves like the actuators
+//! did in a recorded run.
+//!
+//! The run is an `--excite --output json` log, the same one `identify`
+//! reads. Each actuator's speed to velocity response is the first-order
+//! model with dead time [`identify`](crate::identify) fits to it, and each
+//! length report gets noise as large as the log's. Reports come as often as
+//! they did in the log and, like the real firmware, only for the actuator
+//! last commanded.
+//!
+//! Faults can be injected to rehearse handling them, either on a schedule
+//! from the config or as the run goes on, see [`Injection`].
+
+use std::{
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use tokio::{
+    sync::{Mutex, mpsc},
+    time::{MissedTickBehavior, interval},
+};
+
+use crate::{
+    commands::{Actuator, ActuatorCommand, Direction},
+    constraints::Constraints,
+    dsl,
+    identify::{self, Model},
+    link::{Ledger, Link, Outcome, Request},
+    status::StatusEvent,
+    stream::{self, Reports},
+    supervise::Supervisor,
+    telemetry::{Report, Schema},
+};
+
+/// Report period when the log has too few reports to tell.
+const DEFAULT_PERIOD: Duration = Duration::from_millis(50);
+
+/// How the actuators in a log behaved.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Profile {
+    /// indexed by `Actuator as usize`; one the log has no steps for borrows
+    /// the other's
+    pub models: [Model; 2],
+    /// standard deviation of a length report (m)
+    pub noise: [f64; 2],
+    /// time between reports
+    pub period: Duration,
+    /// first length reported (m), or 0 if none were
+    pub start: [f64; 2],
+}
+
+impl Profile {
+    pub fn from_log(src: &str) -> Result<Profile, String> {
+        let traces = identify::traces(src)?;
+        // which bounds the period and the dead time fitted below
+        let times = || traces.iter().flat_map(|trace| trace.drives.iter().map(|d| d.0).chain(trace.lengths.iter().map(|l| l.0)));
+        let (first, last) = (times().fold(f64::INFINITY, f64::min), times().fold(f64::NEG_INFINITY, f64::max));
+        if !Duration::try_from_secs_f64(last - first).is_ok_and(|span| span <= dsl::MAX_DURATION) {
+            return Err(format!("the log runs from t = {first} to {last}, more than {} h", dsl::MAX_DURATION.as_secs() / 3600));
+        }
+        let [m1, m2] = identify::identify(src)?.models.map(|(_, model)| model);
+        let models = match (m1, m2) {
+            (Some(m1), Some(m2)) => [m1, m2],
+            (Some(model), None) | (None, Some(model)) => [model; 2],
+            (None, None) => return Err(String::from("no step responses in the log to model the actuators on")),
+        };
+        let noise = traces.each_ref().map(|trace| noise(&trace.lengths));
+        let start = traces.each_ref().map(|trace| trace.lengths.first().map_or(0.0, |(_, len)| *len));
+        let mut times: Vec<f64> = traces.iter().flat_map(|trace| trace.lengths.iter().map(|(t, _)| *t)).collect();
+        times.sort_by(f64::total_cmp);
+        let mut gaps: Vec<f64> = times.windows(2).map(|w| w[1] - w[0]).filter(|gap| *gap > 0.0).collect();
+        let period = match median(&mut gaps) {
+            Some(gap) => Duration::from_secs_f64(gap),
+            None => DEFAULT_PERIOD,
+        };
+        Ok(Profile { models, noise, period, start })
+    }
+}
+
+/// Noise in `lengths`, from their second differences, which the motion
+/// barely shows up in: white noise of deviation σ gives differences of
+/// deviation σ√6. The median keeps the odd step change out of it.
+fn noise(lengths: &[(f64, f64)]) -> f64 {
+    let mut differences: Vec<f64> = lengths.windows(3).map(|w| (w[2].1 - 2.0 * w[1].1 + w[0].1).abs()).collect();
+    // median absolute deviation to standard deviation, for normal noise
+    median(&mut differences).map_or(0.0, |mad| 1.4826 * mad / 6f64.sqrt())
+}
+
+fn median(values: &mut [f64]) -> Option<f64> {
+    values.sort_by(f64::total_cmp);
+    values.get(values.len() / 2).copied()
+}
+
+/// Something wrong with the simulated hardware.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fault {
+    /// the sensor keeps reporting the length it read when the fault began
+    Stuck,
+    /// the motor doesn't turn, whatever it's sent
+    Stalled,
+    /// no reports at all
+    Dropout,
+    /// the reported length wanders off at this many m/s
+    Drift(f64),
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Fault::Stuck => f.write_str("stuck"),
+            Fault::Stalled => f.write_str("stall"),
+            Fault::Dropout => f.write_str("dropout"),
+            Fault::Drift(rate) => write!(f, "drift {rate}"),
+        }
+    }
+}
+
+/// A fault to start, or `clear` to end them, as in
+/// `[<actuator>] stuck|stall|dropout|drift <m/s>|clear [for <duration>]`.
+/// Without an actuator it's both; without a duration it lasts until cleared.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Injection {
+    pub actuator: Option<Actuator>,
+    /// `None` clears every fault on the actuator
+    pub fault: Option<Fault>,
+    pub duration: Option<Duration>,
+}
+
+impl Injection {
+    pub fn parse(text: &str) -> Result<Injection, String> {
+        let mut words = text.split_whitespace().peekable();
+        let actuator = words.peek().and_then(|word| dsl::parse_actuator(word));
+        if actuator.is_some() {
+            words.next();
+        }
+        let fault = match words.next() {
+            Some("stuck") => Some(Fault::Stuck),
+            Some("stall") => Some(Fault::Stalled),
+            Some("dropout") => Some(Fault::Dropout),
+            Some("drift") => match words.next().map(str::parse::<f64>) {
+                Some(Ok(rate)) if rate.is_finite() => Some(Fault::Drift(rate)),
+                _ => return Err(String::from("expected a rate in m/s after `drift`")),
+            },
+            Some("clear") => None,
+            Some(other) => return Err(format!("unknown fault `{other}`; expected stuck, stall, dropout, drift or clear")),
+            None => return Err(String::from("expected a fault: stuck, stall, dropout, drift or clear")),
+        };
+        let duration = match words.next() {
+            Some("for") if fault.is_some() => {
+                Some(dsl::parse_duration(words.next().ok_or("expected a duration after `for`")?)?)
+            }
+            None => None,
+            Some(word) => return Err(format!("unexpected `{word}`")),
+        };
+        if let Some(word) = words.next() {
+            return Err(format!("unexpected `{word}`"));
+        }
+        Ok(Injection { actuator, fault, duration })
+    }
+}
+
+impl fmt::Display for Injection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(actuator) = self.actuator {
+            write!(f, "{} ", format!("{actuator:?}").to_lowercase())?;
+        }
+        match self.fault {
+            Some(fault) => write!(f, "{fault}")?,
+            None => f.write_str("clear")?,
+        }
+        if let Some(duration) = self.duration {
+            write!(f, " for {}ms", duration.as_millis())?;
+        }
+        Ok(())
+    }
+}
+
+/// A fault in effect on one actuator.
+struct Active {
+    actuator: Actuator,
+    fault: Fault,
+    since: Instant,
+    until: Option<Instant>,
+    /// what the sensor read when it began, for `Stuck`
+    held: f64,
+}
+
+/// Starts simulating `profile`, with lengths kept within `0..=strokes` and
+/// `constraints` enforced as the serial writer would. Each of `schedule` is
+/// injected that long after the start; more can be sent on the returned
+/// channel.
+pub fn open(
+    profile: Profile,
+    strokes: [f64; 2],
+    constraints: Constraints,
+    schedule: Vec<(Duration, Injection)>,
+) -> (Link, mpsc::Sender<Injection>) {
+    let (faults_tx, faults_rx) = mpsc::channel::<Injection>(10);
+    let (tx, rx) = mpsc::channel::<Request>(100);
+    let (status_tx, status_rx) = mpsc::channel::<StatusEvent>(100);
+    let reports = Reports::new();
+    let source = stream::Source::of(&reports);
+    let (ledger, acks_rx) = Ledger::new();
+    let outcomes = ledger.outcomes();
+
+    let supervisor = Supervisor::new(status_tx.clone());
+    let sim = Arc::new(Mutex::new(Sim {
+        rx,
+        profile,
+        strokes,
+        constraints,
+        lengths: profile.start,
+        velocities: [0.0; 2],
+        headings: [Direction::Forward; 2],
+        magnitudes: [0; 2],
+        pending: Vec::new(),
+        stop_at: [None; 2],
+        speeds: [0; 2],
+        last_actuator: Actuator::M1,
+        faults: faults_rx,
+        start: Instant::now(),
+        schedule,
+        active: Vec::new(),
+        reported: profile.start,
+        seed: SystemTime::now().duration_since(UNIX_EPOCH).map_or(1, |d| d.as_nanos() as u64) | 1,
+    }));
+    let schema = Arc::new(Schema::default());
+    let writer = supervisor.spawn("sim", move || {
+        let sim = Arc::clone(&sim);
+        let (status_tx, reports, ledger, schema) = (status_tx.clone(), Arc::clone(&reports), ledger.clone(), Arc::clone(&schema));
+        async move {
+            let mut sim = sim.lock().await;
+            // so `select!` can borrow its two receivers at once
+            let sim = &mut *sim;
+            let mut ticks = interval(sim.profile.period);
+            ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            let mut last = Instant::now();
+            // every sender is gone, but a timed stop is still to come
+            let mut closed = false;
+            loop {
+                tokio::select! {
+                    request = sim.rx.recv(), if !closed => {
+                        let cmd = match request {
+                            Some(Request::Frame(cmd)) => cmd,
+                            Some(Request::StopAfter(actuator, after)) => {
+                                sim.stop_at[actuator as usize] = Some(Instant::now() + after);
+                                continue;
+                            }
+                            None if sim.stop_at.iter().any(Option::is_some) => {
+                                closed = true;
+                                continue;
+                            }
+                            None => return Ok(()),
+                        };
+                        let mut written = cmd;
+                        if let Some(event) = sim.command(cmd, Instant::now()) {
+                            if let StatusEvent::ConstraintStop(actuator, _) = event {
+                                written = ActuatorCommand::SetSpeed(0, actuator);
+                            }
+                            let _ = status_tx.send(event).await;
+                        }
+                        ledger.record(Outcome::Written);
+                        let _ = status_tx.send(StatusEvent::CommandAcked(written)).await;
+                    }
+                    Some(injection) = sim.faults.recv() => sim.inject(injection, Instant::now()),
+                    _ = ticks.tick() => {
+                        let now = Instant::now();
+                        sim.step(now, now.duration_since(last).as_secs_f64());
+                        last = now;
+                        for event in sim.stop_timed(now) {
+                            let _ = status_tx.send(event).await;
+                        }
+                        if closed && sim.stop_at.iter().all(Option::is_none) {
+                            return Ok(());
+                        }
+                        let actuator = sim.last_actuator;
+                        let Some(len) = sim.report(actuator, now) else { continue };
+                        for event in sim.enforce(now) {
+                            let _ = status_tx.send(event).await;
+                        }
+                        let values = vec![len];
+                        if !reports.publish(Report { actuator, schema: Arc::clone(&schema), values }) {
+                            // nobody's listening any more
+                            return Ok(());
+                        }
+                    }
+                }
+            }
+        }
+    });
+
+    let link = Link {
+        commands: tx,
+        status: status_rx,
+        telemetry: source.subscribe(),
+        acks: acks_rx,
+        outcomes,
+        health: supervisor.health(),
+        arming: None,
+        estop: None,
+        writer,
+        reports: source,
+        #[cfg(unix)]
+        _lock: None,
+    };
+    (link, faults_tx)
+}
+
+/// The simulated firmware and what it's driving.
+struct Sim {
+    rx: mpsc::Receiver<Request>,
+    profile: Profile,
+    strokes: [f64; 2],
+    constraints: Constraints,
+    lengths: [f64; 2],
+    /// m/s
+    velocities: [f64; 2],
+    /// as commanded, before the dead time
+    headings: [Direction; 2],
+    magnitudes: [u16; 2],
+    /// speeds waiting out the dead time: when they take effect, and for which actuator
+    pending: Vec<(Instant, Actuator, i32)>,
+    /// when each actuator is due to be stopped, as the link's writer keeps it
+    stop_at: [Option<Instant>; 2],
+    /// the speeds the motors are running at
+    speeds: [i32; 2],
+    last_actuator: Actuator,
+    faults: mpsc::Receiver<Injection>,
+    start: Instant,
+    /// injections still to come, and how long after `start`
+    schedule: Vec<(Duration, Injection)>,
+    active: Vec<Active>,
+    /// the last length each sensor read, faults and noise included
+    reported: [f64; 2],
+    /// xorshift state, never zero
+    seed: u64,
+}
+
+impl Sim {
+    /// Takes in a frame, returning what to report if a constraint stopped it.
+    fn command(&mut self, cmd: ActuatorCommand, now: Instant) -> Option<StatusEvent> {
+        let mut stopped = None;
+        let actuator = match cmd {
+            ActuatorCommand::SetSpeed(speed, actuator) => {
+                let i = actuator as usize;
+                let reported = self.reported.map(Some);
+                self.magnitudes[i] = match self.constraints.blocking(actuator, Some(self.headings[i]), reported) {
+                    Some(rule) if speed != 0 => {
+                        stopped = Some(StatusEvent::ConstraintStop(actuator, rule.to_string()));
+                        0
+                    }
+                    _ => speed,
+                };
+                actuator
+            }
+            ActuatorCommand::SetDirection(direction, actuator) => {
+                self.headings[actuator as usize] = direction;
+                actuator
+            }
+            // held perfectly: nothing moves it
+            ActuatorCommand::HoldPosition(actuator) => {
+                self.magnitudes[actuator as usize] = 0;
+                actuator
+            }
+        };
+        self.last_actuator = actuator;
+        let i = actuator as usize;
+        if !matches!(cmd, ActuatorCommand::SetDirection(..)) {
+            self.stop_at[i] = None;
+        }
+        let speed = self.headings[i].signed(self.magnitudes[i] as u32);
+        let delay = Duration::from_secs_f64(self.profile.models[i].dead_time);
+        self.pending.push((now + delay, actuator, speed));
+        stopped
+    }
+
+    /// Stops whichever actuator is moving the way a constraint says it
+    /// mustn't, given what the sensors last read.
+    fn enforce(&mut self, now: Instant) -> Vec<StatusEvent> {
+        let mut events = Vec::new();
+        for actuator in [Actuator::M1, Actuator::M2] {
+            let i = actuator as usize;
+            if self.magnitudes[i] == 0 {
+                continue;
+            }
+            let reported = self.reported.map(Some);
+            if let Some(rule) = self.constraints.blocking(actuator, Some(self.headings[i]), reported) {
+                events.push(StatusEvent::ConstraintStop(actuator, rule.to_string()));
+                self.command(ActuatorCommand::SetSpeed(0, actuator), now);
+            }
+        }
+        events
+    }
+
+    /// Stops whichever actuator's timed move is up, as the link's writer does.
+    fn stop_timed(&mut self, now: Instant) -> Vec<StatusEvent> {
+        let mut events = Vec::new();
+        for actuator in [Actuator::M1, Actuator::M2] {
+            let i = actuator as usize;
+            if self.stop_at[i].is_none_or(|at| at > now) {
+                continue;
+            }
+            if self.magnitudes[i] != 0 {
+                self.command(ActuatorCommand::SetSpeed(0, actuator), now);
+                events.push(StatusEvent::TimedStop(actuator));
+            }
+            self.stop_at[i] = None;
+        }
+        events
+    }
+
+    /// Starts or clears faults, replacing any of the same kind already on.
+    fn inject(&mut self, injection: Injection, now: Instant) {
+        let actuators = match injection.actuator {
+            Some(actuator) => vec![actuator],
+            None => vec![Actuator::M1, Actuator::M2],
+        };
+        for actuator in actuators {
+            let Some(fault) = injection.fault else {
+                self.active.retain(|active| active.actuator != actuator);
+                continue;
+            };
+            self.active.retain(|active| {
+                active.actuator != actuator || std::mem::discriminant(&active.fault) != std::mem::discriminant(&fault)
+            });
+            self.active.push(Active {
+                actuator,
+                fault,
+                since: now,
+                until: injection.duration.map(|duration| now + duration),
+                held: self.reported[actuator as usize],
+            });
+        }
+    }
+
+    fn faulty(&self, actuator: Actuator, fault: fn(&Fault) -> bool) -> Option<&Active> {
+        self.active.iter().find(|active| active.actuator == actuator && fault(&active.fault))
+    }
+
+    /// Moves both actuators on by `dt` seconds.
+    fn step(&mut self, now: Instant, dt: f64) {
+        let elapsed = now.duration_since(self.start);
+        while let Some(at) = self.schedule.iter().position(|(after, _)| *after <= elapsed) {
+            let (_, injection) = self.schedule.remove(at);
+            self.inject(injection, now);
+        }
+        self.active.retain(|active| active.until.is_none_or(|until| until > now));
+        self.pending.retain(|&(at, actuator, speed)| {
+            if at > now {
+                return true;
+            }
+            self.speeds[actuator as usize] = speed;
+            false
+        });
+        for i in 0..2 {
+            if self.faulty([Actuator::M1, Actuator::M2][i], |fault| *fault == Fault::Stalled).is_some() {
+                self.velocities[i] = 0.0;
+                continue;
+            }
+            let model = self.profile.models[i];
+            let target = model.gain * self.speeds[i] as f64;
+            self.velocities[i] += (target - self.velocities[i]) * (1.0 - (-dt / model.time_constant).exp());
+            let len = self.lengths[i] + self.velocities[i] * dt;
+            self.lengths[i] = len.clamp(0.0, self.strokes[i]);
+            if self.lengths[i] != len {
+                // against an end stop
+                self.velocities[i] = 0.0;
+            }
+        }
+    }
+
+    /// A length report for `actuator`, noise, faults and all, or `None`
+    /// while its reports are dropping out.
+    fn report(&mut self, actuator: Actuator, now: Instant) -> Option<f64> {
+        let i = actuator as usize;
+        if self.faulty(actuator, |fault| *fault == Fault::Dropout).is_some() {
+            return None;
+        }
+        if let Some(stuck) = self.faulty(actuator, |fault| *fault == Fault::Stuck) {
+            return Some(stuck.held);
+        }
+        let drift = match self.faulty(actuator, |fault| matches!(fault, Fault::Drift(_))) {
+            Some(&Active { fault: Fault::Drift(rate), since, .. }) => rate * now.duration_since(since).as_secs_f64(),
+            _ => 0.0,
+        };
+        let len = self.lengths[i] + drift + self.profile.noise[i] * self.gaussian();
+        self.reported[i] = len;
+        Some(len)
+    }
+
+    /// A standard normal sample, by Box-Muller.
+    fn gaussian(&mut self) -> f64 {
+        let mut uniform = || {
+            self.seed ^= self.seed << 13;
+            self.seed ^= self.seed >> 7;
+            self.seed ^= self.seed << 17;
+            // (0, 1], so the log below is finite
+            ((self.seed >> 11) + 1) as f64 / (1u64 << 53) as f64
+        };
+        let (u, v) = (uniform(), uniform());
+        (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn malformed_logs() {
+        let drive = r#"{"t": 0, "event": "drive", "actuator": "m1", "speed": 100}"#;
+        let telemetry = |t: &str| format!(r#"{{"t": {t}, "event": "telemetry", "actuator": "m1", "len": 0.1}}"#);
+        for log in [format!("{drive}\n{}", telemetry("1e300")), format!("{}\n{drive}", telemetry("-1e300")), String::new(), String::from("[")] {
+            assert!(Profile::from_log(&log).is_err(), "{log:?} loaded");
+        }
+    }
+}
@@ -0,0 +1,58 @@
+//! Keeping a pair of lift actuators level.
+//!
+//! Skew is M1's length minus M2's. When correction is on, mirror mode speeds
+//! up whichever actuator is lagging and slows the one that's ahead.
+
+use crate::commands::Direction;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SkewMonitor {
+    /// whether M1 and M2 are a mechanically paired lift at all
+    pub enabled: bool,
+    /// skew (m) above which the operator is warned
+    pub warn: f64,
+    /// apply a corrective bias in mirror mode
+    pub correct: bool,
+    /// speed units of bias per meter of skew
+    pub gain: f64,
+    pub max_correction: i32,
+}
+
+impl Default for SkewMonitor {
+    fn default() -> Self {
+        SkewMonitor {
+            enabled: false,
+            warn: 0.01,
+            correct: false,
+            gain: 200_000.0,
+            max_correction: 10000,
+        }
+    }
+}
+
+/// Corrections only change in steps of this much.
+const CORRECTION_STEP: i32 = 500;
+
+impl SkewMonitor {
+    pub fn skew(&self, lengths: [Option<f64>; 2]) -> Option<f64> {
+        match lengths {
+            [Some(m1), Some(m2)] if self.enabled => Some(m1 - m2),
+            _ => None,
+        }
+    }
+
+    pub fn is_excessive(&self, skew: f64) -> bool {
+        skew.abs() > self.warn
+    }
+
+    /// Speed offset (added to M1, taken from M2) that pulls the pair back
+    /// level while both move in `direction`.
+    pub fn correction(&self, skew: f64, direction: Direction) -> i32 {
+        if !self.correct {
+            return 0;
+        }
+        let sign = if direction == Direction::Forward { 1.0 } else { -1.0 };
+        let bias = (-skew * sign * self.gain) as i32;
+        (bias / CORRECTION_STEP * CORRECTION_STEP).clamp(-self.max_correction, self.max_correction)
+    }
+}
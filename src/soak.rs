@@ -0,0 +1,113 @@
+//! `soak`: the burn-in before a competition. Runs `[soak] cycle` over and
+//! over for `hours` (or `--duration`), watching each actuator's temperature,
+//! current and how repeatably its moves settle (see
+//! [`actuator_controller::health`]), halts on the first anomaly, and writes
+//! the health report. Progress goes to stderr.
+
+use std::{
+    cell::RefCell,
+    time::{Duration, Instant, SystemTime},
+};
+
+use actuator_controller::{
+    app::App,
+    audit::{clock, timestamp},
+    client::{ActuatorClient, Update},
+    config::Config,
+    dsl::Command,
+    health::{Health, SoakConfig},
+    link::Link,
+};
+use tokio::sync::Notify;
+
+/// Runs `[startup]`, then the cycle until `duration` is up, an anomaly, or
+/// Ctrl-C, then stops both actuators and writes the report. Returns the
+/// process exit code: 1 unless every cycle ran clean.
+pub async fn run(mut link: Link, mut app: App, config: &Config, duration: Duration, timeout: Duration) -> i32 {
+    if let Some(channel) = &mut link.arming {
+        app.sync_arming(channel);
+    }
+    if app.arming.required() {
+        eprintln!("error: two-person arming is on, and a soak test can't wait for a second operator");
+        return 1;
+    }
+    let limits = SoakConfig { duration, ..config.soak.clone() };
+    let cycle: Vec<String> = limits.cycle.iter().map(ToString::to_string).collect();
+    app.audit.record(&format!("soak for {} cycling {}", clock(duration), cycle.join("; ")));
+    let mut client = ActuatorClient::new(link, app);
+    client.set_timeout(timeout);
+
+    let title = format!("Soak test, {}", timestamp(SystemTime::now()));
+    let start = Instant::now();
+    let health = RefCell::new(Health::new(&limits, start));
+    let halt = Notify::new();
+    // `step` of the cycle, or `None` outside it, where settles don't count
+    let observe = |step: Option<usize>| {
+        let (health, halt) = (&health, &halt);
+        move |update: Update| {
+            let mut health = health.borrow_mut();
+            match update {
+                Update::Telemetry(report) => health.observe(report, Instant::now()),
+                Update::Status(status) if status.is_error() => health.fail(format!("status: {status}")),
+                Update::Settle(settle) => {
+                    if let Some(step) = step {
+                        health.settled(step, settle);
+                    }
+                }
+                _ => {}
+            }
+            if health.anomaly().is_some() {
+                halt.notify_one();
+            }
+        }
+    };
+
+    let mut stopped = None;
+    'soak: {
+        for cmd in &config.startup {
+            if let Err(e) = client.run_observed(cmd.clone(), observe(None)).await {
+                health.borrow_mut().fail(format!("startup `{cmd}` failed: {e}"));
+                break 'soak;
+            }
+        }
+        while start.elapsed() < duration {
+            for (step, cmd) in limits.cycle.iter().enumerate() {
+                let result = tokio::select! {
+                    result = client.run_observed(cmd.clone(), observe(Some(step))) => result,
+                    _ = halt.notified() => break 'soak,
+                    _ = tokio::signal::ctrl_c() => {
+                        stopped = Some(String::from("Ctrl-C"));
+                        break 'soak;
+                    }
+                };
+                if let Err(e) = result {
+                    health.borrow_mut().fail(format!("`{cmd}` failed: {e}"));
+                }
+                if health.borrow().anomaly().is_some() {
+                    break 'soak;
+                }
+            }
+            let mut health = health.borrow_mut();
+            health.cycle_done(Instant::now());
+            eprintln!("cycle {} done at {} of {}", health.cycles(), clock(start.elapsed()), clock(duration));
+        }
+    }
+
+    // whatever was running when it halted
+    if let Err(e) = client.run(Command::Stop(None)).await {
+        eprintln!("error: stop: {e}");
+    }
+    client.close().await;
+    let mut health = health.into_inner();
+    health.finish(Instant::now(), stopped);
+    eprintln!("{}", health.verdict());
+    let code = if health.passed() { 0 } else { 1 };
+    match std::fs::write(&limits.report, health.report(&title)) {
+        Ok(()) => println!("{}", limits.report.display()),
+        Err(e) => {
+            eprintln!("Couldn't write {}: {e}", limits.report.display());
+            return 1;
+        }
+    }
+    code
+}
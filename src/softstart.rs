@@ -0,0 +1,38 @@
+//! Starting an actuator gently from rest, enforced by the serial writer
+//! alongside the [interlock](crate::interlock).
+//!
+//! A motor started at speed draws a surge of current, and two at once can
+//! brown out a battery bus they share with everything else. With a soft start
+//! set, a `SetSpeed` that gets a stopped actuator moving goes out at no more
+//! than `speed`, and the speed asked for only follows once `time` has passed.
+//! The interlock stops an actuator before it reverses, so every reversal
+//! starts from rest too.
+
+use std::time::Duration;
+
+use crate::commands::ActuatorCommand;
+
+/// How long a soft start lasts, unless the config says otherwise.
+pub const DEFAULT_TIME: Duration = Duration::from_millis(300);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SoftStart {
+    /// fastest an actuator may be started at
+    pub speed: u16,
+    /// how long it's kept there before the speed asked for goes out
+    pub time: Duration,
+}
+
+impl SoftStart {
+    /// What to write in place of `cmd` for an actuator that's `moving` or
+    /// not, and the speed to follow it with after `time`, if `cmd` would
+    /// start it faster than `speed`.
+    pub fn split(&self, cmd: ActuatorCommand, moving: bool) -> Option<(ActuatorCommand, u16)> {
+        match cmd {
+            ActuatorCommand::SetSpeed(speed, actuator) if !moving && speed > self.speed => {
+                Some((ActuatorCommand::SetSpeed(self.speed, actuator), speed))
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,145 @@
+//! The check on connecting that both actuators are stopped before any input
+//! is taken, for firmware that picks its last speed up again after a reset.
+//!
+//! Both are sent a stop, then each in turn is watched until its lengths over
+//! `window` show it standing still: a line fitted through them slopes by
+//! less than `speed`. The firmware only reports the actuator it was last
+//! sent something, so the one being watched is always sent its stop last.
+//! One that hasn't shown it's still after `timeout` is sent the stop again
+//! and the operator is told, and input stays off until it does.
+
+use std::time::{Duration, Instant};
+
+use crate::commands::Actuator;
+
+/// Reports needed over the window before a slope means anything.
+const MIN_REPORTS: usize = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StandstillConfig {
+    pub enabled: bool,
+    /// how long each actuator has to be seen still
+    pub window: Duration,
+    /// fastest an actuator can be creeping (m/s) and still count as stopped
+    pub speed: f64,
+    /// how long to wait for it before stopping it again
+    pub timeout: Duration,
+}
+
+impl Default for StandstillConfig {
+    fn default() -> Self {
+        StandstillConfig {
+            enabled: true,
+            window: Duration::from_millis(500),
+            speed: 0.002,
+            timeout: Duration::from_secs(3),
+        }
+    }
+}
+
+/// What came of the check at a tick.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Step {
+    /// `actuator` was seen still; the check goes on to `next`, which has to
+    /// be sent a stop so that it reports, or is done with `None`
+    Still { actuator: Actuator, next: Option<Actuator> },
+    /// `actuator` wasn't seen still within the timeout, going at `speed`
+    /// (m/s) if it reported at all, and has to be sent the stop again
+    Timeout { actuator: Actuator, speed: Option<f64> },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Watch {
+    actuator: Actuator,
+    /// when its stop was sent, or sent again
+    since: Instant,
+    /// its lengths since, with when they came
+    lengths: Vec<(Instant, f64)>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Standstill {
+    pub config: StandstillConfig,
+    watching: Option<Watch>,
+    /// whether the frontend has started it, so reconnecting starts it again
+    started: bool,
+}
+
+impl Standstill {
+    pub fn new(config: StandstillConfig) -> Standstill {
+        Standstill { config, watching: None, started: false }
+    }
+
+    /// Starts the check over if it's enabled, giving the order to send the
+    /// stops in: the first is watched once the other's stop has gone out.
+    pub fn start(&mut self, now: Instant) -> Option<[Actuator; 2]> {
+        if !self.config.enabled {
+            return None;
+        }
+        self.started = true;
+        self.watching = Some(Watch { actuator: Actuator::M1, since: now, lengths: Vec::new() });
+        Some([Actuator::M2, Actuator::M1])
+    }
+
+    pub fn has_started(&self) -> bool {
+        self.started
+    }
+
+    /// The actuator being watched, while input is off.
+    pub fn checking(&self) -> Option<Actuator> {
+        self.watching.as_ref().map(|watch| watch.actuator)
+    }
+
+    pub fn is_checking(&self) -> bool {
+        self.watching.is_some()
+    }
+
+    /// Takes in a reported `length` of `actuator`.
+    pub fn observe(&mut self, actuator: Actuator, length: f64, now: Instant) {
+        if let Some(watch) = &mut self.watching
+            && watch.actuator == actuator
+            && length.is_finite()
+        {
+            watch.lengths.push((now, length));
+        }
+    }
+
+    pub fn tick(&mut self, now: Instant) -> Option<Step> {
+        let window = self.config.window;
+        let watch = self.watching.as_mut()?;
+        // only the latest window says whether it's still now
+        watch.lengths.retain(|(at, _)| now.duration_since(*at) <= window);
+        let speed = slope(&watch.lengths);
+        let seen = watch.lengths.len() >= MIN_REPORTS && now.duration_since(watch.since) >= window;
+        if seen && speed.is_some_and(|speed| speed.abs() <= self.config.speed) {
+            let actuator = watch.actuator;
+            let next = match actuator {
+                Actuator::M1 => Some(Actuator::M2),
+                Actuator::M2 => None,
+            };
+            self.watching = next.map(|actuator| Watch { actuator, since: now, lengths: Vec::new() });
+            return Some(Step::Still { actuator, next });
+        }
+        if now.duration_since(watch.since) >= self.config.timeout {
+            watch.since = now;
+            let speed = (watch.lengths.len() >= MIN_REPORTS).then_some(speed).flatten();
+            return Some(Step::Timeout { actuator: watch.actuator, speed });
+        }
+        None
+    }
+}
+
+/// The slope (m/s) of the least-squares line through `lengths`.
+fn slope(lengths: &[(Instant, f64)]) -> Option<f64> {
+    let (start, _) = *lengths.first()?;
+    let n = lengths.len() as f64;
+    let points: Vec<(f64, f64)> = lengths.iter().map(|(at, len)| (at.duration_since(start).as_secs_f64(), *len)).collect();
+    let mean_t = points.iter().map(|(t, _)| t).sum::<f64>() / n;
+    let mean_len = points.iter().map(|(_, len)| len).sum::<f64>() / n;
+    let spread = points.iter().map(|(t, _)| (t - mean_t).powi(2)).sum::<f64>();
+    // reports taken all at once say nothing about speed
+    if spread == 0.0 {
+        return None;
+    }
+    Some(points.iter().map(|(t, len)| (t - mean_t) * (len - mean_len)).sum::<f64>() / spread)
+}
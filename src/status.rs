@@ -0,0 +1,189 @@
+//! What a link reports as it goes, as typed events rather than text, so the
+//! UI, the logs, the bridges and anything raising alarms can tell a written
+//! frame from a serial error from a hung link.
+//!
+//! Each event displays as the status line it replaces. A daemon relays them
+//! to its clients with [`StatusEvent::encode`] and [`StatusEvent::decode`].
+
+use std::{fmt, time::Duration};
+
+use crate::commands::{Actuator, ActuatorCommand, Direction};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum StatusEvent {
+    /// a frame was written to the port
+    CommandAcked(ActuatorCommand),
+    /// a frame couldn't be written
+    SerialError(String),
+    /// a stop was written ahead of a reversal, see [`crate::interlock`]
+    InterlockStop(Actuator),
+    /// a stop was written in place of a move that would break a rule, see
+    /// [`crate::constraints`]
+    ConstraintStop(Actuator, String),
+    /// a stop was written because a timed move's time was up, see
+    /// [`crate::link::Request::StopAfter`]
+    TimedStop(Actuator),
+    /// speeds are now scaled by this because of the current drawn (A), see
+    /// [`crate::budget`]
+    BudgetScaled(f64, f64),
+    /// something needs the operator's attention
+    FaultRaised(Fault),
+    /// the watchdog reopened the port
+    Reconnected,
+    /// a background task that had failed was started again, see [`crate::supervise`]
+    TaskRestarted(String),
+    /// the connection to the daemon closed
+    Disconnected,
+    /// a line of debug text from the firmware, see [`crate::telemetry::DEBUG_TEXT`]
+    FirmwareLog(String),
+    /// anything else, e.g. a daemon's reply to a request
+    Message(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Fault {
+    /// nothing could be written for this long while an actuator was moving
+    LinkHung(Duration),
+    /// the watchdog couldn't reopen the port
+    ReopenFailed(String),
+    /// the watchdog couldn't stop this actuator after recovering
+    StopFailed(Actuator, String),
+    /// a background task panicked or gave up, for this reason
+    TaskFailed(String, String),
+}
+
+impl StatusEvent {
+    /// Whether this is something going wrong rather than routine progress.
+    pub fn is_error(&self) -> bool {
+        matches!(self, StatusEvent::SerialError(_) | StatusEvent::FaultRaised(_) | StatusEvent::Disconnected)
+    }
+
+    /// Short name of the kind of event, for JSON output and the daemon protocol.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            StatusEvent::CommandAcked(_) => "acked",
+            StatusEvent::SerialError(_) => "serial_error",
+            StatusEvent::InterlockStop(_) => "interlock_stop",
+            StatusEvent::ConstraintStop(..) => "constraint_stop",
+            StatusEvent::TimedStop(_) => "timed_stop",
+            StatusEvent::BudgetScaled(..) => "budget_scaled",
+            StatusEvent::FaultRaised(Fault::LinkHung(_)) => "link_hung",
+            StatusEvent::FaultRaised(Fault::ReopenFailed(_)) => "reopen_failed",
+            StatusEvent::FaultRaised(Fault::StopFailed(..)) => "stop_failed",
+            StatusEvent::FaultRaised(Fault::TaskFailed(..)) => "task_failed",
+            StatusEvent::Reconnected => "reconnected",
+            StatusEvent::TaskRestarted(_) => "task_restarted",
+            StatusEvent::Disconnected => "disconnected",
+            StatusEvent::FirmwareLog(_) => "firmware_log",
+            StatusEvent::Message(_) => "message",
+        }
+    }
+
+    /// One line, `<kind> <fields...>`, with any free text last.
+    pub fn encode(&self) -> String {
+        let one_line = |s: &str| s.replace('\n', " ");
+        match self {
+            StatusEvent::CommandAcked(cmd) => {
+                let [b0, b1, b2, b3] = cmd.serialize();
+                format!("{} {b0} {b1} {b2} {b3}", self.kind())
+            }
+            StatusEvent::SerialError(e) | StatusEvent::FaultRaised(Fault::ReopenFailed(e)) => {
+                format!("{} {}", self.kind(), one_line(e))
+            }
+            StatusEvent::InterlockStop(actuator) | StatusEvent::TimedStop(actuator) => format!("{} {}", self.kind(), *actuator as u8),
+            StatusEvent::ConstraintStop(actuator, rule) => format!("{} {} {}", self.kind(), *actuator as u8, one_line(rule)),
+            StatusEvent::BudgetScaled(scale, draw) => format!("{} {scale} {draw}", self.kind()),
+            StatusEvent::FaultRaised(Fault::LinkHung(waited)) => format!("{} {}", self.kind(), waited.as_millis()),
+            StatusEvent::FaultRaised(Fault::StopFailed(actuator, e)) => {
+                format!("{} {} {}", self.kind(), *actuator as u8, one_line(e))
+            }
+            StatusEvent::FaultRaised(Fault::TaskFailed(task, e)) => format!("{} {task} {}", self.kind(), one_line(e)),
+            StatusEvent::TaskRestarted(task) => format!("{} {task}", self.kind()),
+            StatusEvent::Reconnected | StatusEvent::Disconnected => self.kind().to_string(),
+            StatusEvent::FirmwareLog(text) | StatusEvent::Message(text) => format!("{} {}", self.kind(), one_line(text)),
+        }
+    }
+
+    /// Reverse of [`encode`](StatusEvent::encode).
+    pub fn decode(line: &str) -> Result<StatusEvent, String> {
+        let (kind, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let actuator = |s: &str| s.parse::<u8>().ok().and_then(|a| Actuator::try_from(a).ok());
+        let event = match kind {
+            "acked" => {
+                let bytes: Vec<u8> = rest.split_whitespace().filter_map(|w| w.parse().ok()).collect();
+                let bytes = <[u8; 4]>::try_from(bytes).map_err(|_| "expected 4 bytes".to_string())?;
+                StatusEvent::CommandAcked(ActuatorCommand::deserialize(bytes)?)
+            }
+            "serial_error" => StatusEvent::SerialError(rest.to_string()),
+            "interlock_stop" => StatusEvent::InterlockStop(actuator(rest).ok_or("bad actuator")?),
+            "timed_stop" => StatusEvent::TimedStop(actuator(rest).ok_or("bad actuator")?),
+            "constraint_stop" => {
+                let (a, rule) = rest.split_once(' ').unwrap_or((rest, ""));
+                StatusEvent::ConstraintStop(actuator(a).ok_or("bad actuator")?, rule.to_string())
+            }
+            "budget_scaled" => {
+                let number = |s: Option<&str>| s.and_then(|s| s.parse().ok()).ok_or_else(|| format!("bad budget `{rest}`"));
+                let mut words = rest.split_whitespace();
+                StatusEvent::BudgetScaled(number(words.next())?, number(words.next())?)
+            }
+            "link_hung" => {
+                let ms = rest.parse().map_err(|_| format!("bad duration `{rest}`"))?;
+                StatusEvent::FaultRaised(Fault::LinkHung(Duration::from_millis(ms)))
+            }
+            "reopen_failed" => StatusEvent::FaultRaised(Fault::ReopenFailed(rest.to_string())),
+            "stop_failed" => {
+                let (a, e) = rest.split_once(' ').unwrap_or((rest, ""));
+                StatusEvent::FaultRaised(Fault::StopFailed(actuator(a).ok_or("bad actuator")?, e.to_string()))
+            }
+            "task_failed" => {
+                let (task, e) = rest.split_once(' ').unwrap_or((rest, ""));
+                StatusEvent::FaultRaised(Fault::TaskFailed(task.to_string(), e.to_string()))
+            }
+            "reconnected" => StatusEvent::Reconnected,
+            "task_restarted" => StatusEvent::TaskRestarted(rest.to_string()),
+            "disconnected" => StatusEvent::Disconnected,
+            "firmware_log" => StatusEvent::FirmwareLog(rest.to_string()),
+            "message" => StatusEvent::Message(rest.to_string()),
+            _ => return Err(format!("unknown status event `{kind}`")),
+        };
+        Ok(event)
+    }
+}
+
+impl fmt::Display for StatusEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StatusEvent::CommandAcked(ActuatorCommand::SetSpeed(speed, _)) => write!(f, "Set speed to {speed}"),
+            StatusEvent::CommandAcked(ActuatorCommand::SetDirection(dir, _)) => {
+                let dir_str = if *dir == Direction::Forward { "forward" } else { "backward" };
+                write!(f, "Set direction to {dir_str}")
+            }
+            StatusEvent::CommandAcked(ActuatorCommand::HoldPosition(actuator)) => write!(f, "Holding {actuator:?}"),
+            StatusEvent::SerialError(e) => write!(f, "Serial error: {e}"),
+            StatusEvent::InterlockStop(actuator) => write!(f, "Stopped {actuator:?} before reversing"),
+            StatusEvent::ConstraintStop(actuator, rule) => write!(f, "Stopped {actuator:?}: {rule}"),
+            StatusEvent::TimedStop(actuator) => write!(f, "Stopped {actuator:?}: its time was up"),
+            StatusEvent::BudgetScaled(scale, draw) if *scale < 1.0 => {
+                write!(f, "Drawing {draw:.1} A, over the power budget: speeds scaled to {:.0}%", scale * 100.0)
+            }
+            StatusEvent::BudgetScaled(_, draw) => write!(f, "Drawing {draw:.1} A, within the power budget: full speeds"),
+            StatusEvent::FaultRaised(fault) => write!(f, "{fault}"),
+            StatusEvent::Reconnected => write!(f, "Reopened the port"),
+            StatusEvent::TaskRestarted(task) => write!(f, "Restarted the {task} task"),
+            StatusEvent::Disconnected => write!(f, "Daemon connection closed"),
+            StatusEvent::FirmwareLog(text) => write!(f, "Firmware: {text}"),
+            StatusEvent::Message(msg) => write!(f, "{msg}"),
+        }
+    }
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Fault::LinkHung(waited) => write!(f, "Watchdog: nothing written for {waited:?} while moving, reopening the port"),
+            Fault::ReopenFailed(e) => write!(f, "Watchdog: couldn't reopen the port: {e}"),
+            Fault::StopFailed(actuator, e) => write!(f, "Watchdog: couldn't stop {actuator:?}: {e}"),
+            Fault::TaskFailed(task, e) => write!(f, "The {task} task failed ({e}), restarting it"),
+        }
+    }
+}
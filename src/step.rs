@@ -0,0 +1,150 @@
+//! `frames`: prints a `--capture` (or a raw dump of the port) a frame at a
+//! time, as the link's reader decoded it, with what looks wrong about each
+//! (see `actuator_controller::frames`). With `--step` it waits after each
+//! frame: Enter for the next, `w` for the next that looks wrong, `c` to run
+//! to the end and `q` to stop.
+
+use std::io::{self, BufRead, Write};
+
+use actuator_controller::{
+    capture,
+    config::Config,
+    frames::{Decoder, Frame, Kind},
+    telemetry::Schema,
+};
+
+/// How far past either end of the stroke a length can be and still look right (m).
+const SLACK: f64 = 0.01;
+
+pub fn run(words: &[String]) -> Result<(), String> {
+    let mut path = None;
+    let mut fields = None;
+    let mut step = false;
+    let mut words = words.iter();
+    while let Some(word) = words.next() {
+        match word.as_str() {
+            "--fields" => fields = Some(words.next().ok_or("--fields needs a list, e.g. len:m,current:A")?),
+            "--step" => step = true,
+            _ if path.is_none() && !word.starts_with("--") => path = Some(word),
+            _ => return Err(format!("unexpected `{word}`")),
+        }
+    }
+    let path = path.ok_or("frames needs the path of a --capture, or a raw dump of the port")?;
+    let schema = match fields {
+        Some(fields) => Schema::parse(fields).map_err(|e| format!("--fields: {e}"))?,
+        None => Schema::default(),
+    };
+    let config = Config::load_default().map_err(|e| format!("Couldn't load config: {e}"))?;
+    let stroke = config.linkage.lift.max_stroke.max(config.linkage.bucket.max_stroke);
+    let data = std::fs::read(path).map_err(|e| format!("{path}: {e}"))?;
+    let entries = capture::parse(&data).map_err(|e| format!("{path}: {e}"))?;
+    let decoder = Decoder::new(&entries, schema, config.encoding, -SLACK..=stroke + SLACK);
+    println!("{} bytes of {}, starting with fields {}", decoder.len(), config.encoding, decoder.schema());
+    let width = config.encoding.width();
+
+    let mut counts = Counts::default();
+    // whether to stop after the next frame, or only after one that looks wrong
+    let mut pause = step.then_some(false);
+    let stdin = io::stdin();
+    for (i, frame) in decoder.enumerate() {
+        print(i, &frame, width);
+        counts.add(&frame);
+        let Some(only_wrong) = pause else { continue };
+        if only_wrong && frame.warnings.is_empty() {
+            continue;
+        }
+        print!("(Enter: next | w: next that looks wrong | c: to the end | q: quit) ");
+        let _ = io::stdout().flush();
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).map_err(|e| e.to_string())? == 0 {
+            break;
+        }
+        match line.trim() {
+            "q" => break,
+            "c" => pause = None,
+            "w" => pause = Some(true),
+            _ => pause = Some(false),
+        }
+    }
+    println!("{counts}");
+    Ok(())
+}
+
+fn print(i: usize, frame: &Frame, width: usize) {
+    let at = frame.at.map(|t| format!("  {t:.3} s")).unwrap_or_default();
+    let path: Vec<String> = frame.path.iter().map(ToString::to_string).collect();
+    let what = match &frame.kind {
+        Kind::Report(..) => "report",
+        Kind::Fields(_) => "field list",
+        Kind::Text(_) => "debug text",
+        Kind::Dropped => "dropped",
+        Kind::Reopened => "reopened",
+        Kind::Incomplete => "incomplete",
+    };
+    println!("#{i} {:#06x}{at}  {what}  {}", frame.offset, path.join(" -> "));
+    // a value to a group, as the reader takes them
+    if !frame.bytes.is_empty() {
+        let groups: Vec<String> = frame.bytes.chunks(width).map(capture::hex).collect();
+        println!("    {}", groups.join(" "));
+    }
+    match &frame.kind {
+        Kind::Report(schema, values) => {
+            let decoded: Vec<String> = schema.fields().iter().zip(values).map(|(field, value)| format!("{field}: {}", number(*value))).collect();
+            println!("    {}", decoded.join(", "));
+        }
+        Kind::Fields(Ok(schema)) => println!("    {schema}"),
+        Kind::Fields(Err(_)) => {}
+        Kind::Text(text) => println!("    {text}"),
+        Kind::Dropped => println!("    the reader came up short and threw these {} bytes away", frame.bytes.len()),
+        Kind::Reopened => println!("    the watchdog reopened the port"),
+        Kind::Incomplete => println!("    the capture ends partway through"),
+    }
+    for warning in &frame.warnings {
+        println!("    ! {warning}");
+    }
+}
+
+/// Short enough to read whatever the bytes came to.
+fn number(value: f64) -> String {
+    if value == 0.0 || (1e-4..1e6).contains(&value.abs()) {
+        format!("{value:.6}")
+    } else {
+        format!("{value:.3e}")
+    }
+}
+
+#[derive(Default)]
+struct Counts {
+    frames: usize,
+    reports: usize,
+    wrong: usize,
+    fields: usize,
+    texts: usize,
+    dropped: usize,
+    reopened: usize,
+}
+
+impl Counts {
+    fn add(&mut self, frame: &Frame) {
+        self.frames += 1;
+        self.wrong += !frame.warnings.is_empty() as usize;
+        match frame.kind {
+            Kind::Report(..) => self.reports += 1,
+            Kind::Fields(_) => self.fields += 1,
+            Kind::Text(_) => self.texts += 1,
+            Kind::Dropped => self.dropped += 1,
+            Kind::Reopened => self.reopened += 1,
+            Kind::Incomplete => {}
+        }
+    }
+}
+
+impl std::fmt::Display for Counts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} frames: {} reports, {} field lists, {} debug text, {} dropped, {} reopens; {} look wrong",
+            self.frames, self.reports, self.fields, self.texts, self.dropped, self.reopened, self.wrong
+        )
+    }
+}
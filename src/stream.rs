@@ -0,0 +1,116 @@
+//! Telemetry for as many consumers as want it, each with a [`Subscription`]
+//! of its own from [`Link::subscribe`](crate::link::Link::subscribe), so a
+//! logger, a bridge and the control loop can all read every report without
+//! one draining them from the others.
+//!
+//! Reports are passed through any [derived channels](crate::channels) once,
+//! as they're read, and handed to every subscription. A subscriber that
+//! falls more than [`BACKLOG`] reports behind misses the oldest rather than
+//! holding up the reader, and can tell how many with
+//! [`Subscription::missed`]. For only the latest report of each actuator,
+//! [`Link::latest`](crate::link::Link::latest) is a `watch` of them.
+
+use std::sync::{Arc, Mutex, Weak};
+
+use tokio::sync::{
+    broadcast::{self, error::RecvError, error::TryRecvError},
+    watch,
+};
+
+use crate::{channels::Channels, telemetry::Report};
+
+/// How many reports a subscriber can fall behind by before it misses some.
+pub const BACKLOG: usize = 64;
+
+/// Where whatever reads reports publishes them. The reader holds the only
+/// strong reference, so subscriptions end once it's gone.
+pub(crate) struct Reports {
+    tx: broadcast::Sender<Report>,
+    latest: watch::Sender<[Option<Report>; 2]>,
+    channels: Mutex<Channels>,
+}
+
+impl Reports {
+    pub(crate) fn new() -> Arc<Reports> {
+        Arc::new(Reports {
+            tx: broadcast::channel(BACKLOG).0,
+            latest: watch::channel([None, None]).0,
+            channels: Mutex::new(Channels::default()),
+        })
+    }
+
+    /// Hands `report` to every subscription, returning whether there's
+    /// anyone left to hand it to.
+    pub(crate) fn publish(&self, report: Report) -> bool {
+        let report = self.channels.lock().unwrap().apply(report);
+        self.latest.send_modify(|latest| latest[report.actuator as usize] = Some(report.clone()));
+        self.tx.send(report).is_ok() || self.latest.receiver_count() > 0
+    }
+}
+
+/// A link's side of [`Reports`], which doesn't keep them going.
+#[derive(Clone)]
+pub(crate) struct Source(Weak<Reports>);
+
+impl Source {
+    pub(crate) fn of(reports: &Arc<Reports>) -> Source {
+        Source(Arc::downgrade(reports))
+    }
+
+    pub(crate) fn subscribe(&self) -> Subscription {
+        let rx = match self.0.upgrade() {
+            Some(reports) => reports.tx.subscribe(),
+            // the reader's gone, so this one's over before it starts
+            None => broadcast::channel(1).1,
+        };
+        Subscription { rx, missed: 0 }
+    }
+
+    pub(crate) fn latest(&self) -> watch::Receiver<[Option<Report>; 2]> {
+        match self.0.upgrade() {
+            Some(reports) => reports.latest.subscribe(),
+            None => watch::channel([None, None]).1,
+        }
+    }
+
+    pub(crate) fn derive(&self, channels: Channels) {
+        if let Some(reports) = self.0.upgrade() {
+            *reports.channels.lock().unwrap() = channels;
+        }
+    }
+}
+
+/// One subscriber's reports, in the order they were read.
+pub struct Subscription {
+    rx: broadcast::Receiver<Report>,
+    missed: u64,
+}
+
+impl Subscription {
+    /// Waits for the next report. `None` once the port is gone.
+    pub async fn recv(&mut self) -> Option<Report> {
+        loop {
+            match self.rx.recv().await {
+                Ok(report) => return Some(report),
+                Err(RecvError::Lagged(n)) => self.missed += n,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    /// The next report, if one has come in since the last.
+    pub fn try_recv(&mut self) -> Option<Report> {
+        loop {
+            match self.rx.try_recv() {
+                Ok(report) => return Some(report),
+                Err(TryRecvError::Lagged(n)) => self.missed += n,
+                Err(TryRecvError::Empty | TryRecvError::Closed) => return None,
+            }
+        }
+    }
+
+    /// How many reports this subscriber missed by falling too far behind.
+    pub fn missed(&self) -> u64 {
+        self.missed
+    }
+}
@@ -0,0 +1,105 @@
+//! Keeping a link's background tasks alive.
+//!
+//! Each task runs under a supervisor that watches for it panicking or giving
+//! up with an error, reports that as a fault, and starts it again after a
+//! backoff that grows with every failure in a row. A task that finishes with
+//! `Ok` is done on purpose and stays stopped.
+
+use std::{
+    any::Any,
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    sync::{mpsc, watch},
+    task::JoinHandle,
+    time::sleep,
+};
+
+use crate::status::{Fault, StatusEvent};
+
+/// Wait before the first restart, doubled for every failure in a row.
+const FIRST_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+/// A task that ran this long before failing starts over from `FIRST_BACKOFF`.
+const STABLE: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TaskState {
+    Running,
+    /// failed for this reason, waiting to be restarted
+    Restarting(String),
+    /// finished on purpose
+    Done,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TaskHealth {
+    pub name: &'static str,
+    pub state: TaskState,
+    pub restarts: u32,
+}
+
+pub struct Supervisor {
+    health: watch::Sender<Vec<TaskHealth>>,
+    status: mpsc::Sender<StatusEvent>,
+}
+
+impl Supervisor {
+    /// Failures and restarts are reported on `status` as well as in [`health`](Supervisor::health).
+    pub fn new(status: mpsc::Sender<StatusEvent>) -> Supervisor {
+        Supervisor { health: watch::channel(Vec::new()).0, status }
+    }
+
+    pub fn health(&self) -> watch::Receiver<Vec<TaskHealth>> {
+        self.health.subscribe()
+    }
+
+    /// Runs the task `start` makes, making a fresh one whenever it fails.
+    /// The handle finishes once a run returns `Ok`.
+    pub fn spawn<F, Fut>(&self, name: &'static str, mut start: F) -> JoinHandle<()>
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<(), String>> + Send + 'static,
+    {
+        let health = self.health.clone();
+        let status = self.status.clone();
+        let index = health.borrow().len();
+        health.send_modify(|tasks| tasks.push(TaskHealth { name, state: TaskState::Running, restarts: 0 }));
+        tokio::spawn(async move {
+            let mut backoff = FIRST_BACKOFF;
+            loop {
+                let started = Instant::now();
+                let reason = match tokio::spawn(start()).await {
+                    Ok(Ok(())) => break,
+                    Ok(Err(e)) => e,
+                    Err(e) if e.is_panic() => format!("panicked: {}", panic_message(e.into_panic())),
+                    // only happens when the runtime is shutting down
+                    Err(_) => break,
+                };
+                if started.elapsed() >= STABLE {
+                    backoff = FIRST_BACKOFF;
+                }
+                health.send_modify(|tasks| tasks[index].state = TaskState::Restarting(reason.clone()));
+                let fault = Fault::TaskFailed(name.to_string(), reason);
+                let _ = status.send(StatusEvent::FaultRaised(fault)).await;
+                sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+                health.send_modify(|tasks| {
+                    tasks[index].state = TaskState::Running;
+                    tasks[index].restarts += 1;
+                });
+                let _ = status.send(StatusEvent::TaskRestarted(name.to_string())).await;
+            }
+            health.send_modify(|tasks| tasks[index].state = TaskState::Done);
+        })
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(msg) => *msg,
+        Err(payload) => payload.downcast_ref::<&str>().map_or("unknown panic", |msg| msg).to_string(),
+    }
+}
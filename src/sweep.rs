@@ -0,0 +1,95 @@
+//! `--sweep`: a swept-sine test of one actuator, printing its frequency
+//! response as CSV on stdout (see [`actuator_controller::bode`]) and how far
+//! along it is on stderr.
+
+use std::{
+    f64::consts::PI,
+    time::{Duration, Instant},
+};
+
+use actuator_controller::{
+    app::App,
+    bode::{self, Point, Sweep, SweepConfig},
+    commands::Drive,
+    link::Link,
+    update::Msg,
+};
+use tokio::time::{interval, MissedTickBehavior};
+
+use crate::excite::SAMPLE;
+
+/// Runs every frequency of `sweep` in turn, or until Ctrl-C, then stops the
+/// actuator. Returns the process exit code.
+pub async fn run(link: &mut Link, mut app: App, sweep: Sweep, config: SweepConfig) -> i32 {
+    if let Some(channel) = &mut link.arming {
+        app.sync_arming(channel);
+    }
+    if app.arming.required() {
+        eprintln!("error: two-person arming is on, and a sweep can't wait for a second operator");
+        return 1;
+    }
+    let actuator = sweep.actuator;
+    let frequencies = sweep.frequencies(config.points);
+    app.audit.record(&format!(
+        "sweep {actuator:?} at {} from {} Hz to {} Hz",
+        sweep.amplitude, sweep.from, sweep.to
+    ));
+
+    println!("{}", bode::CSV_HEADER);
+    let mut ticks = interval(SAMPLE);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let mut failures = 0;
+    'sweep: for (n, &hz) in frequencies.iter().enumerate() {
+        eprintln!("{actuator:?} at {hz:.3} Hz ({}/{})", n + 1, frequencies.len());
+        let settle = 1.0 / hz;
+        let length = Duration::from_secs_f64((1 + config.cycles) as f64 / hz);
+        let start = Instant::now();
+        let mut samples = Vec::new();
+        let mut last = None;
+        loop {
+            tokio::select! {
+                _ = ticks.tick() => {}
+                Some(report) = link.telemetry.recv() => {
+                    let t = start.elapsed().as_secs_f64();
+                    if report.actuator == actuator && t >= settle {
+                        samples.push((t, report.length()));
+                    }
+                    app.update(Msg::Telemetry(report));
+                    continue;
+                }
+                Some(status) = link.status.recv() => {
+                    if status.is_error() {
+                        failures += 1;
+                        eprintln!("status: {status}");
+                    }
+                    app.update(Msg::Status(status));
+                    continue;
+                }
+                _ = tokio::signal::ctrl_c() => break 'sweep,
+                else => break 'sweep,
+            }
+            let elapsed = start.elapsed();
+            if elapsed >= length {
+                break;
+            }
+            let speed = ((2.0 * PI * hz * elapsed.as_secs_f64()).sin() * sweep.amplitude as f64).round() as i32;
+            if last != Some(speed) {
+                last = Some(speed);
+                app.pending.push(Drive::new(actuator, speed));
+                app.flush(&link.commands).await;
+            }
+        }
+        match Point::fit(hz, sweep.amplitude, &samples) {
+            Some(point) => println!("{}", point.csv()),
+            None => {
+                failures += 1;
+                eprintln!("error: only {} reports from {actuator:?} at {hz:.3} Hz, too few to fit", samples.len());
+            }
+        }
+    }
+
+    app.pending.push(Drive::stop(actuator));
+    app.flush(&link.commands).await;
+    app.audit.record("sweep done");
+    if failures > 0 { 1 } else { 0 }
+}
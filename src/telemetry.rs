@@ -0,0 +1,326 @@
+//! What the firmware reports, and thinning it out for consumers that don't
+//! need every report.
+//!
+//! Firmware that only knows about lengths sends each report as one `f64`.
+//! Firmware with more to say first advertises its fields: the 8 bytes of
+//! [`HANDSHAKE`], then a [`Schema`] as text (`len:m,current:A,temp:C`) ending
+//! in a newline. Every report after that is one `f64` per field, in that
+//! order, so the UI, the logs and the bridges pick up new fields without
+//! any change here. The first field is always the length (m), which is what
+//! closed-loop control runs on.
+//!
+//! Between reports the firmware can send a line of debug text the same way:
+//! the 8 bytes of [`DEBUG_TEXT`], then the text, ending in a newline. It
+//! goes to the firmware log rather than being read as telemetry, so
+//! printf-debugging needs no second UART.
+//!
+//! Older boards send each value some other way, e.g. as a 4-byte big-endian
+//! count of tenths of a millimetre. [`Encoding`] says how, from the
+//! `[telemetry]` config, and is what every field is decoded with. Only
+//! 8-byte encodings can carry the handshake, so a narrower one means the
+//! firmware sends lengths alone.
+//!
+//! The firmware can report at hundreds of Hz. Logging (`--monitor`,
+//! `--stdin`, `--shm` and the daemon) and closed-loop control keep every
+//! report, while the terminal UI and the ZeroMQ PUB socket go through a
+//! [`Decimator`], which passes on the mean of each actuator's reports over a
+//! period so a noisy stream is smoothed rather than aliased.
+
+use std::{
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::commands::Actuator;
+
+/// Sent by the firmware ahead of its field list. As an `f64` it's around
+/// 1e93, so it can't be mistaken for a length.
+pub const HANDSHAKE: [u8; 8] = *b"ACFIELDS";
+
+/// Sent by the firmware ahead of a line of debug text. As an `f64` it's
+/// around 6e30, so it can't be mistaken for a length either.
+pub const DEBUG_TEXT: [u8; 8] = *b"ACPRINTF";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Field {
+    pub name: String,
+    /// empty for a unitless field
+    pub unit: String,
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.unit.as_str() {
+            "" => write!(f, "{}", self.name),
+            unit => write!(f, "{} ({unit})", self.name),
+        }
+    }
+}
+
+/// The fields of each report, length first.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Schema {
+    fields: Vec<Field>,
+}
+
+impl Default for Schema {
+    /// What firmware that never advertises its fields sends.
+    fn default() -> Schema {
+        Schema { fields: vec![Field { name: String::from("len"), unit: String::from("m") }] }
+    }
+}
+
+impl Schema {
+    /// Reads `name:unit,name:unit,...`, where the unit may be left off.
+    pub fn parse(text: &str) -> Result<Schema, String> {
+        let mut fields: Vec<Field> = Vec::new();
+        for item in text.trim().split(',') {
+            let (name, unit) = item.split_once(':').unwrap_or((item, ""));
+            let (name, unit) = (name.trim(), unit.trim());
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(format!("bad field name `{name}`"));
+            }
+            if unit.contains([',', ':']) || unit.contains(char::is_whitespace) {
+                return Err(format!("bad unit `{unit}` for {name}"));
+            }
+            // these already mean something in `--output json`
+            if ["event", "t", "actuator"].contains(&name) {
+                return Err(format!("{name} is reserved"));
+            }
+            if fields.iter().any(|f| f.name == name) {
+                return Err(format!("{name} is listed twice"));
+            }
+            fields.push(Field { name: name.to_string(), unit: unit.to_string() });
+        }
+        if fields[0].name != "len" {
+            return Err(format!("the first field has to be len, not {}", fields[0].name));
+        }
+        Ok(Schema { fields })
+    }
+
+    /// Reverse of [`parse`](Schema::parse).
+    pub fn encode(&self) -> String {
+        let item = |f: &Field| if f.unit.is_empty() { f.name.clone() } else { format!("{}:{}", f.name, f.unit) };
+        self.fields.iter().map(item).collect::<Vec<_>>().join(",")
+    }
+
+    pub fn fields(&self) -> &[Field] {
+        &self.fields
+    }
+
+    /// This schema with `more` after its own fields, see [`crate::channels`].
+    pub fn extended(&self, more: impl IntoIterator<Item = Field>) -> Schema {
+        Schema { fields: self.fields.iter().cloned().chain(more).collect() }
+    }
+}
+
+impl fmt::Display for Schema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fields: Vec<String> = self.fields.iter().map(Field::to_string).collect();
+        write!(f, "{}", fields.join(", "))
+    }
+}
+
+/// How wide each value is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Number {
+    F64,
+    F32,
+    I32,
+    U32,
+    I16,
+    U16,
+}
+
+/// How the firmware puts each value of a report into bytes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Encoding {
+    pub number: Number,
+    pub big_endian: bool,
+    /// what each value is multiplied by, e.g. 0.0001 for tenths of a millimetre
+    pub scale: f64,
+}
+
+impl Default for Encoding {
+    /// What the current firmware sends.
+    fn default() -> Encoding {
+        Encoding { number: Number::F64, big_endian: false, scale: 1.0 }
+    }
+}
+
+impl Encoding {
+    /// The names `[telemetry] encoding` takes, widest first.
+    pub const NAMES: [&str; 12] =
+        ["f64le", "f64be", "f32le", "f32be", "i32le", "i32be", "u32le", "u32be", "i16le", "i16be", "u16le", "u16be"];
+
+    /// Reads a name such as `i32be`: the kind of number, then `le` or `be`.
+    pub fn parse(name: &str, scale: f64) -> Result<Encoding, String> {
+        let expected = || format!("unknown encoding `{name}`; expected one of {}", Encoding::NAMES.join(", "));
+        let (number, order) = name.split_at_checked(name.len().saturating_sub(2)).ok_or_else(expected)?;
+        let number = match number {
+            "f64" => Number::F64,
+            "f32" => Number::F32,
+            "i32" => Number::I32,
+            "u32" => Number::U32,
+            "i16" => Number::I16,
+            "u16" => Number::U16,
+            _ => return Err(expected()),
+        };
+        let big_endian = match order {
+            "le" => false,
+            "be" => true,
+            _ => return Err(expected()),
+        };
+        if scale == 0.0 || !scale.is_finite() {
+            return Err(format!("the scale must be a nonzero number, not {scale}"));
+        }
+        Ok(Encoding { number, big_endian, scale })
+    }
+
+    /// Bytes per value.
+    pub fn width(&self) -> usize {
+        match self.number {
+            Number::F64 => 8,
+            Number::F32 | Number::I32 | Number::U32 => 4,
+            Number::I16 | Number::U16 => 2,
+        }
+    }
+
+    /// Whether the firmware can advertise its fields in this encoding.
+    pub fn has_handshake(&self) -> bool {
+        self.width() == HANDSHAKE.len()
+    }
+
+    /// The value in `bytes`, which are [`width`](Encoding::width) long.
+    pub fn decode(&self, bytes: &[u8]) -> f64 {
+        macro_rules! number {
+            ($t:ty) => {{
+                let bytes = bytes.try_into().expect("one value's bytes");
+                (if self.big_endian { <$t>::from_be_bytes(bytes) } else { <$t>::from_le_bytes(bytes) }) as f64
+            }};
+        }
+        let value = match self.number {
+            Number::F64 => number!(f64),
+            Number::F32 => number!(f32),
+            Number::I32 => number!(i32),
+            Number::U32 => number!(u32),
+            Number::I16 => number!(i16),
+            Number::U16 => number!(u16),
+        };
+        value * self.scale
+    }
+
+    /// The values in `bytes`, one per [`width`](Encoding::width).
+    pub fn decode_all(&self, bytes: &[u8]) -> Vec<f64> {
+        bytes.chunks_exact(self.width()).map(|b| self.decode(b)).collect()
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let number = format!("{:?}", self.number).to_lowercase();
+        write!(f, "{number}{}", if self.big_endian { "be" } else { "le" })?;
+        if self.scale != 1.0 {
+            write!(f, " x {}", self.scale)?;
+        }
+        Ok(())
+    }
+}
+
+/// One report from the firmware.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Report {
+    pub actuator: Actuator,
+    pub schema: Arc<Schema>,
+    /// one per field of `schema`
+    pub values: Vec<f64>,
+}
+
+impl Report {
+    /// The length (m), which every schema starts with.
+    pub fn length(&self) -> f64 {
+        self.values[0]
+    }
+
+    /// The field named `current`, if the firmware reports one.
+    pub fn current(&self) -> Option<f64> {
+        self.field("current")
+    }
+
+    /// The field called `name`, if the firmware reports one.
+    pub fn field(&self, name: &str) -> Option<f64> {
+        self.extras().find(|(field, _)| field.name == name).map(|(_, value)| value)
+    }
+
+    /// Every field after the length.
+    pub fn extras(&self) -> impl Iterator<Item = (&Field, f64)> {
+        self.schema.fields.iter().zip(self.values.iter().copied()).skip(1)
+    }
+}
+
+pub const DEFAULT_DISPLAY_RATE: f64 = 20.0;
+pub const DEFAULT_PUBLISH_RATE: f64 = 50.0;
+
+/// Reports per second per actuator; `None` passes every report on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rates {
+    /// what the terminal UI shows
+    pub display: Option<f64>,
+    /// what `--zmq-pub` publishes
+    pub publish: Option<f64>,
+}
+
+impl Default for Rates {
+    fn default() -> Rates {
+        Rates { display: Some(DEFAULT_DISPLAY_RATE), publish: Some(DEFAULT_PUBLISH_RATE) }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct Window {
+    /// when the last mean was passed on
+    emitted: Option<Instant>,
+    /// what `sums` are for; a new schema starts the window over
+    schema: Option<Arc<Schema>>,
+    sums: Vec<f64>,
+    count: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct Decimator {
+    period: Option<Duration>,
+    /// indexed by `Actuator as usize`
+    windows: [Window; 2],
+}
+
+impl Decimator {
+    pub fn new(rate: Option<f64>) -> Decimator {
+        Decimator { period: rate.map(|hz| Duration::from_secs_f64(1.0 / hz)), windows: Default::default() }
+    }
+
+    /// Takes a report that arrived at `now`. Returns the mean of each field
+    /// over the reports since the last one passed on, once a period has gone
+    /// by; the first report is passed on straight away.
+    pub fn push(&mut self, report: &Report, now: Instant) -> Option<Report> {
+        let Some(period) = self.period else { return Some(report.clone()) };
+        let window = &mut self.windows[report.actuator as usize];
+        if window.schema.as_ref() != Some(&report.schema) {
+            window.schema = Some(Arc::clone(&report.schema));
+            window.sums = vec![0.0; report.values.len()];
+            window.count = 0;
+        }
+        for (sum, value) in window.sums.iter_mut().zip(&report.values) {
+            *sum += value;
+        }
+        window.count += 1;
+        if window.emitted.is_some_and(|at| now.duration_since(at) < period) {
+            return None;
+        }
+        let values = window.sums.iter().map(|sum| sum / window.count as f64).collect();
+        window.sums.fill(0.0);
+        window.count = 0;
+        window.emitted = Some(now);
+        Some(Report { actuator: report.actuator, schema: Arc::clone(&report.schema), values })
+    }
+}
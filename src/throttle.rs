@@ -0,0 +1,44 @@
+//! Caps on how often commands go out, so a runaway script can't flood the
+//! serial link.
+//!
+//! The serial writer sends each actuator at most `actuator` speeds a second,
+//! whoever queued them; one that comes too soon waits for its turn, and if
+//! a newer speed for the same actuator turns up meanwhile, only the newer
+//! one is sent (counted in [`crate::link::Acks::coalesced`]). The ZeroMQ
+//! bridge does the same for each connected client at `client` commands a
+//! second. Stops are never held back.
+
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RateLimits {
+    /// speeds per second for each actuator; `None` for no limit
+    pub actuator: Option<f64>,
+    /// commands per second from each bridge client; `None` for no limit
+    pub client: Option<f64>,
+}
+
+/// When the next command from one source may go.
+#[derive(Clone, Copy, Debug)]
+pub struct Throttle {
+    interval: Duration,
+    last: Option<Instant>,
+}
+
+impl Throttle {
+    /// At most `rate` a second; `None` lets everything through at once.
+    pub fn new(rate: Option<f64>) -> Throttle {
+        Throttle { interval: rate.map_or(Duration::ZERO, |hz| Duration::from_secs_f64(1.0 / hz)), last: None }
+    }
+
+    /// When the next command may go, if it has to wait past `now`.
+    pub fn wait_until(&self, now: Instant) -> Option<Instant> {
+        let next = self.last? + self.interval;
+        (next > now).then_some(next)
+    }
+
+    /// Notes a command that went at `now`.
+    pub fn sent(&mut self, now: Instant) {
+        self.last = Some(now);
+    }
+}
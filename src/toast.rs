@@ -0,0 +1,98 @@
+//! Notifications for the operator: each is shown in a corner of the screen
+//! for a while, longer the worse it is, and kept in a log they can look back
+//! through, so a message isn't lost when the next one replaces it in the
+//! status line.
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// How many notifications the log keeps, oldest forgotten first.
+pub const LOG_CAPACITY: usize = 200;
+
+/// How many are shown at once, newest at the top.
+pub const MAX_SHOWN: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Info,
+    Warn,
+    Error,
+}
+
+impl Severity {
+    /// How long a notification stays on screen.
+    pub fn timeout(self) -> Duration {
+        Duration::from_secs(match self {
+            Severity::Info => 3,
+            Severity::Warn => 6,
+            Severity::Error => 10,
+        })
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Info => "info",
+            Severity::Warn => "warn",
+            Severity::Error => "error",
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Toast {
+    pub severity: Severity,
+    pub text: String,
+    /// when it was last raised, for the timeout
+    pub at: Instant,
+    /// the same, for the log
+    pub time: SystemTime,
+    /// how many times in a row it was raised while still shown
+    pub count: u32,
+}
+
+impl Toast {
+    pub fn is_shown(&self, now: Instant) -> bool {
+        now.duration_since(self.at) < self.severity.timeout()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Toasts {
+    log: VecDeque<Toast>,
+}
+
+impl Toasts {
+    /// Raises a notification. One just like the last, while that's still
+    /// shown, counts again instead of stacking up.
+    pub fn push(&mut self, severity: Severity, text: String, now: Instant) {
+        if let Some(last) = self.log.back_mut()
+            && last.severity == severity
+            && last.text == text
+            && last.is_shown(now)
+        {
+            last.count += 1;
+            last.at = now;
+            last.time = SystemTime::now();
+            return;
+        }
+        if self.log.len() == LOG_CAPACITY {
+            self.log.pop_front();
+        }
+        self.log.push_back(Toast { severity, text, at: now, time: SystemTime::now(), count: 1 });
+    }
+
+    /// The ones to draw at `now`, newest first.
+    pub fn shown(&self, now: Instant) -> impl Iterator<Item = &Toast> {
+        self.log.iter().rev().filter(move |toast| toast.is_shown(now)).take(MAX_SHOWN)
+    }
+
+    /// Every notification still kept, newest first.
+    pub fn log(&self) -> impl Iterator<Item = &Toast> {
+        self.log.iter().rev()
+    }
+}
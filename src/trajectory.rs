@@ -0,0 +1,107 @@
+//! Trajectories planned offline: timestamped target lengths, read from CSV
+//! and followed through the position controller (`follow <path>`).
+//!
+//! ```text
+//! t,lift,bucket
+//! 0,0.10,0.05
+//! 0.5,0.12,
+//! 1.0,0.15,0.06
+//! ```
+//!
+//! The first column is seconds from the start and has to go up; the others
+//! are lengths in meters for the actuators named in the header (`m1`,
+//! `lift`, `m2` or `bucket`). A blank cell just means no point for that
+//! actuator at that time. Between points the target moves in a straight
+//! line; an actuator is left alone until its first point and held at its
+//! last.
+
+use crate::{commands::Actuator, dsl};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trajectory {
+    /// (seconds, m) for each actuator, indexed by `Actuator as usize`
+    paths: [Vec<(f64, f64)>; 2],
+}
+
+impl Trajectory {
+    pub fn parse(src: &str) -> Result<Trajectory, String> {
+        let mut lines = src.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+        let Some((_, header)) = lines.next() else {
+            return Err(String::from("empty trajectory; expected a header like `t,lift,bucket`"));
+        };
+        let mut columns = header.split(',').map(str::trim);
+        if !matches!(columns.next(), Some("t" | "time")) {
+            return Err(format!("the first column has to be `t`, the time in seconds, not `{header}`"));
+        }
+        let mut actuators = Vec::new();
+        for name in columns {
+            let actuator = dsl::parse_actuator(name)
+                .ok_or_else(|| format!("unknown actuator column `{name}`; expected m1, m2, lift or bucket"))?;
+            if actuators.contains(&actuator) {
+                return Err(format!("{actuator:?} has two columns"));
+            }
+            actuators.push(actuator);
+        }
+        if actuators.is_empty() {
+            return Err(String::from("no actuator columns after `t`"));
+        }
+
+        let mut paths: [Vec<(f64, f64)>; 2] = Default::default();
+        let mut last = None;
+        for (n, line) in lines {
+            let n = n + 1;
+            let cells: Vec<&str> = line.split(',').map(str::trim).collect();
+            if cells.len() > actuators.len() + 1 {
+                return Err(format!("line {n}: {} cells, but the header only has {}", cells.len(), actuators.len() + 1));
+            }
+            let number = |cell: &str| cell.parse::<f64>().ok().filter(|x| x.is_finite());
+            let t = number(cells[0]).ok_or_else(|| format!("line {n}: `{}` isn't a time in seconds", cells[0]))?;
+            if t < 0.0 || last.is_some_and(|last| t <= last) {
+                return Err(format!("line {n}: times have to start at 0 or later and go up, but {t} doesn't"));
+            }
+            last = Some(t);
+            for (&actuator, cell) in actuators.iter().zip(&cells[1..]) {
+                if cell.is_empty() {
+                    continue;
+                }
+                let len = number(cell).ok_or_else(|| format!("line {n}: `{cell}` isn't a length in meters"))?;
+                paths[actuator as usize].push((t, len));
+            }
+        }
+        if paths.iter().all(Vec::is_empty) {
+            return Err(String::from("no points after the header"));
+        }
+        Ok(Trajectory { paths })
+    }
+
+    /// Seconds until the last point.
+    pub fn duration(&self) -> f64 {
+        self.paths.iter().filter_map(|path| path.last().map(|p| p.0)).fold(0.0, f64::max)
+    }
+
+    /// The target lengths `t` seconds in, `None` for an actuator that has no
+    /// point yet.
+    pub fn at(&self, t: f64) -> [Option<f64>; 2] {
+        self.paths.each_ref().map(|path| {
+            let after = path.iter().position(|&(at, _)| at >= t);
+            match after {
+                None => path.last().map(|p| p.1),
+                Some(0) => (path[0].0 == t).then_some(path[0].1),
+                Some(i) => {
+                    let ((t0, len0), (t1, len1)) = (path[i - 1], path[i]);
+                    Some(len0 + (len1 - len0) * (t - t0) / (t1 - t0))
+                }
+            }
+        })
+    }
+
+    /// `actuator`'s points, as (seconds, m).
+    pub fn path(&self, actuator: Actuator) -> &[(f64, f64)] {
+        &self.paths[actuator as usize]
+    }
+
+    /// The first point of `actuator`'s that isn't within `0..=stroke`.
+    pub fn outside(&self, actuator: Actuator, stroke: f64) -> Option<(f64, f64)> {
+        self.paths[actuator as usize].iter().copied().find(|(_, len)| !(0.0..=stroke).contains(len))
+    }
+}
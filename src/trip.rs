@@ -0,0 +1,150 @@
+//! A trip meter for each run: how far each actuator travelled, the most
+//! current it drew, how often it stalled and how long it sat at a limit,
+//! summed up when the operator ends the run.
+//!
+//! Current comes from a firmware field named `current`, see
+//! [`crate::telemetry`]; without one the summary leaves it out.
+
+use std::{
+    fmt,
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{audit, commands::Actuator, telemetry::Report};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TripConfig {
+    /// how long an actuator has to be driven without getting anywhere to count as stalled
+    pub stall_time: Duration,
+    /// movement (m) within `stall_time` that still counts as getting nowhere
+    pub stall_distance: f64,
+    /// how close (m) to either end of the stroke counts as at the limit
+    pub limit_margin: f64,
+}
+
+impl Default for TripConfig {
+    fn default() -> TripConfig {
+        TripConfig {
+            stall_time: Duration::from_millis(500),
+            stall_distance: 0.001,
+            limit_margin: 0.005,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct Meter {
+    /// total distance moved either way (m)
+    travel: f64,
+    max_current: Option<(f64, String)>,
+    stalls: u32,
+    at_limit: Duration,
+    last: Option<(Instant, f64)>,
+    /// when and where the actuator was last seen getting somewhere while driven
+    progress: Option<(Instant, f64)>,
+    stalled: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct Trip {
+    config: TripConfig,
+    /// full stroke of each actuator (m), indexed by `Actuator as usize`
+    strokes: [f64; 2],
+    started: SystemTime,
+    meters: [Meter; 2],
+}
+
+impl Trip {
+    pub fn new(config: TripConfig, strokes: [f64; 2]) -> Trip {
+        Trip { config, strokes, started: SystemTime::now(), meters: Default::default() }
+    }
+
+    /// Takes a report that arrived at `now`, while `speed` was what the
+    /// actuator was last driven at.
+    pub fn report(&mut self, report: &Report, speed: i32, now: Instant) {
+        let stroke = self.strokes[report.actuator as usize];
+        let margin = self.config.limit_margin;
+        let meter = &mut self.meters[report.actuator as usize];
+        let len = report.length();
+
+        if let Some((at, last)) = meter.last {
+            meter.travel += (len - last).abs();
+            if len <= margin || len >= stroke - margin {
+                meter.at_limit += now.duration_since(at);
+            }
+        }
+        meter.last = Some((now, len));
+
+        if let Some((field, current)) = report.extras().find(|(field, _)| field.name == "current")
+            && meter.max_current.as_ref().is_none_or(|(max, _)| current.abs() > *max)
+        {
+            meter.max_current = Some((current.abs(), field.unit.clone()));
+        }
+
+        match meter.progress {
+            _ if speed == 0 => {
+                meter.progress = None;
+                meter.stalled = false;
+            }
+            Some((_, from)) if (len - from).abs() > self.config.stall_distance => {
+                meter.progress = Some((now, len));
+                meter.stalled = false;
+            }
+            Some((since, _)) if !meter.stalled && now.duration_since(since) >= self.config.stall_time => {
+                meter.stalls += 1;
+                meter.stalled = true;
+            }
+            Some(_) => {}
+            None => meter.progress = Some((now, len)),
+        }
+    }
+
+    /// Everything since the run started, up to now.
+    pub fn summary(&self, operator: &str) -> Summary {
+        let ended = SystemTime::now();
+        Summary {
+            operator: operator.to_string(),
+            started: self.started,
+            duration: ended.duration_since(self.started).unwrap_or_default(),
+            travel: self.meters.each_ref().map(|m| m.travel),
+            max_current: self.meters.each_ref().map(|m| m.max_current.clone()),
+            stalls: self.meters.each_ref().map(|m| m.stalls),
+            at_limit: self.meters.each_ref().map(|m| m.at_limit),
+        }
+    }
+}
+
+/// One run, indexed by `Actuator as usize`. Displays as the report printed
+/// and appended to `--trip-log`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Summary {
+    pub operator: String,
+    pub started: SystemTime,
+    pub duration: Duration,
+    pub travel: [f64; 2],
+    /// largest current seen and its unit, if the firmware reports one
+    pub max_current: [Option<(f64, String)>; 2],
+    pub stalls: [u32; 2],
+    pub at_limit: [Duration; 2],
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let started = audit::timestamp(self.started);
+        writeln!(f, "Run started {started} by {}, {:.1} s", self.operator, self.duration.as_secs_f64())?;
+        writeln!(f, "{:<16} {:>10} {:>10}", "", format!("{:?}", Actuator::M1), format!("{:?}", Actuator::M2))?;
+        let row = |f: &mut fmt::Formatter<'_>, name: &str, cells: [String; 2]| {
+            writeln!(f, "{name:<16} {:>10} {:>10}", cells[0], cells[1])
+        };
+        row(f, "travel (m)", self.travel.map(|t| format!("{t:.3}")))?;
+        if self.max_current.iter().any(Option::is_some) {
+            let current = self.max_current.each_ref().map(|c| match c {
+                Some((max, unit)) => format!("{max:.2} {unit}").trim_end().to_string(),
+                None => String::from("-"),
+            });
+            row(f, "max current", current)?;
+        }
+        row(f, "stalls", self.stalls.map(|s| s.to_string()))?;
+        row(f, "at limits (s)", self.at_limit.map(|t| format!("{:.1}", t.as_secs_f64())))
+    }
+}
@@ -0,0 +1,948 @@
+//! The terminal UI, when no other mode is asked for: the actuators' state
+//! and telemetry, driven from the keyboard, the on-screen pad and whatever
+//! `[input]` sources the config adds. Only with the `tui` feature.
+
+use std::{fs::OpenOptions, io::{self, Write}, path::Path, process::exit, sync::Arc, time::{Duration, Instant, SystemTime}};
+use crossterm::{
+    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEventKind},
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use ratatui::{
+    backend::CrosstermBackend, layout::{Constraint, Layout}, style::{Color, Modifier, Style}, symbols, text::{Line, Span, Text}, widgets::{Axis, Block, Borders, Cell, Chart, Clear, Dataset, GraphType, Paragraph, Row, Table, Tabs, Wrap}, Terminal
+};
+use tokio::sync::mpsc;
+
+use actuator_controller::{app::{Action, App}, dsl, audit, platform, commands::*, safety::Policy, config::Config, countdown, input::{self, InputSource}, keymap::{self, Layer}, latency::Profiler, link::Link, load, mode, sim, supervise::TaskState, telemetry::{Decimator, Report}, toast::{Severity, Toasts}, trip::{Summary, Trip}, update::{Input, Msg}};
+
+use crate::{about, cli::Args, joystick, picker, preflight, scope};
+#[cfg(feature = "bridge")]
+use crate::network;
+
+/// Queued frames at which the Info panel flags a backlog.
+const QUEUE_WARN: u64 = 10;
+
+/// What the pane beside Info shows, in the order `l` goes through them.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Pane {
+    History,
+    Notifications,
+    FirmwareLog,
+}
+
+impl Pane {
+    fn next(self) -> Pane {
+        match self {
+            Pane::History => Pane::Notifications,
+            Pane::Notifications => Pane::FirmwareLog,
+            Pane::FirmwareLog => Pane::History,
+        }
+    }
+}
+
+/// Runs the terminal UI on `link` until the operator quits.
+pub async fn run(
+    args: &Args,
+    config: &Config,
+    mut app: App,
+    mut link: Link,
+    profiler: Option<Arc<Profiler>>,
+    faults: Option<mpsc::Sender<sim::Injection>>,
+) -> Result<(), io::Error> {
+    // the keyboard and pad are filled as their events come in below
+    let mut keyboard = input::Queued::new(input::Source::Keyboard);
+    let mut joystick = input::Queued::new(input::Source::Joystick);
+    let mut sources: Vec<Box<dyn InputSource>> = Vec::new();
+    if let Some(path) = &config.input.fifo {
+        match input::Lines::fifo(path) {
+            Ok(fifo) => sources.push(Box::new(fifo)),
+            Err(e) => {
+                eprintln!("Couldn't open {}: {e}", path.display());
+                exit(2);
+            }
+        }
+    }
+    #[cfg(feature = "bridge")]
+    if let Some(endpoint) = &config.input.listen {
+        match network::Network::bind(endpoint, config.roles.clone(), config.vocabulary()).await {
+            Ok(network) => sources.push(Box::new(network)),
+            Err(e) => {
+                eprintln!("{e}");
+                exit(2);
+            }
+        }
+    }
+    #[cfg(not(feature = "bridge"))]
+    if let Some(endpoint) = &config.input.listen {
+        eprintln!("Can't listen on {endpoint}: built without the bridge feature");
+        exit(2);
+    }
+    if let Some(path) = &config.input.script {
+        match input::Lines::script(path) {
+            Ok(script) => sources.push(Box::new(script)),
+            Err(e) => {
+                eprintln!("{e}");
+                exit(2);
+            }
+        }
+    }
+    let mut merge = input::Merge::new(config.input.policy.clone(), config.vocabulary());
+
+    // only now, since closing the link waits for every sender to be gone
+    let tx = link.commands.clone();
+    enable_raw_mode()?;
+
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+    let backend = CrosstermBackend::new(stdout);
+    let mut terminal = Terminal::new(backend)?;
+    let mut pad = joystick::Pad::default();
+    let mut acked = 0;
+    // control gets every report, the screen only as many as it can show
+    let mut display = Decimator::new(config.telemetry.display);
+    let mut shown: [Option<Report>; 2] = [None, None];
+    // how fast each rod moved between the last two reports shown (m/s)
+    let mut velocity: [Option<f64>; 2] = [None, None];
+    let mut shown_at: [Option<Instant>; 2] = [None, None];
+    let mut scope = scope::Scope::default();
+    scope.channel = config.channels.chart.as_deref().and_then(|name| config.channels.get(name)).map(|channel| channel.field.clone());
+    app.startup = config.startup.iter().cloned().collect();
+    let mut picker: Option<picker::Picker> = None;
+    app.checklist = config.checklist.clone();
+    let missing: Vec<String> = platform::Capability::ALL
+        .into_iter()
+        .filter_map(|capability| match capability.detect() {
+            platform::Support::Unavailable(why) => Some(format!("{capability} ({why})")),
+            platform::Support::Available(_) => None,
+        })
+        .collect();
+    if !missing.is_empty() {
+        app.notify(Severity::Info, format!("Not available here: {}", missing.join(", ")));
+    }
+    if let Some(entry) = &app.interrupted {
+        app.audit.record(&format!("found interrupted {} from {}", entry.running, entry.at));
+        app.notify(Severity::Warn, format!("{} was interrupted at {}; R: Resume | X: Stop and discard", entry.running, entry.at));
+    }
+    let off = config.safety_policies.off();
+    if !off.is_empty() {
+        let off: Vec<&str> = off.into_iter().map(Policy::name).collect();
+        app.audit.record(&format!("safety profile {}: off: {}", config.safety_profile, off.join(", ")));
+        app.notify(Severity::Warn, format!("Safety profile {}: off: {}", config.safety_profile, off.join(", ")));
+    }
+    if app.checklist.enabled() {
+        app.notify(Severity::Warn, format!("Speeds capped at {} until the checklist is done; k: Checklist", app.checklist.unarmed_speed));
+    }
+    // attached, other clients may be driving them on purpose
+    if args.port_path.is_some() || args.sim.is_some() {
+        app.check_standstill();
+    }
+    let mut preflight: Option<preflight::Preflight> = None;
+    let mut about: Option<about::About> = None;
+    let strokes = [config.linkage.lift.max_stroke, config.linkage.bucket.max_stroke];
+    let mut trip = Trip::new(config.trip, strokes);
+    let mut runs = Vec::new();
+    let mut pane = Pane::History;
+    let mut layer = Layer::default();
+    // the screen is redrawn after any input, and otherwise no more often
+    // than telemetry is shown, to leave the control loop its time
+    let frame = config.telemetry.display.map_or(Duration::ZERO, |hz| Duration::from_secs_f64(1.0 / hz));
+    let mut drawn_at: Option<Instant> = None;
+    let mut redraw = true;
+
+    loop {
+        if let Some(profiler) = &profiler {
+            let written = link.acks.borrow().written;
+            profiler.acked(written - acked);
+            acked = written;
+        }
+        while let Ok(status) = link.status.try_recv() {
+            app.update(Msg::Status(status));
+        }
+        while let Some(report) = link.telemetry.try_recv() {
+            trip.report(&report, app.speeds[report.actuator as usize], Instant::now());
+            let now = Instant::now();
+            if let Some(mean) = display.push(&report, now) {
+                let i = report.actuator as usize;
+                if let (Some(prev), Some(at)) = (&shown[i], shown_at[i])
+                    && now > at
+                {
+                    velocity[i] = Some((mean.length() - prev.length()) / now.duration_since(at).as_secs_f64());
+                }
+                let force = mean.field(load::FIELD).map(|force| app.load.force(force));
+                let channel = scope.channel.as_ref().and_then(|channel| mean.field(&channel.name));
+                let sample = [Some(mean.length()), velocity[i], mean.current(), Some(app.commanded[i] as f64), force, channel];
+                scope.push(report.actuator, now, sample);
+                shown[i] = Some(mean);
+                shown_at[i] = Some(now);
+            }
+            app.update(Msg::Telemetry(report));
+        }
+        if let Some(channel) = &mut link.arming {
+            app.sync_arming(channel);
+        }
+        if let Some(channel) = &mut link.estop {
+            app.sync_estop(channel);
+        }
+        let mut events = keyboard.drain();
+        events.extend(joystick.drain());
+        for source in &mut sources {
+            events.extend(source.drain());
+        }
+        for event in events {
+            match merge.admit(&event, Instant::now()) {
+                Ok(()) => app.update(Msg::Input(event.input)),
+                Err(holder) => app.notify(Severity::Warn, format!("Ignored {} input while {holder} has control", event.source)),
+            }
+        }
+        // the closed loop runs at its own fixed rate, whatever else is going on
+        let now = Instant::now();
+        let ticking = app.cadence.is_due(now);
+        if ticking {
+            app.cadence.start(now);
+            app.update(Msg::Tick(now));
+        }
+        app.flush(&tx).await;
+        if ticking {
+            app.cadence.finish(Instant::now());
+        }
+        for (at, event) in app.timeline.drain(..) {
+            scope.note(at, event);
+        }
+        // shown from `last_settle` instead
+        app.settles.clear();
+        
+        if redraw || drawn_at.is_none_or(|at| now.duration_since(at) >= frame) {
+            drawn_at = Some(now);
+            redraw = false;
+            terminal.draw(|f| {
+
+                let chunks = Layout::default()
+                    .direction(ratatui::layout::Direction::Vertical)
+                    .margin(1)
+                    .constraints([
+                        Constraint::Length(3),
+                        Constraint::Percentage(50),
+                        Constraint::Percentage(25),
+                        Constraint::Percentage(25),
+                    ].as_ref())
+                    .split(f.area());
+                let drive_chunks = Layout::default()
+                    .direction(ratatui::layout::Direction::Horizontal)
+                    .constraints([Constraint::Percentage(75), Constraint::Percentage(25)].as_ref())
+                    .split(chunks[1]);
+                let telemetry_chunks = Layout::default()
+                    .direction(ratatui::layout::Direction::Horizontal)
+                    .constraints([Constraint::Percentage(40), Constraint::Percentage(60)].as_ref())
+                    .split(drive_chunks[0]);
+                let motor_chunks = Layout::default()
+                    .direction(ratatui::layout::Direction::Vertical)
+                    .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+                    .split(telemetry_chunks[0]);
+                scope.render(f, telemetry_chunks[1], app.actuator, Instant::now(), &references(&app, config));
+                if scope.picking {
+                    scope.render_picker(f, telemetry_chunks[1]);
+                }
+                if let Some(picker) = &picker {
+                    picker.render(f, drive_chunks[0]);
+                }
+                if let Some(preflight) = &preflight {
+                    preflight.render(f, drive_chunks[0], &app.checklist);
+                }
+                pad.area = drive_chunks[1];
+                f.render_widget(pad.widget(app.speeds[app.actuator as usize], app.max_speed), pad.area);
+
+                let selector = Tabs::new([Actuator::M1, Actuator::M2].map(|a| {
+                    let name = if a == Actuator::M1 { "lift" } else { "bucket" };
+                    format!("{}: {a:?} {name}", app.keys.select[a as usize])
+                }))
+                .select(app.actuator as usize)
+                .highlight_style(Style::default().fg(Color::Black).bg(Color::Cyan).add_modifier(Modifier::BOLD))
+                .block(Block::default().title(format!("Actuator ({} keys)", layer.label())).title(clocks(&app).right_aligned()).borders(Borders::ALL));
+                f.render_widget(selector, chunks[0]);
+
+                let speed_text = match app.mode {
+                    mode::Mode::Split => Text::from(format!(
+                        "M1 speed: {} / {max}\nM2 speed: {} / {max}",
+                        app.speed(Actuator::M1),
+                        app.speed(Actuator::M2),
+                        max = app.max_speed
+                    )),
+                    _ => Text::from(format!("Speed: {} / {}", app.speed(app.actuator), app.max_speed)),
+                };
+                let speed_paragraph = Paragraph::new(speed_text)
+                    .block(Block::default().title("Motor Speed").borders(Borders::ALL));
+                f.render_widget(speed_paragraph, motor_chunks[0]);
+
+                let holding = |actuator: Actuator| if app.held[actuator as usize] { " (holding)" } else { "" };
+                let dir_text = match app.mode {
+                    mode::Mode::Split => Text::from(format!(
+                        "M1 direction: {:?}{}\nM2 direction: {:?}{}",
+                        app.heading[0],
+                        holding(Actuator::M1),
+                        app.heading[1],
+                        holding(Actuator::M2),
+                    )),
+                    _ => Text::from(format!("Direction: {:?}{}", app.heading[app.actuator as usize], holding(app.actuator))),
+                };
+                let dir_paragraph = Paragraph::new(dir_text)
+                    .block(Block::default().title("Motor Direction").borders(Borders::ALL));
+                f.render_widget(dir_paragraph, motor_chunks[1]);
+
+                let status_text = format!("Status: {} | {:?} | {} mode", app.status_message, app.actuator, app.mode.label());
+                let lens = shown.each_ref().map(|report| report.as_ref().map(Report::length));
+                if let Some(preview) = &app.preview {
+                    // where each actuator is now, to see how far the first point is from it
+                    let now = [Actuator::M1, Actuator::M2].map(|a| lens[a as usize].map(|len| vec![(0.0, len)]).unwrap_or_default());
+                    let duration = preview.trajectory.duration().max(f64::EPSILON);
+                    let stroke = strokes[0].max(strokes[1]);
+                    let mut datasets = Vec::new();
+                    for (actuator, color) in [(Actuator::M1, Color::Cyan), (Actuator::M2, Color::Magenta)] {
+                        let name = if actuator == Actuator::M1 { "lift" } else { "bucket" };
+                        datasets.push(
+                            Dataset::default()
+                                .name(name)
+                                .marker(symbols::Marker::Braille)
+                                .graph_type(GraphType::Line)
+                                .style(Style::default().fg(color))
+                                .data(preview.trajectory.path(actuator)),
+                        );
+                        datasets.push(
+                            Dataset::default()
+                                .marker(symbols::Marker::Block)
+                                .graph_type(GraphType::Scatter)
+                                .style(Style::default().fg(color))
+                                .data(&now[actuator as usize]),
+                        );
+                    }
+                    let chart = Chart::new(datasets)
+                        .block(Block::default().title(format!("Preview: follow {} ({duration} s)", preview.path)).borders(Borders::ALL))
+                        .x_axis(Axis::default().title("s").bounds([0.0, duration]).labels([String::from("0"), format!("{duration}")]))
+                        .y_axis(Axis::default().title("m").bounds([0.0, stroke]).labels([String::from("0"), format!("{stroke}")]));
+                    f.render_widget(Clear, drive_chunks[0]);
+                    f.render_widget(chart, drive_chunks[0]);
+                }
+                let actuator_len_text = match lens[app.actuator as usize] {
+                    Some(len) => format!("Actuator len (m): {}", len),
+                    None => String::from("Actuator len (m): -"),
+                };
+
+                let pose = match lens {
+                    [Some(lift), Some(bucket)] => app.linkage.forward(lift, bucket),
+                    _ => None,
+                };
+                let (angle_text, height_text) = match pose {
+                    Some(pose) => (
+                        format!("Bucket angle: {:.1}°", pose.bucket_angle),
+                        format!("Bucket height (m): {:.3}", pose.bucket_height),
+                    ),
+                    None => (String::from("Bucket angle: -"), String::from("Bucket height (m): -")),
+                };
+
+                let skew_row = match app.skew.skew(lens) {
+                    Some(skew) if app.skew.is_excessive(skew) => Row::new(vec![
+                        Cell::new(format!("Lift skew (m): {:+.3} EXCEEDS {}", skew, app.skew.warn)),
+                        Cell::new(format!("Correction: {:+}", app.correction)),
+                    ]).style(Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)),
+                    Some(skew) => Row::new(vec![
+                        Cell::new(format!("Lift skew (m): {:+.3}", skew)),
+                        Cell::new(format!("Correction: {:+}", app.correction)),
+                    ]),
+                    None => Row::new(vec![Cell::new(""), Cell::new("")]),
+                };
+
+                let force_row = app.force.map(|force| {
+                    if app.load.is_excessive(force) {
+                        Row::new(vec![Cell::new(format!("Force (N): {force:.1} EXCEEDS {}", app.load.alarm)), Cell::new("")])
+                            .style(Style::default().fg(Color::Red).add_modifier(Modifier::BOLD))
+                    } else {
+                        Row::new(vec![Cell::new(format!("Force (N): {force:.1}")), Cell::new("")])
+                    }
+                });
+
+                let control_row = Row::new(vec![Cell::new("Control loop:"), Cell::new(app.cadence.summary())]);
+                // a loop that can't keep up steers late, not wrong
+                let control_row = if app.cadence.is_behind() { control_row.style(Style::default().fg(Color::Yellow)) } else { control_row };
+
+                let acks = *link.acks.borrow();
+                let depth = app.sent.saturating_sub(acks.total());
+                let queue_row = Row::new(vec![
+                    Cell::new(format!("Queued frames: {depth}")),
+                    Cell::new(format!("Coalesced: {} | Dropped: {} | Failed: {}", acks.coalesced, acks.dropped, acks.failed)),
+                ]);
+                // a backlog means inputs are being throttled, not that the hardware is dead
+                let queue_row = if depth >= QUEUE_WARN { queue_row.style(Style::default().fg(Color::Yellow)) } else { queue_row };
+
+                let status_row = match &app.fault {
+                    Some(fault) => Row::new(vec![Cell::new(format!("FAULT: {fault}")), Cell::new(actuator_len_text)])
+                        .style(Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)),
+                    None => Row::new(vec![Cell::new(status_text),Cell::new(actuator_len_text)]),
+                };
+
+                let health = link.health.borrow();
+                let tasks_text = health
+                    .iter()
+                    .map(|task| match (&task.state, task.restarts) {
+                        (TaskState::Running, 0) => format!("{} ok", task.name),
+                        (TaskState::Running, n) => format!("{} ok ({n} restarts)", task.name),
+                        (TaskState::Restarting(_), _) => format!("{} RESTARTING", task.name),
+                        (TaskState::Done, _) => format!("{} done", task.name),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" | ");
+                let tasks_text = if tasks_text.is_empty() { String::from("via daemon") } else { tasks_text };
+                let tasks_row = Row::new(vec![Cell::new(format!("Tasks: {tasks_text}")), Cell::new("")]);
+                let tasks_row = if health.iter().any(|task| matches!(task.state, TaskState::Restarting(_))) {
+                    tasks_row.style(Style::default().fg(Color::Red).add_modifier(Modifier::BOLD))
+                } else {
+                    tasks_row
+                };
+                drop(health);
+
+                let physical = [Actuator::M1, Actuator::M2].map(|actuator| {
+                    let joint = app.linkage.joint(actuator);
+                    let extended = match lens[actuator as usize] {
+                        Some(len) => format!("{:.0}% extended", joint.percent_extended(len)),
+                        None => String::from("-% extended"),
+                    };
+                    // only the last actuator driven gets reported, so an idle one's last estimate is stale
+                    let rpm = match velocity[actuator as usize] {
+                        _ if app.speeds[actuator as usize] == 0 => String::from("0 rpm"),
+                        Some(v) => format!("{:.0} rpm", joint.motor_rpm(v)),
+                        None => String::from("- rpm"),
+                    };
+                    Cell::new(format!("{actuator:?}: {extended}, motor {rpm}"))
+                });
+                let [m1_physical, m2_physical] = physical;
+
+                let sensors = [Actuator::M1, Actuator::M2].map(|actuator| app.sensors.health(actuator));
+                let sensor_cells = [Actuator::M1, Actuator::M2].map(|actuator| {
+                    let text = match sensors[actuator as usize] {
+                        Some(health) => match health.worst {
+                            Some(problem) if app.sensors.is_poor(&health) => format!("{:.0} ({problem})", health.score),
+                            _ => format!("{:.0}", health.score),
+                        },
+                        None => String::from("-"),
+                    };
+                    Cell::new(format!("{actuator:?} sensor health: {text}"))
+                });
+                let sensor_row = Row::new(sensor_cells);
+                // a failing potentiometer is worth fixing before a run, not partway through
+                let sensor_row = if sensors.iter().flatten().any(|health| app.sensors.is_poor(health)) {
+                    sensor_row.style(Style::default().fg(Color::Red).add_modifier(Modifier::BOLD))
+                } else {
+                    sensor_row
+                };
+
+                // whatever else the firmware advertised, for the selected actuator
+                let extras: Vec<String> = match &shown[app.actuator as usize] {
+                    Some(report) => report.extras().map(|(field, value)| format!("{field}: {value:.3}")).collect(),
+                    None => Vec::new(),
+                };
+
+                let mut status_table_rows = Vec::new();
+                if let Some(why) = app.estop.why() {
+                    let text = format!("E-STOP LATCHED ({why}): E, then y, to reset");
+                    status_table_rows.push(Row::new(vec![Cell::new(text), Cell::new("")]).style(Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)));
+                }
+                if let Some(entry) = &app.interrupted {
+                    let text = format!("Interrupted: {}", entry.running);
+                    status_table_rows.push(Row::new(vec![Cell::new(text), Cell::new("R: Resume | X: Stop and discard")]).style(Style::default().fg(Color::Yellow)));
+                }
+                status_table_rows.extend([
+                    status_row,
+                    Row::new(vec![Cell::new(angle_text),Cell::new(height_text)]),
+                    Row::new(vec![m1_physical, m2_physical]),
+                    sensor_row,
+                ]);
+                if !extras.is_empty() {
+                    status_table_rows.push(Row::new(vec![Cell::new(extras.join(" | ")), Cell::new("")]));
+                }
+                status_table_rows.extend(force_row);
+                if app.last_settle.iter().any(Option::is_some) {
+                    let cells = [Actuator::M1, Actuator::M2].map(|actuator| match app.last_settle[actuator as usize] {
+                        Some(settle) => Cell::new(format!(
+                            "{actuator:?} settled: {:.2} s, overshoot {:.1} mm",
+                            settle.time.as_secs_f64(),
+                            settle.overshoot * 1000.0
+                        )),
+                        None => Cell::new(format!("{actuator:?} settled: -")),
+                    });
+                    status_table_rows.push(Row::new(cells));
+                }
+                if let Some(actuator) = app.standstill.checking() {
+                    let text = format!("Checking {actuator:?} is stopped: no input until both are");
+                    status_table_rows.push(Row::new(vec![Cell::new(text), Cell::new("")]).style(Style::default().fg(Color::Yellow)));
+                }
+                if !app.checklist.is_armed() {
+                    let text = format!(
+                        "Checklist {}/{} done: speeds capped at {} (k: Checklist)",
+                        app.checklist.done(),
+                        app.checklist.items.len(),
+                        app.checklist.unarmed_speed
+                    );
+                    status_table_rows.push(Row::new(vec![Cell::new(text), Cell::new("")]).style(Style::default().fg(Color::Yellow)));
+                }
+                if app.budget_scale < 1.0 {
+                    let text = format!("Over the power budget: speeds at {:.0}%", app.budget_scale * 100.0);
+                    status_table_rows.push(Row::new(vec![Cell::new(text), Cell::new("")]).style(Style::default().fg(Color::Yellow)));
+                }
+                status_table_rows.extend([skew_row, queue_row, control_row, tasks_row]);
+                let status_table = Table::new(status_table_rows, [Constraint::Percentage(50),Constraint::Percentage(50)])
+                    .block(Block::default().title(format!("Info (operator: {})", app.audit.operator())).borders(Borders::ALL));
+
+                let info_chunks = Layout::default()
+                    .direction(ratatui::layout::Direction::Horizontal)
+                    .constraints([Constraint::Percentage(65), Constraint::Percentage(35)].as_ref())
+                    .split(chunks[2]);
+                f.render_widget(status_table, info_chunks[0]);
+
+                // newest first, as many as fit
+                let history_rows = info_chunks[1].height.saturating_sub(2) as usize;
+                let history_paragraph = match pane {
+                    Pane::Notifications => {
+                        let lines: Vec<Line> = app
+                            .toasts
+                            .log()
+                            .take(history_rows)
+                            .map(|toast| {
+                                let repeats = if toast.count > 1 { format!(" (x{})", toast.count) } else { String::new() };
+                                let text = format!("{} {:5} {}{repeats}", audit::time_of_day(toast.time), toast.severity, toast.text);
+                                Line::styled(text, severity_style(toast.severity))
+                            })
+                            .collect();
+                        Paragraph::new(Text::from(lines)).block(Block::default().title("Notifications (l: Firmware log)").borders(Borders::ALL))
+                    }
+                    Pane::FirmwareLog => {
+                        let lines: Vec<String> = app
+                            .firmware_log
+                            .iter()
+                            .rev()
+                            .take(history_rows)
+                            .map(|(time, text)| format!("{} {text}", audit::time_of_day(*time)))
+                            .collect();
+                        Paragraph::new(Text::from(lines.join("\n"))).block(Block::default().title("Firmware log (l: History)").borders(Borders::ALL))
+                    }
+                    Pane::History => {
+                        let history_text: Vec<String> = app.history.iter().rev().take(history_rows).map(|entry| entry.action.to_string()).collect();
+                        Paragraph::new(Text::from(history_text.join("\n")))
+                            .block(Block::default().title("History (.: Repeat | u: Undo | l: Notifications)").borders(Borders::ALL))
+                    }
+                };
+                f.render_widget(history_paragraph, info_chunks[1]);
+
+                let help_paragraph = match &app.prompt {
+                    Some(prompt) => Paragraph::new(Text::from(format!(":{prompt}")))
+                        .block(Block::default().title("Command (Enter: run | Esc: cancel)").borders(Borders::ALL)),
+                    None if app.preview.is_some() => Paragraph::new(Text::from(
+                        "Check the planned lengths against where the actuators are now (the dots at 0 s)\n\
+                         Enter: Run it | Esc: Cancel",
+                    ))
+                    .block(Block::default().title("Preview").borders(Borders::ALL)),
+                    None if picker.is_some() => Paragraph::new(Text::from("↑/↓: Choose | Type to narrow the list | Enter: Preview | Esc: Cancel"))
+                        .block(Block::default().title("Follow").borders(Borders::ALL)),
+                    None if about.is_some() => Paragraph::new(Text::from("Esc, Enter or i: Close"))
+                        .block(Block::default().title("About").borders(Borders::ALL)),
+                    None if preflight.is_some() => Paragraph::new(Text::from("↑/↓: Choose | Space: Tick off | Enter: Arm for full speed | Esc or k: Close"))
+                        .block(Block::default().title("Checklist").borders(Borders::ALL)),
+                    None if scope.picking => Paragraph::new(Text::from(format!("1-{}: Show/hide a series | Esc, Enter or c: Close", scope.choices())))
+                        .block(Block::default().title("Series").borders(Borders::ALL)),
+                    None if scope.measuring() => Paragraph::new(Text::from(scope.readout(app.actuator))).block(
+                        Block::default()
+                            .title("Cursor (←/→: Move, Shift: finely | Enter: Mark | Esc or x: Resume)")
+                            .borders(Borders::ALL),
+                    ),
+                    None => {
+                        let steps = app.keys.steps(app.actuator);
+                        let arrows = match layer {
+                            Layer::Sequence => "←/→: Trajectory slower/faster | Space: Pause/resume trajectory",
+                            _ => app.mode.arrow_help(),
+                        };
+                        let coarse = if layer == Layer::Fine { steps.normal } else { steps.coarse };
+                        // what the arrows step by in this layer, and with Shift
+                        let step = match layer {
+                            Layer::Normal => format!("↑/↓ step: {} | Shift+↑/↓: Fine adjust by {}", steps.normal, steps.fine),
+                            Layer::Fine => format!("↑/↓ step: {} (fine layer)", steps.fine),
+                            Layer::Sequence => format!("Shift+↑/↓: Fine adjust by {}", steps.fine),
+                        };
+                        let mut help = format!(
+                            "{} | {} | Tab: Next keys layer (Shift: fine, Alt: sequence, for one key) | q: Quit\n\
+                             s: Stop motor | !: E-stop (E, y: Reset) | h: Hold position | +/-: Increase/decrease speed by {} | {}/{}: Select lift/bucket | m: Cycle mode\n\
+                             ':': Command (m1 fwd 50% for 2s, lift goto 0.30, angle 35, stop) | g: Run dig cycle (Esc aborts)\n\
+                             f: Follow a trajectory file | p: Pause/resume trajectory | </>: Trajectory slower/faster (25-100%) | c: Chart series | x: Chart cursor\n\
+                             .: Repeat last command | u: Undo last command (reverse it or stop) | n: Mark the log with a note | e: End run (summary) | k: Checklist | l: Notifications/firmware log | i: About",
+                            arrows,
+                            step,
+                            coarse,
+                            app.keys.select[0],
+                            app.keys.select[1],
+                        );
+                        if let Some(entry) = &app.interrupted {
+                            help = format!("{} was interrupted | R: Resume it | X: Stop and discard it\n{help}", entry.running);
+                        }
+                        if app.estop.is_asking(Instant::now()) {
+                            help = format!("Reset the e-stop? y: Confirm\n{help}");
+                        }
+                        if let Some((_, operator, text)) = &app.arming.incoming {
+                            help = format!("{operator} asks to run `{text}` | y: Confirm\n{help}");
+                        }
+                        Paragraph::new(Text::from(help)).block(Block::default().title("Controls").borders(Borders::ALL))
+                    }
+                };
+                f.render_widget(help_paragraph, chunks[3]);
+                render_toasts(f, chunks[1], &app.toasts, Instant::now());
+                if let Some(about) = &about {
+                    about.render(f, f.area());
+                }
+            })?;
+        }
+
+        let wait = app.cadence.due(Instant::now()).saturating_duration_since(Instant::now());
+        let event = if event::poll(wait)? { Some(event::read()?) } else { None };
+        app.input_at = event.is_some().then(Instant::now);
+        redraw |= event.is_some();
+        if let Some(Event::Mouse(mouse)) = event && app.prompt.is_none() && app.preview.is_none() {
+            let target = match mouse.kind {
+                MouseEventKind::Down(MouseButton::Left) if pad.contains(mouse.column, mouse.row) => {
+                    pad.dragging = true;
+                    Some(pad.value_at(mouse.row, app.max_speed))
+                }
+                MouseEventKind::Drag(MouseButton::Left) if pad.dragging => Some(pad.value_at(mouse.row, app.max_speed)),
+                MouseEventKind::Up(MouseButton::Left) if pad.dragging => {
+                    pad.dragging = false;
+                    Some(0)
+                }
+                _ => None,
+            };
+            if let Some(target) = target && target != app.speeds[app.actuator as usize] {
+                joystick.push(Input::Drive(target));
+            }
+        }
+        if let Some(Event::Key(key)) = event {
+            if let Some(prompt) = &mut app.prompt {
+                match key.code {
+                    KeyCode::Char(c) => prompt.push(c),
+                    KeyCode::Backspace => {
+                        prompt.pop();
+                    }
+                    KeyCode::Esc => app.prompt = None,
+                    KeyCode::Enter => {
+                        let line = app.prompt.take().unwrap_or_default();
+                        match line.trim().strip_prefix("fault") {
+                            Some(fault) if fault.is_empty() || fault.starts_with(char::is_whitespace) => {
+                                let result = inject(faults.as_ref(), fault, &mut app.audit);
+                                app.report(result);
+                            }
+                            _ => keyboard.push(Input::Run(line)),
+                        }
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+            if app.preview.is_some() {
+                match key.code {
+                    KeyCode::Enter => keyboard.push(Input::ConfirmPreview),
+                    KeyCode::Esc => keyboard.push(Input::Abort),
+                    _ => {}
+                }
+                continue;
+            }
+            if let Some(open) = &mut picker {
+                match open.key(key.code) {
+                    Some(picker::Picked::Path(path)) => {
+                        picker = None;
+                        keyboard.push(Input::Run(format!("follow {path}")));
+                    }
+                    Some(picker::Picked::Cancelled) => picker = None,
+                    None => {}
+                }
+                continue;
+            }
+            if let Some(open) = &about {
+                if open.key(key.code) {
+                    about = None;
+                }
+                continue;
+            }
+            if let Some(open) = &mut preflight {
+                match open.key(key.code, &app.checklist) {
+                    Some(preflight::Pressed::Input(input)) => {
+                        let arming = input == Input::Arm;
+                        app.update(Msg::Input(input));
+                        if arming && app.checklist.is_armed() {
+                            preflight = None;
+                        }
+                    }
+                    Some(preflight::Pressed::Closed) => preflight = None,
+                    None => {}
+                }
+                continue;
+            }
+            if scope.picking {
+                match key.code {
+                    KeyCode::Char(c @ '1'..='6') => scope.toggle(c as usize - '0' as usize),
+                    KeyCode::Esc | KeyCode::Enter | KeyCode::Char('c') => scope.picking = false,
+                    _ => {}
+                }
+                continue;
+            }
+            if scope.measuring() {
+                let step = if key.modifiers.contains(KeyModifiers::SHIFT) { scope::CURSOR_FINE_STEP } else { scope::CURSOR_STEP };
+                match key.code {
+                    KeyCode::Left => scope.move_cursor(-step),
+                    KeyCode::Right => scope.move_cursor(step),
+                    KeyCode::Enter => scope.set_mark(),
+                    KeyCode::Esc | KeyCode::Char('x') => scope.toggle_cursor(Instant::now()),
+                    _ => {}
+                }
+                continue;
+            }
+            match key.code {
+                KeyCode::Char(':') => app.prompt = Some(String::new()),
+                KeyCode::Char('c') => scope.picking = true,
+                KeyCode::Char('f') => {
+                    let recent: Vec<String> = app
+                        .history
+                        .iter()
+                        .rev()
+                        .filter_map(|entry| match &entry.action {
+                            Action::Command(dsl::Command::Follow(path)) => Some(path.clone()),
+                            _ => None,
+                        })
+                        .collect();
+                    picker = Some(picker::Picker::open(&config.trajectory_dirs, &recent));
+                }
+                KeyCode::Char('k') if app.checklist.enabled() => preflight = Some(preflight::Preflight::open()),
+                KeyCode::Char('x') => scope.toggle_cursor(Instant::now()),
+                KeyCode::Char('i') => {
+                    let connection = match (&args.sim, &args.port_path) {
+                        (Some(log), _) => format!("simulation of {}", log.display()),
+                        (None, Some(port_path)) => port_path.clone(),
+                        (None, None) => format!("daemon at {}", args.socket.display()),
+                    };
+                    let schema = shown.iter().flatten().next().map(|report| &*report.schema);
+                    about = Some(about::About::open(connection, schema));
+                }
+                KeyCode::Char('n') => app.prompt = Some(String::from("mark ")),
+                KeyCode::Char('l') => pane = pane.next(),
+                KeyCode::Tab => layer = layer.next(),
+                KeyCode::Char('e') => {
+                    let msg = end_run(&mut trip, &mut app, args.trip_log.as_deref(), &mut runs);
+                    app.notify(Severity::Info, msg);
+                    trip = Trip::new(config.trip, strokes);
+                }
+                KeyCode::Char('q') => break,
+                _ => {
+                    if let Some(input) = input_for(&app, key, layer) {
+                        keyboard.push(input);
+                    }
+                }
+            }
+        }
+    }
+
+    // Restore terminal
+    disable_raw_mode()?;
+    execute!(
+        terminal.backend_mut(),
+        LeaveAlternateScreen,
+        DisableMouseCapture
+    )?;
+    terminal.show_cursor()?;
+
+    drop(tx);
+    link.close().await;
+    // quitting isn't a crash, but an interrupted run no one has decided on yet is kept
+    if let Some(journal) = &mut app.journal
+        && app.interrupted.is_none()
+        && let Err(e) = journal.finish()
+    {
+        eprintln!("{e}");
+    }
+    end_run(&mut trip, &mut app, args.trip_log.as_deref(), &mut runs);
+    for run in &runs {
+        eprintln!("{run}");
+    }
+    if let Some(profiler) = &profiler {
+        eprint!("{}", profiler.report());
+        eprint!("{}", app.cadence.report());
+    }
+    Ok(())
+}
+
+/// Closes the current run, appending its summary to `log` if there is one and
+/// keeping it to print on exit. Returns what to tell the operator.
+fn end_run(trip: &mut Trip, app: &mut App, log: Option<&Path>, runs: &mut Vec<Summary>) -> String {
+    let summary = trip.summary(app.audit.operator());
+    app.audit.record(&format!("end run ({:.3} m, {:.3} m travelled)", summary.travel[0], summary.travel[1]));
+    app.audit.start_run();
+    let written = match log {
+        Some(path) => OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| writeln!(file, "{summary}"))
+            .map_err(|e| format!("couldn't write {}: {e}", path.display())),
+        None => Ok(()),
+    };
+    let msg = format!("Run ended: M1 travelled {:.3} m, M2 {:.3} m", summary.travel[0], summary.travel[1]);
+    runs.push(summary);
+    match written {
+        Ok(()) => msg,
+        Err(e) => format!("{msg}, but {e}"),
+    }
+}
+
+/// Starts or clears a simulated fault, for `:fault ...` at the prompt.
+fn inject(faults: Option<&mpsc::Sender<sim::Injection>>, text: &str, audit: &mut audit::AuditLog) -> Result<String, String> {
+    let faults = faults.ok_or("faults can only be injected under --sim")?;
+    let injection = sim::Injection::parse(text)?;
+    audit.record(&format!("sim fault: {injection}"));
+    let _ = faults.try_send(injection);
+    Ok(format!("Injected: {injection}"))
+}
+
+/// How a notification of each severity is coloured, on screen and in the log.
+fn severity_style(severity: Severity) -> Style {
+    match severity {
+        Severity::Info => Style::default().fg(Color::Cyan),
+        Severity::Warn => Style::default().fg(Color::Yellow),
+        Severity::Error => Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+    }
+}
+
+/// The notifications still showing, stacked newest first in the top-right
+/// corner of `area`.
+fn render_toasts(f: &mut ratatui::Frame, area: ratatui::layout::Rect, toasts: &Toasts, now: Instant) {
+    let max_width = (area.width / 2).max(8);
+    let mut y = area.y;
+    for toast in toasts.shown(now) {
+        let text = if toast.count > 1 { format!("{} (x{})", toast.text, toast.count) } else { toast.text.clone() };
+        let chars = text.chars().count() as u16;
+        let width = (chars + 4).min(max_width);
+        // a long message wraps onto a few lines rather than being cut off
+        let height = chars.div_ceil(width - 2).clamp(1, 3) + 2;
+        if y + height > area.y + area.height {
+            break;
+        }
+        let rect = ratatui::layout::Rect { x: area.x + area.width - width, y, width, height };
+        let style = severity_style(toast.severity);
+        let block = Block::default().title(toast.severity.to_string()).borders(Borders::ALL).border_style(style);
+        f.render_widget(Clear, rect);
+        f.render_widget(Paragraph::new(text).style(style).wrap(Wrap { trim: true }).block(block), rect);
+        y += height;
+    }
+}
+
+/// The time of day, session and run clocks, and the countdown if there is one.
+fn clocks(app: &App) -> Line<'static> {
+    let run = app.audit.run();
+    let mut spans = vec![Span::raw(format!(
+        "{} UTC | session {} | run {} (e: End run) ",
+        audit::time_of_day(SystemTime::now()),
+        audit::clock(app.audit.session()),
+        audit::clock(run)
+    ))];
+    if let Some(left) = app.countdown.left(run) {
+        let style = match app.countdown.stage(run) {
+            countdown::Stage::Running => Style::default(),
+            countdown::Stage::Warned => Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+            countdown::Stage::FinalWarned | countdown::Stage::Expired => {
+                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)
+            }
+        };
+        spans.push(Span::styled(format!("| {} left ", audit::clock(left)), style));
+    }
+    Line::from(spans)
+}
+
+/// The lines drawn across the Telemetry chart for the selected actuator.
+fn references(app: &App, config: &Config) -> Vec<scope::Reference> {
+    use scope::{Reference, Series};
+    let actuator = app.actuator;
+    let stroke = app.linkage.joint(actuator).max_stroke;
+    let margin = config.trip.limit_margin;
+    let mut references = vec![
+        Reference { series: Series::Length, value: margin, label: "limits" },
+        Reference { series: Series::Length, value: stroke - margin, label: "limits" },
+    ];
+    if let Some(target) = app.position.target(actuator) {
+        references.push(Reference { series: Series::Length, value: target, label: "target" });
+    }
+    for (direction, sign) in [(Direction::Forward, 1.0), (Direction::Backward, -1.0)] {
+        let cap = config.speed_limits.cap(actuator, Some(direction));
+        if cap < u16::MAX && config.safety_policies.contains(Policy::Limits) {
+            references.push(Reference { series: Series::Speed, value: sign * cap as f64, label: "speed cap" });
+        }
+    }
+    if app.load.alarm > 0.0 {
+        for value in [app.load.alarm, -app.load.alarm] {
+            references.push(Reference { series: Series::Force, value, label: "force alarm" });
+        }
+    }
+    references
+}
+
+/// What a key asks of the app, given the mode it's in and the keys layer
+/// latched.
+fn input_for(app: &App, key: KeyEvent, latched: Layer) -> Option<Input> {
+    let bound = match key.code {
+        KeyCode::F(n) => Some(keymap::Key::F(n)),
+        KeyCode::Char(c) => Some(keymap::Key::Char(c)),
+        _ => None,
+    };
+    if let Some(actuator) = bound.and_then(|k| app.keys.selects(k)) {
+        return Some(Input::Select(actuator));
+    }
+    let layer = Layer::momentary(latched, key.modifiers.contains(KeyModifiers::SHIFT), key.modifiers.contains(KeyModifiers::ALT));
+    let fine = layer == Layer::Fine;
+    let step = |actuator| {
+        let steps = app.keys.steps(actuator);
+        (if fine { steps.fine } else { steps.normal }) as i32
+    };
+    let coarse = |actuator| {
+        let steps = app.keys.steps(actuator);
+        (if fine { steps.normal } else { steps.coarse }) as i32
+    };
+    let split = app.mode == mode::Mode::Split;
+    let sequence = layer == Layer::Sequence;
+    let input = match key.code {
+        KeyCode::Left if sequence => Input::Pace(-1),
+        KeyCode::Right if sequence => Input::Pace(1),
+        KeyCode::Char(' ') if sequence => Input::Pause,
+        KeyCode::Up | KeyCode::Down if sequence => return None,
+        KeyCode::Char('w' | 'W') if split => Input::ChangeSpeed(Actuator::M1, step(Actuator::M1)),
+        KeyCode::Char('s' | 'S') if split => Input::ChangeSpeed(Actuator::M1, -step(Actuator::M1)),
+        KeyCode::Char('a' | 'A') if split => Input::SetDirection(Actuator::M1, Direction::Backward),
+        KeyCode::Char('d' | 'D') if split => Input::SetDirection(Actuator::M1, Direction::Forward),
+        KeyCode::Up if split => Input::ChangeSpeed(Actuator::M2, step(Actuator::M2)),
+        KeyCode::Down if split => Input::ChangeSpeed(Actuator::M2, -step(Actuator::M2)),
+        KeyCode::Left if split => Input::SetDirection(Actuator::M2, Direction::Backward),
+        KeyCode::Right if split => Input::SetDirection(Actuator::M2, Direction::Forward),
+        KeyCode::Char(' ') if split => Input::StopAll,
+        KeyCode::Char('s') => Input::Stop,
+        KeyCode::Char('!') => Input::EStop,
+        KeyCode::Char('E') => Input::ResetEStop,
+        KeyCode::Char('R') => Input::Resume,
+        KeyCode::Char('X') => Input::Discard,
+        KeyCode::Char('h') => Input::Hold,
+        KeyCode::Up => Input::ChangeSpeed(app.actuator, step(app.actuator)),
+        KeyCode::Down => Input::ChangeSpeed(app.actuator, -step(app.actuator)),
+        KeyCode::Left => Input::SetDirection(app.actuator, Direction::Backward),
+        KeyCode::Right => Input::SetDirection(app.actuator, Direction::Forward),
+        KeyCode::Char('+') => Input::ChangeSpeed(app.actuator, coarse(app.actuator)),
+        KeyCode::Char('-') => Input::ChangeSpeed(app.actuator, -coarse(app.actuator)),
+        KeyCode::Esc => Input::Abort,
+        KeyCode::Char('g') => Input::StartDig,
+        KeyCode::Char('y') => Input::ConfirmArming,
+        KeyCode::Char('.') => Input::Repeat,
+        KeyCode::Char('u') => Input::Undo,
+        KeyCode::Char('m') => Input::CycleMode,
+        KeyCode::Char('p') => Input::Pause,
+        KeyCode::Char('<') => Input::Pace(-1),
+        KeyCode::Char('>') => Input::Pace(1),
+        KeyCode::Char(c @ ('[' | ']')) => Input::Skew(if c == ']' { 1000 } else { -1000 }),
+        _ => return None,
+    };
+    Some(input)
+}
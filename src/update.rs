@@ -0,0 +1,231 @@
+//! Driving [`App`] by messages: every key, length report, link status and
+//! clock tick arrives as a [`Msg`] and goes through [`App::update`].
+//!
+//! Nothing here touches the terminal or the port. Whatever has to be sent
+//! ends up in `App::pending` for the caller to flush, so a sequence of
+//! messages, e.g. driving, then the daemon going away, can be played against
+//! an `App` and its state checked afterwards.
+
+use std::time::Instant;
+
+use crate::{
+    app::App,
+    commands::{Actuator, Direction},
+    dsl, load, mode, sequence,
+    status::StatusEvent,
+    telemetry::Report,
+    toast::Severity,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Msg {
+    Input(Input),
+    /// a length report, with whatever else the firmware sends
+    Telemetry(Report),
+    Status(StatusEvent),
+    /// time to run the closed-loop parts
+    Tick(Instant),
+}
+
+/// What the operator asked for, whichever key or widget it came from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Input {
+    /// drive the selected actuator at a signed speed, e.g. from the joystick pad
+    Drive(i32),
+    /// speed an actuator up, or down for a negative step, in its heading
+    ChangeSpeed(Actuator, i32),
+    SetDirection(Actuator, Direction),
+    /// stop the selected actuator
+    Stop,
+    /// have the firmware hold the selected actuator where it is
+    Hold,
+    StopAll,
+    /// stop everything and latch the e-stop, see [`crate::estop`]
+    EStop,
+    /// ask to reset the e-stop, which `ConfirmArming` then confirms
+    ResetEStop,
+    Select(Actuator),
+    CycleMode,
+    /// differential mode: shift the skew toward M1 (positive) or M2
+    Skew(i32),
+    /// stop any move or sequence in progress
+    Abort,
+    /// a line typed at the `:` prompt
+    Run(String),
+    /// run the trajectory being previewed
+    ConfirmPreview,
+    /// pause a running trajectory, or resume it
+    Pause,
+    /// play a running trajectory a step slower (negative) or faster, see [`sequence::PACES`]
+    Pace(i32),
+    StartDig,
+    Repeat,
+    Undo,
+    ConfirmArming,
+    /// tick the `n`th checklist item off, or back on
+    CheckOff(usize),
+    /// lift the checklist's speed cap
+    Arm,
+    /// start what the journal says was interrupted again, see [`crate::journal`]
+    Resume,
+    /// stop both actuators and forget what was interrupted
+    Discard,
+}
+
+impl Input {
+    /// Manual driving always wins over a move or sequence in progress.
+    fn is_manual(&self) -> bool {
+        matches!(
+            self,
+            Input::Drive(_)
+                | Input::ChangeSpeed(..)
+                | Input::SetDirection(..)
+                | Input::Stop
+                | Input::Hold
+                | Input::StopAll
+                | Input::EStop
+                | Input::CycleMode
+                | Input::Abort
+        )
+    }
+}
+
+impl App {
+    pub fn update(&mut self, msg: Msg) {
+        match msg {
+            Msg::Input(input) => self.input(input),
+            Msg::Telemetry(report) => {
+                let i = report.actuator as usize;
+                self.actuator_len_meters[i] = Some(report.length());
+                self.on_length(report.actuator, report.length());
+                self.current[i] = report.current();
+                self.checklist.observe(&report);
+                self.hooks.observe(&report);
+                if let Some(force) = report.field(load::FIELD) {
+                    self.on_force(force);
+                }
+            }
+            Msg::Status(event) => self.on_status(event),
+            Msg::Tick(now) => self.tick(now),
+        }
+    }
+
+    fn input(&mut self, input: Input) {
+        // stops can't hurt, and a typed command is refused by `run` if it would
+        let resetting = matches!(input, Input::ResetEStop)
+            || (matches!(input, Input::ConfirmArming) && self.estop.is_asking(Instant::now()));
+        let safe = resetting
+            || matches!(input, Input::Stop | Input::StopAll | Input::EStop | Input::Abort | Input::Run(_) | Input::CheckOff(_) | Input::Discard);
+        if self.estop.is_latched() && !safe {
+            self.notify(Severity::Warn, String::from("The e-stop is latched; E, then y, to reset"));
+            return;
+        }
+        if self.standstill.is_checking() && !safe {
+            self.notify(Severity::Warn, String::from("No input until both actuators have shown they're stopped"));
+            return;
+        }
+        if input.is_manual() && (self.position.is_active() || self.sequence.is_some()) {
+            self.abort();
+        }
+        match input {
+            Input::Drive(speed) => self.drive_manual(self.actuator, speed),
+            Input::ChangeSpeed(actuator, delta) => self.change_speed(actuator, delta),
+            Input::SetDirection(actuator, direction) => self.set_direction(actuator, direction),
+            Input::Stop => self.drive_manual(self.actuator, 0),
+            Input::Hold => {
+                self.hold(self.actuator);
+                self.notify(Severity::Info, format!("Holding {:?}", self.actuator));
+            }
+            Input::StopAll => self.stop_all(),
+            Input::EStop => {
+                let msg = self.estop("e-stop key");
+                self.notify(Severity::Error, msg);
+            }
+            Input::ResetEStop => self.ask_reset(),
+            Input::ConfirmArming if resetting => self.confirm_reset(),
+            Input::Select(actuator) if actuator != self.actuator => {
+                self.abort();
+                self.drive_manual(self.actuator, 0);
+                self.actuator = actuator;
+                self.notify(Severity::Info, format!("Switched to {:?}", self.actuator));
+                self.audit.record(&format!("select {:?}", self.actuator));
+            }
+            Input::Select(_) => {}
+            Input::CycleMode => {
+                self.stop_all();
+                self.mode = self.mode.next();
+                self.offset = 0;
+                self.notify(Severity::Info, format!("Switched to {} mode", self.mode.label()));
+                self.audit.record(&format!("mode {}", self.mode.label()));
+            }
+            Input::Skew(delta) if self.mode == mode::Mode::Differential => {
+                self.offset += delta;
+                self.drive_manual(self.actuator, self.speeds[self.actuator as usize]);
+                self.notify(Severity::Info, format!("Skew {:+}", self.offset));
+                self.audit.record(&format!("differential offset {:+}", self.offset));
+            }
+            Input::Skew(_) => {}
+            Input::Abort => {
+                if let Some(preview) = self.preview.take() {
+                    self.notify(Severity::Info, format!("Cancelled follow {}", preview.path));
+                }
+            }
+            Input::Run(line) => {
+                // a trajectory is looked over before anything moves
+                let result = self.vocabulary.parse(&line).and_then(|cmd| match cmd {
+                    dsl::Command::Follow(path) => self.preview(path),
+                    cmd => self.run(cmd),
+                });
+                self.report(result);
+            }
+            Input::StartDig => {
+                if let Err(e) = self.start_dig() {
+                    self.notify(Severity::Error, e);
+                }
+            }
+            Input::Repeat => {
+                let result = self.repeat_last();
+                self.report(result);
+            }
+            Input::Undo => {
+                let result = self.undo_last();
+                self.report(result);
+            }
+            Input::ConfirmArming => self.confirm_arming(),
+            Input::CheckOff(i) => self.check_off(i),
+            Input::Arm => self.arm(),
+            Input::Resume => {
+                let result = self.resume_interrupted();
+                self.report(result);
+            }
+            Input::Discard => {
+                let result = self.discard_interrupted();
+                self.report(result);
+            }
+            Input::Pause => match &mut self.sequence {
+                Some(runner) if runner.is_tracking() => {
+                    let paused = runner.toggle_pause();
+                    self.audit.record(if paused { "pause trajectory" } else { "resume trajectory" });
+                    let msg = runner.describe();
+                    self.notify(Severity::Info, msg);
+                }
+                _ => self.notify(Severity::Warn, String::from("No trajectory running to pause")),
+            },
+            Input::Pace(step) => match &mut self.sequence {
+                Some(runner) if runner.is_tracking() => {
+                    let now = sequence::PACES.iter().position(|&p| p >= runner.pace()).unwrap_or(sequence::PACES.len() - 1);
+                    let next = (now as i32 + step).clamp(0, sequence::PACES.len() as i32 - 1) as usize;
+                    runner.set_pace(sequence::PACES[next]);
+                    self.audit.record(&format!("trajectory at {:.0}%", sequence::PACES[next] * 100.0));
+                    let msg = runner.describe();
+                    self.notify(Severity::Info, msg);
+                }
+                _ => self.notify(Severity::Warn, String::from("No trajectory running to slow down or speed up")),
+            },
+            Input::ConfirmPreview => {
+                let result = self.confirm_preview();
+                self.report(result);
+            }
+        }
+    }
+}
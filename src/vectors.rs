@@ -0,0 +1,144 @@
+//! Wire-format test vectors, built from the same types that talk to the
+//! firmware, so the firmware's C decoder and encoder can be checked against
+//! exactly what this side sends and expects (`generate vectors`).
+//!
+//! ```text
+//! {
+//!   "version": 1,
+//!   "frames": [{"name": ..., "command": {...}, "bytes": [0, 0, 128, 0], "hex": "00008000"}, ...],
+//!   "invalid_frames": [{"name": ..., "bytes": [...], "hex": ...}, ...],
+//!   "telemetry": [{"name": ..., "fields": "len:m,current:A", "values": [...], "bytes": [...], "hex": ...}, ...],
+//!   "debug_text": [{"name": ..., "text": "pwm 512", "bytes": [...], "hex": ...}, ...]
+//! }
+//! ```
+//!
+//! A frame's `command` is what the firmware should decode its bytes into,
+//! and an invalid frame one it should reject. A telemetry vector is what the
+//! firmware should send for `values`: `fields` is empty for firmware that
+//! never advertises any, and otherwise the bytes start with the handshake
+//! and field list. A debug text vector is a line of `text` as the firmware
+//! should send it between reports.
+
+use crate::{
+    commands::{Actuator, ActuatorCommand, Direction},
+    json::Value,
+    telemetry::{DEBUG_TEXT, HANDSHAKE, Schema},
+};
+
+/// Bumped whenever a vector changes meaning, not when one is added.
+pub const VERSION: u32 = 1;
+
+/// Speeds at the edges of each byte of the little-endian `u16`.
+const SPEEDS: [u16; 6] = [0, 1, 255, 256, 32768, u16::MAX];
+
+pub fn generate() -> Value {
+    let mut frames = Vec::new();
+    for actuator in [Actuator::M1, Actuator::M2] {
+        for speed in SPEEDS {
+            frames.push(frame(ActuatorCommand::SetSpeed(speed, actuator)));
+        }
+        for direction in [Direction::Forward, Direction::Backward] {
+            frames.push(frame(ActuatorCommand::SetDirection(direction, actuator)));
+        }
+        frames.push(frame(ActuatorCommand::HoldPosition(actuator)));
+    }
+
+    let invalid = [
+        ("unknown tag", [3, 0, 0, 0]),
+        ("unknown actuator", [0, 0, 128, 2]),
+        ("unknown direction", [1, 2, 0, 0]),
+    ];
+    let invalid_frames = invalid
+        .into_iter()
+        .inspect(|(_, bytes)| debug_assert!(ActuatorCommand::deserialize(*bytes).is_err()))
+        .map(|(name, bytes)| object(vec![("name", text(name)), ("bytes", byte_array(&bytes)), ("hex", hex(&bytes))]))
+        .collect();
+
+    let telemetry = vec![
+        report("length only", None, &[0.1234]),
+        report("zero length", None, &[0.0]),
+        report("negative length", None, &[-0.0015]),
+        report("full stroke", None, &[0.3]),
+        report("advertised length and current", Some("len:m,current:A"), &[0.25, 1.5]),
+        report("advertised unitless field", Some("len:m,current:A,temp:C,faults"), &[0.05, -2.25, 41.5, 0.0]),
+    ];
+
+    let debug_text = vec![debug("short line", "pwm 512"), debug("empty line", ""), debug("non-ASCII", "temp 41.5 °C")];
+
+    object(vec![
+        ("version", Value::Number(VERSION as f64)),
+        ("frames", Value::Array(frames)),
+        ("invalid_frames", Value::Array(invalid_frames)),
+        ("telemetry", Value::Array(telemetry)),
+        ("debug_text", Value::Array(debug_text)),
+    ])
+}
+
+fn frame(cmd: ActuatorCommand) -> Value {
+    let bytes = cmd.serialize();
+    debug_assert_eq!(ActuatorCommand::deserialize(bytes), Ok(cmd));
+    let actuator = |a: &Actuator| Value::Number(*a as u8 as f64);
+    let command = match cmd {
+        ActuatorCommand::SetSpeed(speed, a) => {
+            vec![("type", text("set_speed")), ("speed", Value::Number(speed as f64)), ("actuator", actuator(&a))]
+        }
+        ActuatorCommand::SetDirection(direction, a) => vec![
+            ("type", text("set_direction")),
+            ("direction", Value::Number(direction as u8 as f64)),
+            ("actuator", actuator(&a)),
+        ],
+        ActuatorCommand::HoldPosition(a) => vec![("type", text("hold_position")), ("actuator", actuator(&a))],
+    };
+    object(vec![
+        ("name", text(&cmd.to_string())),
+        ("command", object(command)),
+        ("bytes", byte_array(&bytes)),
+        ("hex", hex(&bytes)),
+    ])
+}
+
+/// A report of `values`, after advertising `fields` if there are any.
+fn report(name: &str, fields: Option<&str>, values: &[f64]) -> Value {
+    let mut bytes = Vec::new();
+    if let Some(fields) = fields {
+        let schema = Schema::parse(fields).expect("test vector fields are valid");
+        debug_assert_eq!(schema.fields().len(), values.len());
+        bytes.extend(HANDSHAKE);
+        bytes.extend(schema.encode().bytes());
+        bytes.push(b'\n');
+    }
+    for value in values {
+        bytes.extend(value.to_le_bytes());
+    }
+    object(vec![
+        ("name", text(name)),
+        ("fields", text(fields.unwrap_or(""))),
+        ("values", Value::Array(values.iter().map(|v| Value::Number(*v)).collect())),
+        ("bytes", byte_array(&bytes)),
+        ("hex", hex(&bytes)),
+    ])
+}
+
+/// A line of debug text, as the firmware sends it.
+fn debug(name: &str, line: &str) -> Value {
+    let mut bytes = DEBUG_TEXT.to_vec();
+    bytes.extend(line.bytes());
+    bytes.push(b'\n');
+    object(vec![("name", text(name)), ("text", text(line)), ("bytes", byte_array(&bytes)), ("hex", hex(&bytes))])
+}
+
+fn text(s: &str) -> Value {
+    Value::String(s.to_string())
+}
+
+fn object(fields: Vec<(&str, Value)>) -> Value {
+    Value::Object(fields.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+}
+
+fn byte_array(bytes: &[u8]) -> Value {
+    Value::Array(bytes.iter().map(|b| Value::Number(*b as f64)).collect())
+}
+
+fn hex(bytes: &[u8]) -> Value {
+    Value::String(bytes.iter().map(|b| format!("{b:02x}")).collect())
+}
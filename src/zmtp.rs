@@ -0,0 +1,636 @@
+//! Just enough ZMTP 3.1 (the ZeroMQ wire protocol, <https://rfc.zeromq.org/spec/37/>)
+//! for the bridge: a PUB and a PULL socket that bind to a TCP endpoint and
+//! talk to any libzmq SUB/PUSH peer using the NULL security mechanism, and
+//! the PUSH and SUB ends `remote` connects to them with.
+//!
+//! On top of ZMTP, each side's READY carries an `X-Protocol` property naming
+//! the bridge protocol it speaks, [`PROTOCOL`]. A peer that names another
+//! major version is sent an ERROR and dropped; one that names none, such as
+//! a plain libzmq socket, is taken to speak this one. Peers that greet as
+//! 3.1 are pinged every [`HEARTBEAT`] and dropped after
+//! [`HEARTBEAT_TIMEOUT`] without hearing from them.
+
+use std::{io, net::SocketAddr, sync::Arc, time::Duration};
+
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::{broadcast, mpsc},
+    time::{Instant, interval},
+};
+
+const MORE: u8 = 0x01;
+const LONG: u8 = 0x02;
+const COMMAND: u8 = 0x04;
+/// anything bigger than this from a peer is treated as garbage
+const MAX_FRAME: u64 = 1 << 20;
+/// and so is a message of more frames than this, or more bytes all told
+const MAX_PARTS: usize = 16;
+const MAX_MESSAGE: usize = 4 << 20;
+/// how long a peer has to greet and say it's READY
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The bridge protocol spoken over ZMTP, as `<name>/<major>`: the topics and
+/// JSON bodies of the PUB socket and the commands the PULL socket takes.
+/// The major version only changes when an old peer would misread a new one.
+pub const PROTOCOL: &str = "actuatorctl/1";
+/// how often a 3.1 peer is pinged
+pub const HEARTBEAT: Duration = Duration::from_secs(5);
+/// how long a 3.1 peer may go without being heard from
+pub const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// `tcp://*:5556` or `tcp://127.0.0.1:5556` to something [`TcpListener::bind`] takes.
+pub fn parse_endpoint(endpoint: &str) -> Result<String, String> {
+    let Some(addr) = endpoint.strip_prefix("tcp://") else {
+        return Err(format!("`{endpoint}` must be a tcp:// endpoint, e.g. tcp://*:5556"));
+    };
+    Ok(match addr.strip_prefix("*:") {
+        Some(port) => format!("0.0.0.0:{port}"),
+        None => addr.to_string(),
+    })
+}
+
+/// `tcp://robot.local:5557` to something [`TcpStream::connect`] takes.
+fn parse_peer(endpoint: &str) -> Result<String, String> {
+    match endpoint.strip_prefix("tcp://") {
+        Some(addr) if !addr.starts_with('*') => Ok(addr.to_string()),
+        _ => Err(format!("`{endpoint}` must be a tcp:// endpoint to connect to, e.g. tcp://robot.local:5557")),
+    }
+}
+
+struct Frame {
+    more: bool,
+    command: bool,
+    body: Vec<u8>,
+}
+
+async fn read_frame(r: &mut (impl AsyncRead + Unpin)) -> io::Result<Frame> {
+    let flags = r.read_u8().await?;
+    let size = if flags & LONG != 0 { r.read_u64().await? } else { r.read_u8().await? as u64 };
+    if size > MAX_FRAME {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "frame too large"));
+    }
+    let mut body = vec![0; size as usize];
+    r.read_exact(&mut body).await?;
+    Ok(Frame {
+        more: flags & MORE != 0,
+        command: flags & COMMAND != 0,
+        body,
+    })
+}
+
+fn encode_frame(buf: &mut Vec<u8>, flags: u8, body: &[u8]) {
+    if body.len() > u8::MAX as usize {
+        buf.push(flags | LONG);
+        buf.extend_from_slice(&(body.len() as u64).to_be_bytes());
+    } else {
+        buf.push(flags);
+        buf.push(body.len() as u8);
+    }
+    buf.extend_from_slice(body);
+}
+
+async fn write_frame(w: &mut (impl AsyncWrite + Unpin), flags: u8, body: &[u8]) -> io::Result<()> {
+    let mut buf = Vec::with_capacity(body.len() + 9);
+    encode_frame(&mut buf, flags, body);
+    w.write_all(&buf).await
+}
+
+/// A command frame's body: its name, then `data`.
+fn command(name: &str, data: &[u8]) -> Vec<u8> {
+    let mut body = vec![name.len() as u8];
+    body.extend_from_slice(name.as_bytes());
+    body.extend_from_slice(data);
+    body
+}
+
+/// The data of a command frame called `name`, if that's what `body` is.
+fn command_data<'a>(body: &'a [u8], name: &str) -> Option<&'a [u8]> {
+    let (&len, rest) = body.split_first()?;
+    rest.strip_prefix(name.as_bytes()).filter(|_| len as usize == name.len())
+}
+
+/// READY's properties: a one-byte name length, the name, a four-byte value
+/// length and the value, over and over.
+fn properties(mut data: &[u8]) -> Vec<(String, Vec<u8>)> {
+    let mut out = Vec::new();
+    while let Some((&len, rest)) = data.split_first() {
+        let Some((name, rest)) = rest.split_at_checked(len as usize) else { break };
+        let Some((size, rest)) = rest.split_at_checked(4) else { break };
+        let size = u32::from_be_bytes(size.try_into().unwrap()) as usize;
+        let Some((value, rest)) = rest.split_at_checked(size) else { break };
+        out.push((String::from_utf8_lossy(name).into_owned(), value.to_vec()));
+        data = rest;
+    }
+    out
+}
+
+/// Why a peer naming `theirs` in `X-Protocol` can't be talked to, if it can't.
+fn mismatch(theirs: &str) -> Option<String> {
+    let major = |protocol: &str| protocol.split_once('/').map(|(name, v)| (name.to_string(), v.split('.').next().unwrap_or("").to_string()));
+    (major(theirs) != major(PROTOCOL)).then(|| format!("peer speaks {theirs}, not {PROTOCOL}"))
+}
+
+/// Greeting plus READY in both directions. Returns whether the peer takes
+/// heartbeats.
+async fn handshake(stream: &mut (impl AsyncRead + AsyncWrite + Unpin), socket_type: &str) -> io::Result<bool> {
+    let mut greeting = [0u8; 64];
+    greeting[0] = 0xff;
+    greeting[9] = 0x7f;
+    greeting[10] = 3; // version 3.1
+    greeting[11] = 1;
+    greeting[12..16].copy_from_slice(b"NULL");
+    stream.write_all(&greeting).await?;
+
+    let mut peer = [0u8; 64];
+    stream.read_exact(&mut peer).await?;
+    if peer[0] != 0xff || peer[9] != 0x7f || peer[10] < 3 || &peer[12..17] != b"NULL\0" {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not a ZMTP 3 peer using NULL security"));
+    }
+    let heartbeats = (peer[10], peer[11]) >= (3, 1);
+
+    let mut ready = Vec::new();
+    for (name, value) in [("Socket-Type", socket_type), ("X-Protocol", PROTOCOL)] {
+        ready.push(name.len() as u8);
+        ready.extend_from_slice(name.as_bytes());
+        ready.extend_from_slice(&(value.len() as u32).to_be_bytes());
+        ready.extend_from_slice(value.as_bytes());
+    }
+    write_frame(stream, COMMAND, &command("READY", &ready)).await?;
+
+    let frame = read_frame(stream).await?;
+    if let Some(reason) = command_data(&frame.body, "ERROR").filter(|_| frame.command) {
+        let reason = String::from_utf8_lossy(reason.get(1..).unwrap_or_default()).into_owned();
+        return Err(io::Error::new(io::ErrorKind::ConnectionRefused, format!("refused: {reason}")));
+    }
+    let Some(props) = command_data(&frame.body, "READY").filter(|_| frame.command) else {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "expected READY"));
+    };
+    for (name, value) in properties(props) {
+        if !name.eq_ignore_ascii_case("X-Protocol") {
+            continue;
+        }
+        if let Some(reason) = mismatch(&String::from_utf8_lossy(&value)) {
+            let mut data = vec![reason.len().min(255) as u8];
+            data.extend_from_slice(&reason.as_bytes()[..reason.len().min(255)]);
+            let _ = write_frame(stream, COMMAND, &command("ERROR", &data)).await;
+            return Err(io::Error::new(io::ErrorKind::InvalidData, reason));
+        }
+    }
+    Ok(heartbeats)
+}
+
+/// Adds a message frame to `parts`, returning the message once its last
+/// frame is in.
+fn gather(parts: &mut Vec<Vec<u8>>, frame: Frame) -> io::Result<Option<Vec<Vec<u8>>>> {
+    parts.push(frame.body);
+    if parts.len() > MAX_PARTS || parts.iter().map(Vec::len).sum::<usize>() > MAX_MESSAGE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "message too large"));
+    }
+    Ok((!frame.more).then(|| std::mem::take(parts)))
+}
+
+/// What a [`Connection`] hands on from the peer.
+enum Incoming {
+    /// every frame of a message
+    Message(Vec<Vec<u8>>),
+    /// a command other than the ones the connection handles itself
+    Command(Vec<u8>),
+}
+
+/// One connection after the handshake. Reading and writing happen in
+/// their own tasks, so waiting on either can be abandoned without leaving a
+/// frame half read or written.
+struct Connection {
+    frames: mpsc::Receiver<io::Result<Frame>>,
+    writes: mpsc::Sender<Vec<u8>>,
+    heartbeats: bool,
+    heard: Instant,
+    tick: tokio::time::Interval,
+    /// frames of a message still arriving
+    parts: Vec<Vec<u8>>,
+}
+
+impl Connection {
+    async fn open(mut stream: TcpStream, socket_type: &str) -> io::Result<Connection> {
+        let heartbeats = tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake(&mut stream, socket_type))
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "no handshake in time"))??;
+        let (mut r, mut w) = stream.into_split();
+        let (frames_tx, frames) = mpsc::channel(16);
+        tokio::spawn(async move {
+            loop {
+                let frame = read_frame(&mut r).await;
+                let failed = frame.is_err();
+                if frames_tx.send(frame).await.is_err() || failed {
+                    break;
+                }
+            }
+        });
+        // no buffering here, so a slow peer holds up whoever's sending to it
+        let (writes, mut writes_rx) = mpsc::channel::<Vec<u8>>(1);
+        tokio::spawn(async move {
+            while let Some(buf) = writes_rx.recv().await {
+                if w.write_all(&buf).await.is_err() {
+                    break;
+                }
+            }
+        });
+        let mut tick = interval(HEARTBEAT);
+        tick.reset();
+        Ok(Connection { frames, writes, heartbeats, heard: Instant::now(), tick, parts: Vec::new() })
+    }
+
+    async fn write(&self, buf: Vec<u8>) -> io::Result<()> {
+        self.writes.send(buf).await.map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))
+    }
+
+    /// Sends one message made of `parts`, one frame each.
+    async fn send(&mut self, parts: &[&[u8]]) -> io::Result<()> {
+        let mut buf = Vec::new();
+        for (i, part) in parts.iter().enumerate() {
+            encode_frame(&mut buf, if i + 1 < parts.len() { MORE } else { 0 }, part);
+        }
+        self.write(buf).await?;
+        // the last message made it out, so the peer's still taking them; a
+        // slow one mustn't be dropped for not answering pings we couldn't send
+        self.heard = Instant::now();
+        Ok(())
+    }
+
+    /// The next message or command from the peer, answering and sending
+    /// pings on the way. `Err` once the peer's gone.
+    async fn next(&mut self) -> io::Result<Incoming> {
+        loop {
+            tokio::select! {
+                biased;
+                frame = self.frames.recv() => {
+                    let frame = frame.unwrap_or_else(|| Err(io::ErrorKind::UnexpectedEof.into()))?;
+                    self.heard = Instant::now();
+                    if frame.command {
+                        if let Some(ping) = command_data(&frame.body, "PING") {
+                            let context = ping.get(2..).unwrap_or_default();
+                            let mut buf = Vec::new();
+                            encode_frame(&mut buf, COMMAND, &command("PONG", context));
+                            self.write(buf).await?;
+                        } else if command_data(&frame.body, "PONG").is_none() {
+                            return Ok(Incoming::Command(frame.body));
+                        }
+                        continue;
+                    }
+                    if let Some(parts) = gather(&mut self.parts, frame)? {
+                        return Ok(Incoming::Message(parts));
+                    }
+                }
+                _ = self.tick.tick(), if self.heartbeats => {
+                    if self.heard.elapsed() > HEARTBEAT_TIMEOUT {
+                        return Err(io::Error::new(io::ErrorKind::TimedOut, "peer stopped answering pings"));
+                    }
+                    // the TTL, in tenths of a second, tells the peer when to give up on us
+                    let ttl = (HEARTBEAT_TIMEOUT.as_millis() / 100) as u16;
+                    let mut buf = Vec::new();
+                    encode_frame(&mut buf, COMMAND, &command("PING", &ttl.to_be_bytes()));
+                    self.write(buf).await?;
+                }
+            }
+        }
+    }
+}
+
+/// Messages a PUB socket holds for each subscriber before the oldest are lost.
+const BACKLOG: usize = 256;
+
+/// A published message; `lean` marks telemetry that may be thinned.
+struct Message {
+    topic: String,
+    body: String,
+    lean: Option<Lean>,
+}
+
+/// Telemetry cut down to what matters for a subscriber that's falling
+/// behind, and which stream it's from so each is thinned evenly.
+struct Lean {
+    stream: usize,
+    body: String,
+}
+
+/// A PUB socket. Every message is a topic frame followed by a body frame, and
+/// only goes to subscribers whose subscription is a prefix of the topic.
+///
+/// Subscribers that fall behind never slow us down. As a subscriber's
+/// backlog grows its telemetry is sent lean, then only one in
+/// [`THIN_EVERY`], then not at all, so everything else still gets through;
+/// only past [`BACKLOG`] does it miss messages of every kind.
+pub struct Publisher {
+    tx: broadcast::Sender<Arc<Message>>,
+}
+
+/// Backlogs from which a subscriber's telemetry is sent lean, thinned, and
+/// dropped.
+const LEAN_FROM: usize = BACKLOG / 8;
+const THIN_FROM: usize = BACKLOG / 2;
+const DROP_FROM: usize = BACKLOG * 3 / 4;
+/// while thinned, one telemetry message of each stream in this many is sent
+const THIN_EVERY: u32 = 4;
+
+impl Publisher {
+    pub async fn bind(endpoint: &str) -> Result<Publisher, String> {
+        let listener = TcpListener::bind(parse_endpoint(endpoint)?)
+            .await
+            .map_err(|e| format!("couldn't bind {endpoint}: {e}"))?;
+        let (tx, _) = broadcast::channel(BACKLOG);
+        let publisher = Publisher { tx: tx.clone() };
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(subscriber(stream, tx.subscribe()));
+            }
+        });
+        Ok(publisher)
+    }
+
+    pub fn send(&self, topic: &str, body: String) {
+        // no subscribers is fine
+        let _ = self.tx.send(Arc::new(Message { topic: topic.to_string(), body, lean: None }));
+    }
+
+    /// Sends telemetry from `stream` (e.g. an actuator), with `lean` to go
+    /// to subscribers that are falling behind instead of `body`.
+    pub fn send_telemetry(&self, stream: usize, body: String, lean: String) {
+        let lean = Some(Lean { stream, body: lean });
+        let _ = self.tx.send(Arc::new(Message { topic: String::from("telemetry"), body, lean }));
+    }
+}
+
+async fn subscriber(stream: TcpStream, mut rx: broadcast::Receiver<Arc<Message>>) {
+    let Ok(mut conn) = Connection::open(stream, "PUB").await else {
+        return;
+    };
+    let mut subscriptions = Vec::<Vec<u8>>::new();
+    // telemetry messages seen per stream, for thinning
+    let mut seen: Vec<u32> = Vec::new();
+    loop {
+        tokio::select! {
+            incoming = conn.next() => {
+                // 3.0 peers subscribe with a \x01/\x00-prefixed message, 3.1 peers with a command
+                let (subscribe, topic) = match incoming {
+                    Ok(Incoming::Message(parts)) => match parts.last().map(Vec::as_slice) {
+                        Some([1, topic @ ..]) => (true, topic.to_vec()),
+                        Some([0, topic @ ..]) => (false, topic.to_vec()),
+                        _ => continue,
+                    },
+                    Ok(Incoming::Command(body)) => match (command_data(&body, "SUBSCRIBE"), command_data(&body, "CANCEL")) {
+                        (Some(topic), _) => (true, topic.to_vec()),
+                        (_, Some(topic)) => (false, topic.to_vec()),
+                        _ => continue,
+                    },
+                    Err(_) => break,
+                };
+                if subscribe {
+                    subscriptions.push(topic);
+                } else if let Some(i) = subscriptions.iter().position(|s| *s == topic) {
+                    subscriptions.remove(i);
+                }
+            }
+            msg = rx.recv() => {
+                let msg = match msg {
+                    Ok(msg) => msg,
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                let Message { topic, body, lean } = &*msg;
+                if !subscriptions.iter().any(|s| topic.as_bytes().starts_with(s)) {
+                    continue;
+                }
+                let backlog = rx.len();
+                let body = match lean {
+                    Some(lean) if backlog >= LEAN_FROM => {
+                        if seen.len() <= lean.stream {
+                            seen.resize(lean.stream + 1, 0);
+                        }
+                        let n = seen[lean.stream];
+                        seen[lean.stream] = n.wrapping_add(1);
+                        if backlog >= DROP_FROM || (backlog >= THIN_FROM && !n.is_multiple_of(THIN_EVERY)) {
+                            continue;
+                        }
+                        &lean.body
+                    }
+                    _ => body,
+                };
+                if conn.send(&[topic.as_bytes(), body.as_bytes()]).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// A PULL socket. Yields the frames of every message any PUSH peer sends,
+/// with the address it came from.
+pub async fn bind_pull(endpoint: &str) -> Result<mpsc::Receiver<(SocketAddr, Vec<Vec<u8>>)>, String> {
+    let listener = TcpListener::bind(parse_endpoint(endpoint)?)
+        .await
+        .map_err(|e| format!("couldn't bind {endpoint}: {e}"))?;
+    let (tx, rx) = mpsc::channel(64);
+    tokio::spawn(async move {
+        while let Ok((stream, peer)) = listener.accept().await {
+            tokio::spawn(pusher(stream, peer, tx.clone()));
+        }
+    });
+    Ok(rx)
+}
+
+async fn pusher(stream: TcpStream, peer: SocketAddr, tx: mpsc::Sender<(SocketAddr, Vec<Vec<u8>>)>) {
+    let Ok(mut conn) = Connection::open(stream, "PULL").await else {
+        return;
+    };
+    while let Ok(incoming) = conn.next().await {
+        if let Incoming::Message(parts) = incoming
+            && tx.send((peer, parts)).await.is_err()
+        {
+            break;
+        }
+    }
+}
+
+async fn connect(endpoint: &str, socket_type: &str) -> Result<Connection, String> {
+    let stream = TcpStream::connect(parse_peer(endpoint)?)
+        .await
+        .map_err(|e| format!("couldn't connect to {endpoint}: {e}"))?;
+    Connection::open(stream, socket_type).await.map_err(|e| format!("{endpoint}: {e}"))
+}
+
+/// A PUSH socket connected to one PULL peer, such as the bridge's.
+pub struct Pusher {
+    tx: mpsc::Sender<Vec<Vec<u8>>>,
+}
+
+impl Pusher {
+    pub async fn connect(endpoint: &str) -> Result<Pusher, String> {
+        let mut conn = connect(endpoint, "PUSH").await?;
+        let (tx, mut rx) = mpsc::channel::<Vec<Vec<u8>>>(16);
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    incoming = conn.next() => if incoming.is_err() { break },
+                    parts = rx.recv() => {
+                        let Some(parts) = parts else { break };
+                        let parts: Vec<&[u8]> = parts.iter().map(Vec::as_slice).collect();
+                        if conn.send(&parts).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+        Ok(Pusher { tx })
+    }
+
+    /// Sends one message made of `parts`, one frame each.
+    pub async fn send(&mut self, parts: &[&[u8]]) -> io::Result<()> {
+        let parts = parts.iter().map(|part| part.to_vec()).collect();
+        self.tx.send(parts).await.map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "connection closed"))
+    }
+}
+
+/// A SUB socket connected to one PUB peer and subscribed to everything.
+/// Yields each message's first frame (the topic) and last, until the peer
+/// goes away.
+pub async fn connect_sub(endpoint: &str) -> Result<mpsc::Receiver<(String, String)>, String> {
+    let mut conn = connect(endpoint, "SUB").await?;
+    // the 3.0 way, which 3.1 peers still take: \x01 and an empty prefix
+    conn.send(&[&[1]]).await.map_err(|e| format!("{endpoint}: {e}"))?;
+    let (tx, rx) = mpsc::channel(64);
+    tokio::spawn(async move {
+        while let Ok(incoming) = conn.next().await {
+            let Incoming::Message(parts) = incoming else { continue };
+            let text = |part: &[u8]| String::from_utf8_lossy(part).into_owned();
+            let message = (text(&parts[0]), text(parts.last().unwrap()));
+            if tx.send(message).await.is_err() {
+                break;
+            }
+        }
+    });
+    Ok(rx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(more: bool, body: &[u8]) -> Frame {
+        Frame { more, command: false, body: body.to_vec() }
+    }
+
+    fn greeting(major: u8, mechanism: &[u8]) -> Vec<u8> {
+        let mut greeting = vec![0u8; 64];
+        greeting[0] = 0xff;
+        greeting[9] = 0x7f;
+        greeting[10] = major;
+        greeting[12..12 + mechanism.len()].copy_from_slice(mechanism);
+        greeting
+    }
+
+    #[tokio::test]
+    async fn frames() {
+        let mut buf = Vec::new();
+        encode_frame(&mut buf, MORE, b"telemetry");
+        encode_frame(&mut buf, 0, &[7; 300]);
+        let mut r = buf.as_slice();
+        let first = read_frame(&mut r).await.unwrap();
+        assert!(first.more && !first.command && first.body == b"telemetry");
+        let second = read_frame(&mut r).await.unwrap();
+        assert!(!second.more && second.body.len() == 300);
+        assert!(read_frame(&mut r).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn malformed_frames() {
+        // cut short, and claiming more than MAX_FRAME
+        for bytes in [&[0u8, 5, b'a'][..], &[LONG, 0, 0], &[LONG, 0, 0, 0, 0, 0, 0x10, 0, 1], &[LONG, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]] {
+            let mut r = bytes;
+            assert!(read_frame(&mut r).await.is_err(), "{bytes:?} read");
+        }
+    }
+
+    #[test]
+    fn messages() {
+        let mut parts = Vec::new();
+        assert!(gather(&mut parts, frame(true, b"key")).unwrap().is_none());
+        assert_eq!(gather(&mut parts, frame(false, b"stop")).unwrap(), Some(vec![b"key".to_vec(), b"stop".to_vec()]));
+        assert!(parts.is_empty());
+    }
+
+    #[test]
+    fn oversized_messages() {
+        let mut parts = Vec::new();
+        let endless = (0..).map(|_| gather(&mut parts, frame(true, b"x"))).find(Result::is_err);
+        assert!(endless.is_some());
+        assert_eq!(parts.len(), MAX_PARTS + 1);
+
+        let mut parts = Vec::new();
+        let big = vec![0; MAX_FRAME as usize];
+        let result = (0..MAX_PARTS).map(|_| gather(&mut parts, frame(true, &big))).find(Result::is_err);
+        assert!(result.is_some());
+        assert!(parts.iter().map(Vec::len).sum::<usize>() > MAX_MESSAGE);
+    }
+
+    #[test]
+    fn ready_properties() {
+        let mut data = vec![11];
+        data.extend_from_slice(b"Socket-Type");
+        data.extend_from_slice(&4u32.to_be_bytes());
+        data.extend_from_slice(b"PUSH");
+        assert_eq!(properties(&data), vec![(String::from("Socket-Type"), b"PUSH".to_vec())]);
+        // a value claiming more than is there, a name running off the end, nothing at all
+        let mut truncated = data.clone();
+        truncated.extend_from_slice(&[1, b'X', 0xff, 0xff, 0xff, 0xff, b'y']);
+        assert_eq!(properties(&truncated).len(), 1);
+        assert_eq!(properties(&[200, b'a']), vec![]);
+        assert_eq!(properties(&[]), vec![]);
+        assert_eq!(command_data(&command("READY", b"abc"), "READY"), Some(&b"abc"[..]));
+        assert_eq!(command_data(&command("READYX", b""), "READY"), None);
+        assert_eq!(command_data(&[], "READY"), None);
+    }
+
+    #[test]
+    fn protocol_versions() {
+        assert_eq!(mismatch(PROTOCOL), None);
+        assert_eq!(mismatch("actuatorctl/1.4"), None);
+        assert!(mismatch("actuatorctl/2").is_some());
+        assert!(mismatch("other/1").is_some());
+        assert!(mismatch("").is_some());
+    }
+
+    #[tokio::test]
+    async fn bad_greetings() {
+        for peer in [vec![0u8; 64], greeting(2, b"NULL\0"), greeting(3, b"PLAIN"), greeting(3, b"NULL\0")[..30].to_vec()] {
+            let (mut ours, mut theirs) = tokio::io::duplex(1024);
+            theirs.write_all(&peer).await.unwrap();
+            drop(theirs);
+            assert!(handshake(&mut ours, "PULL").await.is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn ready() {
+        let (mut ours, mut theirs) = tokio::io::duplex(1024);
+        let mut peer = greeting(3, b"NULL\0");
+        peer[11] = 1;
+        let mut props = vec![10];
+        props.extend_from_slice(b"X-Protocol");
+        props.extend_from_slice(&(PROTOCOL.len() as u32).to_be_bytes());
+        props.extend_from_slice(PROTOCOL.as_bytes());
+        encode_frame(&mut peer, COMMAND, &command("READY", &props));
+        theirs.write_all(&peer).await.unwrap();
+        assert!(handshake(&mut ours, "PULL").await.unwrap());
+
+        // the wrong major version, and something other than READY
+        for body in [command("READY", &[&[10][..], b"X-Protocol", &5u32.to_be_bytes(), b"xyz/9"].concat()), b"hello".to_vec()] {
+            let (mut ours, mut theirs) = tokio::io::duplex(1024);
+            let mut peer = greeting(3, b"NULL\0");
+            encode_frame(&mut peer, COMMAND, &body);
+            theirs.write_all(&peer).await.unwrap();
+            assert!(handshake(&mut ours, "PULL").await.is_err());
+        }
+    }
+}